@@ -2,58 +2,307 @@ use serde_derive::{Deserialize, Serialize};
 
 use crate::error::AttTrError;
 
+const BASE58_ALPHABET: &str = "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BECH32_CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+/// DID method of an identifier.
 #[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
 pub enum Schema {
 	Pkh,
+	Key,
+	Web,
+	/// Any other method, kept as its name and opaque method-specific id.
+	Other(String),
+}
+
+impl Schema {
+	fn parse(value: &str) -> Result<Self, AttTrError> {
+		match value {
+			"pkh" => Ok(Self::Pkh),
+			"key" => Ok(Self::Key),
+			"web" => Ok(Self::Web),
+			_ => {
+				let is_valid = !value.is_empty()
+					&& value.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+				if !is_valid {
+					return Err(AttTrError::ParseError);
+				}
+				Ok(Self::Other(value.to_owned()))
+			},
+		}
+	}
+
+	pub fn as_str(&self) -> &str {
+		match self {
+			Self::Pkh => "pkh",
+			Self::Key => "key",
+			Self::Web => "web",
+			Self::Other(method) => method,
+		}
+	}
+}
+
+/// CAIP-2 namespaces we know how to normalize account addresses for.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub enum Namespace {
+	Eip155,
+	Solana,
+	Cosmos,
+	Bip122,
+}
+
+impl Namespace {
+	fn parse(value: &str) -> Result<Self, AttTrError> {
+		match value {
+			"eip155" => Ok(Self::Eip155),
+			"solana" => Ok(Self::Solana),
+			"cosmos" => Ok(Self::Cosmos),
+			"bip122" => Ok(Self::Bip122),
+			_ => Err(AttTrError::ParseError),
+		}
+	}
+
+	fn as_str(&self) -> &'static str {
+		match self {
+			Self::Eip155 => "eip155",
+			Self::Solana => "solana",
+			Self::Cosmos => "cosmos",
+			Self::Bip122 => "bip122",
+		}
+	}
+
+	/// Validates an account address for this namespace and returns its canonical form.
+	fn normalize_address(&self, address: &str) -> Result<String, AttTrError> {
+		match self {
+			// EVM addresses are case-insensitive hex (EIP-55 only adds a checksum).
+			Self::Eip155 => {
+				let hex_part = address.strip_prefix("0x").ok_or(AttTrError::ParseError)?;
+				if hex_part.len() != 40 || hex::decode(hex_part).is_err() {
+					return Err(AttTrError::ParseError);
+				}
+				Ok(format!("0x{}", hex_part.to_lowercase()))
+			},
+			// Base58 is case-sensitive, so the address is kept verbatim.
+			Self::Solana => {
+				if !(32..=44).contains(&address.len()) || !is_base58(address) {
+					return Err(AttTrError::ParseError);
+				}
+				Ok(address.to_owned())
+			},
+			Self::Cosmos => normalize_bech32(address),
+			// Legacy and P2SH addresses are base58, segwit ones are bech32.
+			Self::Bip122 => {
+				if let Ok(address) = normalize_bech32(address) {
+					return Ok(address);
+				}
+				if !(26..=35).contains(&address.len()) || !is_base58(address) {
+					return Err(AttTrError::ParseError);
+				}
+				Ok(address.to_owned())
+			},
+		}
+	}
+}
+
+/// CAIP-2 chain id, e.g. `eip155:1`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq, Eq)]
+pub struct ChainId {
+	pub namespace: Namespace,
+	pub reference: String,
+}
+
+impl ChainId {
+	fn parse(namespace: &str, reference: &str) -> Result<Self, AttTrError> {
+		let namespace = Namespace::parse(namespace)?;
+		let valid_reference = !reference.is_empty()
+			&& reference.len() <= 32
+			&& reference.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+		if !valid_reference {
+			return Err(AttTrError::ParseError);
+		}
+		let reference = match namespace {
+			Namespace::Eip155 => {
+				reference.parse::<u64>().map_err(|_| AttTrError::ParseError)?;
+				reference.to_owned()
+			},
+			// Genesis block hash prefix, hex encoded.
+			Namespace::Bip122 => {
+				if hex::decode(reference).is_err() {
+					return Err(AttTrError::ParseError);
+				}
+				reference.to_lowercase()
+			},
+			Namespace::Solana | Namespace::Cosmos => reference.to_owned(),
+		};
+		Ok(Self { namespace, reference })
+	}
 }
 
 #[derive(Deserialize, Serialize, Clone)]
 pub struct Did {
-	schema: Schema,
+	pub schema: Schema,
+	/// Chain the account lives on. Only set for the 5 part `did:pkh` form.
+	pub chain: Option<ChainId>,
+	/// Identity of the subject used for keying: the raw address bytes for EVM accounts and
+	/// the legacy `did:pkh:<hex>` form, the normalized address for other accounts, and the
+	/// method followed by the normalized method-specific id, as in `web:example.com`, for
+	/// other methods so their ids never collide.
 	pub key: Vec<u8>,
 }
 
 impl Did {
+	/// A DID without a chain, keyed by `key` as described on the field.
 	pub fn new(schema: Schema, key: Vec<u8>) -> Self {
-		Self { schema, key }
+		Self { schema, chain: None, key }
 	}
 
 	pub fn parse(value: String) -> Result<Self, AttTrError> {
-		let parts = value.split(":");
-		let part_slices: Vec<&str> = parts.into_iter().collect();
-		// 3 parts: did, pkh, [public key hash]
-		if part_slices.len() != 3 {
+		let (prefix, rest) = value.split_once(':').ok_or(AttTrError::ParseError)?;
+		if prefix != "did" {
 			return Err(AttTrError::ParseError);
 		}
-		let prefix = part_slices[0];
-		if prefix != "did" {
+		let (method, id) = rest.split_once(':').ok_or(AttTrError::ParseError)?;
+		let schema = Schema::parse(method)?;
+
+		match schema {
+			Schema::Pkh => Self::parse_pkh(id),
+			Schema::Key => {
+				// Multibase encoded public key, only base58btc (`z`) is in use.
+				let key = id.strip_prefix('z').ok_or(AttTrError::ParseError)?;
+				if key.is_empty() || !is_base58(key) {
+					return Err(AttTrError::ParseError);
+				}
+				Ok(Self::with_method_key(schema, id))
+			},
+			Schema::Web => {
+				// The domain is case-insensitive, the path segments after it are not.
+				let (domain, path) = match id.split_once(':') {
+					Some((domain, path)) => (domain, Some(path)),
+					None => (id, None),
+				};
+				let is_valid_domain = !domain.is_empty()
+					&& domain.chars().all(|c| c.is_ascii_alphanumeric() || "-.%".contains(c));
+				if !is_valid_domain || path.map_or(false, |p| !is_valid_id(p)) {
+					return Err(AttTrError::ParseError);
+				}
+				let mut id = domain.to_lowercase();
+				if let Some(path) = path {
+					id = format!("{}:{}", id, path);
+				}
+				Ok(Self::with_method_key(schema, &id))
+			},
+			Schema::Other(_) => {
+				if !is_valid_id(id) {
+					return Err(AttTrError::ParseError);
+				}
+				Ok(Self::with_method_key(schema, id))
+			},
+		}
+	}
+
+	/// Keys a DID of a method other than `pkh` by its method and method-specific `id`.
+	fn with_method_key(schema: Schema, id: &str) -> Self {
+		let key = format!("{}:{}", schema.as_str(), id).into_bytes();
+		Self::new(schema, key)
+	}
+
+	fn parse_pkh(id: &str) -> Result<Self, AttTrError> {
+		let part_slices: Vec<&str> = id.split(':').collect();
+		// 1 part: [public key hash]
+		// 3 parts: [namespace], [reference], [account address]
+		if part_slices.len() == 1 {
+			let key = hex::decode(part_slices[0]).map_err(|_| AttTrError::ParseError)?;
+			return Ok(Self { schema: Schema::Pkh, chain: None, key });
+		}
+		if part_slices.len() != 3 {
 			return Err(AttTrError::ParseError);
 		}
-		let schema = match part_slices[1] {
-			"pkh" => Schema::Pkh,
-			_ => return Err(AttTrError::ParseError),
+
+		let chain = ChainId::parse(part_slices[0], part_slices[1])?;
+		let address = chain.namespace.normalize_address(part_slices[2])?;
+		let key = match chain.namespace {
+			Namespace::Eip155 => hex::decode(&address[2..]).map_err(|_| AttTrError::ParseError)?,
+			_ => address.into_bytes(),
 		};
-		let key = hex::decode(part_slices[2]).map_err(|_| AttTrError::ParseError)?;
 
-		Ok(Self { schema, key })
+		Ok(Self { schema: Schema::Pkh, chain: Some(chain), key })
 	}
 }
 
-impl Into<String> for Did {
-	fn into(self) -> String {
-		let schema = match self.schema {
-			Schema::Pkh => "pkh",
-		};
-		let pkh = hex::encode(self.key);
-		let did_string = format!("did:{}:{}", schema, pkh);
+impl From<Did> for String {
+	fn from(did: Did) -> Self {
+		let schema = did.schema.as_str();
+		match (&did.schema, did.chain) {
+			(Schema::Pkh, None) => format!("did:{}:{}", schema, hex::encode(did.key)),
+			(Schema::Pkh, Some(chain)) => {
+				let address = match chain.namespace {
+					Namespace::Eip155 => format!("0x{}", hex::encode(did.key)),
+					_ => String::from_utf8_lossy(&did.key).into_owned(),
+				};
+				format!(
+					"did:{}:{}:{}:{}",
+					schema,
+					chain.namespace.as_str(),
+					chain.reference,
+					address
+				)
+			},
+			// Keys of other methods start with the method already.
+			_ => format!("did:{}", String::from_utf8_lossy(&did.key)),
+		}
+	}
+}
 
-		did_string
+/// Method-specific ids are colon separated segments of unreserved or percent-encoded chars.
+fn is_valid_id(id: &str) -> bool {
+	let bytes = id.as_bytes();
+	if id.is_empty() || id.ends_with(':') {
+		return false;
 	}
+	let mut i = 0;
+	while i < bytes.len() {
+		match bytes[i] {
+			b'%' => {
+				let is_hex =
+					bytes.get(i + 1..i + 3).map_or(false, |h| h.iter().all(u8::is_ascii_hexdigit));
+				if !is_hex {
+					return false;
+				}
+				i += 3;
+			},
+			b if b.is_ascii_alphanumeric() || b".-_:".contains(&b) => i += 1,
+			_ => return false,
+		}
+	}
+	true
+}
+
+fn is_base58(value: &str) -> bool {
+	value.chars().all(|c| BASE58_ALPHABET.contains(c))
+}
+
+/// Bech32 strings must not mix cases; the canonical form is lowercase.
+fn normalize_bech32(value: &str) -> Result<String, AttTrError> {
+	let is_lower = value == value.to_lowercase();
+	let is_upper = value == value.to_uppercase();
+	if !is_lower && !is_upper {
+		return Err(AttTrError::ParseError);
+	}
+	let value = value.to_lowercase();
+	let (hrp, data) = value.rsplit_once('1').ok_or(AttTrError::ParseError)?;
+	// Human readable part, separator and at least the 6 character checksum.
+	if hrp.is_empty() || data.len() < 6 || value.len() > 90 {
+		return Err(AttTrError::ParseError);
+	}
+	if !data.chars().all(|c| BECH32_CHARSET.contains(c)) {
+		return Err(AttTrError::ParseError);
+	}
+	Ok(value)
 }
 
 #[cfg(test)]
 mod test {
-	use crate::did::Schema;
+	use crate::did::{Namespace, Schema};
 
 	use super::Did;
 
@@ -71,4 +320,95 @@ mod test {
 
 		assert_eq!(did_string, did_new_string);
 	}
+
+	#[test]
+	fn should_normalize_eip155_did() {
+		let did_string = "did:pkh:eip155:1:0x90F8bf6A479f320ead074411a4B0e7944Ea8c9C2".to_string();
+		let did = Did::parse(did_string).unwrap();
+		assert_eq!(did.chain.clone().unwrap().namespace, Namespace::Eip155);
+		assert_eq!(
+			did.key,
+			hex::decode("90f8bf6a479f320ead074411a4b0e7944ea8c9c2").unwrap()
+		);
+
+		let did_new_string: String = did.into();
+		assert_eq!(
+			did_new_string,
+			"did:pkh:eip155:1:0x90f8bf6a479f320ead074411a4b0e7944ea8c9c2"
+		);
+	}
+
+	#[test]
+	fn should_parse_non_evm_dids() {
+		let dids = [
+			"did:pkh:solana:4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZ:CKg5d12Jhpej1JqtmxLJgaFqqeYjxgPqToJ4LBdvG9Ev",
+			"did:pkh:cosmos:cosmoshub-3:cosmos1t2uflqwqe0fsj0shcfkrvpukewcw40yjj6hdc0",
+			"did:pkh:bip122:000000000019d6689c085ae165831e93:128Lkh3S7CkDTBZ8W7BbpsN3YYizJMp8p6",
+		];
+		for did_string in dids {
+			let did = Did::parse(did_string.to_string()).unwrap();
+			let did_new_string: String = did.into();
+			assert_eq!(did_string, did_new_string);
+		}
+	}
+
+	#[test]
+	fn should_lowercase_bech32_addresses() {
+		let did_string =
+			"did:pkh:bip122:000000000019d6689c085ae165831e93:BC1QAR0SRRR7XFKVY5L643LYDNW9RE59GTZZWF5MDQ";
+		let did = Did::parse(did_string.to_string()).unwrap();
+		assert_eq!(
+			did.key,
+			b"bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq".to_vec()
+		);
+	}
+
+	#[test]
+	fn should_parse_other_did_methods() {
+		let did =
+			Did::parse("did:key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK".to_string())
+				.unwrap();
+		assert_eq!(did.schema, Schema::Key);
+
+		let did = Did::parse("did:web:Example.com:user:alice".to_string()).unwrap();
+		assert_eq!(did.schema, Schema::Web);
+		let did_new_string: String = did.into();
+		assert_eq!(did_new_string, "did:web:example.com:user:alice");
+
+		let did = Did::parse("did:ion:EiClkZMDxPKqC9c-umQfTkR8vvZ9JPhl_xLDI9Nfk38w5w".to_string())
+			.unwrap();
+		assert_eq!(did.schema, Schema::Other("ion".to_string()));
+		assert_eq!(
+			did.key,
+			b"ion:EiClkZMDxPKqC9c-umQfTkR8vvZ9JPhl_xLDI9Nfk38w5w".to_vec()
+		);
+	}
+
+	#[test]
+	fn should_key_other_methods_apart() {
+		let key = Did::parse("did:key:zabc".to_string()).unwrap();
+		let web = Did::parse("did:web:zabc".to_string()).unwrap();
+		assert_ne!(key.key, web.key);
+
+		let did = Did::new(Schema::Web, b"web:example.com".to_vec());
+		let did_string: String = did.into();
+		assert_eq!(did_string, "did:web:example.com");
+	}
+
+	#[test]
+	fn should_reject_malformed_dids() {
+		let dids = [
+			"did:pkh:eip155:1:90f8bf6a479f320ead074411a4b0e7944ea8c9c2",
+			"did:pkh:solana:4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZ:0OIl",
+			"did:pkh:cosmos:cosmoshub-3:Cosmos1t2uflqwqe0fsj0shcfkrvpukewcw40yjj6hdc0",
+			"did:pkh:tezos:NetXdQprcVkpaWU:tz1TzrmTBSuiVHV2VfMnGRMYvTEPCP42oSM8",
+			"did:key:6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK",
+			"did:web:example.com:user:",
+			"did:Web:example.com",
+			"did:example:abc%zz",
+		];
+		for did_string in dids {
+			assert!(Did::parse(did_string.to_string()).is_err());
+		}
+	}
 }
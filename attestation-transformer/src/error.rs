@@ -13,9 +13,9 @@ pub enum AttTrError {
 	#[error("DbError: {0}")]
 	DbError(RocksDbError),
 
-	#[error("NotFoundError")]
-	NotFoundError,
-
 	#[error("ParseError")]
 	ParseError,
+
+	#[error("IncompatibleService: {0}")]
+	IncompatibleService(String),
 }
@@ -1,18 +1,25 @@
 use error::AttTrError;
 use futures::stream::iter;
 use proto_buf::combiner::linear_combiner_client::LinearCombinerClient;
-use proto_buf::common::Void;
+use proto_buf::common::{ErrorDetail, PageRequest, ServiceInfo, Void};
 use proto_buf::indexer::indexer_client::IndexerClient;
 use proto_buf::indexer::{IndexerEvent, Query};
 use proto_buf::transformer::transformer_server::{Transformer, TransformerServer};
-use proto_buf::transformer::{TermBatch, TermObject};
-use rocksdb::{WriteBatch, DB};
+use proto_buf::transformer::{TermBatch, TermObject, TermObjectBatch};
+use proto_buf::{is_retryable, PROTOCOL_VERSION};
+use rocksdb::{IteratorMode, WriteBatch, DB};
 use schemas::{AuditApproveSchema, AuditDisapproveSchema, FollowSchema, SchemaType};
 use serde_json::from_str;
+use std::collections::HashMap;
+use std::env;
 use std::error::Error;
+use std::future::Future;
+use std::time::Duration;
 use term::{IntoTerm, Term};
+use tokio::time::sleep;
+use tonic::metadata::{Ascii, MetadataValue};
 use tonic::transport::Channel;
-use tonic::{transport::Server, Request, Response, Status};
+use tonic::{transport::Server, Code, Request, Response, Status};
 
 mod did;
 mod error;
@@ -23,27 +30,62 @@ mod utils;
 const MAX_TERM_BATCH_SIZE: u32 = 1000;
 const MAX_ATT_BATCH_SIZE: u32 = 1000;
 const ATTESTATION_SOURCE_ADDRESS: &str = "0x1";
-const FOLLOW_SCHEMA_ID: &str = "0x2";
+const INDEXED_SCHEMA_IDS: [&str; 3] = ["1", "2", "3"];
+/// Format of the stored terms, present once the terms stored before their account keys were
+/// length-prefixed are rewritten.
+const TERM_FORMAT_KEY: &[u8] = b"term_format";
+const TERM_FORMAT: u8 = 1;
+/// Times a request failing with a retryable status is retried.
+const MAX_RETRIES: u32 = 3;
+/// Wait before the first retry when the server suggests none, doubling with every retry.
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// Features of the linear combiner the terms sent rely on, being tagged with a source and
+/// sequence to deduplicate them by.
+const REQUIRED_LT_FEATURES: [&str; 2] = ["source_checkpoints", "sequence_dedup"];
 
 #[derive(Debug)]
 struct TransformerService {
 	indexer_channel: Channel,
 	lt_channel: Channel,
+	/// Bearer token presented to the linear combiner, if it requires one.
+	lt_token: Option<String>,
+	/// Source ID the terms are tagged with, telling this pipeline apart from others.
+	source: String,
 	db: String,
+	/// Events fetched per sync, within the limits of the indexer.
+	att_batch_size: u32,
+	/// Most terms sent per stream, within the limits of the linear combiner.
+	term_batch_size: u32,
+	/// Terms sent per batch, if the linear combiner takes them in batches rather than one by
+	/// one.
+	lt_batch_size: Option<u32>,
 }
 
 impl TransformerService {
 	fn new(
-		indexer_channel: Channel, lt_channel: Channel, db_url: &str,
+		indexer_channel: Channel, lt_channel: Channel, lt_token: Option<String>, source: String,
+		db_url: &str, indexer_info: &ServiceInfo, lt_info: &ServiceInfo,
 	) -> Result<Self, AttTrError> {
 		let db = DB::open_default(db_url).map_err(|x| AttTrError::DbError(x))?;
+		Self::migrate_terms(&db)?;
 		let checkpoint = db.get(b"checkpoint").map_err(|x| AttTrError::DbError(x))?;
 		if let None = checkpoint {
 			let count = 0u32.to_be_bytes();
 			db.put(b"checkpoint", count).map_err(|e| AttTrError::DbError(e))?;
 		}
 
-		Ok(Self { indexer_channel, lt_channel, db: db_url.to_string() })
+		let takes_batches = lt_info.features.iter().any(|feature| feature == "term_batches");
+		let lt_batch_size = capped(lt_info.limit("max_term_batch_size"), MAX_TERM_BATCH_SIZE);
+		Ok(Self {
+			indexer_channel,
+			lt_channel,
+			lt_token,
+			source,
+			db: db_url.to_string(),
+			att_batch_size: capped(indexer_info.limit("max_query_count"), MAX_ATT_BATCH_SIZE),
+			term_batch_size: capped(lt_info.limit("max_stream_terms"), MAX_TERM_BATCH_SIZE),
+			lt_batch_size: takes_batches.then_some(lt_batch_size),
+		})
 	}
 
 	fn read_checkpoint(db: &DB) -> Result<u32, AttTrError> {
@@ -62,19 +104,51 @@ impl TransformerService {
 		Ok(())
 	}
 
+	/// Rewrites the terms stored before their keys were length-prefixed, then marks the
+	/// database so it is done once.
+	fn migrate_terms(db: &DB) -> Result<(), AttTrError> {
+		if db.get(TERM_FORMAT_KEY).map_err(|e| AttTrError::DbError(e))?.is_some() {
+			return Ok(());
+		}
+		let mut batch = WriteBatch::default();
+		for item in db.iterator(IteratorMode::Start) {
+			let (key, value) = item.map_err(|e| AttTrError::DbError(e))?;
+			// Terms are keyed by the ID of their event, every other key is longer.
+			if key.len() != 4 {
+				continue;
+			}
+			let term = Term::from_legacy_bytes(value.to_vec())?;
+			batch.put(key, term.into_bytes()?);
+		}
+		batch.put(TERM_FORMAT_KEY, [TERM_FORMAT]);
+		db.write(batch).map_err(|e| AttTrError::DbError(e))
+	}
+
 	fn read_terms(db: &DB, batch: TermBatch) -> Result<Vec<TermObject>, AttTrError> {
 		let mut terms = Vec::new();
 		for i in batch.start..batch.size {
 			let id_bytes = i.to_be_bytes();
-			let res_opt = db.get(id_bytes).map_err(|e| AttTrError::DbError(e))?;
-			let res = res_opt.ok_or_else(|| AttTrError::NotFoundError)?;
+			// IDs of events the indexer filtered out have no term.
+			let Some(res) = db.get(id_bytes).map_err(AttTrError::DbError)? else {
+				continue;
+			};
 			let term = Term::from_bytes(res)?;
-			let term_obj: TermObject = term.into();
+			let mut term_obj: TermObject = term.into();
+			term_obj.sequence = u64::from(i) + 1;
 			terms.push(term_obj);
 		}
 		Ok(terms)
 	}
 
+	/// Splits `terms` into batches of up to `size` terms, numbered from 1.
+	fn batch_terms(terms: &[TermObject], size: u32) -> Vec<TermObjectBatch> {
+		let chunks = terms.chunks(size.max(1) as usize);
+		chunks
+			.zip(1..)
+			.map(|(terms, sequence)| TermObjectBatch { terms: terms.to_vec(), sequence })
+			.collect()
+	}
+
 	fn parse_event(event: IndexerEvent) -> Result<(u32, Term), AttTrError> {
 		let schema_id = event.schema_id;
 		let schema_type = SchemaType::from(schema_id);
@@ -125,22 +199,27 @@ impl Transformer for TransformerService {
 
 		let indexer_query = Query {
 			source_address: ATTESTATION_SOURCE_ADDRESS.to_owned(),
-			schema_id: vec![FOLLOW_SCHEMA_ID.to_owned()],
-			offset,
-			count: MAX_ATT_BATCH_SIZE,
+			schema_id: INDEXED_SCHEMA_IDS.iter().map(|id| id.to_string()).collect(),
+			page: Some(PageRequest::from_offset(offset, self.att_batch_size)),
+			..Query::default()
 		};
 
-		let mut client = IndexerClient::new(self.indexer_channel.clone());
-		let mut response = client.subscribe(indexer_query).await?.into_inner();
+		let mut response = with_retries(|| {
+			let mut client = IndexerClient::new(self.indexer_channel.clone());
+			let query = indexer_query.clone();
+			async move { client.subscribe(query).await }
+		})
+		.await?
+		.into_inner();
 		let mut count = offset;
 		let mut terms = Vec::new();
 		// ResponseStream
 		while let Ok(Some(res)) = response.message().await {
-			assert!(res.id == count);
+			// Events of other schemas are filtered out, leaving gaps between IDs.
+			count = res.id + 1;
 			let term =
 				Self::parse_event(res).map_err(|_| Status::internal("Failed to parse event"))?;
 			terms.push(term);
-			count += 1;
 		}
 
 		Self::write_terms(&db, terms).map_err(|_| Status::internal("Failed to write terms"))?;
@@ -152,32 +231,155 @@ impl Transformer for TransformerService {
 
 	async fn term_stream(&self, request: Request<TermBatch>) -> Result<Response<Void>, Status> {
 		let inner = request.into_inner();
-		if inner.size > MAX_TERM_BATCH_SIZE {
-			return Result::Err(Status::invalid_argument(format!(
-				"Batch size too big. Max size: {}",
-				MAX_TERM_BATCH_SIZE
-			)));
+		if inner.size > self.term_batch_size {
+			let msg = format!("Batch size too big. Max size: {}", self.term_batch_size);
+			let detail = ErrorDetail::permanent().with_field("size");
+			return Result::Err(detail.into_status(Code::InvalidArgument, msg));
 		}
 
 		let db = DB::open_default(self.db.clone())
 			.map_err(|_| Status::internal("Failed to connect to DB"))?;
 
-		let terms =
+		let mut terms =
 			Self::read_terms(&db, inner).map_err(|_| Status::internal("Failed to read terms"))?;
+		terms.iter_mut().for_each(|term| term.source = self.source.clone());
 
-		let mut client = LinearCombinerClient::new(self.lt_channel.clone());
-		let res = client.sync_transformer(Request::new(iter(terms))).await?;
+		let authorization: Option<MetadataValue<Ascii>> = match &self.lt_token {
+			Some(token) => Some(
+				format!("Bearer {}", token)
+					.parse()
+					.map_err(|_| Status::invalid_argument("Invalid combiner token"))?,
+			),
+			None => None,
+		};
+		// Terms the combiner applied before failing are skipped by their sequence when the
+		// stream is retried.
+		if let Some(size) = self.lt_batch_size {
+			let batches = Self::batch_terms(&terms, size);
+			return with_retries(|| {
+				let mut client = LinearCombinerClient::new(self.lt_channel.clone());
+				let request = authorized(iter(batches.clone()), authorization.as_ref());
+				async move {
+					let mut acks = client.sync_term_batches(request).await?.into_inner();
+					while acks.message().await?.is_some() {}
+					Ok(Response::new(Void::default()))
+				}
+			})
+			.await;
+		}
+		let res = with_retries(|| {
+			let mut client = LinearCombinerClient::new(self.lt_channel.clone());
+			let request = authorized(iter(terms.clone()), authorization.as_ref());
+			async move { client.sync_transformer(request).await }
+		})
+		.await?;
 
 		Ok(res)
 	}
+
+	async fn get_service_info(&self, _: Request<Void>) -> Result<Response<ServiceInfo>, Status> {
+		let limits = HashMap::from([(
+			"max_term_batch_size".to_string(),
+			u64::from(self.term_batch_size),
+		)]);
+		Ok(Response::new(ServiceInfo {
+			service: "attestation-transformer".to_string(),
+			version: env!("CARGO_PKG_VERSION").to_string(),
+			protocol_version: PROTOCOL_VERSION,
+			features: vec!["term_sources".to_string()],
+			limits,
+		}))
+	}
+}
+
+/// Checks the info a service answered `GetServiceInfo` with, so that running against a
+/// version it can't work with fails on startup rather than halfway through a sync.
+fn negotiate(
+	name: &str, response: Result<Response<ServiceInfo>, Status>, features: &[&str],
+) -> Result<ServiceInfo, AttTrError> {
+	let info = match response {
+		Ok(response) => response.into_inner(),
+		Err(status) if status.code() == Code::Unimplemented => {
+			return Err(AttTrError::IncompatibleService(format!(
+				"{} predates GetServiceInfo, expected protocol version {}",
+				name, PROTOCOL_VERSION
+			)));
+		},
+		Err(status) => {
+			return Err(AttTrError::IncompatibleService(format!(
+				"{} info unavailable: {}",
+				name,
+				status.message()
+			)));
+		},
+	};
+	println!(
+		"Connected to {} {} speaking protocol version {}, features: {:?}, limits: {:?}",
+		info.service, info.version, info.protocol_version, info.features, info.limits
+	);
+	info.check(features).map_err(AttTrError::IncompatibleService)?;
+	Ok(info)
+}
+
+/// Request of `message`, bearing `authorization` if any.
+fn authorized<T>(message: T, authorization: Option<&MetadataValue<Ascii>>) -> Request<T> {
+	let mut request = Request::new(message);
+	if let Some(value) = authorization {
+		request.metadata_mut().insert("authorization", value.clone());
+	}
+	request
+}
+
+/// Runs `call` until it succeeds, fails with a status not worth retrying, or was retried
+/// `MAX_RETRIES` times, waiting in between as long as the server asks or backing off
+/// exponentially.
+async fn with_retries<T, F, Fut>(mut call: F) -> Result<T, Status>
+where
+	F: FnMut() -> Fut,
+	Fut: Future<Output = Result<T, Status>>,
+{
+	let mut retries = 0;
+	loop {
+		match call().await {
+			Err(status) if retries < MAX_RETRIES && is_retryable(&status) => {
+				let wait = match ErrorDetail::of(&status) {
+					Some(detail) if detail.retry_after > 0 => {
+						Duration::from_millis(detail.retry_after)
+					},
+					_ => RETRY_BACKOFF * 2u32.pow(retries),
+				};
+				println!("Retrying in {:?} after: {}", wait, status.message());
+				sleep(wait).await;
+				retries += 1;
+			},
+			result => return result,
+		}
+	}
+}
+
+/// `max`, lowered to a limit of the server if there is a lower one.
+fn capped(limit: Option<u64>, max: u32) -> u32 {
+	limit.map_or(max, |limit| limit.min(u64::from(max)) as u32)
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
 	let indexer_channel = Channel::from_static("http://localhost:50050").connect().await?;
 	let lt_channel = Channel::from_static("http://localhost:50052").connect().await?;
+	let lt_token = env::var("LC_TRANSFORMER_TOKEN").ok();
+	let source = env::var("LC_SOURCE_ID").unwrap_or_default();
 	let db_url = "att-tr-storage";
-	let tr_service = TransformerService::new(indexer_channel, lt_channel, db_url)?;
+
+	let mut indexer_client = IndexerClient::new(indexer_channel.clone());
+	let response = indexer_client.get_service_info(Void {}).await;
+	let indexer_info = negotiate("indexer", response, &[])?;
+	let mut lt_client = LinearCombinerClient::new(lt_channel.clone());
+	let response = lt_client.get_service_info(Void {}).await;
+	let lt_info = negotiate("linear combiner", response, &REQUIRED_LT_FEATURES)?;
+
+	let tr_service = TransformerService::new(
+		indexer_channel, lt_channel, lt_token, source, db_url, &indexer_info, &lt_info,
+	)?;
 
 	let addr = "[::1]:50051".parse()?;
 	Server::builder().add_service(TransformerServer::new(tr_service)).serve(addr).await?;
@@ -186,14 +388,18 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
 #[cfg(test)]
 mod test {
+	use proto_buf::common::{ErrorDetail, ServiceInfo};
 	use proto_buf::indexer::IndexerEvent;
 	use proto_buf::transformer::{TermBatch, TermObject};
-	use rocksdb::DB;
+	use proto_buf::PROTOCOL_VERSION;
+	use rocksdb::{Options, DB};
 	use serde_json::to_string;
+	use std::collections::HashMap;
+	use tonic::{Code, Response, Status};
 
 	use crate::schemas::Scope;
 	use crate::term::IntoTerm;
-	use crate::{schemas::FollowSchema, TransformerService};
+	use crate::{capped, negotiate, schemas::FollowSchema, with_retries, TransformerService};
 
 	#[test]
 	fn should_write_read_checkpoint() {
@@ -217,6 +423,9 @@ mod test {
 			schema_id: 1,
 			schema_value: to_string(&follow_schema).unwrap(),
 			timestamp: 2397848,
+			source: "mock".to_string(),
+			received_at: 0,
+			domain: 0,
 		};
 		let term = TransformerService::parse_event(indexed_event).unwrap();
 		TransformerService::write_terms(&db, vec![term]).unwrap();
@@ -225,7 +434,88 @@ mod test {
 		let terms = TransformerService::read_terms(&db, term_batch).unwrap();
 
 		let term = follow_schema.into_term().unwrap();
-		let term_obj: TermObject = term.into();
+		let mut term_obj: TermObject = term.into();
+		term_obj.sequence = 1;
 		assert_eq!(terms, vec![term_obj]);
 	}
+
+	#[test]
+	fn should_migrate_legacy_terms() {
+		let path = "att-tr-legacy-test-storage";
+		// Left behind by an earlier run, which migrated it already.
+		let _ = DB::destroy(&Options::default(), path);
+		let db = DB::open_default(path).unwrap();
+		let from = hex::decode("90f8bf6a479f320ead074411a4b0e7944ea8c9c1").unwrap();
+		let to = hex::decode("90f8bf6a479f320ead074411a4b0e7944ea8c9c2").unwrap();
+		let legacy = [from, to, 50u32.to_be_bytes().to_vec(), 0u32.to_be_bytes().to_vec(), vec![0]];
+		db.put(0u32.to_be_bytes(), legacy.concat()).unwrap();
+		TransformerService::write_checkpoint(&db, 1).unwrap();
+
+		TransformerService::migrate_terms(&db).unwrap();
+		TransformerService::migrate_terms(&db).unwrap();
+		let term_batch = TermBatch { start: 0, size: 1 };
+		let terms = TransformerService::read_terms(&db, term_batch).unwrap();
+		assert_eq!(terms.len(), 1);
+		assert_eq!(terms[0].to, "90f8bf6a479f320ead074411a4b0e7944ea8c9c2");
+		assert_eq!(TransformerService::read_checkpoint(&db).unwrap(), 1);
+	}
+
+	#[test]
+	fn should_batch_terms() {
+		let terms = vec![TermObject::default(); 5];
+		let batches = TransformerService::batch_terms(&terms, 2);
+		let batches: Vec<_> =
+			batches.iter().map(|batch| (batch.sequence, batch.terms.len())).collect();
+		assert_eq!(batches, vec![(1, 2), (2, 2), (3, 1)]);
+	}
+
+	#[test]
+	fn should_negotiate_with_services() {
+		let info = ServiceInfo {
+			service: "linear-combiner".to_string(),
+			version: "0.1.0".to_string(),
+			protocol_version: PROTOCOL_VERSION,
+			features: vec!["sequence_dedup".to_string()],
+			limits: HashMap::from([("max_stream_terms".to_string(), 500)]),
+		};
+		let response = Ok(Response::new(info.clone()));
+		let info = negotiate("linear combiner", response, &["sequence_dedup"]).unwrap();
+		assert_eq!(capped(info.limit("max_stream_terms"), 1000), 500);
+		assert_eq!(capped(info.limit("max_query_count"), 1000), 1000);
+
+		let response = Ok(Response::new(info.clone()));
+		assert!(negotiate("linear combiner", response, &["source_checkpoints"]).is_err());
+		let response = Ok(Response::new(ServiceInfo { protocol_version: 0, ..info }));
+		assert!(
+			negotiate("linear combiner", response, &[]).is_err(),
+			"should refuse other protocol versions"
+		);
+		let response = Err(Status::unimplemented("GetServiceInfo"));
+		assert!(negotiate("indexer", response, &[]).is_err());
+	}
+
+	#[tokio::test]
+	async fn should_retry_only_retryable_statuses() {
+		let mut calls = 0;
+		let result = with_retries(|| {
+			calls += 1;
+			let result = match calls {
+				1 | 2 => Err(ErrorDetail::retryable(1).into_status(Code::Internal, "DB busy")),
+				calls => Ok(calls),
+			};
+			async move { result }
+		})
+		.await;
+		assert_eq!(result.unwrap(), 3);
+
+		let mut calls = 0;
+		let result: Result<(), _> = with_retries(|| {
+			calls += 1;
+			let status = ErrorDetail::permanent().with_field("size");
+			async move { Err(status.into_status(Code::InvalidArgument, "Batch size too big")) }
+		})
+		.await;
+		assert_eq!(result.unwrap_err().code(), Code::InvalidArgument);
+		assert_eq!(calls, 1, "should not retry requests at fault");
+	}
 }
@@ -321,7 +321,7 @@ mod test {
 	use crate::{
 		did::Did,
 		schemas::{AuditApproveSchema, AuditDisapproveSchema, StatusReason},
-		term::Validation,
+		term::{IntoTerm, Validation},
 	};
 	use secp256k1::{generate_keypair, rand::thread_rng, Message, Secp256k1};
 	use sha3::{Digest, Keccak256};
@@ -429,4 +429,16 @@ mod test {
 
 		assert_eq!(rec_pk, pk);
 	}
+
+	#[test]
+	fn should_convert_non_evm_subjects_into_terms() {
+		let sol_did = "did:pkh:solana:4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZ:CKg5d12Jhpej1JqtmxLJgaFqqeYjxgPqToJ4LBdvG9Ev";
+		let cosmos_did = "did:pkh:cosmos:cosmoshub-3:cosmos1t2uflqwqe0fsj0shcfkrvpukewcw40yjj6hdc0";
+
+		let approve = AuditApproveSchema::new(sol_did.to_owned());
+		let disapprove = AuditDisapproveSchema::new(cosmos_did.to_owned(), StatusReason::Scam);
+
+		assert!(approve.into_term().is_ok());
+		assert!(disapprove.into_term().is_ok());
+	}
 }
@@ -3,6 +3,10 @@ use secp256k1::PublicKey;
 
 use crate::{did::Did, error::AttTrError};
 
+/// Size of a term stored before account keys were length-prefixed: both 20 byte keys, the
+/// weight, the domain and the form.
+const LEGACY_TERM_SIZE: usize = 49;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 enum TermForm {
 	Trust,
@@ -62,11 +66,17 @@ impl Term {
 
 		let from_bytes = hex::decode(self.from).map_err(|_| AttTrError::SerialisationError)?;
 		let to_bytes = hex::decode(self.to).map_err(|_| AttTrError::SerialisationError)?;
+		let from_len =
+			u8::try_from(from_bytes.len()).map_err(|_| AttTrError::SerialisationError)?;
+		let to_len = u8::try_from(to_bytes.len()).map_err(|_| AttTrError::SerialisationError)?;
 		let weight_bytes = self.weight.to_be_bytes();
 		let domain_bytes = self.domain.to_be_bytes();
 		let form_byte: u8 = self.form.into();
 
+		// Account keys are length-prefixed, since non-EVM addresses aren't 20 bytes.
+		bytes.push(from_len);
 		bytes.extend_from_slice(&from_bytes);
+		bytes.push(to_len);
 		bytes.extend_from_slice(&to_bytes);
 		bytes.extend_from_slice(&weight_bytes);
 		bytes.extend_from_slice(&domain_bytes);
@@ -76,8 +86,11 @@ impl Term {
 	}
 
 	pub fn from_bytes(mut bytes: Vec<u8>) -> Result<Self, AttTrError> {
-		let from_bytes: Vec<u8> = bytes.drain(..20).collect();
-		let to_bytes: Vec<u8> = bytes.drain(..20).collect();
+		let from_bytes = Self::drain_prefixed(&mut bytes)?;
+		let to_bytes = Self::drain_prefixed(&mut bytes)?;
+		if bytes.len() != 9 {
+			return Err(AttTrError::SerialisationError);
+		}
 		let weight_bytes: [u8; 4] = bytes
 			.drain(..4)
 			.collect::<Vec<u8>>()
@@ -98,6 +111,28 @@ impl Term {
 
 		Ok(Self { from, to, weight, domain, form })
 	}
+
+	/// Reads a term stored before account keys were length-prefixed, when they were all 20
+	/// byte EVM addresses.
+	pub fn from_legacy_bytes(mut bytes: Vec<u8>) -> Result<Self, AttTrError> {
+		if bytes.len() != LEGACY_TERM_SIZE {
+			return Err(AttTrError::SerialisationError);
+		}
+		let mut prefixed = Vec::with_capacity(bytes.len() + 2);
+		prefixed.push(20);
+		prefixed.extend(bytes.drain(..20));
+		prefixed.push(20);
+		prefixed.extend(bytes);
+		Self::from_bytes(prefixed)
+	}
+
+	fn drain_prefixed(bytes: &mut Vec<u8>) -> Result<Vec<u8>, AttTrError> {
+		let len = usize::from(*bytes.first().ok_or(AttTrError::SerialisationError)?);
+		if bytes.len() < len + 1 {
+			return Err(AttTrError::SerialisationError);
+		}
+		Ok(bytes.drain(..len + 1).skip(1).collect())
+	}
 }
 
 impl Into<TermObject> for Term {
@@ -106,9 +141,11 @@ impl Into<TermObject> for Term {
 		TermObject {
 			from: self.from,
 			to: self.to,
-			weight: self.weight,
+			weight: f64::from(self.weight),
 			domain: self.domain,
 			form: form.into(),
+			sequence: 0,
+			source: String::new(),
 		}
 	}
 }
@@ -142,4 +179,40 @@ mod test {
 
 		assert_eq!(term, rec_term);
 	}
+
+	#[test]
+	fn should_read_legacy_terms() {
+		let from = hex::decode("90f8bf6a479f320ead074411a4b0e7944ea8c9c1").unwrap();
+		let to = hex::decode("90f8bf6a479f320ead074411a4b0e7944ea8c9c2").unwrap();
+		let bytes = [from, to, 50u32.to_be_bytes().to_vec(), 3u32.to_be_bytes().to_vec(), vec![1]];
+		let term = Term::from_legacy_bytes(bytes.concat()).unwrap();
+		assert_eq!(
+			term,
+			Term {
+				from: "90f8bf6a479f320ead074411a4b0e7944ea8c9c1".to_owned(),
+				to: "90f8bf6a479f320ead074411a4b0e7944ea8c9c2".to_owned(),
+				weight: 50,
+				domain: 3,
+				form: TermForm::Distrust,
+			}
+		);
+		assert!(Term::from_legacy_bytes(vec![0; 48]).is_err());
+	}
+
+	#[test]
+	fn should_convert_non_evm_term_to_bytes_and_back() {
+		let to = "CKg5d12Jhpej1JqtmxLJgaFqqeYjxgPqToJ4LBdvG9Ev";
+		let term = Term {
+			from: "90f8bf6a479f320ead074411a4b0e7944ea8c9c1".to_owned(),
+			to: hex::encode(to.as_bytes()),
+			weight: 50,
+			domain: 1,
+			form: TermForm::Distrust,
+		};
+
+		let bytes = term.clone().into_bytes().unwrap();
+		let rec_term = Term::from_bytes(bytes).unwrap();
+
+		assert_eq!(term, rec_term);
+	}
 }
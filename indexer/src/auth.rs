@@ -0,0 +1,135 @@
+use crate::{config::ClientConfig, limit::RateLimiter};
+use proto_buf::common::ErrorDetail;
+use std::{
+	collections::HashMap,
+	fs, io,
+	path::Path,
+	sync::{Arc, Mutex, PoisonError},
+};
+use tonic::{service::Interceptor, Code, Request, Status};
+
+/// Name of the client making a request, empty for anonymous callers. Added to requests by
+/// `Clients`.
+#[derive(Debug, Clone)]
+pub struct ClientName(pub String);
+
+/// Identifies callers from a bearer token or their TLS client certificate and holds each of
+/// them to its own rate limit.
+///
+/// Anonymous callers are only served when no credentials are configured, sharing one limit.
+#[derive(Debug, Clone)]
+pub struct Clients {
+	tokens: Arc<HashMap<String, String>>,
+	certs: Arc<HashMap<Vec<u8>, String>>,
+	rate: u32,
+	/// Rate limiter of every client seen so far, by name. Anonymous callers go under "".
+	limiters: Arc<Mutex<HashMap<String, Arc<RateLimiter>>>>,
+}
+
+impl Clients {
+	pub fn new(config: &ClientConfig) -> io::Result<Self> {
+		let tokens = config.client_tokens.iter().map(|(name, token)| (token.clone(), name.clone()));
+		let mut certs = HashMap::new();
+		for (name, path) in &config.client_certs {
+			certs.insert(read_leaf_cert(Path::new(path))?, name.clone());
+		}
+		Ok(Self {
+			tokens: Arc::new(tokens.collect()),
+			certs: Arc::new(certs),
+			rate: config.client_rate,
+			limiters: Arc::default(),
+		})
+	}
+
+	fn identify<T>(&self, request: &Request<T>) -> Result<Option<String>, Status> {
+		let header = request.metadata().get("authorization").and_then(|v| v.to_str().ok());
+		if let Some(header) = header {
+			let token = header.strip_prefix("Bearer ").unwrap_or(header);
+			return match self.tokens.get(token) {
+				Some(name) => Ok(Some(name.clone())),
+				None => Err(Status::unauthenticated("Invalid token!")),
+			};
+		}
+		let leaf = request.peer_certs().and_then(|certs| certs.first().cloned());
+		Ok(leaf.and_then(|cert| self.certs.get(cert.get_ref()).cloned()))
+	}
+
+	fn limiter(&self, name: String) -> Arc<RateLimiter> {
+		let mut limiters = self.limiters.lock().unwrap_or_else(PoisonError::into_inner);
+		limiters.entry(name).or_insert_with(|| Arc::new(RateLimiter::new(self.rate))).clone()
+	}
+}
+
+impl Interceptor for Clients {
+	fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+		let name = match self.identify(&request)? {
+			Some(name) => name,
+			None if self.tokens.is_empty() && self.certs.is_empty() => String::new(),
+			None => return Err(Status::unauthenticated("Missing credentials!")),
+		};
+		if !self.limiter(name.clone()).try_acquire(1) {
+			// A token is back after a second's worth divided by the rate.
+			let detail = ErrorDetail::retryable(1000 / u64::from(self.rate.max(1)));
+			return Err(detail.into_status(Code::ResourceExhausted, "Rate limit exceeded!"));
+		}
+		request.extensions_mut().insert(ClientName(name));
+		Ok(request)
+	}
+}
+
+/// Reads the DER encoding of the first certificate in a PEM file.
+fn read_leaf_cert(path: &Path) -> io::Result<Vec<u8>> {
+	let pem = fs::read(path)?;
+	let certs = rustls_pemfile::certs(&mut pem.as_slice())?;
+	certs.into_iter().next().ok_or_else(|| {
+		io::Error::new(
+			io::ErrorKind::InvalidData,
+			format!("No certificate in {}", path.display()),
+		)
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::{ClientName, Clients};
+	use crate::config::ClientConfig;
+	use tonic::{service::Interceptor, Code, Request};
+
+	fn bearer(token: &str) -> Request<()> {
+		let mut request = Request::new(());
+		let value = format!("Bearer {}", token).parse().unwrap();
+		request.metadata_mut().insert("authorization", value);
+		request
+	}
+
+	#[test]
+	fn should_limit_each_client() {
+		let config = ClientConfig {
+			client_tokens: vec![
+				("scores".to_string(), "s3cret".to_string()),
+				("backfill".to_string(), "b4ckfill".to_string()),
+			],
+			client_rate: 2,
+			..ClientConfig::default()
+		};
+		let mut clients = Clients::new(&config).unwrap();
+		let request = clients.call(bearer("s3cret")).unwrap();
+		let name = request.extensions().get::<ClientName>().map(|name| name.0.as_str());
+		assert_eq!(name, Some("scores"), "should tell requests apart by client");
+		clients.call(bearer("s3cret")).unwrap();
+		let status = clients.call(bearer("s3cret")).unwrap_err();
+		assert_eq!(status.code(), Code::ResourceExhausted);
+		clients.call(bearer("b4ckfill")).expect("should limit clients separately");
+
+		let status = clients.call(bearer("forged")).unwrap_err();
+		assert_eq!(status.code(), Code::Unauthenticated);
+		let status = clients.call(Request::new(())).unwrap_err();
+		assert_eq!(status.code(), Code::Unauthenticated);
+	}
+
+	#[test]
+	fn should_serve_anonymous_callers_without_credentials() {
+		let mut clients = Clients::new(&ClientConfig::default()).unwrap();
+		clients.call(Request::new(())).unwrap();
+	}
+}
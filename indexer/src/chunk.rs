@@ -0,0 +1,144 @@
+use crate::{
+	error::IndexerError,
+	limit::RateLimiter,
+	store::{EventFilter, EventStore},
+};
+use proto_buf::{common::PageResponse, indexer::EventChunk};
+use std::sync::Arc;
+
+/// Reads the events of a query from the store a chunk at a time, so responses hold at most
+/// one chunk in memory however many events they ask for, and paces them to a rate.
+pub struct ChunkReader {
+	store: Arc<dyn EventStore>,
+	filter: EventFilter,
+	next_offset: u32,
+	/// Events still to read.
+	remaining: u32,
+	chunk_size: u32,
+	limiter: RateLimiter,
+	/// Whether the store had no more matching events.
+	complete: bool,
+}
+
+impl ChunkReader {
+	/// Reads up to `count` events matching `filter` from ID `offset`, `chunk_size` at a time and
+	/// `rate` per second, unlimited if zero.
+	pub fn new(
+		store: Arc<dyn EventStore>, filter: EventFilter, offset: u32, count: u32, chunk_size: u32,
+		rate: u32,
+	) -> Self {
+		Self {
+			store,
+			filter,
+			next_offset: offset,
+			remaining: count,
+			chunk_size: chunk_size.max(1),
+			limiter: RateLimiter::new(rate),
+			complete: false,
+		}
+	}
+
+	/// Reads the next chunk once the rate allows, or returns `None` after the last one. Reads
+	/// whose events all belong to other domains are skipped over rather than sent empty.
+	pub async fn next(&mut self) -> Result<Option<EventChunk>, IndexerError> {
+		let events = loop {
+			if self.remaining == 0 || self.complete {
+				return Ok(None);
+			}
+			let size = self.chunk_size.min(self.remaining);
+			let mut events = self.store.read(self.next_offset, size, &self.filter).await?;
+			self.complete = events.len() < size as usize;
+			if let Some(last) = events.last() {
+				self.next_offset = last.id + 1;
+			}
+			events.retain(|event| self.filter.matches_domain(event));
+			self.remaining -= events.len() as u32;
+			if !events.is_empty() || self.complete {
+				break events;
+			}
+		};
+		self.limiter.acquire(events.len() as u32).await;
+		let page = PageResponse::at_offset(self.next_offset, !self.complete);
+		Ok(Some(EventChunk {
+			events,
+			next_offset: self.next_offset,
+			complete: self.complete,
+			page: Some(page),
+		}))
+	}
+
+	/// Offset of the query continuing after the chunks read so far.
+	pub fn next_offset(&self) -> u32 {
+		self.next_offset
+	}
+
+	/// Whether the store had no more matching events as of the chunks read so far.
+	pub fn is_complete(&self) -> bool {
+		self.complete
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::ChunkReader;
+	use crate::{
+		source::SourceEvent,
+		store::{memory::MemoryStore, EventFilter, EventStore},
+	};
+	use proto_buf::common::PageResponse;
+	use std::sync::Arc;
+
+	#[tokio::test]
+	async fn should_read_in_chunks_with_cursors() {
+		let store = Arc::new(MemoryStore::new());
+		let events = (0..10)
+			.map(|i| SourceEvent { schema_id: i % 2 + 1, ..SourceEvent::default() })
+			.collect();
+		store.append(events).await.unwrap();
+
+		let filter = EventFilter { schema_ids: [2].into(), ..EventFilter::default() };
+		let mut chunks = ChunkReader::new(store.clone(), filter.clone(), 0, 3, 2, 0);
+		let chunk = chunks.next().await.unwrap().unwrap();
+		assert_eq!(chunk.events.len(), 2);
+		assert_eq!(chunk.page, Some(PageResponse::at_offset(4, true)));
+		let chunk = chunks.next().await.unwrap().unwrap();
+		assert_eq!(chunk.events.len(), 1, "should stop at the count");
+		assert_eq!(chunk.page.unwrap().next_offset(), Some(6));
+		assert!(chunks.next().await.unwrap().is_none());
+
+		let mut chunks = ChunkReader::new(store, filter, 6, 10, 4, 0);
+		let chunk = chunks.next().await.unwrap().unwrap();
+		assert_eq!(chunk.events.len(), 2);
+		assert_eq!(
+			chunk.page,
+			Some(PageResponse::at_offset(10, false)),
+			"should tell when no events are left"
+		);
+		assert_eq!(
+			(chunk.next_offset, chunk.complete),
+			(10, true),
+			"should fill the paging fields of older clients"
+		);
+		assert!(chunks.next().await.unwrap().is_none());
+	}
+
+	#[tokio::test]
+	async fn should_skip_events_of_other_domains() {
+		let store = Arc::new(MemoryStore::new());
+		let events = (0..10)
+			.map(|i| SourceEvent { domain: u32::from(i >= 7), ..SourceEvent::default() })
+			.collect();
+		store.append(events).await.unwrap();
+
+		let filter = EventFilter { domains: [1].into(), ..EventFilter::default() };
+		let mut chunks = ChunkReader::new(store, filter, 0, 10, 3, 0);
+		let chunk = chunks.next().await.unwrap().unwrap();
+		let ids: Vec<_> = chunk.events.iter().map(|event| event.id).collect();
+		assert_eq!(ids, [7, 8], "should not send the chunks left empty");
+		assert_eq!(chunk.page, Some(PageResponse::at_offset(9, true)));
+		let chunk = chunks.next().await.unwrap().unwrap();
+		assert_eq!(chunk.events[0].id, 9);
+		assert_eq!(chunk.page, Some(PageResponse::at_offset(10, false)));
+		assert!(chunks.next().await.unwrap().is_none());
+	}
+}
@@ -0,0 +1,683 @@
+use crate::{schedule::Schedule, schemas::EAS_SCHEMA_ID, store::segment::Rotation};
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, ValueEnum};
+use std::{
+	env,
+	ffi::OsString,
+	fs, io,
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+use toml::Value;
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+/// Indexer service. Every option can also be set through its environment variable, or in a
+/// TOML config file keyed by the option's long name. Flags take precedence over the
+/// environment, which takes precedence over the file.
+#[derive(Debug, Clone, Parser)]
+#[command(version, about, args_override_self = true)]
+pub struct Config {
+	/// TOML file of options, e.g. `listen_addr = "[::]:50050"`. Tables group options
+	/// without affecting their names.
+	#[arg(long, env = "INDEXER_CONFIG_FILE")]
+	pub config_file: Option<PathBuf>,
+
+	/// Address the gRPC server listens on.
+	#[arg(long, env = "INDEXER_LISTEN_ADDR", default_value = "[::1]:50050")]
+	pub listen_addr: SocketAddr,
+
+	/// Address of the HTTP endpoint issuers push credentials to, which is disabled when unset.
+	#[arg(long, env = "INDEXER_INGEST_ADDR", requires = "ingest_token")]
+	pub ingest_addr: Option<SocketAddr>,
+
+	/// Bearer token issuers must present to the ingest endpoint.
+	#[arg(long, env = "INDEXER_INGEST_TOKEN")]
+	pub ingest_token: Option<String>,
+
+	/// Address GraphQL queries are answered on at `/graphql`, which is disabled when unset.
+	/// Queries are not authenticated, so it should only be reachable by trusted callers.
+	#[arg(long, env = "INDEXER_GRAPHQL_ADDR")]
+	pub graphql_addr: Option<SocketAddr>,
+
+	/// Address `/metrics` is served on in the Prometheus text format, which is disabled when
+	/// unset.
+	#[arg(long, env = "INDEXER_METRICS_ADDR")]
+	pub metrics_addr: Option<SocketAddr>,
+
+	/// Seconds a source may go without a successful poll before it is reported unhealthy.
+	#[arg(long, env = "INDEXER_SOURCE_STALE_AFTER", default_value_t = 300)]
+	pub source_stale_after: u64,
+
+	/// Messages buffered per response stream before sending waits for the client to catch up.
+	#[arg(
+		long,
+		env = "INDEXER_STREAM_BUFFER_SIZE",
+		default_value_t = 4,
+		value_parser = clap::value_parser!(u16).range(1..)
+	)]
+	pub stream_buffer_size: u16,
+
+	/// Stamps every stored event with the time it was received, for consumers to window by
+	/// rather than the timestamp given by its source.
+	#[arg(long, env = "INDEXER_STAMP_RECEIVED_AT")]
+	pub stamp_received_at: bool,
+
+	/// Comma separated `source:domain` pairs of sources whose events belong to a trust domain
+	/// other than the default domain 0, e.g. `eas:1,kafka:2`.
+	#[arg(long, env = "INDEXER_SOURCE_DOMAINS", value_delimiter = ',', value_parser = parse_domain)]
+	pub source_domains: Vec<(String, u32)>,
+
+	/// Directory of `<schema ID>.json` JSON Schemas adding to or replacing the built-in ones.
+	#[arg(long, env = "INDEXER_SCHEMA_DIR")]
+	pub schema_dir: Option<PathBuf>,
+
+	#[command(flatten)]
+	pub tuning: TuningConfig,
+
+	#[command(flatten)]
+	pub export: ExportConfig,
+
+	#[command(flatten)]
+	pub tls: TlsConfig,
+
+	#[command(flatten)]
+	pub clients: ClientConfig,
+
+	#[command(flatten)]
+	pub store: StoreConfig,
+
+	#[command(flatten)]
+	pub ceramic: CeramicConfig,
+
+	#[command(flatten)]
+	pub eas: EasConfig,
+
+	#[command(flatten)]
+	pub kafka: KafkaConfig,
+
+	#[command(flatten)]
+	pub nats: NatsConfig,
+
+	#[command(flatten)]
+	pub ipfs: IpfsConfig,
+
+	#[command(flatten)]
+	pub s3: S3Config,
+
+	/// Directory of credential dumps to ingest, or glob pattern of their names in one, e.g.
+	/// `/data/dumps/*.csv`. Dumps hold CSV of credential envelopes, or JSON lines of envelopes
+	/// or of events exported with `--export`, possibly compressed with gzip or zstd. They are
+	/// loaded in order of name as they appear.
+	#[arg(long, env = "INDEXER_DUMP_PATH")]
+	pub dump_path: Option<PathBuf>,
+
+	/// Comma separated URLs of credential dumps served over HTTP(S), in the formats of
+	/// `--dump-path` and JSON lines unless their path tells otherwise. Dumps are loaded again
+	/// whole whenever they change.
+	#[arg(long, env = "INDEXER_DUMP_URLS", value_delimiter = ',')]
+	pub dump_urls: Vec<String>,
+}
+
+impl Config {
+	/// Parses the process arguments and environment over the config file, exiting with a
+	/// usage error if any option is invalid.
+	pub fn load() -> Self {
+		Self::load_from(env::args_os()).unwrap_or_else(|e| e.exit())
+	}
+
+	pub fn load_from(
+		args: impl IntoIterator<Item = impl Into<OsString>>,
+	) -> Result<Self, clap::Error> {
+		let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+		// Other options may only be valid along with those from the file, so they are not
+		// checked until it is read.
+		let matches = Self::command().ignore_errors(true).try_get_matches_from(&args)?;
+		let Some(path) = matches.get_one::<PathBuf>("config_file") else {
+			return Self::try_parse_from(args);
+		};
+		// Options from the file go first, for those given again as flags to override them.
+		let mut merged = args[..1].to_vec();
+		merged.extend(file_args(path)?);
+		merged.extend_from_slice(&args[1..]);
+		Self::try_parse_from(merged)
+	}
+}
+
+/// Turns the options of a config file into flags, leaving out those set in the environment.
+fn file_args(path: &Path) -> Result<Vec<OsString>, clap::Error> {
+	let invalid = |reason: String| {
+		Config::command().error(
+			ErrorKind::InvalidValue,
+			format!("{}: {}", path.display(), reason),
+		)
+	};
+	let contents = fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+	let table: toml::Table =
+		contents.parse().map_err(|e: toml::de::Error| invalid(e.to_string()))?;
+	let mut options = Vec::new();
+	flatten(table, &mut options);
+
+	let command = Config::command();
+	let mut args = Vec::new();
+	for (key, value) in options {
+		let long = key.replace('_', "-");
+		let arg = command
+			.get_arguments()
+			.find(|arg| arg.get_long() == Some(long.as_str()))
+			.ok_or_else(|| invalid(format!("Unknown option `{}`", key)))?;
+		if arg.get_env().map_or(false, |name| env::var_os(name).is_some()) {
+			continue;
+		}
+		// Flags are given without a value, when true.
+		if !arg.get_action().takes_values() {
+			match value {
+				Value::Boolean(true) => args.push(format!("--{}", long).into()),
+				Value::Boolean(false) => {},
+				_ => return Err(invalid(format!("`{}` should be a boolean", key))),
+			}
+			continue;
+		}
+		let value = match value {
+			Value::String(value) => value,
+			Value::Array(values) => {
+				let values: Vec<_> =
+					values.into_iter().map(scalar).collect::<Option<_>>().ok_or_else(|| {
+						invalid(format!("`{}` should only hold strings and numbers", key))
+					})?;
+				values.join(",")
+			},
+			value => scalar(value)
+				.ok_or_else(|| invalid(format!("`{}` should be a string or number", key)))?,
+		};
+		args.push(format!("--{}", long).into());
+		args.push(value.into());
+	}
+	Ok(args)
+}
+
+/// Collects the options of `table` and its subtables.
+fn flatten(table: toml::Table, options: &mut Vec<(String, Value)>) {
+	for (key, value) in table {
+		match value {
+			Value::Table(table) => flatten(table, options),
+			value => options.push((key, value)),
+		}
+	}
+}
+
+fn scalar(value: Value) -> Option<String> {
+	match value {
+		Value::String(value) => Some(value),
+		Value::Integer(value) => Some(value.to_string()),
+		Value::Float(value) => Some(value.to_string()),
+		Value::Boolean(value) => Some(value.to_string()),
+		_ => None,
+	}
+}
+
+/// Knobs of the background tasks and streams.
+#[derive(Debug, Clone, Args)]
+pub struct TuningConfig {
+	/// Milliseconds between polls of the sources.
+	#[arg(long, env = "INDEXER_POLL_INTERVAL_MS", default_value_t = 1000)]
+	pub poll_interval_ms: u64,
+
+	/// Seconds a watch waits for new events before sending a heartbeat.
+	#[arg(long, env = "INDEXER_HEARTBEAT_INTERVAL_SECS", default_value_t = 15)]
+	pub heartbeat_interval_secs: u64,
+
+	/// Events read at a time by watches not giving a count.
+	#[arg(long, env = "INDEXER_WATCH_PAGE_SIZE", default_value_t = 1000)]
+	pub watch_page_size: u32,
+
+	/// Events produced per poll by the mock source, used when no other source is configured.
+	#[arg(long, env = "INDEXER_MOCK_BATCH_SIZE", default_value_t = 10)]
+	pub mock_batch_size: usize,
+
+	/// Semicolon separated `source:schedule` pairs of sources polled on their own schedule
+	/// rather than every poll interval, e.g. `s3:1h;eas:0 */6 * * *`. Schedules are intervals
+	/// such as `30s`, `5m` or `1h`, or cron expressions in UTC.
+	#[arg(
+		long,
+		env = "INDEXER_SOURCE_SCHEDULES",
+		value_delimiter = ';',
+		value_parser = parse_schedule
+	)]
+	pub source_schedules: Vec<(String, Schedule)>,
+
+	/// Most milliseconds scheduled polls are delayed by at random, spreading sources on the
+	/// same schedule.
+	#[arg(long, env = "INDEXER_POLL_JITTER_MS", default_value_t = 0)]
+	pub poll_jitter_ms: u64,
+}
+
+impl TuningConfig {
+	pub fn poll_interval(&self) -> Duration {
+		Duration::from_millis(self.poll_interval_ms.max(1))
+	}
+
+	pub fn heartbeat_interval(&self) -> Duration {
+		Duration::from_secs(self.heartbeat_interval_secs.max(1))
+	}
+
+	pub fn poll_jitter(&self) -> Duration {
+		Duration::from_millis(self.poll_jitter_ms)
+	}
+}
+
+/// Dumps the stored events instead of serving them, when a destination is given.
+#[derive(Debug, Clone, Default, Args)]
+pub struct ExportConfig {
+	/// File the events are written to as JSON lines, `-` for stdout, e.g. to pipe them into
+	/// `aws s3 cp - s3://bucket/events.jsonl`. The indexer exits once they are written.
+	#[arg(long, env = "INDEXER_EXPORT")]
+	pub export: Option<PathBuf>,
+
+	/// Comma separated schema IDs of the exported events, every schema when empty.
+	#[arg(long, env = "INDEXER_EXPORT_SCHEMA_IDS", value_delimiter = ',')]
+	pub export_schema_ids: Vec<u32>,
+
+	/// Earliest timestamp of the exported events, inclusive.
+	#[arg(long, env = "INDEXER_EXPORT_FROM_TIMESTAMP", default_value_t = 0)]
+	pub export_from_timestamp: u64,
+
+	/// Timestamp the exported events must precede, if any.
+	#[arg(long, env = "INDEXER_EXPORT_TO_TIMESTAMP")]
+	pub export_to_timestamp: Option<u64>,
+}
+
+/// Serves plaintext unless a certificate and key are given.
+#[derive(Debug, Clone, Default, Args)]
+pub struct TlsConfig {
+	/// PEM certificate chain presented by the server.
+	#[arg(long, env = "INDEXER_TLS_CERT", requires = "tls_key")]
+	pub tls_cert: Option<PathBuf>,
+
+	/// PEM private key of the server certificate.
+	#[arg(long, env = "INDEXER_TLS_KEY", requires = "tls_cert")]
+	pub tls_key: Option<PathBuf>,
+
+	/// PEM CA bundle client certificates must chain to. Enables mutual TLS.
+	#[arg(long, env = "INDEXER_TLS_CLIENT_CA", requires = "tls_cert")]
+	pub tls_client_ca: Option<PathBuf>,
+}
+
+impl TlsConfig {
+	/// Loads the configured certificates, `None` when TLS is disabled.
+	pub fn load(&self) -> io::Result<Option<ServerTlsConfig>> {
+		let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) else {
+			return Ok(None);
+		};
+		let identity = Identity::from_pem(fs::read(cert)?, fs::read(key)?);
+		let mut tls = ServerTlsConfig::new().identity(identity);
+		if let Some(client_ca) = &self.tls_client_ca {
+			tls = tls.client_ca_root(Certificate::from_pem(fs::read(client_ca)?));
+		}
+		Ok(Some(tls))
+	}
+}
+
+/// Clients of the gRPC server and their quotas. Without any credentials configured, callers
+/// are served anonymously under one shared rate limit.
+#[derive(Debug, Clone, Default, Args)]
+pub struct ClientConfig {
+	/// Comma separated `name:token` pairs of clients identified by a bearer token.
+	#[arg(long, env = "INDEXER_CLIENT_TOKENS", value_delimiter = ',', value_parser = parse_named)]
+	pub client_tokens: Vec<(String, String)>,
+
+	/// Comma separated `name:path` pairs of clients identified by the PEM TLS client
+	/// certificate at `path`.
+	#[arg(long, env = "INDEXER_CLIENT_CERTS", value_delimiter = ',', value_parser = parse_named)]
+	pub client_certs: Vec<(String, String)>,
+
+	/// Queries each client may make per second, 0 for no limit.
+	#[arg(long, env = "INDEXER_CLIENT_RATE", default_value_t = 10)]
+	pub client_rate: u32,
+
+	/// Comma separated names of the clients allowed to call admin RPCs, like `Reindex`.
+	#[arg(long, env = "INDEXER_ADMIN_CLIENTS", value_delimiter = ',')]
+	pub admin_clients: Vec<String>,
+
+	/// Most events a single query may ask for.
+	#[arg(long, env = "INDEXER_MAX_QUERY_COUNT", default_value_t = 10_000)]
+	pub max_query_count: u32,
+
+	/// Most events read from the store at a time, and sent in one chunk.
+	#[arg(long, env = "INDEXER_MAX_CHUNK_SIZE", default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+	pub max_chunk_size: u32,
+
+	/// Events per second sent on every response stream, unlimited if zero.
+	#[arg(long, env = "INDEXER_STREAM_RATE", default_value_t = 0)]
+	pub stream_rate: u32,
+}
+
+fn parse_named(value: &str) -> Result<(String, String), String> {
+	match value.split_once(':') {
+		Some((name, value)) => Ok((name.to_string(), value.to_string())),
+		None => Err(format!("Expected `name:value`, got `{}`", value)),
+	}
+}
+
+fn parse_schedule(value: &str) -> Result<(String, Schedule), String> {
+	let (name, schedule) = parse_named(value)?;
+	Ok((name, schedule.parse()?))
+}
+
+fn parse_domain(value: &str) -> Result<(String, u32), String> {
+	let (name, domain) = parse_named(value)?;
+	let domain = domain.parse().map_err(|_| format!("Invalid domain `{}`", domain))?;
+	Ok((name, domain))
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct StoreConfig {
+	/// Backend the indexed events are kept in.
+	#[arg(long, env = "INDEXER_STORE", value_enum, default_value_t = StoreBackend::RocksDb)]
+	pub store: StoreBackend,
+
+	/// Directory of the `rocksdb` store.
+	#[arg(long, env = "INDEXER_ROCKSDB_PATH", default_value = "indexer-storage")]
+	pub rocksdb_path: PathBuf,
+
+	/// Connection string of the `postgres` store, e.g. `postgres://user@localhost/indexer`.
+	#[arg(long, env = "INDEXER_POSTGRES_URL")]
+	pub postgres_url: Option<String>,
+
+	/// Database file of the `sqlite` store, created if missing.
+	#[arg(long, env = "INDEXER_SQLITE_PATH", default_value = "indexer-storage.sqlite")]
+	pub sqlite_path: PathBuf,
+
+	/// Directory of the `segments` store.
+	#[arg(long, env = "INDEXER_SEGMENTS_PATH", default_value = "indexer-segments")]
+	pub segments_path: PathBuf,
+
+	/// Size in MiB a segment is sealed at, for appends to go to a new one.
+	#[arg(long, env = "INDEXER_SEGMENT_MAX_MB", default_value_t = 64)]
+	pub segment_max_mb: u64,
+
+	/// Hours a segment is sealed after, never if zero.
+	#[arg(long, env = "INDEXER_SEGMENT_MAX_AGE_HOURS", default_value_t = 24)]
+	pub segment_max_age_hours: u64,
+
+	/// Shell command archiving every sealed segment, given its path as `$1`, e.g.
+	/// `aws s3 cp "$1" s3://bucket/indexer/`.
+	#[arg(long, env = "INDEXER_SEGMENT_ARCHIVE_COMMAND")]
+	pub segment_archive_command: Option<String>,
+}
+
+impl StoreConfig {
+	pub fn rotation(&self) -> Rotation {
+		Rotation {
+			max_bytes: self.segment_max_mb.saturating_mul(1024 * 1024),
+			max_age_secs: self.segment_max_age_hours.saturating_mul(3600),
+			archive_command: self.segment_archive_command.clone(),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum StoreBackend {
+	/// Event log on local disk, indexed by schema and timestamp.
+	#[default]
+	#[value(name = "rocksdb")]
+	RocksDb,
+	/// Keeps events in memory, losing them on exit.
+	Memory,
+	Postgres,
+	/// Embedded database in a single file, for single node deployments.
+	Sqlite,
+	/// JSON lines files rotated by size and age, which can be archived once sealed.
+	Segments,
+}
+
+/// ComposeDB model index attestations are read from, when a URL is given. The mock source
+/// runs instead if no source is configured.
+#[derive(Debug, Clone, Args)]
+pub struct CeramicConfig {
+	/// GraphQL endpoint of the ComposeDB node, e.g. `http://localhost:7007/graphql`.
+	#[arg(long, env = "INDEXER_CERAMIC_URL")]
+	pub ceramic_url: Option<String>,
+
+	/// Index query of the attestation model.
+	#[arg(long, env = "INDEXER_CERAMIC_INDEX", default_value = "attestationIndex")]
+	pub ceramic_index: String,
+
+	/// Document fields to select, in GraphQL syntax. They make up the schema value.
+	#[arg(long, env = "INDEXER_CERAMIC_FIELDS", default_value = "id")]
+	pub ceramic_fields: String,
+
+	/// Schema ID the documents are indexed under.
+	#[arg(long, env = "INDEXER_CERAMIC_SCHEMA_ID", default_value_t = 1)]
+	pub ceramic_schema_id: u32,
+
+	/// Document field holding the Unix time in seconds of the attestation. Documents are
+	/// stamped with their time of receipt without one.
+	#[arg(long, env = "INDEXER_CERAMIC_TIMESTAMP_FIELD")]
+	pub ceramic_timestamp_field: Option<String>,
+
+	/// Documents requested per GraphQL query.
+	#[arg(long, env = "INDEXER_CERAMIC_PAGE_SIZE", default_value_t = 100)]
+	pub ceramic_page_size: u32,
+}
+
+/// Ethereum Attestation Service schema followed on chain, when an RPC URL is given.
+#[derive(Debug, Clone, Args)]
+pub struct EasConfig {
+	/// JSON-RPC endpoint of the chain the EAS contract is deployed on.
+	#[arg(long, env = "INDEXER_EAS_RPC_URL", requires = "eas_schema_uid")]
+	pub eas_rpc_url: Option<String>,
+
+	/// Comma separated `name:rpc_url` pairs of further chains to follow the same contract and
+	/// schema on, as source `eas-<name>`.
+	#[arg(
+		long,
+		env = "INDEXER_EAS_CHAINS",
+		value_delimiter = ',',
+		value_parser = parse_named,
+		requires = "eas_schema_uid"
+	)]
+	pub eas_chains: Vec<(String, String)>,
+
+	/// Address of the EAS contract, the Ethereum mainnet deployment by default.
+	#[arg(
+		long,
+		env = "INDEXER_EAS_CONTRACT",
+		default_value = "0xA1207F3BBa224E2c9c3c6D5aF63D0eb1582Ce587"
+	)]
+	pub eas_contract: String,
+
+	/// UID of the followed schema, as 32 hex encoded bytes.
+	#[arg(long, env = "INDEXER_EAS_SCHEMA_UID", default_value = "")]
+	pub eas_schema_uid: String,
+
+	/// Schema ID the attestations and revocations are indexed under, whose JSON Schema must
+	/// accept them as read from the contract.
+	#[arg(long, env = "INDEXER_EAS_SCHEMA_ID", default_value_t = EAS_SCHEMA_ID)]
+	pub eas_schema_id: u32,
+
+	/// Block to start following the contract from.
+	#[arg(long, env = "INDEXER_EAS_START_BLOCK", default_value_t = 0)]
+	pub eas_start_block: u64,
+
+	/// Blocks requested per `eth_getLogs` call.
+	#[arg(long, env = "INDEXER_EAS_BLOCK_RANGE", default_value_t = 2000)]
+	pub eas_block_range: u64,
+
+	/// Block ranges fetched concurrently while catching up, merged back in block order.
+	#[arg(long, env = "INDEXER_EAS_PARALLEL_RANGES", default_value_t = 4)]
+	pub eas_parallel_ranges: usize,
+
+	/// Blocks that must follow a block before its attestations are indexed.
+	#[arg(long, env = "INDEXER_EAS_CONFIRMATIONS", default_value_t = 12)]
+	pub eas_confirmations: u64,
+
+	/// Blocks indexed blocks are checked for reorganizations over, retracting their
+	/// attestations should they be orphaned.
+	#[arg(long, env = "INDEXER_EAS_REORG_WINDOW", default_value_t = 128)]
+	pub eas_reorg_window: u64,
+}
+
+/// Kafka topics carrying credential envelopes, consumed when brokers are given.
+#[derive(Debug, Clone, Args)]
+pub struct KafkaConfig {
+	/// Comma separated `host:port` list of bootstrap brokers.
+	#[arg(long, env = "INDEXER_KAFKA_BROKERS", requires = "kafka_topics")]
+	pub kafka_brokers: Option<String>,
+
+	/// Comma separated topics to consume.
+	#[arg(long, env = "INDEXER_KAFKA_TOPICS", value_delimiter = ',')]
+	pub kafka_topics: Vec<String>,
+
+	/// Consumer group whose committed offsets the indexer resumes from.
+	#[arg(long, env = "INDEXER_KAFKA_GROUP_ID", default_value = "indexer")]
+	pub kafka_group_id: String,
+}
+
+/// JetStream stream carrying credential envelopes, consumed when a server URL is given.
+#[derive(Debug, Clone, Args)]
+pub struct NatsConfig {
+	/// URL of the NATS server, e.g. `nats://localhost:4222`.
+	#[arg(long, env = "INDEXER_NATS_URL")]
+	pub nats_url: Option<String>,
+
+	/// JetStream stream to consume.
+	#[arg(long, env = "INDEXER_NATS_STREAM", default_value = "attestations")]
+	pub nats_stream: String,
+
+	/// Subject of the stream to consume, every subject when unset.
+	#[arg(long, env = "INDEXER_NATS_SUBJECT")]
+	pub nats_subject: Option<String>,
+
+	/// Durable consumer whose acknowledgements the indexer resumes from.
+	#[arg(long, env = "INDEXER_NATS_DURABLE", default_value = "indexer")]
+	pub nats_durable: String,
+}
+
+/// Attestation archives backfilled from IPFS, when a gateway is given.
+#[derive(Debug, Clone, Args)]
+pub struct IpfsConfig {
+	/// HTTP gateway to fetch archives through, e.g. `https://ipfs.io`.
+	#[arg(long, env = "INDEXER_IPFS_GATEWAY", requires = "ipfs_cids")]
+	pub ipfs_gateway: Option<String>,
+
+	/// Comma separated base32 CIDv1s of the archives, loaded in order.
+	#[arg(long, env = "INDEXER_IPFS_CIDS", value_delimiter = ',')]
+	pub ipfs_cids: Vec<String>,
+
+	#[arg(long, env = "INDEXER_IPFS_FORMAT", value_enum, default_value_t = ArchiveFormat::Jsonl)]
+	pub ipfs_format: ArchiveFormat,
+}
+
+/// Credential dumps ingested from an S3 bucket, when one is given.
+#[derive(Debug, Clone, Args)]
+pub struct S3Config {
+	/// Bucket the dumps are delivered to, as JSON lines or CSV of credential envelopes,
+	/// possibly compressed with gzip or zstd.
+	#[arg(long, env = "INDEXER_S3_BUCKET")]
+	pub s3_bucket: Option<String>,
+
+	/// Prefix of the keys of the dumps, e.g. `dumps/`.
+	#[arg(long, env = "INDEXER_S3_PREFIX", default_value = "")]
+	pub s3_prefix: String,
+
+	#[arg(long, env = "INDEXER_S3_REGION", default_value = "us-east-1")]
+	pub s3_region: String,
+
+	/// Endpoint of an S3 compatible store, e.g. `http://localhost:9000`, addressed path style.
+	/// Defaults to AWS.
+	#[arg(long, env = "INDEXER_S3_ENDPOINT")]
+	pub s3_endpoint: Option<String>,
+
+	/// Access key requests are signed with, anonymous without.
+	#[arg(long, env = "INDEXER_S3_ACCESS_KEY_ID", requires = "s3_secret_access_key")]
+	pub s3_access_key_id: Option<String>,
+
+	#[arg(long, env = "INDEXER_S3_SECRET_ACCESS_KEY", hide_env_values = true)]
+	pub s3_secret_access_key: Option<String>,
+}
+
+/// How archives hold their credential envelopes, one JSON object per line either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ArchiveFormat {
+	/// A single raw block.
+	Jsonl,
+	/// A CAR file of raw blocks, fetched from a trustless gateway.
+	Car,
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Config, StoreBackend};
+	use clap::Parser;
+	use std::{env, fs, time::Duration};
+
+	#[test]
+	fn should_parse_store_backend() {
+		let config = Config::try_parse_from([
+			"indexer", "--store", "postgres", "--postgres-url", "postgres://localhost/indexer",
+		])
+		.unwrap();
+		assert_eq!(config.store.store, StoreBackend::Postgres);
+		assert_eq!(config.listen_addr.port(), 50050);
+
+		let config =
+			Config::try_parse_from(["indexer", "--client-tokens", "scores:s3cret"]).unwrap();
+		assert_eq!(config.store.store, StoreBackend::RocksDb);
+		assert_eq!(
+			config.clients.client_tokens,
+			vec![("scores".to_string(), "s3cret".to_string())]
+		);
+		assert!(config.tls.load().unwrap().is_none());
+
+		let schedules = "s3:1h;eas:0,30 * * * *";
+		let config = Config::try_parse_from(["indexer", "--source-schedules", schedules]).unwrap();
+		let names: Vec<_> =
+			config.tuning.source_schedules.iter().map(|(name, _)| name.as_str()).collect();
+		assert_eq!(
+			names,
+			["s3", "eas"],
+			"should keep commas within cron expressions"
+		);
+	}
+
+	#[test]
+	fn should_require_tls_key_with_cert() {
+		let result = Config::try_parse_from(["indexer", "--tls-client-ca", "ca.pem"]);
+		assert!(result.is_err());
+		let result = Config::try_parse_from(["indexer", "--tls-cert", "server.pem"]);
+		assert!(result.is_err());
+	}
+
+	#[test]
+	fn should_load_config_file_under_flags() {
+		let path = env::temp_dir().join(format!("indexer-config-{}.toml", std::process::id()));
+		fs::write(
+			&path,
+			"listen_addr = \"127.0.0.1:6000\"\nclient_rate = 5\nstamp_received_at = true\n\
+			[store]\nstore = \"sqlite\"\nsqlite_path = \"events.db\"\n\
+			[tuning]\npoll_interval_ms = 250\nwatch_page_size = 100\n",
+		)
+		.unwrap();
+		let config_file = path.to_str().unwrap();
+		let config =
+			Config::load_from(["indexer", "--config-file", config_file, "--client-rate", "20"])
+				.unwrap();
+		assert_eq!(config.listen_addr.port(), 6000);
+		assert!(config.stamp_received_at);
+		assert_eq!(config.store.store, StoreBackend::Sqlite);
+		assert_eq!(config.tuning.poll_interval(), Duration::from_millis(250));
+		assert_eq!(config.tuning.watch_page_size, 100);
+		assert_eq!(
+			config.clients.client_rate, 20,
+			"should prefer flags over the file"
+		);
+
+		fs::write(&path, "listen_adr = \"127.0.0.1:6000\"\n").unwrap();
+		let error = Config::load_from(["indexer", "--config-file", config_file]).unwrap_err();
+		assert!(
+			error.to_string().contains("Unknown option `listen_adr`"),
+			"should name unknown options"
+		);
+		fs::remove_file(path).unwrap();
+	}
+}
@@ -0,0 +1,84 @@
+use proto_buf::common::ErrorDetail;
+use rdkafka::error::KafkaError;
+use rocksdb::Error as RocksDbError;
+use rusqlite::Error as SqliteError;
+use thiserror::Error;
+use tokio_postgres::Error as PgError;
+use tonic::{Code, Status};
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Error)]
+pub enum IndexerError {
+	#[error("SourceError: {0}")]
+	SourceError(String),
+
+	#[error("PostgresError: {0}")]
+	PostgresError(PgError),
+
+	#[error("DbError: {0}")]
+	DbError(RocksDbError),
+
+	#[error("NotFoundError")]
+	NotFoundError,
+
+	#[error("ParseError")]
+	ParseError,
+
+	#[error("SqliteError: {0}")]
+	SqliteError(SqliteError),
+
+	#[error("KafkaError: {0}")]
+	KafkaError(KafkaError),
+
+	#[error("TaskError")]
+	TaskError,
+
+	#[error("ConfigError: {0}")]
+	ConfigError(&'static str),
+
+	#[error("SchemaError: {0}")]
+	SchemaError(String),
+
+	#[error("IoError: {0}")]
+	IoError(std::io::Error),
+}
+
+impl IndexerError {
+	/// Whether the operation failing with this error may succeed if tried again, errors of
+	/// sources and stores being mostly down to them being unavailable for a while.
+	pub fn is_transient(&self) -> bool {
+		matches!(
+			self,
+			Self::SourceError(_)
+				| Self::PostgresError(_)
+				| Self::DbError(_)
+				| Self::KafkaError(_)
+				| Self::TaskError
+				| Self::IoError(_)
+		)
+	}
+
+	pub fn into_status(self) -> Status {
+		let detail = match self.is_transient() {
+			true => ErrorDetail::retryable(0),
+			false => ErrorDetail::permanent(),
+		};
+		let message = format!("Internal error: {}", self);
+		detail.with_cause(self).into_status(Code::Internal, message)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::IndexerError;
+	use proto_buf::{common::ErrorDetail, is_retryable};
+
+	#[test]
+	fn should_tell_clients_whether_to_retry() {
+		let status = IndexerError::SourceError("timed out".to_string()).into_status();
+		assert!(is_retryable(&status));
+		let detail = ErrorDetail::of(&status).unwrap();
+		assert_eq!(detail.cause, "SourceError: timed out");
+		assert!(!is_retryable(&IndexerError::ParseError.into_status()));
+	}
+}
@@ -0,0 +1,118 @@
+use crate::{
+	chunk::ChunkReader,
+	config::ExportConfig,
+	error::IndexerError,
+	store::{EventFilter, EventStore},
+};
+use serde_json::json;
+use std::{
+	fs::File,
+	io::{self, BufWriter, Write},
+	sync::Arc,
+};
+
+/// Writes the events selected by `config` to its destination, `chunk_size` at a time.
+/// Returns how many were written.
+pub async fn export(
+	store: Arc<dyn EventStore>, config: &ExportConfig, chunk_size: u32,
+) -> Result<u64, IndexerError> {
+	let Some(path) = &config.export else {
+		return Ok(0);
+	};
+	let filter = EventFilter {
+		schema_ids: config.export_schema_ids.iter().copied().collect(),
+		from_timestamp: config.export_from_timestamp,
+		to_timestamp: config.export_to_timestamp,
+		..EventFilter::default()
+	};
+	let chunks = ChunkReader::new(store, filter, 0, u32::MAX, chunk_size, 0);
+	if path.as_os_str() == "-" {
+		write_events(chunks, io::stdout().lock()).await
+	} else {
+		let file = File::create(path).map_err(IndexerError::IoError)?;
+		write_events(chunks, BufWriter::new(file)).await
+	}
+}
+
+/// Writes every event as a line of JSON, keeping the schema value in its original encoding
+/// for the export to be replayed as is.
+async fn write_events(mut chunks: ChunkReader, mut out: impl Write) -> Result<u64, IndexerError> {
+	let mut written = 0;
+	while let Some(chunk) = chunks.next().await? {
+		for event in chunk.events {
+			let line = json!({
+				"id": event.id,
+				"schema_id": event.schema_id,
+				"schema_value": event.schema_value,
+				"timestamp": event.timestamp,
+				"source": event.source,
+				"received_at": event.received_at,
+				"domain": event.domain,
+			});
+			writeln!(out, "{}", line).map_err(IndexerError::IoError)?;
+			written += 1;
+		}
+	}
+	out.flush().map_err(IndexerError::IoError)?;
+	Ok(written)
+}
+
+#[cfg(test)]
+mod test {
+	use super::write_events;
+	use crate::{
+		chunk::ChunkReader,
+		source::SourceEvent,
+		store::{memory::MemoryStore, EventFilter, EventStore},
+	};
+	use serde_json::{json, Value};
+	use std::sync::Arc;
+
+	#[tokio::test]
+	async fn should_write_filtered_events_as_json_lines() {
+		let store = Arc::new(MemoryStore::new());
+		let events = (0..5)
+			.map(|i| SourceEvent {
+				schema_id: i % 2 + 1,
+				schema_value: format!("{{\"n\":{}}}", i),
+				timestamp: 100 + u64::from(i),
+				source: "eas".to_string(),
+				..SourceEvent::default()
+			})
+			.collect();
+		store.append(events).await.unwrap();
+
+		let filter =
+			EventFilter { schema_ids: [1].into(), from_timestamp: 101, ..EventFilter::default() };
+		let chunks = ChunkReader::new(store, filter, 0, u32::MAX, 1, 0);
+		let mut out = Vec::new();
+		assert_eq!(write_events(chunks, &mut out).await.unwrap(), 2);
+
+		let lines: Vec<Value> = String::from_utf8(out)
+			.unwrap()
+			.lines()
+			.map(|line| serde_json::from_str(line).unwrap())
+			.collect();
+		assert_eq!(
+			lines,
+			[
+				json!({
+					"id": 2,
+					"schema_id": 1,
+					"schema_value": "{\"n\":2}",
+					"timestamp": 102,
+					"source": "eas",
+					"received_at": 0,
+				}),
+				json!({
+					"id": 4,
+					"schema_id": 1,
+					"schema_value": "{\"n\":4}",
+					"timestamp": 104,
+					"source": "eas",
+					"received_at": 0,
+				}),
+			]
+		);
+	}
+}
@@ -0,0 +1,247 @@
+use crate::{
+	chunk::ChunkReader,
+	schemas::SchemaRegistry,
+	store::{EventFilter, EventStore},
+};
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Schema, SimpleObject};
+use hyper::{
+	body::to_bytes,
+	header::CONTENT_TYPE,
+	service::{make_service_fn, service_fn},
+	Body, Method, Request, Response, StatusCode,
+};
+use proto_buf::indexer::IndexerEvent;
+use serde_json::Value;
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+/// Fields naming the issuer of an attestation, in EAS attestations and credentials.
+const ISSUER_FIELDS: [&str; 2] = ["attester", "issuer"];
+/// Fields naming the subject of an attestation, in EAS attestations and credentials.
+const SUBJECT_FIELDS: [&str; 2] = ["recipient", "id"];
+
+type IndexerSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// What queries read from, shared through the schema's data.
+struct Events {
+	store: Arc<dyn EventStore>,
+	registry: Arc<SchemaRegistry>,
+	/// Most events a query may scan, like a gRPC query may ask for.
+	max_scan: u32,
+	chunk_size: u32,
+}
+
+#[derive(SimpleObject)]
+struct Event {
+	id: u32,
+	schema_id: u32,
+	/// Payload of the attestation, as JSON.
+	schema_value: String,
+	timestamp: u64,
+	source: String,
+	received_at: u64,
+	domain: u32,
+}
+
+impl From<IndexerEvent> for Event {
+	fn from(event: IndexerEvent) -> Self {
+		Self {
+			id: event.id,
+			schema_id: event.schema_id,
+			schema_value: event.schema_value,
+			timestamp: event.timestamp,
+			source: event.source,
+			received_at: event.received_at,
+			domain: event.domain,
+		}
+	}
+}
+
+#[derive(SimpleObject)]
+struct EventPage {
+	events: Vec<Event>,
+	/// Offset the next page starts from.
+	next_offset: u32,
+	/// Whether no more events matched as of this page.
+	complete: bool,
+}
+
+struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+	/// Up to `first` events from ID `offset` on matching every filter given, until `nextOffset`.
+	#[allow(clippy::too_many_arguments)]
+	async fn events(
+		&self, ctx: &Context<'_>, schema_ids: Option<Vec<String>>, issuer: Option<String>,
+		subject: Option<String>, domains: Option<Vec<u32>>, from_timestamp: Option<u64>,
+		to_timestamp: Option<u64>, #[graphql(default)] offset: u32,
+		#[graphql(default = 100)] first: u32,
+	) -> async_graphql::Result<EventPage> {
+		let events = ctx.data::<Events>()?;
+		let schema_ids = schema_ids
+			.unwrap_or_default()
+			.iter()
+			.map(|id| events.registry.parse(id))
+			.collect::<Result<_, _>>()?;
+		let filter = EventFilter {
+			schema_ids,
+			from_timestamp: from_timestamp.unwrap_or(0),
+			to_timestamp,
+			domains: domains.unwrap_or_default().into_iter().collect(),
+		};
+		let first = first.min(events.max_scan) as usize;
+		let store = events.store.clone();
+		let mut chunks =
+			ChunkReader::new(store, filter, offset, events.max_scan, events.chunk_size, 0);
+
+		let mut page = EventPage { events: Vec::new(), next_offset: offset, complete: false };
+		while page.events.len() < first {
+			let Some(chunk) = chunks.next().await? else {
+				break;
+			};
+			for event in chunk.events {
+				page.next_offset = event.id + 1;
+				let value = serde_json::from_str(&event.schema_value).unwrap_or(Value::Null);
+				if matches(&value, &ISSUER_FIELDS, issuer.as_deref())
+					&& matches(&value, &SUBJECT_FIELDS, subject.as_deref())
+				{
+					page.events.push(event.into());
+					if page.events.len() == first {
+						return Ok(page);
+					}
+				}
+			}
+			page.next_offset = chunks.next_offset();
+			page.complete = chunks.is_complete();
+		}
+		Ok(page)
+	}
+}
+
+/// Whether one of `fields` of `value` is `expected`, ignoring case as addresses may be
+/// checksummed, or whether nothing is expected.
+fn matches(value: &Value, fields: &[&str], expected: Option<&str>) -> bool {
+	let Some(expected) = expected else {
+		return true;
+	};
+	fields.iter().any(|field| {
+		value[field].as_str().map_or(false, |actual| actual.eq_ignore_ascii_case(expected))
+	})
+}
+
+/// Answers GraphQL queries POSTed to `/graphql`.
+#[derive(Clone)]
+pub struct GraphqlService {
+	schema: IndexerSchema,
+}
+
+impl GraphqlService {
+	pub fn new(
+		store: Arc<dyn EventStore>, registry: Arc<SchemaRegistry>, max_scan: u32, chunk_size: u32,
+	) -> Self {
+		let events = Events { store, registry, max_scan, chunk_size };
+		let schema =
+			Schema::build(QueryRoot, EmptyMutation, EmptySubscription).data(events).finish();
+		Self { schema }
+	}
+
+	/// Serves the endpoint on `addr` until the server fails.
+	pub async fn serve(self, addr: SocketAddr) -> hyper::Result<()> {
+		let make_service = make_service_fn(move |_| {
+			let service = self.clone();
+			async move {
+				Ok::<_, Infallible>(service_fn(move |request| {
+					let service = service.clone();
+					async move { Ok::<_, Infallible>(service.handle(request).await) }
+				}))
+			}
+		});
+		hyper::Server::bind(&addr).serve(make_service).await
+	}
+
+	async fn handle(&self, request: Request<Body>) -> Response<Body> {
+		if request.uri().path() != "/graphql" {
+			return reply(StatusCode::NOT_FOUND, "Not found".into());
+		}
+		if request.method() != Method::POST {
+			return reply(StatusCode::METHOD_NOT_ALLOWED, "Use POST".into());
+		}
+		let body = match to_bytes(request.into_body()).await {
+			Ok(body) => body,
+			Err(e) => return reply(StatusCode::BAD_REQUEST, e.to_string().into()),
+		};
+		let query: async_graphql::Request = match serde_json::from_slice(&body) {
+			Ok(query) => query,
+			Err(e) => return reply(StatusCode::BAD_REQUEST, e.to_string().into()),
+		};
+		let response = self.schema.execute(query).await;
+		match serde_json::to_vec(&response) {
+			Ok(body) => reply(StatusCode::OK, body.into()),
+			Err(e) => reply(StatusCode::INTERNAL_SERVER_ERROR, e.to_string().into()),
+		}
+	}
+}
+
+fn reply(status: StatusCode, body: Body) -> Response<Body> {
+	let mut response = Response::new(body);
+	*response.status_mut() = status;
+	if status == StatusCode::OK {
+		response.headers_mut().insert(CONTENT_TYPE, "application/json".parse().unwrap());
+	}
+	response
+}
+
+#[cfg(test)]
+mod test {
+	use super::GraphqlService;
+	use crate::{
+		schemas::SchemaRegistry,
+		source::SourceEvent,
+		store::{memory::MemoryStore, EventStore},
+	};
+	use serde_json::json;
+	use std::sync::Arc;
+
+	#[tokio::test]
+	async fn should_filter_by_issuer_and_page() {
+		let store = Arc::new(MemoryStore::new());
+		let attestation = |attester: &str, recipient: &str| SourceEvent {
+			schema_id: 1,
+			schema_value: json!({ "attester": attester, "recipient": recipient }).to_string(),
+			timestamp: 10,
+			..SourceEvent::default()
+		};
+		store
+			.append(vec![
+				attestation("0xaa", "0x01"),
+				attestation("0xbb", "0x01"),
+				attestation("0xAA", "0x02"),
+				attestation("0xaa", "0x03"),
+			])
+			.await
+			.unwrap();
+		let service = GraphqlService::new(store, Arc::new(SchemaRegistry::default()), 100, 2);
+
+		let query = r#"{ events(schemaIds: ["1"], issuer: "0xaa", first: 2) {
+			events { id } nextOffset complete
+		} }"#;
+		let response = service.schema.execute(query).await;
+		assert!(response.errors.is_empty(), "{:?}", response.errors);
+		assert_eq!(
+			response.data.into_json().unwrap(),
+			json!({
+				"events": { "events": [{ "id": 0 }, { "id": 2 }], "nextOffset": 3, "complete": false },
+			})
+		);
+
+		let query = r#"{ events(issuer: "0xaa", subject: "0x03", offset: 3) {
+			events { id } complete
+		} }"#;
+		let response = service.schema.execute(query).await;
+		assert_eq!(
+			response.data.into_json().unwrap(),
+			json!({ "events": { "events": [{ "id": 3 }], "complete": true } }),
+			"should match subjects"
+		);
+	}
+}
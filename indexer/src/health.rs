@@ -0,0 +1,107 @@
+use crate::{metrics::Metrics, source::now_secs, store::EventStore, IndexerService};
+use proto_buf::indexer::indexer_server::IndexerServer;
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use tonic::transport::NamedService;
+use tonic_health::{server::HealthReporter, ServingStatus};
+
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Reports the indexer ready once its store answers and every source has been polled
+/// successfully, so consumers don't read an empty log while it is still catching up. Every
+/// source is also reported under `indexer.source.<name>`, unhealthy once it has gone
+/// `stale_after` without a successful poll.
+pub struct HealthCheck {
+	store: Arc<dyn EventStore>,
+	metrics: Arc<Metrics>,
+	sources: Vec<String>,
+	stale_after: Duration,
+}
+
+impl HealthCheck {
+	pub fn new(
+		store: Arc<dyn EventStore>, metrics: Arc<Metrics>, sources: Vec<String>,
+		stale_after: Duration,
+	) -> Self {
+		Self { store, metrics, sources, stale_after }
+	}
+
+	/// Unix time in seconds `source` was last polled successfully, if it ever was.
+	fn last_success(&self, source: &str) -> Option<u64> {
+		let time = self.metrics.source_last_success.with_label_values(&[source]).get();
+		(time > 0).then_some(time as u64)
+	}
+
+	fn is_source_healthy(&self, source: &str, now: u64) -> bool {
+		self.last_success(source).map_or(false, |time| {
+			now.saturating_sub(time) <= self.stale_after.as_secs()
+		})
+	}
+
+	async fn is_ready(&self) -> bool {
+		self.store.count().await.is_ok()
+			&& self.sources.iter().all(|source| self.last_success(source).is_some())
+	}
+
+	pub fn spawn(self, mut reporter: HealthReporter) {
+		tokio::spawn(async move {
+			let mut ticker = interval(HEALTH_CHECK_INTERVAL);
+			loop {
+				ticker.tick().await;
+				let status = serving_status(self.is_ready().await);
+				reporter.set_service_status("", status).await;
+				reporter
+					.set_service_status(
+						<IndexerServer<IndexerService> as NamedService>::NAME,
+						status,
+					)
+					.await;
+
+				let now = now_secs();
+				for source in &self.sources {
+					let status = serving_status(self.is_source_healthy(source, now));
+					reporter.set_service_status(format!("indexer.source.{}", source), status).await;
+				}
+			}
+		});
+	}
+}
+
+fn serving_status(is_serving: bool) -> ServingStatus {
+	match is_serving {
+		true => ServingStatus::Serving,
+		false => ServingStatus::NotServing,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::HealthCheck;
+	use crate::{metrics::Metrics, store::memory::MemoryStore};
+	use std::{sync::Arc, time::Duration};
+
+	#[tokio::test]
+	async fn should_wait_for_every_source() {
+		let metrics = Arc::new(Metrics::new());
+		let sources = vec!["eas".to_string(), "nats".to_string()];
+		let health = HealthCheck::new(
+			Arc::new(MemoryStore::new()),
+			metrics.clone(),
+			sources,
+			Duration::from_secs(60),
+		);
+		metrics.source_last_success.with_label_values(&["eas"]).set(1_000);
+		assert!(
+			!health.is_ready().await,
+			"should wait for the first poll of nats"
+		);
+		metrics.source_last_success.with_label_values(&["nats"]).set(1_030);
+		assert!(health.is_ready().await);
+
+		assert!(health.is_source_healthy("nats", 1_060));
+		assert!(
+			!health.is_source_healthy("eas", 1_061),
+			"should flag stale sources"
+		);
+	}
+}
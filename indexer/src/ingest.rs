@@ -0,0 +1,255 @@
+use crate::{
+	metrics::Metrics,
+	schemas::SchemaRegistry,
+	source::{now_secs, Envelope},
+	store::EventStore,
+};
+use hyper::{
+	body::HttpBody,
+	header::{AUTHORIZATION, CONTENT_TYPE},
+	service::{make_service_fn, service_fn},
+	Body, Method, Request, Response, StatusCode,
+};
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use std::{convert::Infallible, net::SocketAddr, sync::Arc};
+
+/// Credentials accepted per request.
+const MAX_BATCH_SIZE: usize = 1000;
+const MAX_BODY_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Body of an ingest request, one credential or a batch of them.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Submissions {
+	Batch(Vec<Envelope>),
+	Single(Envelope),
+}
+
+/// Appends the credentials POSTed to `/credentials` to the store.
+#[derive(Clone)]
+pub struct IngestService {
+	store: Arc<dyn EventStore>,
+	registry: Arc<SchemaRegistry>,
+	metrics: Arc<Metrics>,
+	token: Arc<String>,
+}
+
+impl IngestService {
+	pub fn new(
+		store: Arc<dyn EventStore>, registry: Arc<SchemaRegistry>, metrics: Arc<Metrics>,
+		token: String,
+	) -> Self {
+		Self { store, registry, metrics, token: Arc::new(token) }
+	}
+
+	/// Serves the endpoint on `addr` until the server fails.
+	pub async fn serve(self, addr: SocketAddr) -> hyper::Result<()> {
+		let make_service = make_service_fn(move |_| {
+			let service = self.clone();
+			async move {
+				Ok::<_, Infallible>(service_fn(move |request| {
+					let service = service.clone();
+					async move { Ok::<_, Infallible>(service.handle(request).await) }
+				}))
+			}
+		});
+		hyper::Server::bind(&addr).serve(make_service).await
+	}
+
+	async fn handle(&self, request: Request<Body>) -> Response<Body> {
+		if request.uri().path() != "/credentials" {
+			return reply(StatusCode::NOT_FOUND, json!({ "error": "Not found" }));
+		}
+		if request.method() != Method::POST {
+			return reply(
+				StatusCode::METHOD_NOT_ALLOWED,
+				json!({ "error": "Use POST" }),
+			);
+		}
+		let header = request.headers().get(AUTHORIZATION).and_then(|v| v.to_str().ok());
+		if header.and_then(|v| v.strip_prefix("Bearer ")) != Some(self.token.as_str()) {
+			return reply(
+				StatusCode::UNAUTHORIZED,
+				json!({ "error": "Invalid token" }),
+			);
+		}
+		let body = match read_body(request.into_body()).await {
+			Ok(Some(body)) => body,
+			Ok(None) => {
+				return reply(
+					StatusCode::PAYLOAD_TOO_LARGE,
+					json!({ "error": "Body too large" }),
+				)
+			},
+			Err(e) => return reply(StatusCode::BAD_REQUEST, json!({ "error": e.to_string() })),
+		};
+		match self.ingest(&body).await {
+			Ok((first_id, count)) => reply(
+				StatusCode::OK,
+				json!({ "first_id": first_id, "count": count }),
+			),
+			Err((status, reason)) => reply(status, json!({ "error": reason })),
+		}
+	}
+
+	/// Validates every credential of `body` and appends them all, or none if any is invalid.
+	/// Returns the ID of the first one and how many there were.
+	async fn ingest(&self, body: &[u8]) -> Result<(u32, u32), (StatusCode, String)> {
+		let submissions = match serde_json::from_slice(body) {
+			Ok(Submissions::Batch(batch)) => batch,
+			Ok(Submissions::Single(envelope)) => vec![envelope],
+			Err(e) => return Err((StatusCode::BAD_REQUEST, e.to_string())),
+		};
+		if submissions.len() > MAX_BATCH_SIZE {
+			let reason = format!("Batch size too big. Max size: {}", MAX_BATCH_SIZE);
+			return Err((StatusCode::PAYLOAD_TOO_LARGE, reason));
+		}
+
+		let received_at = now_secs();
+		let mut events = Vec::with_capacity(submissions.len());
+		for (i, envelope) in submissions.into_iter().enumerate() {
+			if let Err(reason) = self.registry.validate(envelope.schema_id, &envelope.credential) {
+				self.metrics.events_rejected.with_label_values(&["ingest"]).inc();
+				return Err((
+					StatusCode::BAD_REQUEST,
+					format!("Credential {}: {}", i, reason),
+				));
+			}
+			events.push(envelope.into_event("ingest", received_at));
+		}
+
+		let count = events.len() as u32;
+		let total = self
+			.store
+			.append(events)
+			.await
+			.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+		self.metrics.events_ingested.with_label_values(&["ingest"]).inc_by(u64::from(count));
+		Ok((total - count, count))
+	}
+}
+
+/// Reads `body` whole, or returns `None` as soon as it grows past `MAX_BODY_SIZE`. Chunked
+/// bodies declare no length up front, so the limit is enforced on what actually arrives.
+async fn read_body(mut body: Body) -> hyper::Result<Option<Vec<u8>>> {
+	let mut bytes = Vec::new();
+	while let Some(chunk) = body.data().await {
+		let chunk = chunk?;
+		if (bytes.len() + chunk.len()) as u64 > MAX_BODY_SIZE {
+			return Ok(None);
+		}
+		bytes.extend_from_slice(&chunk);
+	}
+	Ok(Some(bytes))
+}
+
+fn reply(status: StatusCode, body: Value) -> Response<Body> {
+	let mut response = Response::new(Body::from(body.to_string()));
+	*response.status_mut() = status;
+	response.headers_mut().insert(CONTENT_TYPE, "application/json".parse().unwrap());
+	response
+}
+
+#[cfg(test)]
+mod test {
+	use super::IngestService;
+	use crate::{
+		metrics::Metrics,
+		schemas::SchemaRegistry,
+		store::{memory::MemoryStore, EventFilter, EventStore},
+	};
+	use hyper::{body::Bytes, Body, Request, StatusCode};
+	use serde_json::{json, Value};
+	use std::sync::Arc;
+
+	fn service() -> (IngestService, Arc<MemoryStore>) {
+		let store = Arc::new(MemoryStore::new());
+		let registry = Arc::new(SchemaRegistry::default());
+		(
+			IngestService::new(
+				store.clone(),
+				registry,
+				Arc::new(Metrics::new()),
+				"issuer".to_string(),
+			),
+			store,
+		)
+	}
+
+	/// Submission of an audit credential about `id`, signed with a dummy signature.
+	fn audit(schema_id: u32, id: &str) -> Value {
+		let bytes = vec![1; 32];
+		json!({ "schema_id": schema_id, "credential": { "id": id, "sig": [0, bytes, bytes] } })
+	}
+
+	#[tokio::test]
+	async fn should_append_single_and_batched_credentials() {
+		let (service, store) = service();
+		let single = audit(2, "did:pkh:1").to_string();
+		assert_eq!(service.ingest(single.as_bytes()).await.unwrap(), (0, 1));
+
+		let batch = json!([audit(2, "did:pkh:2"), audit(3, "did:pkh:3")]).to_string();
+		assert_eq!(service.ingest(batch.as_bytes()).await.unwrap(), (1, 2));
+		let events = store.read(0, 10, &EventFilter::default()).await.unwrap();
+		assert_eq!(events[2].schema_id, 3);
+		assert!(events[2].schema_value.starts_with(r#"{"id":"did:pkh:3","sig":[0,"#));
+	}
+
+	#[tokio::test]
+	async fn should_reject_invalid_batches_whole() {
+		let (service, store) = service();
+		let unsigned = json!({ "schema_id": 2, "credential": { "id": "did:pkh:2" } });
+		let batch = json!([audit(2, "did:pkh:1"), unsigned]).to_string();
+		let (status, reason) = service.ingest(batch.as_bytes()).await.unwrap_err();
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+		assert!(
+			reason.starts_with("Credential 1") && reason.contains("sig"),
+			"should name the invalid credential and why"
+		);
+		assert_eq!(store.count().await.unwrap(), 0, "should append nothing");
+
+		let batch = json!([audit(99, "did:pkh:1")]).to_string();
+		let reason = service.ingest(batch.as_bytes()).await.unwrap_err().1;
+		assert!(reason.contains("Unknown schema"));
+
+		let status = service.ingest(b"not json").await.unwrap_err().0;
+		assert_eq!(status, StatusCode::BAD_REQUEST);
+	}
+
+	#[tokio::test]
+	async fn should_require_token() {
+		let (service, _) = service();
+		let request = Request::post("/credentials").body(Body::from("[]")).unwrap();
+		assert_eq!(
+			service.handle(request).await.status(),
+			StatusCode::UNAUTHORIZED
+		);
+
+		let request = Request::post("/credentials")
+			.header("authorization", "Bearer issuer")
+			.body(Body::from("[]"))
+			.unwrap();
+		assert_eq!(service.handle(request).await.status(), StatusCode::OK);
+	}
+
+	#[tokio::test]
+	async fn should_limit_chunked_bodies() {
+		let (service, store) = service();
+		let (mut sender, body) = Body::channel();
+		tokio::spawn(async move {
+			let chunk = Bytes::from(vec![b' '; 1024 * 1024]);
+			while sender.send_data(chunk.clone()).await.is_ok() {}
+		});
+		let request = Request::post("/credentials")
+			.header("authorization", "Bearer issuer")
+			.body(body)
+			.unwrap();
+		assert_eq!(
+			service.handle(request).await.status(),
+			StatusCode::PAYLOAD_TOO_LARGE,
+			"should stop reading past the limit"
+		);
+		assert_eq!(store.count().await.unwrap(), 0);
+	}
+}
@@ -1,27 +1,156 @@
-use proto_buf::indexer::{
-	indexer_server::{Indexer, IndexerServer},
-	IndexerEvent, Query,
+use auth::{ClientName, Clients};
+use chunk::ChunkReader;
+use config::Config;
+use error::IndexerError;
+use graphql::GraphqlService;
+use health::HealthCheck;
+use ingest::IngestService;
+use metrics::{Metrics, RpcMetricsLayer};
+use proto_buf::{
+	common::{ErrorDetail, PageRequest, ServiceInfo, Void},
+	indexer::{
+		indexer_server::{Indexer, IndexerServer},
+		EventChunk, IndexerEvent, Query, ReindexRequest, ReindexResponse, SchemaStats, Stats,
+		WatchEvent,
+	},
+	PROTOCOL_VERSION,
+};
+use schemas::SchemaRegistry;
+use source::{
+	ceramic::CeramicSource, eas::EasSource, file::FileSource, http::HttpSource, ipfs::IpfsSource,
+	kafka::KafkaSource, mock::MockSource, nats::NatsSource, s3::S3Source, Source,
 };
 use std::{
+	collections::{HashMap, HashSet},
 	error::Error,
-	time::{SystemTime, UNIX_EPOCH},
+	sync::Arc,
+	time::Duration,
 };
-use tokio::sync::mpsc::channel;
+use store::{notify::NotifyingStore, stamp::StampingStore, EventFilter, EventStats, EventStore};
+use stream::StreamSender;
+use tasks::{HighWater, TaskService};
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::{transport::Server, Request, Response, Status};
+use tonic::{codegen::InterceptedService, transport::Server, Code, Request, Response, Status};
+use tonic_health::server::health_reporter;
+
+mod auth;
+mod chunk;
+mod config;
+mod error;
+mod export;
+mod graphql;
+mod health;
+mod ingest;
+mod limit;
+mod metrics;
+mod schedule;
+mod schemas;
+mod source;
+mod store;
+mod stream;
+mod tasks;
+mod watch;
 
-const FOLLOW_MOCK: &str = "{
-    \"id\": \"did:pkh:90f8bf6a479f320ead074411a4b0e7944ea8c9c2\",
-    \"is_trustworthy\": true,
-    \"scope\": \"Reviewer\",
-    \"sig\": [
-        0,
-        [165, 27, 231, 102, 0, 210, 165, 235, 176, 250, 84, 181, 240, 246, 182, 135, 85, 181, 106, 145, 41, 107, 207, 81, 49, 37, 133, 183, 171, 151, 67, 67],
-        [116, 33, 248, 224, 110, 187, 80, 139, 81, 22, 199, 37, 68, 255, 180, 55, 159, 59, 232, 70, 206, 232, 38, 165, 54, 233, 19, 31, 57, 139, 186, 54]
-    ]
-}";
+/// Optional RPCs and behaviors reported to clients by `get_service_info`.
+const FEATURES: [&str; 6] =
+	["subscribe_chunks", "watch", "watch_watermarks", "stats", "reindex", "domains"];
 
-struct IndexerService;
+struct IndexerService {
+	store: Arc<NotifyingStore>,
+	registry: Arc<SchemaRegistry>,
+	metrics: Arc<Metrics>,
+	stream_buffer_size: usize,
+	max_query_count: u32,
+	max_chunk_size: u32,
+	stream_rate: u32,
+	watch_page_size: u32,
+	heartbeat_interval: Duration,
+	high_water: tokio::sync::watch::Receiver<HighWater>,
+	admin_clients: HashSet<String>,
+}
+
+impl IndexerService {
+	fn new(
+		store: Arc<NotifyingStore>, registry: Arc<SchemaRegistry>, metrics: Arc<Metrics>,
+		high_water: tokio::sync::watch::Receiver<HighWater>, config: &Config,
+	) -> Self {
+		Self {
+			store,
+			registry,
+			metrics,
+			stream_buffer_size: usize::from(config.stream_buffer_size),
+			max_query_count: config.clients.max_query_count,
+			max_chunk_size: config.clients.max_chunk_size,
+			stream_rate: config.clients.stream_rate,
+			watch_page_size: config.tuning.watch_page_size,
+			heartbeat_interval: config.tuning.heartbeat_interval(),
+			high_water,
+			admin_clients: config.clients.admin_clients.iter().cloned().collect(),
+		}
+	}
+
+	/// Lets only the clients named by `--admin-clients` through.
+	fn authorize_admin<T>(&self, request: &Request<T>) -> Result<(), Status> {
+		match request.extensions().get::<ClientName>() {
+			Some(ClientName(name)) if !name.is_empty() && self.admin_clients.contains(name) => {
+				Ok(())
+			},
+			_ => Err(Status::permission_denied("Admin clients only!")),
+		}
+	}
+
+	fn chunks(&self, filter: EventFilter, offset: u32, count: u32) -> ChunkReader {
+		let (size, rate) = (self.max_chunk_size, self.stream_rate);
+		ChunkReader::new(self.store.clone(), filter, offset, count, size, rate)
+	}
+
+	/// Offset and size of the page a query asks for, `default_size` events if it names no size
+	/// and at most as many as a client may ask for. Queries without a page are read by the
+	/// offset and count of protocol version 1.
+	fn page(&self, query: &Query, default_size: u32) -> Result<(u32, u32), Status> {
+		let page = match &query.page {
+			Some(page) => page.clone(),
+			None => PageRequest::from_offset(query.offset, query.count),
+		};
+		let offset = page.offset().ok_or_else(|| {
+			let detail = ErrorDetail::permanent().with_field("page.cursor");
+			detail.into_status(Code::InvalidArgument, "Invalid cursor!")
+		})?;
+		let size = match page.page_size {
+			0 => default_size,
+			size => size.min(self.max_query_count),
+		};
+		Ok((offset, size))
+	}
+
+	/// Builds the filter of a query, which selects every schema when none is given and leaves
+	/// the time range open at the end when `to_timestamp` is zero.
+	fn filter(&self, query: &Query) -> Result<EventFilter, Status> {
+		let schema_ids = query
+			.schema_id
+			.iter()
+			.map(|id| self.registry.parse(id))
+			.collect::<Result<_, _>>()
+			.map_err(|msg| {
+				let detail = ErrorDetail::permanent().with_field("schema_id");
+				detail.into_status(Code::InvalidArgument, msg)
+			})?;
+		let to_timestamp = (query.to_timestamp > 0).then_some(query.to_timestamp);
+		if to_timestamp.map_or(false, |to| to < query.from_timestamp) {
+			let detail = ErrorDetail::permanent().with_field("to_timestamp");
+			return Err(detail.into_status(
+				Code::InvalidArgument,
+				"to_timestamp precedes from_timestamp",
+			));
+		}
+		Ok(EventFilter {
+			schema_ids,
+			from_timestamp: query.from_timestamp,
+			to_timestamp,
+			domains: query.domain.iter().copied().collect(),
+		})
+	}
+}
 
 #[tonic::async_trait]
 impl Indexer for IndexerService {
@@ -30,30 +159,289 @@ impl Indexer for IndexerService {
 		&self, request: Request<Query>,
 	) -> Result<Response<Self::SubscribeStream>, Status> {
 		let inner = request.into_inner();
+		let filter = self.filter(&inner)?;
+		let (offset, size) = self.page(&inner, self.max_query_count)?;
+		let mut chunks = self.chunks(filter, offset, size);
+
+		let (tx, rx) =
+			StreamSender::open("subscribe", self.stream_buffer_size, self.metrics.clone());
+		tokio::spawn(async move {
+			loop {
+				let events = match chunks.next().await {
+					Ok(Some(chunk)) => chunk.events,
+					Ok(None) => break,
+					Err(e) => {
+						tx.send(Err(e.into_status())).await;
+						break;
+					},
+				};
+				for event in events {
+					// The client went away, leaving nobody to send the rest to.
+					if !tx.send(Ok(event)).await {
+						return;
+					}
+				}
+			}
+		});
 
-		let start = SystemTime::now();
-		let current_secs = start.duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
+		Ok(Response::new(rx))
+	}
 
-		let (tx, rx) = channel(1);
+	type SubscribeChunksStream = ReceiverStream<Result<EventChunk, Status>>;
+	async fn subscribe_chunks(
+		&self, request: Request<Query>,
+	) -> Result<Response<Self::SubscribeChunksStream>, Status> {
+		let inner = request.into_inner();
+		let filter = self.filter(&inner)?;
+		let (offset, size) = self.page(&inner, self.max_query_count)?;
+		let mut chunks = self.chunks(filter.clone(), offset, size);
+		let store = self.store.clone();
+
+		let (tx, rx) = StreamSender::open(
+			"subscribe_chunks",
+			self.stream_buffer_size,
+			self.metrics.clone(),
+		);
 		tokio::spawn(async move {
-			for i in inner.offset..inner.offset + inner.count {
-				let event = IndexerEvent {
-					id: i,
-					schema_id: 1,
-					schema_value: FOLLOW_MOCK.to_string(),
-					timestamp: current_secs,
+			// Counted as of opening, so events ingested while streaming may go uncounted.
+			let mut total = match store.stats(offset, &filter).await {
+				Ok(stats) => Some(stats.values().map(|stats| stats.count).sum::<u64>()),
+				Err(e) => {
+					tx.send(Err(e.into_status())).await;
+					return;
+				},
+			};
+			loop {
+				let chunk = match chunks.next().await {
+					Ok(Some(mut chunk)) => {
+						if let (Some(page), Some(total)) = (&mut chunk.page, total.take()) {
+							page.total = total;
+						}
+						Ok(chunk)
+					},
+					Ok(None) => break,
+					Err(e) => Err(e.into_status()),
 				};
-				tx.send(Ok(event)).await.unwrap();
+				let failed = chunk.is_err();
+				if !tx.send(chunk).await || failed {
+					break;
+				}
 			}
 		});
 
-		Ok(Response::new(ReceiverStream::new(rx)))
+		Ok(Response::new(rx))
+	}
+
+	type WatchStream = ReceiverStream<Result<WatchEvent, Status>>;
+	async fn watch(&self, request: Request<Query>) -> Result<Response<Self::WatchStream>, Status> {
+		let inner = request.into_inner();
+		let filter = self.filter(&inner)?;
+		let default_size = self.watch_page_size.min(self.max_query_count);
+		let (offset, page_size) = self.page(&inner, default_size)?;
+
+		let (tx, rx) = StreamSender::open("watch", self.stream_buffer_size, self.metrics.clone());
+		tokio::spawn(watch::follow(
+			self.store.clone(),
+			self.high_water.clone(),
+			filter,
+			offset,
+			page_size,
+			self.heartbeat_interval,
+			tx,
+		));
+
+		Ok(Response::new(rx))
+	}
+
+	async fn get_stats(&self, request: Request<Query>) -> Result<Response<Stats>, Status> {
+		let inner = request.into_inner();
+		let filter = self.filter(&inner)?;
+		let (offset, _) = self.page(&inner, 0)?;
+		let by_schema = self.store.stats(offset, &filter).await.map_err(|e| e.into_status())?;
+
+		let mut total = EventStats::default();
+		let mut schemas = Vec::with_capacity(by_schema.len());
+		for (schema_id, stats) in by_schema {
+			total.merge(&stats);
+			schemas.push(SchemaStats {
+				schema_id,
+				count: stats.count,
+				min_timestamp: stats.min_timestamp,
+				max_timestamp: stats.max_timestamp,
+			});
+		}
+		Ok(Response::new(Stats {
+			count: total.count,
+			min_timestamp: total.min_timestamp,
+			max_timestamp: total.max_timestamp,
+			schemas,
+		}))
+	}
+
+	async fn reindex(
+		&self, request: Request<ReindexRequest>,
+	) -> Result<Response<ReindexResponse>, Status> {
+		self.authorize_admin(&request)?;
+		let count = self.store.reindex().await.map_err(|e| e.into_status())?;
+		println!("Reindexed {} events", count);
+		Ok(Response::new(ReindexResponse { count }))
+	}
+
+	async fn get_service_info(&self, _: Request<Void>) -> Result<Response<ServiceInfo>, Status> {
+		let mut limits = HashMap::from([
+			(
+				"max_query_count".to_string(),
+				u64::from(self.max_query_count),
+			),
+			("max_chunk_size".to_string(), u64::from(self.max_chunk_size)),
+		]);
+		if self.stream_rate > 0 {
+			limits.insert("stream_rate".to_string(), u64::from(self.stream_rate));
+		}
+		Ok(Response::new(ServiceInfo {
+			service: "indexer".to_string(),
+			version: env!("CARGO_PKG_VERSION").to_string(),
+			protocol_version: PROTOCOL_VERSION,
+			features: FEATURES.iter().map(|f| f.to_string()).collect(),
+			limits,
+		}))
 	}
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-	let addr = "[::1]:50050".parse()?;
-	Server::builder().add_service(IndexerServer::new(IndexerService)).serve(addr).await?;
+	let config = Config::load();
+	let store = store::open(&config.store).await?;
+	if config.export.export.is_some() {
+		let max_chunk_size = config.clients.max_chunk_size;
+		let count = export::export(store, &config.export, max_chunk_size).await?;
+		eprintln!("Exported {} events", count);
+		return Ok(());
+	}
+	let store: Arc<dyn EventStore> = match config.stamp_received_at {
+		true => Arc::new(StampingStore::new(store).await?),
+		false => store,
+	};
+	let store = Arc::new(NotifyingStore::new(store).await?);
+	let registry = Arc::new(SchemaRegistry::load(config.schema_dir.as_deref())?);
+	let metrics = Arc::new(Metrics::new());
+	let schedules = config.tuning.source_schedules.iter().cloned().collect();
+	let mut tasks = TaskService::new(
+		store.clone(),
+		registry.clone(),
+		metrics.clone(),
+		config.tuning.poll_interval(),
+	)
+	.with_schedules(schedules, config.tuning.poll_jitter())
+	.with_domains(config.source_domains.iter().cloned().collect());
+	let mut sources: Vec<Box<dyn Source>> = Vec::new();
+	if let Some(url) = &config.ceramic.ceramic_url {
+		sources.push(Box::new(CeramicSource::new(url, &config.ceramic)));
+	}
+	if let Some(rpc_url) = &config.eas.eas_rpc_url {
+		sources.push(Box::new(EasSource::new(
+			"eas".to_string(),
+			rpc_url,
+			&config.eas,
+		)));
+	}
+	for (chain, rpc_url) in &config.eas.eas_chains {
+		let name = format!("eas-{}", chain);
+		sources.push(Box::new(EasSource::new(name, rpc_url, &config.eas)));
+	}
+	if let Some(brokers) = &config.kafka.kafka_brokers {
+		sources.push(Box::new(KafkaSource::new(brokers, &config.kafka)?));
+	}
+	if let Some(url) = &config.nats.nats_url {
+		sources.push(Box::new(NatsSource::connect(url, &config.nats).await?));
+	}
+	if let Some(gateway) = &config.ipfs.ipfs_gateway {
+		sources.push(Box::new(IpfsSource::new(gateway, &config.ipfs)));
+	}
+	if let Some(bucket) = &config.s3.s3_bucket {
+		sources.push(Box::new(S3Source::new(bucket, &config.s3)));
+	}
+	if let Some(path) = &config.dump_path {
+		sources.push(Box::new(FileSource::new(path)));
+	}
+	if !config.dump_urls.is_empty() {
+		sources.push(Box::new(HttpSource::new(&config.dump_urls)));
+	}
+	if sources.is_empty() {
+		sources.push(Box::new(MockSource::new(config.tuning.mock_batch_size)));
+	}
+	let source_names: Vec<_> = sources.iter().map(|source| source.name().to_string()).collect();
+	// Names key the checkpoints and metrics of the sources.
+	if source_names.iter().collect::<HashSet<_>>().len() < source_names.len() {
+		return Err(
+			IndexerError::ConfigError("Chains of --eas-chains should be named uniquely").into(),
+		);
+	}
+	if config.tuning.source_schedules.iter().any(|(name, _)| !source_names.contains(name)) {
+		return Err(IndexerError::ConfigError("--source-schedules names unknown sources").into());
+	}
+	if config.source_domains.iter().any(|(name, _)| !source_names.contains(name)) {
+		return Err(IndexerError::ConfigError("--source-domains names unknown sources").into());
+	}
+	sources.into_iter().for_each(|source| tasks.add_source(source));
+	let high_water = tasks.watch_high_water();
+	tokio::spawn(tasks.run());
+
+	if let (Some(addr), Some(token)) = (config.ingest_addr, &config.ingest_token) {
+		let ingest = IngestService::new(
+			store.clone(),
+			registry.clone(),
+			metrics.clone(),
+			token.clone(),
+		);
+		tokio::spawn(async move {
+			if let Err(e) = ingest.serve(addr).await {
+				println!("Ingest endpoint failed: {}", e);
+			}
+		});
+	}
+
+	if let Some(addr) = config.graphql_addr {
+		let graphql = GraphqlService::new(
+			store.clone(),
+			registry.clone(),
+			config.clients.max_query_count,
+			config.clients.max_chunk_size,
+		);
+		tokio::spawn(async move {
+			if let Err(e) = graphql.serve(addr).await {
+				println!("GraphQL endpoint failed: {}", e);
+			}
+		});
+	}
+
+	if let Some(addr) = config.metrics_addr {
+		let (metrics, store) = (metrics.clone(), store.clone());
+		tokio::spawn(async move {
+			if let Err(e) = metrics::serve(addr, metrics, store).await {
+				println!("Metrics endpoint failed: {}", e);
+			}
+		});
+	}
+
+	let (reporter, health_service) = health_reporter();
+	let stale_after = Duration::from_secs(config.source_stale_after);
+	HealthCheck::new(store.clone(), metrics.clone(), source_names, stale_after).spawn(reporter);
+
+	let rpc_metrics = RpcMetricsLayer::new(metrics.clone());
+	let service = IndexerService::new(store, registry, metrics, high_water, &config);
+	let clients = Clients::new(&config.clients)?;
+	let mut server = Server::builder().layer(rpc_metrics);
+	if let Some(tls) = config.tls.load()? {
+		server = server.tls_config(tls)?;
+	}
+	server
+		.add_service(health_service)
+		.add_service(InterceptedService::new(
+			IndexerServer::new(service),
+			clients,
+		))
+		.serve(config.listen_addr)
+		.await?;
 	Ok(())
 }
@@ -0,0 +1,238 @@
+use crate::store::notify::NotifyingStore;
+use hyper::{
+	header::CONTENT_TYPE,
+	service::{make_service_fn, service_fn},
+	Body, Response, StatusCode,
+};
+use prometheus::{
+	Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+	TextEncoder,
+};
+use std::{
+	convert::Infallible,
+	future::Future,
+	net::SocketAddr,
+	pin::Pin,
+	sync::Arc,
+	task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+/// Metrics of the indexer, exported in the Prometheus text format.
+pub struct Metrics {
+	registry: Registry,
+	pub events_ingested: IntCounterVec,
+	pub events_rejected: IntCounterVec,
+	/// Unix time in seconds of the last successful poll of every source.
+	pub source_last_success: IntGaugeVec,
+	/// How far behind upstream every source is, in its own unit: blocks, messages or archives.
+	pub source_lag: IntGaugeVec,
+	store_events: IntGauge,
+	pub streams_active: IntGaugeVec,
+	pub stream_events_sent: IntCounterVec,
+	pub stream_disconnects: IntCounterVec,
+	pub stream_send_wait: HistogramVec,
+	rpc_duration: HistogramVec,
+}
+
+impl Metrics {
+	pub fn new() -> Self {
+		let events_ingested = IntCounterVec::new(
+			Opts::new(
+				"indexer_events_ingested_total", "Events appended to the store",
+			),
+			&["source"],
+		)
+		.unwrap();
+		let events_rejected = IntCounterVec::new(
+			Opts::new(
+				"indexer_events_rejected_total",
+				"Events dropped for not conforming to their schema",
+			),
+			&["source"],
+		)
+		.unwrap();
+		let source_last_success = IntGaugeVec::new(
+			Opts::new(
+				"indexer_source_last_success_timestamp_seconds",
+				"Time of the last successful poll",
+			),
+			&["source"],
+		)
+		.unwrap();
+		let source_lag = IntGaugeVec::new(
+			Opts::new(
+				"indexer_source_lag",
+				"Blocks, messages or archives a source has yet to fetch",
+			),
+			&["source"],
+		)
+		.unwrap();
+		let store_events =
+			IntGauge::new("indexer_store_events", "Events held by the store").unwrap();
+		let streams_active = IntGaugeVec::new(
+			Opts::new("indexer_streams_active", "Response streams being sent"),
+			&["method"],
+		)
+		.unwrap();
+		let stream_events_sent = IntCounterVec::new(
+			Opts::new(
+				"indexer_stream_events_sent_total", "Messages handed to response streams",
+			),
+			&["method"],
+		)
+		.unwrap();
+		let stream_disconnects = IntCounterVec::new(
+			Opts::new(
+				"indexer_stream_disconnects_total",
+				"Streams whose client went away before they ended",
+			),
+			&["method"],
+		)
+		.unwrap();
+		let stream_send_wait = HistogramVec::new(
+			HistogramOpts::new(
+				"indexer_stream_send_wait_seconds",
+				"Time a message waited for room in the stream buffer",
+			)
+			.buckets(vec![0.0001, 0.001, 0.01, 0.1, 1.0, 10.0]),
+			&["method"],
+		)
+		.unwrap();
+		let rpc_duration = HistogramVec::new(
+			HistogramOpts::new(
+				"indexer_rpc_duration_seconds",
+				"Time RPCs took to answer, up to the start of their stream",
+			),
+			&["method"],
+		)
+		.unwrap();
+
+		let registry = Registry::new();
+		registry.register(Box::new(events_ingested.clone())).unwrap();
+		registry.register(Box::new(events_rejected.clone())).unwrap();
+		registry.register(Box::new(source_last_success.clone())).unwrap();
+		registry.register(Box::new(source_lag.clone())).unwrap();
+		registry.register(Box::new(store_events.clone())).unwrap();
+		registry.register(Box::new(streams_active.clone())).unwrap();
+		registry.register(Box::new(stream_events_sent.clone())).unwrap();
+		registry.register(Box::new(stream_disconnects.clone())).unwrap();
+		registry.register(Box::new(stream_send_wait.clone())).unwrap();
+		registry.register(Box::new(rpc_duration.clone())).unwrap();
+
+		Self {
+			registry,
+			events_ingested,
+			events_rejected,
+			source_last_success,
+			source_lag,
+			store_events,
+			streams_active,
+			stream_events_sent,
+			stream_disconnects,
+			stream_send_wait,
+			rpc_duration,
+		}
+	}
+
+	/// Refreshes the store size and encodes every metric.
+	fn render(&self, store: &NotifyingStore) -> Vec<u8> {
+		self.store_events.set(i64::from(*store.watch_count().borrow()));
+		let mut buffer = Vec::new();
+		// Encoding into a `Vec` cannot fail.
+		let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buffer);
+		buffer
+	}
+}
+
+/// Serves `/metrics` on `addr` until the server fails.
+pub async fn serve(
+	addr: SocketAddr, metrics: Arc<Metrics>, store: Arc<NotifyingStore>,
+) -> hyper::Result<()> {
+	let make_service = make_service_fn(move |_| {
+		let metrics = metrics.clone();
+		let store = store.clone();
+		async move {
+			Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+				let response = match request.uri().path() {
+					"/metrics" => Response::builder()
+						.header(CONTENT_TYPE, TextEncoder::new().format_type())
+						.body(Body::from(metrics.render(&store))),
+					_ => Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()),
+				};
+				async move { response }
+			}))
+		}
+	});
+	hyper::Server::bind(&addr).serve(make_service).await
+}
+
+/// Times every call of the wrapped service by method.
+#[derive(Clone)]
+pub struct RpcMetricsLayer {
+	metrics: Arc<Metrics>,
+}
+
+impl RpcMetricsLayer {
+	pub fn new(metrics: Arc<Metrics>) -> Self {
+		Self { metrics }
+	}
+}
+
+impl<S> Layer<S> for RpcMetricsLayer {
+	type Service = RpcMetrics<S>;
+
+	fn layer(&self, inner: S) -> Self::Service {
+		RpcMetrics { inner, metrics: self.metrics.clone() }
+	}
+}
+
+#[derive(Clone)]
+pub struct RpcMetrics<S> {
+	inner: S,
+	metrics: Arc<Metrics>,
+}
+
+impl<S, B> Service<hyper::Request<B>> for RpcMetrics<S>
+where
+	S: Service<hyper::Request<B>>,
+	S::Future: Send + 'static,
+{
+	type Response = S::Response;
+	type Error = S::Error;
+	type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+	fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+		self.inner.poll_ready(cx)
+	}
+
+	fn call(&mut self, request: hyper::Request<B>) -> Self::Future {
+		let method = request.uri().path().rsplit('/').next().unwrap_or_default().to_string();
+		let timer = self.metrics.rpc_duration.with_label_values(&[&method]).start_timer();
+		let response = self.inner.call(request);
+		Box::pin(async move {
+			let response = response.await;
+			timer.observe_duration();
+			response
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Metrics;
+	use crate::store::{memory::MemoryStore, notify::NotifyingStore};
+	use std::sync::Arc;
+
+	#[tokio::test]
+	async fn should_render_metrics() {
+		let store = NotifyingStore::new(Arc::new(MemoryStore::new())).await.unwrap();
+		let metrics = Metrics::new();
+		metrics.events_ingested.with_label_values(&["eas"]).inc_by(3);
+		metrics.source_lag.with_label_values(&["eas"]).set(12);
+		let text = String::from_utf8(metrics.render(&store)).unwrap();
+		assert!(text.contains("indexer_events_ingested_total{source=\"eas\"} 3"));
+		assert!(text.contains("indexer_source_lag{source=\"eas\"} 12"));
+		assert!(text.contains("indexer_store_events 0"));
+	}
+}
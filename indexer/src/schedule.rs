@@ -0,0 +1,232 @@
+use std::{str::FromStr, time::Duration};
+
+const MINUTES_PER_DAY: u64 = 24 * 60;
+/// Days a cron expression is searched over for its next time, past which it is deemed never to
+/// fire, e.g. on February 30th.
+const CRON_HORIZON_DAYS: u64 = 5 * 366;
+
+/// When a source is polled, rather than every round.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+	/// Polled again once the interval elapsed since the last poll ended.
+	Every(Duration),
+	Cron(Cron),
+}
+
+impl Schedule {
+	/// Unix time in milliseconds of the first poll, given it is `now`.
+	pub fn first(&self, now: u64) -> u64 {
+		match self {
+			Self::Every(_) => now,
+			Self::Cron(cron) => cron.next_after(now),
+		}
+	}
+
+	/// Unix time in milliseconds of the poll following one ended `now`. Polls missed meanwhile
+	/// are skipped rather than caught up on.
+	pub fn after(&self, now: u64) -> u64 {
+		match self {
+			Self::Every(interval) => now.saturating_add(interval.as_millis() as u64),
+			Self::Cron(cron) => cron.next_after(now),
+		}
+	}
+}
+
+/// Parses intervals such as `500ms`, `30s`, `5m` or `1h`, and five field cron expressions.
+impl FromStr for Schedule {
+	type Err = String;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		if text.split_whitespace().count() == 5 {
+			return text.parse().map(Self::Cron);
+		}
+		let text = text.trim();
+		let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+		let (value, unit) = text.split_at(split);
+		let value: u64 = value.parse().map_err(|_| format!("Invalid schedule `{}`", text))?;
+		let interval = match unit {
+			"ms" => Duration::from_millis(value),
+			"s" => Duration::from_secs(value),
+			"m" => Duration::from_secs(value * 60),
+			"h" => Duration::from_secs(value * 3600),
+			_ => return Err(format!("Invalid schedule `{}`", text)),
+		};
+		Ok(Self::Every(interval))
+	}
+}
+
+/// A cron expression, `minute hour day-of-month month day-of-week`, evaluated in UTC. Fields
+/// list values, ranges `a-b`, `*`, and steps of either such as `*/15`, separated by commas.
+/// Sunday is either 0 or 7.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cron {
+	/// Values of each field, as bit masks.
+	minutes: u64,
+	hours: u64,
+	days: u64,
+	months: u64,
+	weekdays: u64,
+	/// Whether days are restricted by their day of the month or of the week only. Restricted
+	/// by both, they match either, as with cron.
+	any_day: bool,
+	any_weekday: bool,
+}
+
+impl Cron {
+	/// Unix time in milliseconds of the first minute matching after `now`, or never.
+	fn next_after(&self, now: u64) -> u64 {
+		let mut minute = now / 60_000 + 1;
+		let horizon = minute / MINUTES_PER_DAY + CRON_HORIZON_DAYS;
+		while minute / MINUTES_PER_DAY < horizon {
+			let days = minute / MINUTES_PER_DAY;
+			let (_, month, day) = civil_from_days(days);
+			// The epoch fell on a Thursday.
+			let weekday = (days + 4) % 7;
+			if !self.matches_day(month, day, weekday) {
+				minute = (days + 1) * MINUTES_PER_DAY;
+			} else if !has(self.hours, minute % MINUTES_PER_DAY / 60) {
+				minute = (minute / 60 + 1) * 60;
+			} else if !has(self.minutes, minute % 60) {
+				minute += 1;
+			} else {
+				return minute * 60_000;
+			}
+		}
+		u64::MAX
+	}
+
+	fn matches_day(&self, month: u64, day: u64, weekday: u64) -> bool {
+		if !has(self.months, month) {
+			return false;
+		}
+		let (day, weekday) = (has(self.days, day), has(self.weekdays, weekday));
+		if self.any_day || self.any_weekday {
+			day && weekday
+		} else {
+			day || weekday
+		}
+	}
+}
+
+impl FromStr for Cron {
+	type Err = String;
+
+	fn from_str(text: &str) -> Result<Self, Self::Err> {
+		let fields: Vec<_> = text.split_whitespace().collect();
+		let [minutes, hours, days, months, weekdays] = fields[..] else {
+			return Err(format!("Expected five cron fields, got `{}`", text));
+		};
+		let mut weekday_mask = parse_field(weekdays, 0, 7)?;
+		if has(weekday_mask, 7) {
+			weekday_mask |= 1;
+		}
+		Ok(Self {
+			minutes: parse_field(minutes, 0, 59)?,
+			hours: parse_field(hours, 0, 23)?,
+			days: parse_field(days, 1, 31)?,
+			months: parse_field(months, 1, 12)?,
+			weekdays: weekday_mask,
+			any_day: days == "*",
+			any_weekday: weekdays == "*",
+		})
+	}
+}
+
+/// Parses a cron field into the bit mask of its values, from `min` to `max`.
+fn parse_field(field: &str, min: u64, max: u64) -> Result<u64, String> {
+	let invalid = || format!("Invalid cron field `{}`", field);
+	let value = |text: &str| text.parse::<u64>().map_err(|_| invalid());
+	let mut mask = 0;
+	for part in field.split(',') {
+		let (range, step) = match part.split_once('/') {
+			Some((range, step)) => (range, value(step)?),
+			None => (part, 1),
+		};
+		let (start, end) = match range.split_once('-') {
+			_ if range == "*" => (min, max),
+			Some((start, end)) => (value(start)?, value(end)?),
+			// A single value with a step runs to the end of the field.
+			None if step > 1 => (value(range)?, max),
+			None => (value(range)?, value(range)?),
+		};
+		if step == 0 || start < min || end > max || start > end {
+			return Err(invalid());
+		}
+		for value in (start..=end).step_by(step as usize) {
+			mask |= 1 << value;
+		}
+	}
+	Ok(mask)
+}
+
+fn has(mask: u64, value: u64) -> bool {
+	mask & (1 << value) != 0
+}
+
+/// Year, month and day of the month of a count of days since the Unix epoch.
+pub fn civil_from_days(days: u64) -> (u64, u64, u64) {
+	// Years are shifted to start in March, for leap days to end them.
+	let z = days + 719_468;
+	let era = z / 146_097;
+	let day_of_era = z % 146_097;
+	let year_of_era =
+		(day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let shifted_month = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+	let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+	let year = era * 400 + year_of_era + u64::from(month <= 2);
+	(year, month, day)
+}
+
+#[cfg(test)]
+mod test {
+	use super::{civil_from_days, Schedule};
+	use std::time::Duration;
+
+	/// Unix time in milliseconds of 2024-02-29T10:17:00Z, a Thursday.
+	const LEAP_DAY: u64 = 1_709_201_820_000;
+	const MINUTE: u64 = 60_000;
+
+	#[test]
+	fn should_parse_schedules() {
+		assert_eq!(
+			"30s".parse::<Schedule>().unwrap(),
+			Schedule::Every(Duration::from_secs(30))
+		);
+		assert_eq!(
+			"5m".parse::<Schedule>().unwrap(),
+			Schedule::Every(Duration::from_secs(300))
+		);
+		assert!("30".parse::<Schedule>().is_err());
+		assert!("60 * * * *".parse::<Schedule>().is_err());
+		assert!("*/0 * * * *".parse::<Schedule>().is_err());
+		assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+	}
+
+	#[test]
+	fn should_find_next_cron_time() {
+		let next = |cron: &str, now| cron.parse::<Schedule>().unwrap().after(now);
+		assert_eq!(next("*/15 * * * *", LEAP_DAY), LEAP_DAY + 13 * MINUTE);
+		assert_eq!(
+			next("0 9 * * *", LEAP_DAY),
+			LEAP_DAY + (22 * 60 + 43) * MINUTE,
+			"should move to the next day"
+		);
+		assert_eq!(
+			next("0 0 1 3 *", LEAP_DAY),
+			LEAP_DAY + (13 * 60 + 43) * MINUTE,
+			"should go past leap days"
+		);
+		assert_eq!(
+			next("0 0 13 * 5", LEAP_DAY),
+			LEAP_DAY + (13 * 60 + 43) * MINUTE,
+			"should match either day restriction"
+		);
+		assert_eq!(next("0 0 30 2 *", LEAP_DAY), u64::MAX);
+
+		let every = Schedule::Every(Duration::from_secs(30));
+		assert_eq!(every.first(LEAP_DAY), LEAP_DAY);
+		assert_eq!(every.after(LEAP_DAY), LEAP_DAY + MINUTE / 2);
+	}
+}
@@ -0,0 +1,158 @@
+use crate::error::IndexerError;
+use jsonschema::JSONSchema;
+use serde_json::Value;
+use std::{collections::BTreeMap, fs, path::Path};
+
+/// Schema of the attestations read from EAS contracts, as `getAttestation` returns them.
+pub const EAS_SCHEMA_ID: u32 = 4;
+
+/// JSON Schemas of the attestations the indexer understands, by schema ID.
+const BUILTIN_SCHEMAS: [(u32, &str); 4] = [
+	(1, include_str!("../schemas/1.json")),
+	(2, include_str!("../schemas/2.json")),
+	(3, include_str!("../schemas/3.json")),
+	(EAS_SCHEMA_ID, include_str!("../schemas/4.json")),
+];
+
+/// Schemas attestations may be ingested under, each with the JSON Schema their payloads
+/// must conform to.
+pub struct SchemaRegistry {
+	schemas: BTreeMap<u32, JSONSchema>,
+}
+
+impl Default for SchemaRegistry {
+	fn default() -> Self {
+		let mut registry = Self { schemas: BTreeMap::new() };
+		for (schema_id, schema) in BUILTIN_SCHEMAS {
+			registry.insert(schema_id, schema).expect("built-in schemas should compile");
+		}
+		registry
+	}
+}
+
+impl SchemaRegistry {
+	/// Builds the registry of the built-in schemas, adding or replacing them with the
+	/// `<schema ID>.json` files of `dir` if given.
+	pub fn load(dir: Option<&Path>) -> Result<Self, IndexerError> {
+		let mut registry = Self::default();
+		let Some(dir) = dir else {
+			return Ok(registry);
+		};
+		let entries = fs::read_dir(dir).map_err(|e| IndexerError::SchemaError(e.to_string()))?;
+		for entry in entries {
+			let path = entry.map_err(|e| IndexerError::SchemaError(e.to_string()))?.path();
+			if path.extension().map_or(true, |extension| extension != "json") {
+				continue;
+			}
+			let schema_id = path
+				.file_stem()
+				.and_then(|stem| stem.to_str())
+				.and_then(|stem| stem.parse().ok())
+				.ok_or_else(|| {
+					IndexerError::SchemaError(format!(
+						"{} is not named by a schema ID",
+						path.display()
+					))
+				})?;
+			let schema =
+				fs::read_to_string(&path).map_err(|e| IndexerError::SchemaError(e.to_string()))?;
+			registry.insert(schema_id, &schema)?;
+		}
+		Ok(registry)
+	}
+
+	fn insert(&mut self, schema_id: u32, schema: &str) -> Result<(), IndexerError> {
+		let invalid =
+			|reason: String| IndexerError::SchemaError(format!("Schema {}: {}", schema_id, reason));
+		let schema: Value = serde_json::from_str(schema).map_err(|e| invalid(e.to_string()))?;
+		let compiled = JSONSchema::compile(&schema).map_err(|e| invalid(e.to_string()))?;
+		self.schemas.insert(schema_id, compiled);
+		Ok(())
+	}
+
+	pub fn contains(&self, schema_id: u32) -> bool {
+		self.schemas.contains_key(&schema_id)
+	}
+
+	/// Resolves a schema ID given in a query, in decimal or `0x`-prefixed hex, to a known schema.
+	pub fn parse(&self, schema_id: &str) -> Result<u32, String> {
+		let parsed = match schema_id.strip_prefix("0x") {
+			Some(hex) => u32::from_str_radix(hex, 16),
+			None => schema_id.parse(),
+		};
+		match parsed {
+			Ok(id) if self.contains(id) => Ok(id),
+			Ok(_) => Err(format!("Unknown schema {}", schema_id)),
+			Err(_) => Err(format!("Invalid schema ID {}", schema_id)),
+		}
+	}
+
+	/// Checks that `value` conforms to the JSON Schema of `schema_id`, describing every
+	/// violation otherwise.
+	pub fn validate(&self, schema_id: u32, value: &Value) -> Result<(), String> {
+		let schema =
+			self.schemas.get(&schema_id).ok_or_else(|| format!("Unknown schema {}", schema_id))?;
+		schema.validate(value).map_err(|errors| {
+			let reasons: Vec<_> = errors
+				.map(|error| match error.instance_path.to_string() {
+					path if path.is_empty() => error.to_string(),
+					path => format!("{}: {}", path, error),
+				})
+				.collect();
+			reasons.join("; ")
+		})
+	}
+
+	/// Validates a payload as stored in an event, which must first of all be JSON.
+	pub fn validate_str(&self, schema_id: u32, value: &str) -> Result<(), String> {
+		let value: Value = serde_json::from_str(value).map_err(|e| e.to_string())?;
+		self.validate(schema_id, &value)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::SchemaRegistry;
+	use serde_json::json;
+
+	#[test]
+	fn should_parse_decimal_and_hex_ids() {
+		let registry = SchemaRegistry::default();
+		assert_eq!(registry.parse("2"), Ok(2));
+		assert_eq!(registry.parse("0x3"), Ok(3));
+		assert!(
+			registry.parse("0x63").is_err(),
+			"should reject unknown schemas"
+		);
+		assert!(
+			registry.parse("follow").is_err(),
+			"should reject malformed ids"
+		);
+	}
+
+	#[test]
+	fn should_validate_payloads_against_schema() {
+		let registry = SchemaRegistry::default();
+		let bytes = vec![1; 32];
+		let sig = json!([0, bytes, bytes]);
+		let approve =
+			json!({ "id": "did:pkh:90f8bf6a479f320ead074411a4b0e7944ea8c9c2", "sig": sig });
+		registry.validate(2, &approve).unwrap();
+
+		let follow = json!({ "id": "did:pkh:1", "is_trustworthy": "yes", "sig": sig });
+		let reason = registry.validate(1, &follow).unwrap_err();
+		assert!(
+			reason.contains("/is_trustworthy"),
+			"should locate invalid fields"
+		);
+		assert!(reason.contains("scope"), "should report missing fields");
+
+		let truncated = json!({ "id": "did:pkh:1", "sig": [0, &bytes[1..], bytes] });
+		assert!(registry.validate(3, &truncated).is_err());
+		assert!(
+			registry.validate_str(3, "{").is_err(),
+			"should reject malformed json"
+		);
+		assert!(registry.validate(9, &approve).is_err());
+	}
+}
@@ -0,0 +1,177 @@
+use crate::error::IndexerError;
+use serde_derive::Deserialize;
+use serde_json::Value;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod ceramic;
+pub mod dump;
+pub mod eas;
+pub mod file;
+pub mod http;
+pub mod ipfs;
+pub mod kafka;
+pub mod mock;
+pub mod nats;
+pub mod s3;
+
+/// An attestation read from a source, not yet assigned an ID.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceEvent {
+	pub schema_id: u32,
+	pub schema_value: String,
+	/// Unix time in seconds the attestation was made at.
+	pub timestamp: u64,
+	/// Name of the source the attestation was read from.
+	pub source: String,
+	/// Unix time in seconds the indexer stored the attestation at, zero unless stamped.
+	pub received_at: u64,
+	/// Trust domain of the attestation, zero for the default one.
+	pub domain: u32,
+}
+
+/// A credential as pushed by issuers, over HTTP or through a message broker.
+#[derive(Debug, Deserialize)]
+pub struct Envelope {
+	#[serde(alias = "schema")]
+	pub schema_id: u32,
+	pub credential: Value,
+	/// Unix time in seconds the credential was issued at, if the issuer tells.
+	pub timestamp: Option<u64>,
+	/// Trust domain of the credential, the default one unless the issuer tells.
+	#[serde(default)]
+	pub domain: u32,
+}
+
+impl Envelope {
+	/// Converts the envelope received through `source` into an event, stamped `received_at`
+	/// unless it carries a time.
+	pub fn into_event(self, source: &str, received_at: u64) -> SourceEvent {
+		SourceEvent {
+			schema_id: self.schema_id,
+			schema_value: self.credential.to_string(),
+			timestamp: self.timestamp.unwrap_or(received_at),
+			source: source.to_string(),
+			domain: self.domain,
+			..SourceEvent::default()
+		}
+	}
+}
+
+/// A line of a JSON lines archive or dump.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Line {
+	Envelope(Envelope),
+	/// An event as exported by the indexer, its schema value kept in its original encoding.
+	Exported {
+		schema_id: u32,
+		schema_value: String,
+		timestamp: u64,
+		#[serde(default)]
+		domain: u32,
+	},
+}
+
+/// Parses one credential envelope or exported event per non-empty line.
+pub fn parse_jsonl(
+	data: &[u8], source: &str, received_at: u64,
+) -> Result<Vec<SourceEvent>, IndexerError> {
+	let mut events = Vec::new();
+	for line in data.split(|&b| b == b'\n') {
+		if line.iter().all(u8::is_ascii_whitespace) {
+			continue;
+		}
+		let line: Line = serde_json::from_slice(line).map_err(|_| IndexerError::ParseError)?;
+		events.push(match line {
+			Line::Envelope(envelope) => envelope.into_event(source, received_at),
+			Line::Exported { schema_id, schema_value, timestamp, domain } => SourceEvent {
+				schema_id,
+				schema_value,
+				timestamp,
+				source: source.to_string(),
+				domain,
+				..SourceEvent::default()
+			},
+		});
+	}
+	Ok(events)
+}
+
+/// Upstream the task service pulls attestations from.
+#[tonic::async_trait]
+pub trait Source: Send {
+	/// Name identifying the source in logs, metrics and checkpoints, and recorded on its
+	/// events. It is unique among the configured sources.
+	fn name(&self) -> &str;
+
+	/// Fetches the attestations that appeared since the previous call, possibly none.
+	async fn poll(&mut self) -> Result<Vec<SourceEvent>, IndexerError>;
+
+	/// Acknowledges the events of the last poll once they are stored, for sources tracking
+	/// delivery upstream.
+	async fn commit(&mut self) -> Result<(), IndexerError> {
+		Ok(())
+	}
+
+	/// How far behind upstream the source was as of the last poll, in its own unit, for
+	/// sources that can tell.
+	fn lag(&self) -> Option<u64> {
+		None
+	}
+
+	/// Position the next poll continues from, for sources the indexer tracks the progress of
+	/// itself rather than upstream. It is saved along with the events polled up to it.
+	fn checkpoint(&self) -> Option<String> {
+		None
+	}
+
+	/// Continues from a checkpoint saved by an earlier run.
+	fn restore(&mut self, _checkpoint: &str) -> Result<(), IndexerError> {
+		Ok(())
+	}
+}
+
+/// Unix time in seconds, the timestamp of attestations that don't carry their own.
+pub fn now_secs() -> u64 {
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards");
+	now.as_secs()
+}
+
+#[cfg(test)]
+mod test {
+	use super::{parse_jsonl, Envelope};
+
+	#[test]
+	fn should_stamp_envelopes_without_time() {
+		let envelope: Envelope =
+			serde_json::from_str(r#"{ "schema_id": 1, "credential": { "id": "a" } }"#).unwrap();
+		let event = envelope.into_event("ingest", 50);
+		assert_eq!((event.schema_id, event.timestamp), (1, 50));
+		assert_eq!(event.source, "ingest");
+		assert_eq!(event.schema_value, r#"{"id":"a"}"#);
+
+		let envelope: Envelope =
+			serde_json::from_str(r#"{ "schema_id": 1, "credential": {}, "timestamp": 7 }"#)
+				.unwrap();
+		assert_eq!(envelope.into_event("ingest", 50).timestamp, 7);
+	}
+
+	#[test]
+	fn should_parse_exported_lines() {
+		let data =
+			br#"{ "id": 4, "schema_id": 2, "schema_value": "{\"id\": \"a\"}", "timestamp": 6, "domain": 3 }
+
+{ "schema": 1, "credential": { "id": "b" } }
+"#;
+		let events = parse_jsonl(data, "files", 9).unwrap();
+		assert_eq!(events.len(), 2);
+		assert_eq!((events[0].schema_id, events[0].timestamp), (2, 6));
+		assert_eq!((events[0].domain, events[1].domain), (3, 0));
+		assert_eq!(
+			events[0].schema_value, r#"{"id": "a"}"#,
+			"should keep exported values as is"
+		);
+		assert_eq!((events[1].schema_id, events[1].timestamp), (1, 9));
+		assert!(parse_jsonl(br#"{ "schema_id": 1 }"#, "files", 9).is_err());
+	}
+}
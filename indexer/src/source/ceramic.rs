@@ -0,0 +1,186 @@
+use super::{now_secs, Source, SourceEvent};
+use crate::{config::CeramicConfig, error::IndexerError};
+use reqwest::Client;
+use serde_json::{json, Value};
+
+/// Follows a ComposeDB model index over GraphQL, oldest documents first.
+pub struct CeramicSource {
+	client: Client,
+	url: String,
+	query: String,
+	index: String,
+	schema_id: u32,
+	timestamp_field: Option<String>,
+	page_size: u32,
+	/// Cursor of the last document read, `None` until the first one is.
+	cursor: Option<String>,
+}
+
+impl CeramicSource {
+	pub fn new(url: &str, config: &CeramicConfig) -> Self {
+		let query = format!(
+			"query Documents($first: Int!, $after: String) {{
+				{}(first: $first, after: $after) {{
+					edges {{ cursor node {{ {} }} }}
+					pageInfo {{ hasNextPage }}
+				}}
+			}}",
+			config.ceramic_index, config.ceramic_fields,
+		);
+		Self {
+			client: Client::new(),
+			url: url.to_string(),
+			query,
+			index: config.ceramic_index.clone(),
+			schema_id: config.ceramic_schema_id,
+			timestamp_field: config.ceramic_timestamp_field.clone(),
+			page_size: config.ceramic_page_size,
+			cursor: None,
+		}
+	}
+
+	async fn fetch_page(
+		&self, body: &Value,
+	) -> Result<(Vec<SourceEvent>, Option<String>, bool), IndexerError> {
+		let response: Value = self
+			.client
+			.post(&self.url)
+			.json(body)
+			.send()
+			.await
+			.and_then(|response| response.error_for_status())
+			.map_err(|e| IndexerError::SourceError(e.to_string()))?
+			.json()
+			.await
+			.map_err(|e| IndexerError::SourceError(e.to_string()))?;
+		self.parse_page(&response)
+	}
+
+	/// Normalizes a page of the index into events, returning the cursor of its last document
+	/// and whether more follow.
+	fn parse_page(
+		&self, response: &Value,
+	) -> Result<(Vec<SourceEvent>, Option<String>, bool), IndexerError> {
+		if let Some(errors) = response.get("errors") {
+			return Err(IndexerError::SourceError(format!(
+				"ComposeDB query failed: {}",
+				errors
+			)));
+		}
+		let connection = &response["data"][&self.index];
+		let edges = connection["edges"].as_array().ok_or_else(|| {
+			IndexerError::SourceError(format!("ComposeDB returned no {} edges", self.index))
+		})?;
+
+		let received_at = now_secs();
+		let mut events = Vec::with_capacity(edges.len());
+		let mut cursor = None;
+		for edge in edges {
+			let node = &edge["node"];
+			let timestamp = self
+				.timestamp_field
+				.as_ref()
+				.and_then(|field| node[field].as_u64())
+				.unwrap_or(received_at);
+			events.push(SourceEvent {
+				schema_id: self.schema_id,
+				schema_value: node.to_string(),
+				timestamp,
+				source: "ceramic".to_string(),
+				..SourceEvent::default()
+			});
+			cursor = edge["cursor"].as_str().map(str::to_string);
+		}
+		let has_next_page = connection["pageInfo"]["hasNextPage"].as_bool().unwrap_or(false);
+		Ok((events, cursor, has_next_page))
+	}
+}
+
+#[tonic::async_trait]
+impl Source for CeramicSource {
+	fn name(&self) -> &str {
+		"ceramic"
+	}
+
+	async fn poll(&mut self) -> Result<Vec<SourceEvent>, IndexerError> {
+		let mut events = Vec::new();
+		loop {
+			let body = json!({
+				"query": self.query,
+				"variables": { "first": self.page_size, "after": self.cursor },
+			});
+			let (page, cursor, has_next_page) = match self.fetch_page(&body).await {
+				Ok(page) => page,
+				Err(e) if events.is_empty() => return Err(e),
+				// The pages loaded so far are kept, the failed one is retried on the next poll.
+				Err(e) => {
+					println!("Failed to load a page of {}: {}", self.index, e);
+					return Ok(events);
+				},
+			};
+			events.extend(page);
+			if cursor.is_some() {
+				self.cursor = cursor;
+			}
+			if !has_next_page {
+				return Ok(events);
+			}
+		}
+	}
+
+	/// Cursor of the last document read.
+	fn checkpoint(&self) -> Option<String> {
+		self.cursor.clone()
+	}
+
+	fn restore(&mut self, checkpoint: &str) -> Result<(), IndexerError> {
+		self.cursor = Some(checkpoint.to_string());
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::CeramicSource;
+	use crate::config::CeramicConfig;
+	use serde_json::json;
+
+	#[test]
+	fn should_normalize_documents() {
+		let config = CeramicConfig {
+			ceramic_url: None,
+			ceramic_index: "attestationIndex".to_string(),
+			ceramic_fields: "id issuer subject".to_string(),
+			ceramic_schema_id: 2,
+			ceramic_timestamp_field: Some("issuedAt".to_string()),
+			ceramic_page_size: 100,
+		};
+		let source = CeramicSource::new("http://localhost:7007/graphql", &config);
+		let response = json!({
+			"data": {
+				"attestationIndex": {
+					"edges": [
+						{ "cursor": "a", "node": { "id": "k1", "issuedAt": 1700000000 } },
+						{ "cursor": "b", "node": { "id": "k2" } },
+					],
+					"pageInfo": { "hasNextPage": true },
+				},
+			},
+		});
+
+		let (events, cursor, has_next_page) = source.parse_page(&response).unwrap();
+		assert_eq!(events.len(), 2);
+		assert_eq!(events[0].schema_id, 2);
+		assert_eq!(events[0].timestamp, 1700000000);
+		assert_eq!(events[1].schema_value, r#"{"id":"k2"}"#);
+		assert_eq!(
+			cursor.as_deref(),
+			Some("b"),
+			"should resume after the last document"
+		);
+		assert!(has_next_page);
+
+		let failed = json!({ "errors": [{ "message": "unknown field" }] });
+		assert!(source.parse_page(&failed).is_err());
+	}
+}
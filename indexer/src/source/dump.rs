@@ -0,0 +1,177 @@
+use super::{parse_jsonl, Envelope, SourceEvent};
+use crate::error::IndexerError;
+use flate2::read::MultiGzDecoder;
+use serde_json::Value;
+use std::{io::Read, mem};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// How a dump holds its credential envelopes, told by the extension of its name.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+	/// One JSON envelope per line.
+	Jsonl,
+	/// A header row naming a `schema_id` and a `credential` column, the latter holding JSON,
+	/// and optionally a `timestamp` one.
+	Csv,
+}
+
+impl DumpFormat {
+	/// Format of the dump named `name`, compressed or not.
+	pub fn of(name: &str) -> Option<Self> {
+		let name = [".gz", ".zst"]
+			.iter()
+			.find_map(|extension| name.strip_suffix(extension))
+			.unwrap_or(name);
+		if name.ends_with(".jsonl") || name.ends_with(".ndjson") {
+			Some(Self::Jsonl)
+		} else if name.ends_with(".csv") {
+			Some(Self::Csv)
+		} else {
+			None
+		}
+	}
+
+	/// Parses the events of a dump read from `source`, decompressing it first if need be.
+	pub fn parse(
+		self, data: Vec<u8>, source: &str, received_at: u64,
+	) -> Result<Vec<SourceEvent>, IndexerError> {
+		let data = decompress(data)?;
+		match self {
+			Self::Jsonl => parse_jsonl(&data, source, received_at),
+			Self::Csv => parse_csv(&data, source, received_at),
+		}
+	}
+}
+
+/// Decompresses `data` if gzipped or compressed with zstd, as told by its magic bytes, possibly
+/// as several concatenated members or frames.
+fn decompress(data: Vec<u8>) -> Result<Vec<u8>, IndexerError> {
+	if data.starts_with(&GZIP_MAGIC) {
+		let mut inflated = Vec::new();
+		MultiGzDecoder::new(data.as_slice())
+			.read_to_end(&mut inflated)
+			.map_err(IndexerError::IoError)?;
+		Ok(inflated)
+	} else if data.starts_with(&ZSTD_MAGIC) {
+		zstd::stream::decode_all(data.as_slice()).map_err(IndexerError::IoError)
+	} else {
+		Ok(data)
+	}
+}
+
+/// Parses a CSV dump into events, in the order of its rows.
+fn parse_csv(
+	data: &[u8], source: &str, received_at: u64,
+) -> Result<Vec<SourceEvent>, IndexerError> {
+	let text = std::str::from_utf8(data).map_err(|_| IndexerError::ParseError)?;
+	let mut records = read_records(text)?.into_iter();
+	let Some(header) = records.next() else {
+		return Ok(Vec::new());
+	};
+	let column = |name: &str| header.iter().position(|column| column.trim() == name);
+	let (Some(schema_id), Some(credential)) = (column("schema_id"), column("credential")) else {
+		return Err(IndexerError::SourceError(
+			"CSV dumps need schema_id and credential columns".to_string(),
+		));
+	};
+	let (timestamp, domain) = (column("timestamp"), column("domain"));
+
+	let mut events = Vec::new();
+	for record in records {
+		let field = |index: usize| record.get(index).map_or("", |field| field.trim());
+		let envelope = Envelope {
+			schema_id: field(schema_id).parse().map_err(|_| IndexerError::ParseError)?,
+			credential: serde_json::from_str::<Value>(field(credential))
+				.map_err(|_| IndexerError::ParseError)?,
+			timestamp: match timestamp.map(field).filter(|timestamp| !timestamp.is_empty()) {
+				Some(timestamp) => Some(timestamp.parse().map_err(|_| IndexerError::ParseError)?),
+				None => None,
+			},
+			domain: match domain.map(field).filter(|domain| !domain.is_empty()) {
+				Some(domain) => domain.parse().map_err(|_| IndexerError::ParseError)?,
+				None => 0,
+			},
+		};
+		events.push(envelope.into_event(source, received_at));
+	}
+	Ok(events)
+}
+
+/// Splits RFC 4180 CSV into records of fields, skipping blank lines.
+fn read_records(text: &str) -> Result<Vec<Vec<String>>, IndexerError> {
+	let mut records = Vec::new();
+	let mut record = Vec::new();
+	let mut field = String::new();
+	let mut quoted = false;
+	let mut chars = text.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'"' if quoted => {
+				if chars.peek() == Some(&'"') {
+					chars.next();
+					field.push('"');
+				} else {
+					quoted = false;
+				}
+			},
+			_ if quoted => field.push(c),
+			'"' if field.is_empty() => quoted = true,
+			',' => record.push(mem::take(&mut field)),
+			'\r' => {},
+			'\n' => {
+				record.push(mem::take(&mut field));
+				if record.len() > 1 || !record[0].is_empty() {
+					records.push(mem::take(&mut record));
+				}
+				record.clear();
+			},
+			_ => field.push(c),
+		}
+	}
+	if quoted {
+		return Err(IndexerError::ParseError);
+	}
+	if !record.is_empty() || !field.is_empty() {
+		record.push(field);
+		records.push(record);
+	}
+	Ok(records)
+}
+
+#[cfg(test)]
+mod test {
+	use super::{decompress, parse_csv, DumpFormat};
+	use flate2::{write::GzEncoder, Compression};
+	use std::io::Write;
+
+	#[test]
+	fn should_parse_compressed_dumps() {
+		let csv = "schema_id,timestamp,credential\n\
+			1,5,\"{\"\"id\"\":\"\"a, b\"\"}\"\r\n\
+			\n\
+			2,,{}\n";
+		let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(csv.as_bytes()).unwrap();
+		let data = decompress(encoder.finish().unwrap()).unwrap();
+		assert_eq!(data, csv.as_bytes());
+		let compressed = zstd::stream::encode_all(csv.as_bytes(), 0).unwrap();
+		assert_eq!(decompress(compressed).unwrap(), csv.as_bytes());
+
+		let events = parse_csv(&data, "files", 9).unwrap();
+		assert_eq!(events.len(), 2);
+		assert_eq!((events[0].schema_id, events[0].timestamp), (1, 5));
+		assert_eq!(events[0].schema_value, r#"{"id":"a, b"}"#);
+		assert_eq!(
+			(events[1].schema_id, events[1].timestamp),
+			(2, 9),
+			"should stamp rows without time"
+		);
+		assert!(parse_csv(b"schema_id\n1\n", "files", 9).is_err());
+
+		assert_eq!(DumpFormat::of("dumps/1.jsonl.gz"), Some(DumpFormat::Jsonl));
+		assert_eq!(DumpFormat::of("dumps/1.csv.zst"), Some(DumpFormat::Csv));
+		assert_eq!(DumpFormat::of("dumps/"), None);
+	}
+}
@@ -0,0 +1,513 @@
+use super::{Source, SourceEvent};
+use crate::{config::EasConfig, error::IndexerError};
+use futures::future::join_all;
+use reqwest::Client;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha3::{Digest, Keccak256};
+use std::collections::BTreeMap;
+
+const ATTESTED_EVENT: &str = "Attested(address,address,bytes32,bytes32)";
+const REVOKED_EVENT: &str = "Revoked(address,address,bytes32,bytes32)";
+const GET_ATTESTATION: &str = "getAttestation(bytes32)";
+const WORD: usize = 32;
+
+/// An attestation as returned by `getAttestation`, in the order of its ABI encoding.
+#[derive(Debug, PartialEq)]
+struct Attestation {
+	uid: [u8; 32],
+	schema: [u8; 32],
+	time: u64,
+	expiration_time: u64,
+	revocation_time: u64,
+	ref_uid: [u8; 32],
+	recipient: [u8; 20],
+	attester: [u8; 20],
+	revocable: bool,
+	data: Vec<u8>,
+}
+
+impl Attestation {
+	/// Decodes the return data of `getAttestation`, a single tuple holding dynamic `bytes`.
+	fn decode(output: &[u8]) -> Result<Self, IndexerError> {
+		let tuple = output.get(WORD..).ok_or(IndexerError::ParseError)?;
+		let word = |i: usize| tuple.get(i * WORD..(i + 1) * WORD).ok_or(IndexerError::ParseError);
+		let uint = |i: usize| -> Result<u64, IndexerError> {
+			let bytes = word(i)?;
+			Ok(u64::from_be_bytes(bytes[WORD - 8..].try_into().unwrap()))
+		};
+		let address = |i: usize| -> Result<[u8; 20], IndexerError> {
+			Ok(word(i)?[WORD - 20..].try_into().unwrap())
+		};
+
+		let data_offset = uint(9)? as usize;
+		let data_len = tuple
+			.get(data_offset..data_offset + WORD)
+			.map(|len| u64::from_be_bytes(len[WORD - 8..].try_into().unwrap()) as usize)
+			.ok_or(IndexerError::ParseError)?;
+		let data_start = data_offset + WORD;
+		let data = tuple.get(data_start..data_start + data_len).ok_or(IndexerError::ParseError)?;
+
+		Ok(Self {
+			uid: word(0)?.try_into().unwrap(),
+			schema: word(1)?.try_into().unwrap(),
+			time: uint(2)?,
+			expiration_time: uint(3)?,
+			revocation_time: uint(4)?,
+			ref_uid: word(5)?.try_into().unwrap(),
+			recipient: address(6)?,
+			attester: address(7)?,
+			revocable: uint(8)? != 0,
+			data: data.to_vec(),
+		})
+	}
+
+	/// Schema value of the indexer event, revocations flagged as `revoked`. Events retracting
+	/// it after a reorganization are flagged as `orphaned`.
+	fn to_json(&self, revoked: bool) -> Value {
+		json!({
+			"uid": hex_string(&self.uid),
+			"schema": hex_string(&self.schema),
+			"time": self.time,
+			"expirationTime": self.expiration_time,
+			"revocationTime": self.revocation_time,
+			"refUID": hex_string(&self.ref_uid),
+			"recipient": hex_string(&self.recipient),
+			"attester": hex_string(&self.attester),
+			"revocable": self.revocable,
+			"data": hex_string(&self.data),
+			"revoked": revoked,
+			"orphaned": false,
+		})
+	}
+}
+
+/// Block processed recently enough to be checked for reorganizations.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ProcessedBlock {
+	hash: String,
+	/// Schema values and timestamps of the events read from the block.
+	events: Vec<(String, u64)>,
+}
+
+/// Progress of the source, as saved in checkpoints.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Progress {
+	/// First block not processed yet.
+	next_block: u64,
+	/// Processed blocks within the reorg window that had events, and the last one processed,
+	/// by number.
+	blocks: BTreeMap<u64, ProcessedBlock>,
+}
+
+/// Follows the `Attested` and `Revoked` events of one schema on an EAS contract, block range by
+/// block range.
+///
+/// Blocks are only processed once `confirmations` deep, and kept for `reorg_window` more blocks
+/// to be checked against the chain. Should a reorganization still orphan some, their events are
+/// retracted and the blocks processed again.
+pub struct EasSource {
+	/// `eas`, or `eas-<chain>` when several chains are followed.
+	name: String,
+	client: Client,
+	rpc_url: String,
+	contract: String,
+	schema_uid: String,
+	schema_id: u32,
+	block_range: u64,
+	/// Block ranges fetched at once while behind.
+	parallel_ranges: usize,
+	confirmations: u64,
+	reorg_window: u64,
+	progress: Progress,
+	/// Latest block as of the last poll.
+	head: Option<u64>,
+}
+
+impl EasSource {
+	pub fn new(name: String, rpc_url: &str, config: &EasConfig) -> Self {
+		Self {
+			name,
+			client: Client::new(),
+			rpc_url: rpc_url.to_string(),
+			contract: config.eas_contract.clone(),
+			schema_uid: config.eas_schema_uid.clone(),
+			schema_id: config.eas_schema_id,
+			block_range: config.eas_block_range.max(1),
+			parallel_ranges: config.eas_parallel_ranges.max(1),
+			confirmations: config.eas_confirmations,
+			reorg_window: config.eas_reorg_window,
+			progress: Progress { next_block: config.eas_start_block, blocks: BTreeMap::new() },
+			head: None,
+		}
+	}
+
+	async fn call(&self, method: &str, params: Value) -> Result<Value, IndexerError> {
+		let body = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+		let mut response: Value = self
+			.client
+			.post(&self.rpc_url)
+			.json(&body)
+			.send()
+			.await
+			.and_then(|response| response.error_for_status())
+			.map_err(|e| IndexerError::SourceError(e.to_string()))?
+			.json()
+			.await
+			.map_err(|e| IndexerError::SourceError(e.to_string()))?;
+		if let Some(error) = response.get("error") {
+			return Err(IndexerError::SourceError(format!(
+				"{} failed: {}",
+				method, error
+			)));
+		}
+		Ok(response["result"].take())
+	}
+
+	async fn block_number(&self) -> Result<u64, IndexerError> {
+		let result = self.call("eth_blockNumber", json!([])).await?;
+		parse_quantity(&result)
+	}
+
+	async fn block_hash(&self, number: u64) -> Result<String, IndexerError> {
+		let block = self
+			.call(
+				"eth_getBlockByNumber",
+				json!([format!("{:#x}", number), false]),
+			)
+			.await?;
+		block["hash"].as_str().map(str::to_string).ok_or(IndexerError::ParseError)
+	}
+
+	/// Attestation `uid` as of block `number`, so later revocations don't show through.
+	async fn get_attestation(&self, uid: &str, number: u64) -> Result<Attestation, IndexerError> {
+		let input = format!(
+			"{}{}",
+			hex_string(&selector(GET_ATTESTATION)),
+			uid.trim_start_matches("0x")
+		);
+		let result = self
+			.call(
+				"eth_call",
+				json!([{ "to": self.contract, "data": input }, format!("{:#x}", number)]),
+			)
+			.await?;
+		Attestation::decode(&parse_bytes(&result)?)
+	}
+
+	/// Converts the logs of a block range into events, in the order they were emitted, along
+	/// with the number and hash of their block.
+	async fn fetch_range(
+		&self, from: u64, to: u64,
+	) -> Result<Vec<(u64, String, SourceEvent)>, IndexerError> {
+		let attested = event_topic(ATTESTED_EVENT);
+		let revoked = event_topic(REVOKED_EVENT);
+		let filter = json!({
+			"fromBlock": format!("{:#x}", from),
+			"toBlock": format!("{:#x}", to),
+			"address": self.contract,
+			"topics": [[attested, revoked], null, null, self.schema_uid],
+		});
+		let logs = self.call("eth_getLogs", json!([filter])).await?;
+		let logs = logs.as_array().ok_or(IndexerError::ParseError)?;
+
+		let mut events = Vec::with_capacity(logs.len());
+		for log in logs {
+			let is_revocation = log["topics"][0].as_str() == Some(revoked.as_str());
+			// The only non-indexed parameter of both events is the attestation UID.
+			let uid = log["data"].as_str().ok_or(IndexerError::ParseError)?;
+			let number = parse_quantity(&log["blockNumber"])?;
+			let attestation = self.get_attestation(uid, number).await?;
+			let timestamp =
+				if is_revocation { attestation.revocation_time } else { attestation.time };
+			let event = SourceEvent {
+				schema_id: self.schema_id,
+				schema_value: attestation.to_json(is_revocation).to_string(),
+				timestamp,
+				source: self.name.clone(),
+				..SourceEvent::default()
+			};
+			let hash = log["blockHash"].as_str().ok_or(IndexerError::ParseError)?;
+			events.push((number, hash.to_string(), event));
+		}
+		Ok(events)
+	}
+
+	/// Retracts the events of the processed blocks a reorganization orphaned, rewinding to
+	/// process the blocks after the fork again.
+	async fn retract_orphans(&mut self) -> Result<Vec<SourceEvent>, IndexerError> {
+		let mut orphaned = Vec::new();
+		let mut fork = None;
+		// A block still on the chain vouches for its ancestors, so checking stops there.
+		for (&number, block) in self.progress.blocks.iter().rev() {
+			if self.block_hash(number).await? == block.hash {
+				fork = Some(number + 1);
+				break;
+			}
+			orphaned.push(number);
+		}
+		let Some(&oldest) = orphaned.last() else {
+			return Ok(Vec::new());
+		};
+		println!(
+			"Blocks from {} of {} were reorganized",
+			fork.unwrap_or(oldest),
+			self.name
+		);
+
+		let mut retractions = Vec::new();
+		for number in orphaned.into_iter().rev() {
+			let block = self.progress.blocks.remove(&number).unwrap();
+			for (value, timestamp) in block.events {
+				retractions.push(self.retraction(&value, timestamp)?);
+			}
+		}
+		self.progress.next_block = self.progress.next_block.min(fork.unwrap_or(oldest));
+		Ok(retractions)
+	}
+
+	/// Event withdrawing the one with schema value `value`.
+	fn retraction(&self, value: &str, timestamp: u64) -> Result<SourceEvent, IndexerError> {
+		let mut value: Value = serde_json::from_str(value).map_err(|_| IndexerError::ParseError)?;
+		value["orphaned"] = json!(true);
+		Ok(SourceEvent {
+			schema_id: self.schema_id,
+			schema_value: value.to_string(),
+			timestamp,
+			source: self.name.clone(),
+			..SourceEvent::default()
+		})
+	}
+
+	/// Remembers the events of a processed block, to retract them should it be orphaned.
+	fn record(&mut self, number: u64, hash: String, event: &SourceEvent) {
+		let block = self
+			.progress
+			.blocks
+			.entry(number)
+			.or_insert_with(|| ProcessedBlock { hash, events: Vec::new() });
+		block.events.push((event.schema_value.clone(), event.timestamp));
+	}
+}
+
+#[tonic::async_trait]
+impl Source for EasSource {
+	fn name(&self) -> &str {
+		&self.name
+	}
+
+	async fn poll(&mut self) -> Result<Vec<SourceEvent>, IndexerError> {
+		let head = self.block_number().await?;
+		self.head = Some(head);
+		let mut events = self.retract_orphans().await?;
+		let confirmed = head.saturating_sub(self.confirmations);
+		let first_block = self.progress.next_block;
+		// Backfills fetch several ranges at once, merged back in block order. Only one round
+		// of them is fetched per poll, so its events are stored and checkpointed before the
+		// following polls fetch the rest.
+		let ranges = split_ranges(
+			self.progress.next_block, confirmed, self.block_range, self.parallel_ranges,
+		);
+		let fetched = join_all(ranges.iter().map(|&(from, to)| self.fetch_range(from, to))).await;
+		for ((from, to), range) in ranges.into_iter().zip(fetched) {
+			match range {
+				Ok(range) => {
+					for (number, hash, event) in range {
+						self.record(number, hash, &event);
+						events.push(event);
+					}
+				},
+				Err(e) if events.is_empty() => return Err(e),
+				// The ranges loaded before are kept, the failed one and those after it are
+				// retried on the next poll.
+				Err(e) => {
+					println!("Failed to load blocks {}-{} from EAS: {}", from, to, e);
+					break;
+				},
+			}
+			self.progress.next_block = to + 1;
+		}
+
+		// The last block processed is checked too, as it may be orphaned without having events.
+		let last = self.progress.next_block.checked_sub(1);
+		if let Some(last) = last.filter(|&last| last >= first_block) {
+			if !self.progress.blocks.contains_key(&last) {
+				match self.block_hash(last).await {
+					Ok(hash) => {
+						let block = ProcessedBlock { hash, events: Vec::new() };
+						self.progress.blocks.insert(last, block);
+					},
+					Err(e) => println!("Failed to load block {} from EAS: {}", last, e),
+				}
+			}
+		}
+		let window_start = self.progress.next_block.saturating_sub(self.reorg_window);
+		self.progress.blocks = self.progress.blocks.split_off(&window_start);
+		Ok(events)
+	}
+
+	/// Confirmed blocks not processed yet, left over when a range failed to load.
+	fn lag(&self) -> Option<u64> {
+		let confirmed = self.head?.saturating_sub(self.confirmations);
+		Some((confirmed + 1).saturating_sub(self.progress.next_block))
+	}
+
+	/// JSON of the first block not processed yet and the blocks within the reorg window.
+	fn checkpoint(&self) -> Option<String> {
+		serde_json::to_string(&self.progress).ok()
+	}
+
+	fn restore(&mut self, checkpoint: &str) -> Result<(), IndexerError> {
+		self.progress = serde_json::from_str(checkpoint).map_err(|_| IndexerError::ParseError)?;
+		Ok(())
+	}
+}
+
+/// Up to `count` consecutive ranges of `size` blocks from `from`, the last one ending by `to`.
+fn split_ranges(from: u64, to: u64, size: u64, count: usize) -> Vec<(u64, u64)> {
+	let mut ranges = Vec::new();
+	let mut start = from;
+	while start <= to && ranges.len() < count {
+		let end = to.min(start + size - 1);
+		ranges.push((start, end));
+		start = end + 1;
+	}
+	ranges
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+	format!("0x{}", hex::encode(bytes))
+}
+
+fn selector(signature: &str) -> [u8; 4] {
+	Keccak256::digest(signature.as_bytes())[..4].try_into().unwrap()
+}
+
+fn event_topic(signature: &str) -> String {
+	hex_string(&Keccak256::digest(signature.as_bytes()))
+}
+
+fn parse_quantity(value: &Value) -> Result<u64, IndexerError> {
+	let quantity = value.as_str().ok_or(IndexerError::ParseError)?;
+	u64::from_str_radix(quantity.trim_start_matches("0x"), 16).map_err(|_| IndexerError::ParseError)
+}
+
+fn parse_bytes(value: &Value) -> Result<Vec<u8>, IndexerError> {
+	let bytes = value.as_str().ok_or(IndexerError::ParseError)?;
+	hex::decode(bytes.trim_start_matches("0x")).map_err(|_| IndexerError::ParseError)
+}
+
+#[cfg(test)]
+mod test {
+	use super::{
+		event_topic, parse_quantity, split_ranges, Attestation, EasSource, ATTESTED_EVENT, WORD,
+	};
+	use crate::{
+		config::EasConfig,
+		schemas::{SchemaRegistry, EAS_SCHEMA_ID},
+		source::Source,
+	};
+	use serde_json::{json, Value};
+
+	fn uint_word(value: u64) -> Vec<u8> {
+		let mut word = vec![0; WORD];
+		word[WORD - 8..].copy_from_slice(&value.to_be_bytes());
+		word
+	}
+
+	#[test]
+	fn should_decode_attestation() {
+		let data = b"follow".to_vec();
+		let mut address = vec![0; WORD];
+		address[WORD - 20..].copy_from_slice(&[0xaa; 20]);
+		let output = [
+			uint_word(0x20),
+			vec![1; WORD],
+			vec![2; WORD],
+			uint_word(1700000000),
+			uint_word(0),
+			uint_word(1700000100),
+			vec![0; WORD],
+			address.clone(),
+			address,
+			uint_word(1),
+			uint_word(10 * WORD as u64),
+			uint_word(data.len() as u64),
+			[data.clone(), vec![0; WORD - data.len()]].concat(),
+		]
+		.concat();
+
+		let attestation = Attestation::decode(&output).unwrap();
+		assert_eq!(attestation.uid, [1; 32]);
+		assert_eq!(attestation.time, 1700000000);
+		assert_eq!(attestation.revocation_time, 1700000100);
+		assert_eq!(attestation.attester, [0xaa; 20]);
+		assert!(attestation.revocable);
+		assert_eq!(attestation.data, data);
+		assert!(
+			Attestation::decode(&output[..10 * WORD]).is_err(),
+			"should reject truncated data"
+		);
+
+		let value = attestation.to_json(true);
+		assert_eq!(value["revoked"], json!(true));
+		assert_eq!(value["data"], json!("0x666f6c6c6f77"));
+		let registry = SchemaRegistry::default();
+		assert_eq!(registry.validate(EAS_SCHEMA_ID, &value), Ok(()));
+	}
+
+	#[test]
+	fn should_encode_rpc_values() {
+		assert_eq!(
+			event_topic(ATTESTED_EVENT),
+			"0x8bf46bf4cfd674fa735a3d63ec1c9ad4153f033c290341f3a588b75685141b35"
+		);
+		assert_eq!(parse_quantity(&json!("0x1b4")).unwrap(), 436);
+	}
+
+	#[test]
+	fn should_split_backfills_into_ranges() {
+		assert_eq!(split_ranges(10, 35, 10, 4), [(10, 19), (20, 29), (30, 35)]);
+		assert_eq!(
+			split_ranges(0, 99, 10, 2),
+			[(0, 9), (10, 19)],
+			"should stop at the parallelism"
+		);
+		assert!(split_ranges(5, 4, 10, 4).is_empty());
+	}
+
+	#[test]
+	fn should_checkpoint_blocks_to_retract() {
+		let config = EasConfig {
+			eas_rpc_url: None,
+			eas_chains: Vec::new(),
+			eas_contract: "0xA1207F3BBa224E2c9c3c6D5aF63D0eb1582Ce587".to_string(),
+			eas_schema_uid: "0x01".to_string(),
+			eas_schema_id: EAS_SCHEMA_ID,
+			eas_start_block: 0,
+			eas_block_range: 2000,
+			eas_parallel_ranges: 4,
+			eas_confirmations: 12,
+			eas_reorg_window: 128,
+		};
+		let mut source = EasSource::new("eas".to_string(), "http://localhost:8545", &config);
+		source.restore(r#"{"next_block":120,"blocks":{}}"#).unwrap();
+		assert_eq!(source.progress.next_block, 120);
+		assert!(source.restore("120").is_err());
+
+		let event = source.retraction(r#"{"uid":"0x01","orphaned":false}"#, 7).unwrap();
+		let value: Value = serde_json::from_str(&event.schema_value).unwrap();
+		assert_eq!(value["orphaned"], json!(true));
+		assert_eq!(value["uid"], json!("0x01"));
+		assert_eq!(event.timestamp, 7);
+
+		source.record(130, "0xaa".to_string(), &event);
+		let checkpoint = source.checkpoint().unwrap();
+		let mut restored = EasSource::new("eas".to_string(), "http://localhost:8545", &config);
+		restored.restore(&checkpoint).unwrap();
+		assert_eq!(
+			restored.progress, source.progress,
+			"should keep the blocks to check for reorganizations"
+		);
+	}
+}
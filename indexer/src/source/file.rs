@@ -0,0 +1,174 @@
+use super::{dump::DumpFormat, now_secs, Source, SourceEvent};
+use crate::error::IndexerError;
+use std::{
+	collections::VecDeque,
+	fs,
+	path::{Path, PathBuf},
+};
+
+/// Ingests the credential dumps of a directory, one file per poll in order of name, and keeps
+/// picking up new ones as long as they sort after those loaded. Dumps should be moved into
+/// the directory once written, not written in place.
+pub struct FileSource {
+	dir: PathBuf,
+	/// Pattern the names of the files must match, `*` standing for any characters and `?` for
+	/// one.
+	pattern: String,
+	/// Names of the files found after the last one loaded, in the order they are loaded.
+	pending: VecDeque<String>,
+	/// Name of the last file loaded.
+	loaded: Option<String>,
+}
+
+impl FileSource {
+	/// Follows the dumps in `path` if a directory, else those its last component matches if a
+	/// glob pattern, else the single file it names.
+	pub fn new(path: &Path) -> Self {
+		let (dir, pattern) = match (path.is_dir(), path.file_name()) {
+			(false, Some(name)) => {
+				let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+				(dir, name.to_string_lossy().into_owned())
+			},
+			_ => (path.to_path_buf(), "*".to_string()),
+		};
+		let dir = if dir.as_os_str().is_empty() { PathBuf::from(".") } else { dir };
+		Self { dir, pattern, pending: VecDeque::new(), loaded: None }
+	}
+
+	/// Names of the dumps after the last one loaded, sorted.
+	fn list(&self) -> Result<Vec<String>, IndexerError> {
+		let mut names = Vec::new();
+		for entry in fs::read_dir(&self.dir).map_err(IndexerError::IoError)? {
+			let entry = entry.map_err(IndexerError::IoError)?;
+			let Ok(name) = entry.file_name().into_string() else {
+				continue;
+			};
+			let is_new = self.loaded.as_ref().map_or(true, |loaded| name > *loaded);
+			if is_new
+				&& glob_match(&self.pattern, &name)
+				&& DumpFormat::of(&name).is_some()
+				&& entry.file_type().map_err(IndexerError::IoError)?.is_file()
+			{
+				names.push(name);
+			}
+		}
+		names.sort_unstable();
+		Ok(names)
+	}
+
+	fn load(&self, name: &str) -> Result<Vec<SourceEvent>, IndexerError> {
+		let format = DumpFormat::of(name).ok_or(IndexerError::ParseError)?;
+		let data = fs::read(self.dir.join(name)).map_err(IndexerError::IoError)?;
+		format.parse(data, self.name(), now_secs())
+	}
+}
+
+#[tonic::async_trait]
+impl Source for FileSource {
+	fn name(&self) -> &str {
+		"files"
+	}
+
+	async fn poll(&mut self) -> Result<Vec<SourceEvent>, IndexerError> {
+		if self.pending.is_empty() {
+			self.pending = self.list()?.into();
+		}
+		let Some(name) = self.pending.front() else {
+			return Ok(Vec::new());
+		};
+		// Files are loaded one per poll, and retried until they load.
+		let events = self.load(name)?;
+		self.loaded = self.pending.pop_front();
+		Ok(events)
+	}
+
+	/// Files left to load.
+	fn lag(&self) -> Option<u64> {
+		Some(self.pending.len() as u64)
+	}
+
+	/// Name of the last file loaded.
+	fn checkpoint(&self) -> Option<String> {
+		self.loaded.clone()
+	}
+
+	fn restore(&mut self, checkpoint: &str) -> Result<(), IndexerError> {
+		self.loaded = Some(checkpoint.to_string());
+		self.pending.clear();
+		Ok(())
+	}
+}
+
+/// Whether `name` matches `pattern`, where `*` stands for any characters and `?` for one.
+fn glob_match(pattern: &str, name: &str) -> bool {
+	let pattern: Vec<char> = pattern.chars().collect();
+	let name: Vec<char> = name.chars().collect();
+	let (mut p, mut n) = (0, 0);
+	// Where to resume from should the characters matched by the last `*` fall short.
+	let mut backtrack = None;
+	while n < name.len() {
+		match pattern.get(p) {
+			Some(&'*') => {
+				backtrack = Some((p + 1, n));
+				p += 1;
+			},
+			Some(&c) if c == '?' || c == name[n] => {
+				p += 1;
+				n += 1;
+			},
+			_ => {
+				let Some((after_star, matched)) = backtrack else {
+					return false;
+				};
+				backtrack = Some((after_star, matched + 1));
+				p = after_star;
+				n = matched + 1;
+			},
+		}
+	}
+	pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod test {
+	use super::{glob_match, FileSource};
+	use crate::source::Source;
+	use std::{env, fs};
+
+	#[test]
+	fn should_match_globs() {
+		assert!(glob_match("*.csv", "2024-01.csv"));
+		assert!(glob_match("dump-??.*", "dump-01.jsonl.gz"));
+		assert!(glob_match("*-*.csv", "a-b-c.csv"));
+		assert!(!glob_match("*.csv", "2024-01.csv.bak"));
+		assert!(!glob_match("dump-?.csv", "dump-01.csv"));
+	}
+
+	#[tokio::test]
+	async fn should_load_new_files_in_order() {
+		let dir = env::temp_dir().join(format!("indexer-files-{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		let dump =
+			|schema_id: u32| format!("{{\"schema_id\":{},\"credential\":{{}}}}\n", schema_id);
+		fs::write(dir.join("b.jsonl"), dump(2)).unwrap();
+		fs::write(dir.join("a.jsonl"), dump(1)).unwrap();
+		fs::write(dir.join("a.txt"), "not a dump").unwrap();
+
+		let mut source = FileSource::new(&dir.join("*.jsonl"));
+		assert_eq!(source.poll().await.unwrap()[0].schema_id, 1);
+		assert_eq!(source.lag(), Some(1));
+		assert_eq!(source.poll().await.unwrap()[0].schema_id, 2);
+		assert!(source.poll().await.unwrap().is_empty());
+
+		fs::write(dir.join("c.jsonl"), dump(3)).unwrap();
+		let mut restored = FileSource::new(&dir);
+		restored.restore(&source.checkpoint().unwrap()).unwrap();
+		let events = restored.poll().await.unwrap();
+		assert_eq!(
+			events[0].schema_id, 3,
+			"should pick up files added after the checkpoint"
+		);
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,214 @@
+use super::{dump::DumpFormat, now_secs, Source, SourceEvent};
+use crate::error::IndexerError;
+use reqwest::{
+	header::{
+		HeaderMap, HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED,
+		RANGE,
+	},
+	Client, StatusCode, Url,
+};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// Version of a dump, as told by the validators of the response serving it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Version {
+	etag: Option<String>,
+	last_modified: Option<String>,
+}
+
+impl Version {
+	fn of(headers: &HeaderMap) -> Self {
+		let header = |name: HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+		Self {
+			etag: header(ETAG).map(str::to_string),
+			last_modified: header(LAST_MODIFIED).map(str::to_string),
+		}
+	}
+
+	/// Validator a ranged request may resume a download of this version with. Weak ETags
+	/// don't tell whether bytes are the same, so only strong ones will do.
+	fn range_validator(&self) -> Option<&str> {
+		self.etag
+			.as_deref()
+			.filter(|etag| !etag.starts_with("W/"))
+			.or(self.last_modified.as_deref())
+	}
+}
+
+/// A download cut short, resumed from where it stopped unless the dump changed meanwhile.
+struct Partial {
+	version: Version,
+	data: Vec<u8>,
+}
+
+/// Pulls credential dumps served over HTTP(S), loading each again only once it changed, as
+/// told by conditional requests. Every version of a dump is ingested whole.
+pub struct HttpSource {
+	client: Client,
+	urls: Vec<String>,
+	/// Version of the dump loaded last, by URL.
+	loaded: BTreeMap<String, Version>,
+	/// Downloads to resume, by URL.
+	partial: HashMap<String, Partial>,
+}
+
+impl HttpSource {
+	pub fn new(urls: &[String]) -> Self {
+		Self {
+			client: Client::new(),
+			urls: urls.to_vec(),
+			loaded: BTreeMap::new(),
+			partial: HashMap::new(),
+		}
+	}
+
+	/// Downloads the dump at `url` unless unchanged since loaded last, resuming the download
+	/// cut short last if any.
+	async fn download(&mut self, url: &str) -> Result<Option<Vec<u8>>, IndexerError> {
+		let mut request = self.client.get(url);
+		if let Some(loaded) = self.loaded.get(url) {
+			if let Some(etag) = &loaded.etag {
+				request = request.header(IF_NONE_MATCH, etag);
+			}
+			if let Some(last_modified) = &loaded.last_modified {
+				request = request.header(IF_MODIFIED_SINCE, last_modified);
+			}
+		}
+		let partial = self.partial.remove(url);
+		if let Some(partial) = &partial {
+			if let Some(validator) = partial.version.range_validator() {
+				request = request
+					.header(RANGE, format!("bytes={}-", partial.data.len()))
+					.header(IF_RANGE, validator);
+			}
+		}
+		let mut response = request
+			.send()
+			.await
+			.and_then(|response| response.error_for_status())
+			.map_err(|e| IndexerError::SourceError(e.to_string()))?;
+		if response.status() == StatusCode::NOT_MODIFIED {
+			return Ok(None);
+		}
+
+		let version = Version::of(response.headers());
+		// Servers answer in full when the dump changed since the download was cut short.
+		let mut data = match partial {
+			Some(partial) if response.status() == StatusCode::PARTIAL_CONTENT => partial.data,
+			_ => Vec::new(),
+		};
+		loop {
+			match response.chunk().await {
+				Ok(Some(chunk)) => data.extend_from_slice(&chunk),
+				Ok(None) => break,
+				Err(e) => {
+					if version.range_validator().is_some() {
+						self.partial.insert(url.to_string(), Partial { version, data });
+					}
+					return Err(IndexerError::SourceError(e.to_string()));
+				},
+			}
+		}
+		self.loaded.insert(url.to_string(), version);
+		Ok(Some(data))
+	}
+}
+
+#[tonic::async_trait]
+impl Source for HttpSource {
+	fn name(&self) -> &str {
+		"http"
+	}
+
+	async fn poll(&mut self) -> Result<Vec<SourceEvent>, IndexerError> {
+		let mut events = Vec::new();
+		let mut error = None;
+		for url in self.urls.clone() {
+			let dump = match self.download(&url).await {
+				Ok(Some(dump)) => dump,
+				Ok(None) => continue,
+				Err(e) => {
+					println!("Failed to load {}: {}", url, e);
+					error = Some(e);
+					continue;
+				},
+			};
+			// Dumps are told apart by the extension of their path, JSON lines by default.
+			let path = Url::parse(&url).map(|url| url.path().to_string()).unwrap_or_default();
+			let format = DumpFormat::of(&path).unwrap_or(DumpFormat::Jsonl);
+			events.extend(format.parse(dump, self.name(), now_secs())?);
+		}
+		match error {
+			Some(e) if events.is_empty() => Err(e),
+			_ => Ok(events),
+		}
+	}
+
+	/// JSON of the versions of the dumps loaded, by URL.
+	fn checkpoint(&self) -> Option<String> {
+		serde_json::to_string(&self.loaded).ok()
+	}
+
+	fn restore(&mut self, checkpoint: &str) -> Result<(), IndexerError> {
+		self.loaded = serde_json::from_str(checkpoint).map_err(|_| IndexerError::ParseError)?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{HttpSource, Version};
+	use crate::source::Source;
+	use hyper::{
+		header::{ETAG, IF_NONE_MATCH},
+		service::{make_service_fn, service_fn},
+		Body, Request, Response, StatusCode,
+	};
+	use std::convert::Infallible;
+
+	#[test]
+	fn should_resume_only_with_strong_validators() {
+		let version = Version {
+			etag: Some("W/\"1\"".to_string()),
+			last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+		};
+		assert_eq!(
+			version.range_validator(),
+			Some("Wed, 21 Oct 2015 07:28:00 GMT")
+		);
+		let version = Version { etag: Some("\"1\"".to_string()), ..version };
+		assert_eq!(version.range_validator(), Some("\"1\""));
+		assert_eq!(Version::default().range_validator(), None);
+	}
+
+	#[tokio::test]
+	async fn should_skip_unchanged_dumps() {
+		let make_service = make_service_fn(|_| async {
+			Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+				let mut response = Response::new(Body::empty());
+				if request.headers().get(IF_NONE_MATCH).map_or(false, |etag| etag == "\"1\"") {
+					*response.status_mut() = StatusCode::NOT_MODIFIED;
+				} else {
+					*response.body_mut() = r#"{ "schema_id": 1, "credential": {} }"#.into();
+					response.headers_mut().insert(ETAG, "\"1\"".parse().unwrap());
+				}
+				Ok::<_, Infallible>(response)
+			}))
+		});
+		let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+		let url = format!("http://{}/dump.jsonl", server.local_addr());
+		tokio::spawn(server);
+
+		let mut source = HttpSource::new(&[url]);
+		assert_eq!(source.poll().await.unwrap().len(), 1);
+		assert!(
+			source.poll().await.unwrap().is_empty(),
+			"should not load the same version again"
+		);
+
+		let mut restored = HttpSource::new(&source.urls);
+		restored.restore(&source.checkpoint().unwrap()).unwrap();
+		assert!(restored.poll().await.unwrap().is_empty());
+	}
+}
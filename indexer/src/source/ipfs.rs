@@ -0,0 +1,435 @@
+use super::{now_secs, parse_jsonl, Source, SourceEvent};
+use crate::{
+	config::{ArchiveFormat, IpfsConfig},
+	error::IndexerError,
+};
+use reqwest::Client;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+const RAW_CODEC: u64 = 0x55;
+const DAG_PB_CODEC: u64 = 0x70;
+const SHA2_256: u64 = 0x12;
+
+/// A version 1 content identifier, the hash of a block along with how to decode it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Cid {
+	codec: u64,
+	hash_code: u64,
+	digest: Vec<u8>,
+}
+
+impl Cid {
+	/// Parses the base32 text form, the default of CIDv1 strings (`bafy...`).
+	fn parse(text: &str) -> Result<Self, IndexerError> {
+		let encoded = text.strip_prefix('b').ok_or_else(|| {
+			IndexerError::SourceError(format!("Unsupported CID {}, only base32 CIDv1 is", text))
+		})?;
+		let bytes = decode_base32(encoded).ok_or(IndexerError::ParseError)?;
+		let mut cursor = bytes.as_slice();
+		let cid = Self::read(&mut cursor)?;
+		if !cursor.is_empty() {
+			return Err(IndexerError::ParseError);
+		}
+		Ok(cid)
+	}
+
+	/// Reads the binary form off the front of `bytes`.
+	fn read(bytes: &mut &[u8]) -> Result<Self, IndexerError> {
+		if read_varint(bytes)? != 1 {
+			return Err(IndexerError::SourceError(
+				"Only CIDv1 is supported".to_string(),
+			));
+		}
+		let codec = read_varint(bytes)?;
+		let hash_code = read_varint(bytes)?;
+		let len = read_varint(bytes)? as usize;
+		let digest = take(bytes, len)?.to_vec();
+		Ok(Self { codec, hash_code, digest })
+	}
+
+	/// Checks that `data` is the block this CID addresses.
+	fn verify(&self, data: &[u8]) -> Result<(), IndexerError> {
+		if self.hash_code != SHA2_256 {
+			let reason = format!("Unsupported multihash {:#x}", self.hash_code);
+			return Err(IndexerError::SourceError(reason));
+		}
+		if Sha256::digest(data).as_slice() != self.digest.as_slice() {
+			return Err(IndexerError::SourceError(
+				"Content hash mismatch".to_string(),
+			));
+		}
+		Ok(())
+	}
+}
+
+/// Backfills attestation archives addressed by CID from an IPFS gateway, verifying every
+/// block against its hash. Each archive is loaded once.
+pub struct IpfsSource {
+	client: Client,
+	gateway: String,
+	format: ArchiveFormat,
+	/// Archives not loaded yet, in the order they are loaded.
+	pending: VecDeque<String>,
+	/// Last archive loaded.
+	loaded: Option<String>,
+}
+
+impl IpfsSource {
+	pub fn new(gateway: &str, config: &IpfsConfig) -> Self {
+		Self {
+			client: Client::new(),
+			gateway: gateway.trim_end_matches('/').to_string(),
+			format: config.ipfs_format,
+			pending: config.ipfs_cids.iter().cloned().collect(),
+			loaded: None,
+		}
+	}
+
+	async fn fetch(&self, cid: &str, format: &str) -> Result<Vec<u8>, IndexerError> {
+		let url = format!("{}/ipfs/{}?format={}", self.gateway, cid, format);
+		let response = self
+			.client
+			.get(url)
+			.send()
+			.await
+			.and_then(|response| response.error_for_status())
+			.map_err(|e| IndexerError::SourceError(e.to_string()))?;
+		let body = response.bytes().await.map_err(|e| IndexerError::SourceError(e.to_string()))?;
+		Ok(body.to_vec())
+	}
+
+	async fn load(&self, text: &str) -> Result<Vec<SourceEvent>, IndexerError> {
+		let cid = Cid::parse(text)?;
+		let blocks = match self.format {
+			ArchiveFormat::Jsonl => {
+				let data = self.fetch(text, "raw").await?;
+				cid.verify(&data)?;
+				vec![data]
+			},
+			ArchiveFormat::Car => read_car(&cid, &self.fetch(text, "car").await?)?,
+		};
+		let received_at = now_secs();
+		let mut events = Vec::new();
+		for block in blocks {
+			events.extend(parse_jsonl(&block, self.name(), received_at)?);
+		}
+		Ok(events)
+	}
+}
+
+#[tonic::async_trait]
+impl Source for IpfsSource {
+	fn name(&self) -> &str {
+		"ipfs"
+	}
+
+	async fn poll(&mut self) -> Result<Vec<SourceEvent>, IndexerError> {
+		let Some(cid) = self.pending.front() else {
+			return Ok(Vec::new());
+		};
+		// Archives are loaded one per poll, and retried until they load.
+		let events = self.load(cid).await?;
+		self.loaded = self.pending.pop_front();
+		Ok(events)
+	}
+
+	/// Archives left to load.
+	fn lag(&self) -> Option<u64> {
+		Some(self.pending.len() as u64)
+	}
+
+	/// CID of the last archive loaded.
+	fn checkpoint(&self) -> Option<String> {
+		self.loaded.clone()
+	}
+
+	/// Skips the archives configured up to the one checkpointed. They are all loaded again if it
+	/// is no longer configured, as there is no telling which of them were.
+	fn restore(&mut self, checkpoint: &str) -> Result<(), IndexerError> {
+		if let Some(position) = self.pending.iter().position(|cid| cid == checkpoint) {
+			self.loaded = self.pending.drain(..=position).last();
+		}
+		Ok(())
+	}
+}
+
+/// Verifies every block of a CARv1 archive holding `root`, returning the data of the raw
+/// leaves of the DAG under `root` in link order. Archives holding blocks the DAG does not
+/// link to are rejected, as their content would otherwise go unchecked.
+fn read_car(root: &Cid, car: &[u8]) -> Result<Vec<Vec<u8>>, IndexerError> {
+	let mut bytes = car;
+	let header_len = read_varint(&mut bytes)? as usize;
+	take(&mut bytes, header_len)?;
+
+	let mut blocks = HashMap::new();
+	while !bytes.is_empty() {
+		let section_len = read_varint(&mut bytes)? as usize;
+		let mut section = take(&mut bytes, section_len)?;
+		let cid = Cid::read(&mut section)?;
+		cid.verify(section)?;
+		blocks.insert(cid, section);
+	}
+
+	let mut leaves = Vec::new();
+	let mut reached = HashSet::new();
+	// Links are pushed last to first, so they are walked in order.
+	let mut stack = vec![root.clone()];
+	while let Some(cid) = stack.pop() {
+		let block = *blocks.get(&cid).ok_or_else(|| {
+			IndexerError::SourceError("Archive lacks a block of its DAG".to_string())
+		})?;
+		match cid.codec {
+			RAW_CODEC => leaves.push(block.to_vec()),
+			DAG_PB_CODEC => stack.extend(read_links(block)?.into_iter().rev()),
+			codec => {
+				let reason = format!("Unsupported codec {:#x}", codec);
+				return Err(IndexerError::SourceError(reason));
+			},
+		}
+		reached.insert(cid);
+	}
+	if reached.len() != blocks.len() {
+		return Err(IndexerError::SourceError(
+			"Archive holds blocks its root does not link to".to_string(),
+		));
+	}
+	Ok(leaves)
+}
+
+/// Reads the CIDs a dag-pb node links to, in order. Both fields of a node, its links and
+/// its data, are length-delimited.
+fn read_links(mut node: &[u8]) -> Result<Vec<Cid>, IndexerError> {
+	let mut links = Vec::new();
+	while !node.is_empty() {
+		let key = read_varint(&mut node)?;
+		if key & 7 != 2 {
+			return Err(IndexerError::ParseError);
+		}
+		let len = read_varint(&mut node)? as usize;
+		let value = take(&mut node, len)?;
+		if key >> 3 == 2 {
+			links.push(read_link_hash(value)?);
+		}
+	}
+	Ok(links)
+}
+
+/// Reads the CID a dag-pb link points to, skipping its name and size.
+fn read_link_hash(mut link: &[u8]) -> Result<Cid, IndexerError> {
+	while !link.is_empty() {
+		let key = read_varint(&mut link)?;
+		match key & 7 {
+			0 => {
+				read_varint(&mut link)?;
+			},
+			2 => {
+				let len = read_varint(&mut link)? as usize;
+				let mut value = take(&mut link, len)?;
+				if key >> 3 == 1 {
+					let cid = Cid::read(&mut value)?;
+					if !value.is_empty() {
+						return Err(IndexerError::ParseError);
+					}
+					return Ok(cid);
+				}
+			},
+			_ => return Err(IndexerError::ParseError),
+		}
+	}
+	Err(IndexerError::ParseError)
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], IndexerError> {
+	if bytes.len() < len {
+		return Err(IndexerError::ParseError);
+	}
+	let (head, tail) = bytes.split_at(len);
+	*bytes = tail;
+	Ok(head)
+}
+
+/// Reads an unsigned LEB128 varint off the front of `bytes`.
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, IndexerError> {
+	let mut value = 0u64;
+	for shift in (0..64).step_by(7) {
+		let (&byte, rest) = bytes.split_first().ok_or(IndexerError::ParseError)?;
+		*bytes = rest;
+		value |= u64::from(byte & 0x7f) << shift;
+		if byte & 0x80 == 0 {
+			return Ok(value);
+		}
+	}
+	Err(IndexerError::ParseError)
+}
+
+/// Decodes unpadded lowercase RFC 4648 base32.
+fn decode_base32(text: &str) -> Option<Vec<u8>> {
+	let mut bytes = Vec::with_capacity(text.len() * 5 / 8);
+	let mut buffer = 0u64;
+	let mut bits = 0;
+	for c in text.bytes() {
+		let value = match c {
+			b'a'..=b'z' => c - b'a',
+			b'2'..=b'7' => c - b'2' + 26,
+			_ => return None,
+		};
+		buffer = (buffer << 5) | u64::from(value);
+		bits += 5;
+		if bits >= 8 {
+			bits -= 8;
+			bytes.push((buffer >> bits) as u8);
+		}
+	}
+	Some(bytes)
+}
+
+#[cfg(test)]
+mod test {
+	use super::{read_car, Cid, IpfsSource, DAG_PB_CODEC, RAW_CODEC, SHA2_256};
+	use crate::{
+		config::{ArchiveFormat, IpfsConfig},
+		source::{parse_jsonl, Source},
+	};
+	use sha2::{Digest, Sha256};
+
+	fn varint(mut value: usize) -> Vec<u8> {
+		let mut bytes = Vec::new();
+		while value >= 0x80 {
+			bytes.push(value as u8 | 0x80);
+			value >>= 7;
+		}
+		bytes.push(value as u8);
+		bytes
+	}
+
+	fn cid(codec: u64, data: &[u8]) -> (Cid, Vec<u8>) {
+		let digest = Sha256::digest(data).to_vec();
+		let bytes = [[1, codec as u8, SHA2_256 as u8, 32].as_slice(), &digest].concat();
+		(Cid { codec, hash_code: SHA2_256, digest }, bytes)
+	}
+
+	fn raw_cid(data: &[u8]) -> (Cid, Vec<u8>) {
+		cid(RAW_CODEC, data)
+	}
+
+	/// A dag-pb node linking to `links` by their binary CIDs.
+	fn dag_pb_node(links: &[&[u8]]) -> Vec<u8> {
+		let mut node = Vec::new();
+		for hash in links {
+			let link = [[0x0a].as_slice(), &varint(hash.len()), hash].concat();
+			node.extend([[0x12].as_slice(), &varint(link.len()), &link].concat());
+		}
+		node
+	}
+
+	fn car(blocks: &[(&[u8], &[u8])]) -> Vec<u8> {
+		let header = b"header";
+		let mut car = [varint(header.len()).as_slice(), header].concat();
+		for (cid, data) in blocks {
+			car.extend(varint(cid.len() + data.len()));
+			car.extend_from_slice(cid);
+			car.extend_from_slice(data);
+		}
+		car
+	}
+
+	#[test]
+	fn should_parse_base32_cid() {
+		let cid =
+			Cid::parse("bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e").unwrap();
+		assert_eq!(cid.codec, RAW_CODEC);
+		assert_eq!(cid.hash_code, SHA2_256);
+		assert_eq!(cid.digest.len(), 32);
+		cid.verify(b"hello world").unwrap();
+		assert!(
+			cid.verify(b"hello world!").is_err(),
+			"should reject tampered content"
+		);
+		assert!(Cid::parse("QmYwAPJzv5CZsnA625s3Xf2nemtYgPpHdWEz79ojWnPbdG").is_err());
+	}
+
+	#[test]
+	fn should_verify_car_blocks() {
+		let data = br#"{ "schema_id": 1, "credential": { "id": "a" }, "timestamp": 5 }
+{ "schema_id": 2, "credential": { "id": "b" } }
+"#;
+		let (root, cid_bytes) = raw_cid(data);
+		let section = [cid_bytes, data.to_vec()].concat();
+		let header = b"header";
+		let car =
+			[varint(header.len()).as_slice(), header, &varint(section.len()), &section].concat();
+
+		let blocks = read_car(&root, &car).unwrap();
+		let events = parse_jsonl(&blocks[0], "ipfs", 9).unwrap();
+		assert_eq!(events.len(), 2);
+		assert_eq!((events[0].schema_id, events[0].timestamp), (1, 5));
+		assert_eq!((events[1].schema_id, events[1].timestamp), (2, 9));
+
+		let mut tampered = car.clone();
+		*tampered.last_mut().unwrap() = b' ';
+		assert!(
+			read_car(&root, &tampered).is_err(),
+			"should reject tampered blocks"
+		);
+		let (other, _) = raw_cid(b"other");
+		assert!(
+			read_car(&other, &car).is_err(),
+			"should require the root block"
+		);
+	}
+
+	#[test]
+	fn should_walk_car_dag_from_root() {
+		let (_, first) = raw_cid(b"first");
+		let (_, second) = raw_cid(b"second");
+		let node = dag_pb_node(&[&second, &first]);
+		let (root, root_bytes) = cid(DAG_PB_CODEC, &node);
+
+		let archive = car(&[(&root_bytes, &node), (&first, b"first"), (&second, b"second")]);
+		let blocks = read_car(&root, &archive).unwrap();
+		assert_eq!(
+			blocks,
+			[b"second".to_vec(), b"first".to_vec()],
+			"should follow the links in order"
+		);
+
+		let (_, stray) = raw_cid(b"stray");
+		let archive = car(&[
+			(&root_bytes, &node),
+			(&first, b"first"),
+			(&second, b"second"),
+			(&stray, b"stray"),
+		]);
+		assert!(
+			read_car(&root, &archive).is_err(),
+			"should reject blocks the root does not link to"
+		);
+		let archive = car(&[(&root_bytes, &node), (&first, b"first")]);
+		assert!(
+			read_car(&root, &archive).is_err(),
+			"should require every linked block"
+		);
+	}
+
+	#[test]
+	fn should_skip_checkpointed_archives() {
+		let config = IpfsConfig {
+			ipfs_gateway: None,
+			ipfs_cids: vec!["ba".to_string(), "bb".to_string(), "bc".to_string()],
+			ipfs_format: ArchiveFormat::Jsonl,
+		};
+		let mut source = IpfsSource::new("https://ipfs.io", &config);
+		source.restore("bb").unwrap();
+		assert_eq!(source.pending, ["bc"]);
+		assert_eq!(source.checkpoint().as_deref(), Some("bb"));
+
+		let mut source = IpfsSource::new("https://ipfs.io", &config);
+		source.restore("bd").unwrap();
+		assert_eq!(
+			source.pending.len(),
+			3,
+			"should load every archive past an unknown checkpoint"
+		);
+	}
+}
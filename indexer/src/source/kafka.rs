@@ -0,0 +1,171 @@
+use super::{now_secs, Envelope, Source, SourceEvent};
+use crate::{config::KafkaConfig, error::IndexerError};
+use rdkafka::{
+	consumer::{CommitMode, Consumer, StreamConsumer},
+	ClientConfig, Message, Offset, TopicPartitionList,
+};
+use std::{collections::HashMap, future::Future, time::Duration};
+use tokio::time::timeout;
+
+/// Messages taken per poll.
+const MAX_BATCH_SIZE: usize = 1000;
+/// How long a poll waits for the next message before returning what it has.
+const RECEIVE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Name of the source, recorded on its events.
+const NAME: &str = "kafka";
+
+/// What a poll keeps of a message.
+struct Received {
+	/// Topic and partition the message came from.
+	partition: (String, i32),
+	offset: i64,
+	/// Event of the message, `None` if it was malformed.
+	event: Option<SourceEvent>,
+}
+
+/// Consumes credential envelopes from Kafka topics, committing the offsets of the consumer
+/// group only once the events are stored.
+pub struct KafkaSource {
+	consumer: StreamConsumer,
+	/// Offset to resume every partition from, not committed yet.
+	pending: HashMap<(String, i32), i64>,
+}
+
+impl KafkaSource {
+	pub fn new(brokers: &str, config: &KafkaConfig) -> Result<Self, IndexerError> {
+		let consumer: StreamConsumer = ClientConfig::new()
+			.set("bootstrap.servers", brokers)
+			.set("group.id", &config.kafka_group_id)
+			.set("enable.auto.commit", "false")
+			.set("auto.offset.reset", "earliest")
+			.create()
+			.map_err(IndexerError::KafkaError)?;
+		let topics: Vec<_> = config.kafka_topics.iter().map(String::as_str).collect();
+		consumer.subscribe(&topics).map_err(IndexerError::KafkaError)?;
+		Ok(Self { consumer, pending: HashMap::new() })
+	}
+
+	/// Receives the next message, or `None` if none came within `RECEIVE_TIMEOUT`.
+	async fn receive(consumer: &StreamConsumer) -> Option<Result<Received, IndexerError>> {
+		let message = match timeout(RECEIVE_TIMEOUT, consumer.recv()).await {
+			Ok(Ok(message)) => message,
+			Ok(Err(e)) => return Some(Err(IndexerError::KafkaError(e))),
+			Err(_) => return None,
+		};
+		let partition = (message.topic().to_string(), message.partition());
+		let received_at =
+			message.timestamp().to_millis().map_or_else(now_secs, |ms| ms as u64 / 1000);
+		let event = match message.payload().map(serde_json::from_slice::<Envelope>) {
+			Some(Ok(envelope)) => Some(envelope.into_event(NAME, received_at)),
+			// Malformed messages are skipped, or they would block their partition forever.
+			_ => {
+				println!(
+					"Skipping malformed message {} of {}/{}",
+					message.offset(),
+					partition.0,
+					partition.1
+				);
+				None
+			},
+		};
+		Some(Ok(Received { partition, offset: message.offset(), event }))
+	}
+}
+
+/// Takes up to `MAX_BATCH_SIZE` messages from `receive`, recording in `pending` where the
+/// partitions of the messages taken resume. Messages taken before a failure are returned,
+/// since the consumer has moved past them, and the failure only when there are none.
+async fn take_batch<F, Fut>(
+	mut receive: F, pending: &mut HashMap<(String, i32), i64>,
+) -> Result<Vec<SourceEvent>, IndexerError>
+where
+	F: FnMut() -> Fut,
+	Fut: Future<Output = Option<Result<Received, IndexerError>>>,
+{
+	let mut events = Vec::new();
+	let mut offsets = HashMap::new();
+	while events.len() < MAX_BATCH_SIZE {
+		let received = match receive().await {
+			Some(Ok(received)) => received,
+			Some(Err(e)) if offsets.is_empty() => return Err(e),
+			Some(Err(e)) => {
+				println!("Ending the batch early: {}", e);
+				break;
+			},
+			None => break,
+		};
+		offsets.insert(received.partition, received.offset + 1);
+		events.extend(received.event);
+	}
+	pending.extend(offsets);
+	Ok(events)
+}
+
+#[tonic::async_trait]
+impl Source for KafkaSource {
+	fn name(&self) -> &str {
+		NAME
+	}
+
+	async fn poll(&mut self) -> Result<Vec<SourceEvent>, IndexerError> {
+		let consumer = &self.consumer;
+		take_batch(|| Self::receive(consumer), &mut self.pending).await
+	}
+
+	async fn commit(&mut self) -> Result<(), IndexerError> {
+		if self.pending.is_empty() {
+			return Ok(());
+		}
+		let mut offsets = TopicPartitionList::new();
+		for ((topic, partition), offset) in self.pending.drain() {
+			offsets
+				.add_partition_offset(&topic, partition, Offset::Offset(offset))
+				.map_err(IndexerError::KafkaError)?;
+		}
+		self.consumer.commit(&offsets, CommitMode::Async).map_err(IndexerError::KafkaError)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{take_batch, Received};
+	use crate::{error::IndexerError, source::SourceEvent};
+	use std::collections::{HashMap, VecDeque};
+
+	fn received(offset: i64, event: Option<SourceEvent>) -> Result<Received, IndexerError> {
+		Ok(Received { partition: ("credentials".to_string(), 0), offset, event })
+	}
+
+	#[tokio::test]
+	async fn should_keep_messages_taken_before_a_failure() {
+		let failure = || Err(IndexerError::SourceError("broker down".to_string()));
+		let mut messages = VecDeque::from([
+			received(4, Some(SourceEvent::default())),
+			received(5, None),
+			failure(),
+			failure(),
+		]);
+		let mut pending = HashMap::new();
+		let mut receive = || {
+			let message = messages.pop_front();
+			async move { message }
+		};
+
+		let events = take_batch(&mut receive, &mut pending).await.unwrap();
+		assert_eq!(
+			events.len(),
+			1,
+			"should return the events taken before the failure"
+		);
+		assert_eq!(pending[&("credentials".to_string(), 0)], 6);
+
+		assert!(take_batch(&mut receive, &mut pending).await.is_err());
+		assert_eq!(
+			pending[&("credentials".to_string(), 0)],
+			6,
+			"should not move past messages that were not returned"
+		);
+		assert!(take_batch(&mut receive, &mut pending).await.unwrap().is_empty());
+	}
+}
@@ -0,0 +1,43 @@
+use super::{now_secs, Source, SourceEvent};
+use crate::error::IndexerError;
+
+const FOLLOW_MOCK: &str = "{
+    \"id\": \"did:pkh:90f8bf6a479f320ead074411a4b0e7944ea8c9c2\",
+    \"is_trustworthy\": true,
+    \"scope\": \"Reviewer\",
+    \"sig\": [
+        0,
+        [165, 27, 231, 102, 0, 210, 165, 235, 176, 250, 84, 181, 240, 246, 182, 135, 85, 181, 106, 145, 41, 107, 207, 81, 49, 37, 133, 183, 171, 151, 67, 67],
+        [116, 33, 248, 224, 110, 187, 80, 139, 81, 22, 199, 37, 68, 255, 180, 55, 159, 59, 232, 70, 206, 232, 38, 165, 54, 233, 19, 31, 57, 139, 186, 54]
+    ]
+}";
+
+/// Produces the same follow attestation `batch_size` times per poll, standing in for a real
+/// upstream.
+pub struct MockSource {
+	batch_size: usize,
+}
+
+impl MockSource {
+	pub fn new(batch_size: usize) -> Self {
+		Self { batch_size }
+	}
+}
+
+#[tonic::async_trait]
+impl Source for MockSource {
+	fn name(&self) -> &str {
+		"mock"
+	}
+
+	async fn poll(&mut self) -> Result<Vec<SourceEvent>, IndexerError> {
+		let event = SourceEvent {
+			schema_id: 1,
+			schema_value: FOLLOW_MOCK.to_string(),
+			timestamp: now_secs(),
+			source: self.name().to_string(),
+			..SourceEvent::default()
+		};
+		Ok(vec![event; self.batch_size])
+	}
+}
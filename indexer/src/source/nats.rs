@@ -0,0 +1,96 @@
+use super::{now_secs, Envelope, Source, SourceEvent};
+use crate::{config::NatsConfig, error::IndexerError};
+use async_nats::jetstream::{
+	self,
+	consumer::{pull, AckPolicy, PullConsumer},
+	Message,
+};
+use std::time::Duration;
+use tokio_stream::StreamExt;
+
+/// Messages fetched per poll.
+const MAX_BATCH_SIZE: usize = 1000;
+/// How long a fetch waits for messages before returning what it has.
+const FETCH_EXPIRY: Duration = Duration::from_millis(500);
+
+fn nats_error(e: impl ToString) -> IndexerError {
+	IndexerError::SourceError(e.to_string())
+}
+
+/// Pulls credential envelopes from a JetStream durable consumer, acknowledging them only
+/// once the events are stored.
+pub struct NatsSource {
+	consumer: PullConsumer,
+	/// Messages of the last poll, acknowledged on commit.
+	pending: Vec<Message>,
+	/// Messages the consumer had yet to deliver after the last poll.
+	behind: u64,
+}
+
+impl NatsSource {
+	pub async fn connect(url: &str, config: &NatsConfig) -> Result<Self, IndexerError> {
+		let client = async_nats::connect(url).await.map_err(nats_error)?;
+		let stream =
+			jetstream::new(client).get_stream(&config.nats_stream).await.map_err(nats_error)?;
+		let consumer_config = pull::Config {
+			durable_name: Some(config.nats_durable.clone()),
+			filter_subject: config.nats_subject.clone().unwrap_or_default(),
+			ack_policy: AckPolicy::Explicit,
+			..Default::default()
+		};
+		let consumer = stream
+			.get_or_create_consumer(&config.nats_durable, consumer_config)
+			.await
+			.map_err(nats_error)?;
+		Ok(Self { consumer, pending: Vec::new(), behind: 0 })
+	}
+}
+
+#[tonic::async_trait]
+impl Source for NatsSource {
+	fn name(&self) -> &str {
+		"nats"
+	}
+
+	async fn poll(&mut self) -> Result<Vec<SourceEvent>, IndexerError> {
+		let mut messages = self
+			.consumer
+			.fetch()
+			.max_messages(MAX_BATCH_SIZE)
+			.expires(FETCH_EXPIRY)
+			.messages()
+			.await
+			.map_err(nats_error)?;
+
+		let mut events = Vec::new();
+		// Acknowledged only along with the events returned, so a failure has them redelivered.
+		let mut taken = Vec::new();
+		self.behind = 0;
+		while let Some(message) = messages.next().await {
+			let message = message.map_err(nats_error)?;
+			let info = message.info().ok();
+			self.behind = info.as_ref().map_or(0, |info| info.pending);
+			let received_at =
+				info.map_or_else(now_secs, |info| info.published.unix_timestamp() as u64);
+			match serde_json::from_slice::<Envelope>(&message.payload) {
+				Ok(envelope) => events.push(envelope.into_event(self.name(), received_at)),
+				// Malformed messages are acknowledged too, or they would be redelivered forever.
+				Err(e) => println!("Skipping malformed message on {}: {}", message.subject, e),
+			}
+			taken.push(message);
+		}
+		self.pending.extend(taken);
+		Ok(events)
+	}
+
+	async fn commit(&mut self) -> Result<(), IndexerError> {
+		for message in self.pending.drain(..) {
+			message.ack().await.map_err(nats_error)?;
+		}
+		Ok(())
+	}
+
+	fn lag(&self) -> Option<u64> {
+		Some(self.behind)
+	}
+}
@@ -0,0 +1,156 @@
+use crate::{
+	config::{StoreBackend, StoreConfig},
+	error::IndexerError,
+	source::SourceEvent,
+};
+use memory::MemoryStore;
+use postgres::PostgresStore;
+use proto_buf::indexer::IndexerEvent;
+use rocks::RocksDbStore;
+use segment::SegmentStore;
+use serde_derive::{Deserialize, Serialize};
+use sqlite::SqliteStore;
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	sync::Arc,
+};
+
+pub mod memory;
+pub mod notify;
+pub mod postgres;
+pub mod rocks;
+pub mod segment;
+pub mod sqlite;
+pub mod stamp;
+
+/// Events a read selects, on top of its ID window.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventFilter {
+	/// Schemas the events must have one of, any if empty.
+	pub schema_ids: BTreeSet<u32>,
+	/// Earliest timestamp of the events, inclusive.
+	pub from_timestamp: u64,
+	/// Timestamp the events must precede, if any.
+	pub to_timestamp: Option<u64>,
+	/// Trust domains the events must belong to one of, any if empty. Stores don't index
+	/// domains, so reads leave them to their readers, which drop the events of other domains,
+	/// while stats count only the events of these domains.
+	pub domains: BTreeSet<u32>,
+}
+
+impl EventFilter {
+	pub fn matches(&self, event: &IndexerEvent) -> bool {
+		(self.schema_ids.is_empty() || self.schema_ids.contains(&event.schema_id))
+			&& self.contains_timestamp(event.timestamp)
+	}
+
+	pub fn matches_domain(&self, event: &IndexerEvent) -> bool {
+		self.domains.is_empty() || self.domains.contains(&event.domain)
+	}
+
+	pub fn contains_timestamp(&self, timestamp: u64) -> bool {
+		timestamp >= self.from_timestamp && self.to_timestamp.map_or(true, |to| timestamp < to)
+	}
+
+	pub fn has_time_range(&self) -> bool {
+		self.from_timestamp > 0 || self.to_timestamp.is_some()
+	}
+}
+
+/// Number and time span of a set of events.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct EventStats {
+	pub count: u64,
+	/// Earliest timestamp of the events, zero if there are none.
+	pub min_timestamp: u64,
+	/// Latest timestamp of the events, zero if there are none.
+	pub max_timestamp: u64,
+}
+
+impl EventStats {
+	pub fn add(&mut self, timestamp: u64) {
+		self.merge(&Self { count: 1, min_timestamp: timestamp, max_timestamp: timestamp });
+	}
+
+	pub fn merge(&mut self, other: &Self) {
+		if other.count == 0 {
+			return;
+		}
+		if self.count == 0 {
+			*self = *other;
+			return;
+		}
+		self.count += other.count;
+		self.min_timestamp = self.min_timestamp.min(other.min_timestamp);
+		self.max_timestamp = self.max_timestamp.max(other.max_timestamp);
+	}
+}
+
+/// Position a source reached upstream.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+	pub source: String,
+	pub position: String,
+}
+
+/// Append-only log of events, numbered from zero in the order they were ingested.
+#[tonic::async_trait]
+pub trait EventStore: Send + Sync {
+	/// Assigns the next IDs to `events` and appends them, returning the new number of events.
+	async fn append(&self, events: Vec<SourceEvent>) -> Result<u32, IndexerError>;
+
+	/// Reads up to `count` events matching `filter`, starting with ID `offset`. Reads filtered
+	/// by schema go through the schema index rather than scanning the log.
+	async fn read(
+		&self, offset: u32, count: u32, filter: &EventFilter,
+	) -> Result<Vec<IndexerEvent>, IndexerError>;
+
+	/// Number of events ingested, which is also the ID of the next one.
+	async fn count(&self) -> Result<u32, IndexerError>;
+
+	/// Stats of the events matching `filter`, domains included, from ID `offset` on, by schema.
+	/// Schemas without such events are left out.
+	async fn stats(
+		&self, offset: u32, filter: &EventFilter,
+	) -> Result<BTreeMap<u32, EventStats>, IndexerError>;
+
+	/// Appends `events` like `append`, saving the checkpoints of the sources they came from in
+	/// the same write, so a restart neither refetches nor skips them.
+	async fn append_checkpointed(
+		&self, events: Vec<SourceEvent>, checkpoints: &[Checkpoint],
+	) -> Result<u32, IndexerError>;
+
+	/// Position `source` was last checkpointed at, if ever.
+	async fn read_checkpoint(&self, source: &str) -> Result<Option<String>, IndexerError>;
+
+	/// Rebuilds the indexes derived from the events, after their layout changed or they were
+	/// found corrupted. Returns the number of events indexed.
+	async fn reindex(&self) -> Result<u32, IndexerError>;
+}
+
+/// Opens the configured backend.
+pub async fn open(config: &StoreConfig) -> Result<Arc<dyn EventStore>, IndexerError> {
+	match config.store {
+		StoreBackend::Memory => Ok(Arc::new(MemoryStore::new())),
+		StoreBackend::Postgres => {
+			let url = config.postgres_url.as_deref().ok_or(IndexerError::ConfigError(
+				"--postgres-url is required by the postgres store",
+			))?;
+			Ok(Arc::new(PostgresStore::connect(url).await?))
+		},
+		StoreBackend::RocksDb => Ok(Arc::new(RocksDbStore::open(&config.rocksdb_path)?)),
+		StoreBackend::Sqlite => Ok(Arc::new(SqliteStore::open(&config.sqlite_path)?)),
+		StoreBackend::Segments => Ok(Arc::new(SegmentStore::open(
+			&config.segments_path,
+			config.rotation(),
+		)?)),
+	}
+}
+
+/// Merges ascending ID lists into the lowest `count` IDs of them all.
+pub fn merge_ids(lists: Vec<Vec<u32>>, count: u32) -> Vec<u32> {
+	let mut ids: Vec<u32> = lists.into_iter().flatten().collect();
+	ids.sort_unstable();
+	ids.truncate(count as usize);
+	ids
+}
@@ -0,0 +1,255 @@
+use super::{merge_ids, Checkpoint, EventFilter, EventStats, EventStore};
+use crate::{error::IndexerError, source::SourceEvent};
+use proto_buf::indexer::IndexerEvent;
+use std::{
+	collections::{BTreeMap, HashMap},
+	sync::{PoisonError, RwLock},
+};
+
+#[derive(Debug, Default)]
+struct Log {
+	/// Events in the order they were ingested, the position of each being its ID.
+	events: Vec<IndexerEvent>,
+	/// IDs of the events of every schema, ascending.
+	by_schema: HashMap<u32, Vec<u32>>,
+	/// Position of every source, by name.
+	checkpoints: HashMap<String, String>,
+}
+
+impl Log {
+	fn append(&mut self, events: Vec<SourceEvent>) -> u32 {
+		for event in events {
+			let id = self.events.len() as u32;
+			self.by_schema.entry(event.schema_id).or_default().push(id);
+			self.events.push(IndexerEvent {
+				id,
+				schema_id: event.schema_id,
+				schema_value: event.schema_value,
+				timestamp: event.timestamp,
+				source: event.source,
+				received_at: event.received_at,
+				domain: event.domain,
+			});
+		}
+		self.events.len() as u32
+	}
+}
+
+/// Keeps events in memory, losing them on exit.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+	log: RwLock<Log>,
+}
+
+impl MemoryStore {
+	pub fn new() -> Self {
+		Self::default()
+	}
+}
+
+#[tonic::async_trait]
+impl EventStore for MemoryStore {
+	async fn append(&self, events: Vec<SourceEvent>) -> Result<u32, IndexerError> {
+		Ok(self.log.write().unwrap_or_else(PoisonError::into_inner).append(events))
+	}
+
+	async fn read(
+		&self, offset: u32, count: u32, filter: &EventFilter,
+	) -> Result<Vec<IndexerEvent>, IndexerError> {
+		let log = self.log.read().unwrap_or_else(PoisonError::into_inner);
+		if filter.schema_ids.is_empty() {
+			let start = (offset as usize).min(log.events.len());
+			if filter.has_time_range() {
+				let events = log.events[start..].iter().filter(|event| filter.matches(event));
+				return Ok(events.take(count as usize).cloned().collect());
+			}
+			let end = start.saturating_add(count as usize).min(log.events.len());
+			return Ok(log.events[start..end].to_vec());
+		}
+
+		let lists = filter
+			.schema_ids
+			.iter()
+			.filter_map(|schema_id| log.by_schema.get(schema_id))
+			.map(|ids| {
+				let start = ids.partition_point(|&id| id < offset);
+				ids[start..]
+					.iter()
+					.filter(|&&id| filter.contains_timestamp(log.events[id as usize].timestamp))
+					.take(count as usize)
+					.copied()
+					.collect()
+			})
+			.collect();
+		let ids = merge_ids(lists, count);
+		Ok(ids.into_iter().map(|id| log.events[id as usize].clone()).collect())
+	}
+
+	async fn count(&self) -> Result<u32, IndexerError> {
+		Ok(self.log.read().unwrap_or_else(PoisonError::into_inner).events.len() as u32)
+	}
+
+	async fn stats(
+		&self, offset: u32, filter: &EventFilter,
+	) -> Result<BTreeMap<u32, EventStats>, IndexerError> {
+		let log = self.log.read().unwrap_or_else(PoisonError::into_inner);
+		let start = (offset as usize).min(log.events.len());
+		let mut stats = BTreeMap::<_, EventStats>::new();
+		let matching = log.events[start..]
+			.iter()
+			.filter(|event| filter.matches(event) && filter.matches_domain(event));
+		for event in matching {
+			stats.entry(event.schema_id).or_default().add(event.timestamp);
+		}
+		Ok(stats)
+	}
+
+	async fn append_checkpointed(
+		&self, events: Vec<SourceEvent>, checkpoints: &[Checkpoint],
+	) -> Result<u32, IndexerError> {
+		let mut log = self.log.write().unwrap_or_else(PoisonError::into_inner);
+		for checkpoint in checkpoints {
+			log.checkpoints.insert(checkpoint.source.clone(), checkpoint.position.clone());
+		}
+		Ok(log.append(events))
+	}
+
+	async fn read_checkpoint(&self, source: &str) -> Result<Option<String>, IndexerError> {
+		let log = self.log.read().unwrap_or_else(PoisonError::into_inner);
+		Ok(log.checkpoints.get(source).cloned())
+	}
+
+	async fn reindex(&self) -> Result<u32, IndexerError> {
+		let mut log = self.log.write().unwrap_or_else(PoisonError::into_inner);
+		let mut by_schema = HashMap::<_, Vec<_>>::new();
+		for event in &log.events {
+			by_schema.entry(event.schema_id).or_default().push(event.id);
+		}
+		log.by_schema = by_schema;
+		Ok(log.events.len() as u32)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::MemoryStore;
+	use crate::{
+		source::SourceEvent,
+		store::{EventFilter, EventStore},
+	};
+	use proto_buf::indexer::IndexerEvent;
+
+	#[tokio::test]
+	async fn should_assign_consecutive_ids() {
+		let store = MemoryStore::new();
+		let event = |timestamp| SourceEvent {
+			schema_id: 1,
+			schema_value: "{}".to_string(),
+			timestamp,
+			..SourceEvent::default()
+		};
+		assert_eq!(store.append(vec![event(10), event(11)]).await.unwrap(), 2);
+		assert_eq!(store.append(vec![event(12)]).await.unwrap(), 3);
+
+		let events = store.read(2, 1, &EventFilter::default()).await.unwrap();
+		assert_eq!((events[0].id, events[0].timestamp), (2, 12));
+		assert_eq!(store.count().await.unwrap(), 3);
+	}
+
+	#[tokio::test]
+	async fn should_read_exact_window() {
+		let store = MemoryStore::new();
+		let events = (0..10)
+			.map(|timestamp| SourceEvent {
+				schema_id: 1,
+				schema_value: "{}".to_string(),
+				timestamp,
+				..SourceEvent::default()
+			})
+			.collect();
+		store.append(events).await.unwrap();
+		let all = EventFilter::default();
+		let ids = |events: Vec<IndexerEvent>| -> Vec<u32> {
+			events.into_iter().map(|event| event.id).collect()
+		};
+
+		assert_eq!(ids(store.read(0, 3, &all).await.unwrap()), vec![0, 1, 2]);
+		assert_eq!(
+			ids(store.read(4, 2, &all).await.unwrap()),
+			vec![4, 5],
+			"should start at the offset"
+		);
+		assert_eq!(
+			ids(store.read(8, 5, &all).await.unwrap()),
+			vec![8, 9],
+			"should stop at the last event"
+		);
+		assert_eq!(ids(store.read(9, 1, &all).await.unwrap()), vec![9]);
+		assert!(
+			store.read(3, 0, &all).await.unwrap().is_empty(),
+			"should read nothing for a zero count"
+		);
+		assert!(
+			store.read(10, 1, &all).await.unwrap().is_empty(),
+			"should read nothing past the end"
+		);
+		assert!(store.read(u32::MAX, u32::MAX, &all).await.unwrap().is_empty());
+		assert_eq!(
+			ids(store.read(7, u32::MAX, &all).await.unwrap()),
+			vec![7, 8, 9],
+			"should not overflow the window"
+		);
+	}
+
+	#[tokio::test]
+	async fn should_filter_by_schemas() {
+		let store = MemoryStore::new();
+		let events = (0..9)
+			.map(|i| SourceEvent {
+				schema_id: i % 3 + 1,
+				schema_value: "{}".to_string(),
+				timestamp: 0,
+				..SourceEvent::default()
+			})
+			.collect();
+		store.append(events).await.unwrap();
+
+		let filter = EventFilter { schema_ids: [1, 3].into(), ..EventFilter::default() };
+		let events = store.read(1, 3, &filter).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.schema_id)).collect();
+		assert_eq!(ids, vec![(2, 3), (3, 1), (5, 3)]);
+
+		let filter = EventFilter { schema_ids: [4].into(), ..EventFilter::default() };
+		assert!(store.read(0, 10, &filter).await.unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn should_filter_by_time_range() {
+		let store = MemoryStore::new();
+		// Timestamps go backwards, so the time order differs from the ID order.
+		let events = (0..9)
+			.map(|i| SourceEvent {
+				schema_id: i % 3 + 1,
+				schema_value: "{}".to_string(),
+				timestamp: 100 - u64::from(i) * 10,
+				..SourceEvent::default()
+			})
+			.collect();
+		store.append(events).await.unwrap();
+
+		let window =
+			EventFilter { from_timestamp: 30, to_timestamp: Some(80), ..EventFilter::default() };
+		let events = store.read(3, 10, &window).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.timestamp)).collect();
+		assert_eq!(ids, vec![(3, 70), (4, 60), (5, 50), (6, 40), (7, 30)]);
+
+		let filter = EventFilter { schema_ids: [2].into(), ..window };
+		let events = store.read(0, 1, &filter).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.timestamp)).collect();
+		assert_eq!(
+			ids,
+			vec![(4, 60)],
+			"should apply both the schema and the time range"
+		);
+	}
+}
@@ -0,0 +1,73 @@
+use super::{Checkpoint, EventFilter, EventStats, EventStore};
+use crate::{error::IndexerError, source::SourceEvent};
+use proto_buf::indexer::IndexerEvent;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::watch;
+
+/// Wraps a store to let readers wait for events to be appended.
+pub struct NotifyingStore {
+	inner: Arc<dyn EventStore>,
+	count: watch::Sender<u32>,
+}
+
+impl NotifyingStore {
+	pub async fn new(inner: Arc<dyn EventStore>) -> Result<Self, IndexerError> {
+		let (count, _) = watch::channel(inner.count().await?);
+		Ok(Self { inner, count })
+	}
+
+	/// Number of events stored, updated after every append.
+	pub fn watch_count(&self) -> watch::Receiver<u32> {
+		self.count.subscribe()
+	}
+
+	fn publish(&self, count: u32) {
+		// Concurrent appends may finish out of order, so the count only ever grows.
+		self.count.send_if_modified(|current| {
+			let grew = count > *current;
+			*current = (*current).max(count);
+			grew
+		});
+	}
+}
+
+#[tonic::async_trait]
+impl EventStore for NotifyingStore {
+	async fn append(&self, events: Vec<SourceEvent>) -> Result<u32, IndexerError> {
+		let count = self.inner.append(events).await?;
+		self.publish(count);
+		Ok(count)
+	}
+
+	async fn read(
+		&self, offset: u32, count: u32, filter: &EventFilter,
+	) -> Result<Vec<IndexerEvent>, IndexerError> {
+		self.inner.read(offset, count, filter).await
+	}
+
+	async fn count(&self) -> Result<u32, IndexerError> {
+		self.inner.count().await
+	}
+
+	async fn stats(
+		&self, offset: u32, filter: &EventFilter,
+	) -> Result<BTreeMap<u32, EventStats>, IndexerError> {
+		self.inner.stats(offset, filter).await
+	}
+
+	async fn append_checkpointed(
+		&self, events: Vec<SourceEvent>, checkpoints: &[Checkpoint],
+	) -> Result<u32, IndexerError> {
+		let count = self.inner.append_checkpointed(events, checkpoints).await?;
+		self.publish(count);
+		Ok(count)
+	}
+
+	async fn read_checkpoint(&self, source: &str) -> Result<Option<String>, IndexerError> {
+		self.inner.read_checkpoint(source).await
+	}
+
+	async fn reindex(&self) -> Result<u32, IndexerError> {
+		self.inner.reindex().await
+	}
+}
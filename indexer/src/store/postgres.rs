@@ -0,0 +1,361 @@
+use super::{Checkpoint, EventFilter, EventStats, EventStore};
+use crate::{error::IndexerError, source::SourceEvent};
+use proto_buf::indexer::IndexerEvent;
+use std::collections::BTreeMap;
+use tokio::sync::Mutex;
+use tokio_postgres::{types::ToSql, Client, NoTls, Row};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    id BIGINT PRIMARY KEY,
+    schema_id BIGINT NOT NULL,
+    schema_value TEXT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    source TEXT NOT NULL,
+    received_at BIGINT NOT NULL,
+    domain BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_schema_id_id ON events (schema_id, id);
+CREATE INDEX IF NOT EXISTS events_timestamp ON events (timestamp);
+CREATE TABLE IF NOT EXISTS checkpoints (
+    source TEXT PRIMARY KEY,
+    position TEXT NOT NULL
+);
+";
+
+/// Reads `$2` events from ID `$1` timestamped from `$4` and before `$5`, if not null, when the
+/// schemas `$3` are left empty.
+const READ: &str = "
+SELECT id, schema_id, schema_value, timestamp, source, received_at, domain FROM events
+WHERE id >= $1 AND cardinality($3::BIGINT[]) = 0
+    AND timestamp >= $4 AND ($5::BIGINT IS NULL OR timestamp < $5)
+ORDER BY id LIMIT $2
+";
+
+/// Reads like `READ` the events having one of the schemas `$3`.
+const READ_SCHEMAS: &str = "
+SELECT id, schema_id, schema_value, timestamp, source, received_at, domain FROM events
+WHERE schema_id = ANY($3::BIGINT[]) AND id >= $1
+    AND timestamp >= $4 AND ($5::BIGINT IS NULL OR timestamp < $5)
+ORDER BY id LIMIT $2
+";
+
+/// Numbers the appended rows after the highest ID stored, in a single atomic statement.
+const APPEND: &str = "
+INSERT INTO events (id, schema_id, schema_value, timestamp, source, received_at, domain)
+SELECT (SELECT COALESCE(MAX(id), -1) FROM events) + batch.ord, batch.schema_id,
+    batch.schema_value, batch.timestamp, batch.source, batch.received_at, batch.domain
+FROM UNNEST($1::BIGINT[], $2::TEXT[], $3::BIGINT[], $4::TEXT[], $5::BIGINT[], $6::BIGINT[])
+    WITH ORDINALITY
+    AS batch(schema_id, schema_value, timestamp, source, received_at, domain, ord)
+";
+
+/// Appends like `APPEND` and saves the positions `$8` of sources `$7` in the same statement.
+const APPEND_CHECKPOINTED: &str = "
+WITH appended AS (
+    INSERT INTO events (id, schema_id, schema_value, timestamp, source, received_at, domain)
+    SELECT (SELECT COALESCE(MAX(id), -1) FROM events) + batch.ord, batch.schema_id,
+        batch.schema_value, batch.timestamp, batch.source, batch.received_at, batch.domain
+    FROM UNNEST($1::BIGINT[], $2::TEXT[], $3::BIGINT[], $4::TEXT[], $5::BIGINT[], $6::BIGINT[])
+        WITH ORDINALITY
+        AS batch(schema_id, schema_value, timestamp, source, received_at, domain, ord)
+)
+INSERT INTO checkpoints (source, position)
+SELECT * FROM UNNEST($7::TEXT[], $8::TEXT[])
+ON CONFLICT (source) DO UPDATE SET position = EXCLUDED.position
+";
+
+/// Keeps events in a PostgreSQL `events` table, created on first use.
+pub struct PostgresStore {
+	client: Client,
+	/// Serializes appends, which would otherwise race for the same IDs.
+	append_lock: Mutex<()>,
+}
+
+impl PostgresStore {
+	pub async fn connect(url: &str) -> Result<Self, IndexerError> {
+		let (client, connection) =
+			tokio_postgres::connect(url, NoTls).await.map_err(IndexerError::PostgresError)?;
+		tokio::spawn(async move {
+			if let Err(e) = connection.await {
+				println!("PostgreSQL connection failed: {}", e);
+			}
+		});
+		client.batch_execute(SCHEMA).await.map_err(IndexerError::PostgresError)?;
+		Ok(Self { client, append_lock: Mutex::new(()) })
+	}
+
+	/// Runs `statement` with the columns of `events` as its first six parameters, followed by
+	/// `params`, then counts the events.
+	async fn insert(
+		&self, statement: &str, events: Vec<SourceEvent>, params: &[&(dyn ToSql + Sync)],
+	) -> Result<u32, IndexerError> {
+		let mut schema_ids = Vec::with_capacity(events.len());
+		let mut schema_values = Vec::with_capacity(events.len());
+		let mut timestamps = Vec::with_capacity(events.len());
+		let mut sources = Vec::with_capacity(events.len());
+		let mut received_ats = Vec::with_capacity(events.len());
+		let mut domains = Vec::with_capacity(events.len());
+		for event in events {
+			schema_ids.push(i64::from(event.schema_id));
+			schema_values.push(event.schema_value);
+			timestamps.push(event.timestamp as i64);
+			sources.push(event.source);
+			received_ats.push(event.received_at as i64);
+			domains.push(i64::from(event.domain));
+		}
+		let columns: [&(dyn ToSql + Sync); 6] =
+			[&schema_ids, &schema_values, &timestamps, &sources, &received_ats, &domains];
+		let params: Vec<_> = columns.into_iter().chain(params.iter().copied()).collect();
+
+		let _guard = self.append_lock.lock().await;
+		self.client.execute(statement, &params).await.map_err(IndexerError::PostgresError)?;
+		self.count().await
+	}
+
+	fn event_from_row(row: &Row) -> IndexerEvent {
+		IndexerEvent {
+			id: row.get::<_, i64>("id") as u32,
+			schema_id: row.get::<_, i64>("schema_id") as u32,
+			schema_value: row.get("schema_value"),
+			timestamp: row.get::<_, i64>("timestamp") as u64,
+			source: row.get("source"),
+			received_at: row.get::<_, i64>("received_at") as u64,
+			domain: row.get::<_, i64>("domain") as u32,
+		}
+	}
+}
+
+#[tonic::async_trait]
+impl EventStore for PostgresStore {
+	async fn append(&self, events: Vec<SourceEvent>) -> Result<u32, IndexerError> {
+		self.insert(APPEND, events, &[]).await
+	}
+
+	async fn read(
+		&self, offset: u32, count: u32, filter: &EventFilter,
+	) -> Result<Vec<IndexerEvent>, IndexerError> {
+		let schema_ids: Vec<i64> = filter.schema_ids.iter().copied().map(i64::from).collect();
+		let from_timestamp = filter.from_timestamp as i64;
+		let to_timestamp = filter.to_timestamp.map(|to| to as i64);
+		// Kept apart so the plan of schema filtered reads can range scan (schema_id, id).
+		let query = match schema_ids.is_empty() {
+			true => READ,
+			false => READ_SCHEMAS,
+		};
+		let rows = self
+			.client
+			.query(
+				query,
+				&[
+					&i64::from(offset),
+					&i64::from(count),
+					&schema_ids,
+					&from_timestamp,
+					&to_timestamp,
+				],
+			)
+			.await
+			.map_err(IndexerError::PostgresError)?;
+		Ok(rows.iter().map(Self::event_from_row).collect())
+	}
+
+	async fn count(&self) -> Result<u32, IndexerError> {
+		// IDs have no gaps, so the highest one is found through the index rather than counting.
+		let row = self
+			.client
+			.query_one("SELECT COALESCE(MAX(id) + 1, 0) AS count FROM events", &[])
+			.await
+			.map_err(IndexerError::PostgresError)?;
+		Ok(row.get::<_, i64>("count") as u32)
+	}
+
+	async fn stats(
+		&self, offset: u32, filter: &EventFilter,
+	) -> Result<BTreeMap<u32, EventStats>, IndexerError> {
+		let schema_ids: Vec<i64> = filter.schema_ids.iter().copied().map(i64::from).collect();
+		let from_timestamp = filter.from_timestamp as i64;
+		let to_timestamp = filter.to_timestamp.map(|to| to as i64);
+		let domains: Vec<i64> = filter.domains.iter().copied().map(i64::from).collect();
+		let rows = self
+			.client
+			.query(
+				"SELECT schema_id, COUNT(*) AS count, MIN(timestamp) AS min_timestamp,
+					MAX(timestamp) AS max_timestamp
+				FROM events
+				WHERE id >= $1 AND (cardinality($2::BIGINT[]) = 0 OR schema_id = ANY($2))
+				AND timestamp >= $3 AND ($4::BIGINT IS NULL OR timestamp < $4)
+				AND (cardinality($5::BIGINT[]) = 0 OR domain = ANY($5))
+				GROUP BY schema_id",
+				&[&i64::from(offset), &schema_ids, &from_timestamp, &to_timestamp, &domains],
+			)
+			.await
+			.map_err(IndexerError::PostgresError)?;
+		let stats = rows.iter().map(|row| {
+			let stats = EventStats {
+				count: row.get::<_, i64>("count") as u64,
+				min_timestamp: row.get::<_, i64>("min_timestamp") as u64,
+				max_timestamp: row.get::<_, i64>("max_timestamp") as u64,
+			};
+			(row.get::<_, i64>("schema_id") as u32, stats)
+		});
+		Ok(stats.collect())
+	}
+
+	async fn append_checkpointed(
+		&self, events: Vec<SourceEvent>, checkpoints: &[Checkpoint],
+	) -> Result<u32, IndexerError> {
+		let sources: Vec<_> = checkpoints.iter().map(|checkpoint| &checkpoint.source).collect();
+		let positions: Vec<_> = checkpoints.iter().map(|checkpoint| &checkpoint.position).collect();
+		self.insert(APPEND_CHECKPOINTED, events, &[&sources, &positions]).await
+	}
+
+	async fn read_checkpoint(&self, source: &str) -> Result<Option<String>, IndexerError> {
+		let row = self
+			.client
+			.query_opt(
+				"SELECT position FROM checkpoints WHERE source = $1",
+				&[&source],
+			)
+			.await
+			.map_err(IndexerError::PostgresError)?;
+		Ok(row.map(|row| row.get("position")))
+	}
+
+	async fn reindex(&self) -> Result<u32, IndexerError> {
+		let _guard = self.append_lock.lock().await;
+		self.client
+			.batch_execute("REINDEX TABLE events")
+			.await
+			.map_err(IndexerError::PostgresError)?;
+		self.count().await
+	}
+}
+
+// Ignored unless a scratch database is given, e.g.
+// `DATABASE_URL=postgres://localhost/indexer cargo test -p indexer -- --ignored postgres`.
+#[cfg(test)]
+mod test {
+	use super::PostgresStore;
+	use crate::{
+		source::SourceEvent,
+		store::{Checkpoint, EventFilter, EventStore},
+	};
+	use std::env;
+	use tokio_postgres::NoTls;
+
+	/// Store on the database at `DATABASE_URL`, in a schema of its own recreated empty, so
+	/// tests running at once stay apart.
+	async fn store(schema: &str) -> PostgresStore {
+		let url = env::var("DATABASE_URL").expect("DATABASE_URL should name a test database");
+		let (client, connection) = tokio_postgres::connect(&url, NoTls).await.unwrap();
+		tokio::spawn(connection);
+		let sql = format!(
+			"DROP SCHEMA IF EXISTS {0} CASCADE; CREATE SCHEMA {0}",
+			schema
+		);
+		client.batch_execute(&sql).await.unwrap();
+		connect(&url, schema).await
+	}
+
+	async fn connect(url: &str, schema: &str) -> PostgresStore {
+		let separator = if url.contains('?') { '&' } else { '?' };
+		let url = format!("{}{}options=-csearch_path%3D{}", url, separator, schema);
+		PostgresStore::connect(&url).await.unwrap()
+	}
+
+	#[tokio::test]
+	#[ignore]
+	async fn should_append_and_read_window() {
+		let store = store("indexer_window_test").await;
+		let events = |range: std::ops::Range<u64>| {
+			range
+				.map(|timestamp| SourceEvent {
+					schema_id: 1,
+					schema_value: "{}".to_string(),
+					timestamp,
+					..SourceEvent::default()
+				})
+				.collect()
+		};
+		assert_eq!(store.append(events(0..6)).await.unwrap(), 6);
+		assert_eq!(store.append(events(6..10)).await.unwrap(), 10);
+		assert_eq!(store.count().await.unwrap(), 10);
+
+		let events = store.read(8, 5, &EventFilter::default()).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.timestamp)).collect();
+		assert_eq!(ids, vec![(8, 8), (9, 9)], "should stop at the last event");
+		assert!(store.read(10, 1, &EventFilter::default()).await.unwrap().is_empty());
+		assert!(store.read(3, 0, &EventFilter::default()).await.unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	#[ignore]
+	async fn should_filter_by_time_range() {
+		let store = store("indexer_time_test").await;
+		// Timestamps go backwards, so the time order differs from the ID order.
+		let events = (0..9)
+			.map(|i| SourceEvent {
+				schema_id: i % 3 + 1,
+				schema_value: "{}".to_string(),
+				timestamp: 100 - u64::from(i) * 10,
+				..SourceEvent::default()
+			})
+			.collect();
+		store.append(events).await.unwrap();
+
+		let window =
+			EventFilter { from_timestamp: 30, to_timestamp: Some(80), ..EventFilter::default() };
+		let events = store.read(3, 10, &window).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.timestamp)).collect();
+		assert_eq!(ids, vec![(3, 70), (4, 60), (5, 50), (6, 40), (7, 30)]);
+		let events = store.read(5, 2, &window).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| event.id).collect();
+		assert_eq!(ids, vec![5, 6], "should page from the offset");
+
+		let filter = EventFilter { schema_ids: [2].into(), ..window };
+		let events = store.read(0, 1, &filter).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.timestamp)).collect();
+		assert_eq!(
+			ids,
+			vec![(4, 60)],
+			"should apply both the schema and the time range"
+		);
+	}
+
+	#[tokio::test]
+	#[ignore]
+	async fn should_keep_checkpoints_across_restarts() {
+		let store = store("indexer_checkpoint_test").await;
+		assert_eq!(store.read_checkpoint("eas").await.unwrap(), None);
+		let checkpoint = |position: &str| Checkpoint {
+			source: "eas".to_string(),
+			position: position.to_string(),
+		};
+		let event =
+			SourceEvent { schema_id: 1, source: "eas".to_string(), ..SourceEvent::default() };
+		let count = store.append_checkpointed(vec![event.clone()], &[checkpoint("120")]).await;
+		assert_eq!(count.unwrap(), 1);
+		store.append_checkpointed(Vec::new(), &[checkpoint("180")]).await.unwrap();
+		drop(store);
+
+		let url = env::var("DATABASE_URL").unwrap();
+		let store = connect(&url, "indexer_checkpoint_test").await;
+		assert_eq!(
+			store.read_checkpoint("eas").await.unwrap().as_deref(),
+			Some("180")
+		);
+		assert_eq!(
+			store.append_checkpointed(vec![event], &[checkpoint("240")]).await.unwrap(),
+			2,
+			"should number events on from the highest ID"
+		);
+		let events = store.read(0, 2, &EventFilter::default()).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.source.as_str())).collect();
+		assert_eq!(
+			ids,
+			vec![(0, "eas"), (1, "eas")],
+			"should record where events came from"
+		);
+	}
+}
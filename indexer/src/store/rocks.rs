@@ -0,0 +1,580 @@
+use super::{merge_ids, Checkpoint, EventFilter, EventStats, EventStore};
+use crate::{error::IndexerError, source::SourceEvent};
+use prost::Message;
+use proto_buf::indexer::IndexerEvent;
+use rocksdb::{
+	AsColumnFamilyRef, ColumnFamilyDescriptor, Direction, IteratorMode, Options, WriteBatch, DB,
+};
+use std::{
+	collections::BTreeMap,
+	path::Path,
+	sync::{Arc, Mutex, PoisonError, RwLock},
+};
+
+/// Event ID -> encoded `IndexerEvent`.
+const EVENTS_CF: &str = "events";
+/// Schema ID and event ID -> timestamp.
+const SCHEMA_INDEX_CF: &str = "schema_index";
+/// Timestamp and event ID -> schema ID.
+const TIME_INDEX_CF: &str = "time_index";
+/// Source name -> position.
+const CHECKPOINTS_CF: &str = "checkpoints";
+const COLUMN_FAMILIES: [&str; 4] = [EVENTS_CF, SCHEMA_INDEX_CF, TIME_INDEX_CF, CHECKPOINTS_CF];
+/// Index entries written at a time when reindexing.
+const REINDEX_BATCH_SIZE: usize = 10_000;
+
+/// Event log of a `RocksDbStore`, shared with the blocking tasks reading and writing it.
+struct Log {
+	db: DB,
+	/// Serializes appends, which would otherwise race for the same IDs.
+	append_lock: Mutex<()>,
+	/// Held by reads going through the indexes, and exclusively while reindexing, so none of
+	/// them sees the indexes half rebuilt.
+	index_lock: RwLock<()>,
+}
+
+/// Keeps events in a RocksDB log keyed by big-endian event IDs, with secondary indexes
+/// on schema and timestamp.
+pub struct RocksDbStore {
+	log: Arc<Log>,
+}
+
+impl RocksDbStore {
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, IndexerError> {
+		Self::open_with(&Options::default(), path)
+	}
+
+	fn open_with(base: &Options, path: impl AsRef<Path>) -> Result<Self, IndexerError> {
+		let mut opts = base.clone();
+		opts.create_if_missing(true);
+		opts.create_missing_column_families(true);
+		let cfs = COLUMN_FAMILIES.map(|name| ColumnFamilyDescriptor::new(name, Options::default()));
+		let db = DB::open_cf_descriptors(&opts, path, cfs).map_err(IndexerError::DbError)?;
+		let log = Log { db, append_lock: Mutex::new(()), index_lock: RwLock::new(()) };
+		Ok(Self { log: Arc::new(log) })
+	}
+
+	/// Runs `f` on the log off the async runtime, as RocksDB calls block.
+	async fn with_log<T, F>(&self, f: F) -> Result<T, IndexerError>
+	where
+		T: Send + 'static,
+		F: FnOnce(&Log) -> Result<T, IndexerError> + Send + 'static,
+	{
+		let log = self.log.clone();
+		tokio::task::spawn_blocking(move || f(&log)).await.map_err(|_| IndexerError::TaskError)?
+	}
+}
+
+impl Log {
+	fn decode(value: &[u8]) -> Result<IndexerEvent, IndexerError> {
+		IndexerEvent::decode(value).map_err(|_| IndexerError::ParseError)
+	}
+
+	/// Stats of the events matching `filter`, domains included, read off the events themselves
+	/// as no index holds their domains.
+	fn scan_stats(
+		&self, offset: u32, filter: &EventFilter,
+	) -> Result<BTreeMap<u32, EventStats>, IndexerError> {
+		let events_cf = self.db.cf_handle(EVENTS_CF).ok_or(IndexerError::NotFoundError)?;
+		let start = offset.to_be_bytes();
+		let mode = IteratorMode::From(&start, Direction::Forward);
+		let mut stats = BTreeMap::<_, EventStats>::new();
+		for item in self.db.iterator_cf(&events_cf, mode) {
+			let (_, value) = item.map_err(IndexerError::DbError)?;
+			let event = Self::decode(&value)?;
+			if filter.matches(&event) && filter.matches_domain(&event) {
+				stats.entry(event.schema_id).or_default().add(event.timestamp);
+			}
+		}
+		Ok(stats)
+	}
+
+	/// IDs of the first `count` events from `offset` having one of the filter's schemas and
+	/// a timestamp within its range, read off the schema index.
+	fn read_schema_index(
+		&self, offset: u32, count: u32, filter: &EventFilter,
+	) -> Result<Vec<u32>, IndexerError> {
+		let schema_cf = self.db.cf_handle(SCHEMA_INDEX_CF).ok_or(IndexerError::NotFoundError)?;
+		let mut lists = Vec::with_capacity(filter.schema_ids.len());
+		for schema_id in &filter.schema_ids {
+			let prefix = schema_id.to_be_bytes();
+			let start = [prefix, offset.to_be_bytes()].concat();
+			let mode = IteratorMode::From(&start, Direction::Forward);
+			let mut ids = Vec::new();
+			for item in self.db.iterator_cf(&schema_cf, mode) {
+				let (key, value) = item.map_err(IndexerError::DbError)?;
+				if !key.starts_with(&prefix) || ids.len() == count as usize {
+					break;
+				}
+				let timestamp = value.as_ref().try_into().map_err(|_| IndexerError::ParseError)?;
+				if filter.contains_timestamp(u64::from_be_bytes(timestamp)) {
+					ids.push(u32::from_be_bytes(key[4..8].try_into().unwrap()));
+				}
+			}
+			lists.push(ids);
+		}
+		Ok(merge_ids(lists, count))
+	}
+
+	/// The first `count` events from `offset` within the filter's time range, scanning the log
+	/// itself. IDs don't follow timestamps, so the time index can't be sought by ID, while the
+	/// log lets every page of a stream pick up where the last one ended.
+	fn scan_events(
+		&self, offset: u32, count: u32, filter: &EventFilter,
+	) -> Result<Vec<IndexerEvent>, IndexerError> {
+		let events_cf = self.db.cf_handle(EVENTS_CF).ok_or(IndexerError::NotFoundError)?;
+		let start = offset.to_be_bytes();
+		let mode = IteratorMode::From(&start, Direction::Forward);
+		let mut events = Vec::new();
+		for item in self.db.iterator_cf(&events_cf, mode) {
+			if events.len() == count as usize {
+				break;
+			}
+			let (_, value) = item.map_err(IndexerError::DbError)?;
+			let event = Self::decode(&value)?;
+			if filter.contains_timestamp(event.timestamp) {
+				events.push(event);
+			}
+		}
+		Ok(events)
+	}
+
+	/// Stats of the events from `offset` within the filter's time range, read off the time
+	/// index. Only the requested period is scanned.
+	fn time_index_stats(
+		&self, offset: u32, filter: &EventFilter,
+	) -> Result<BTreeMap<u32, EventStats>, IndexerError> {
+		let time_cf = self.db.cf_handle(TIME_INDEX_CF).ok_or(IndexerError::NotFoundError)?;
+		let start = filter.from_timestamp.to_be_bytes();
+		let mode = IteratorMode::From(&start, Direction::Forward);
+		let mut stats = BTreeMap::<_, EventStats>::new();
+		for item in self.db.iterator_cf(&time_cf, mode) {
+			let (key, value) = item.map_err(IndexerError::DbError)?;
+			let timestamp = u64::from_be_bytes(key[..8].try_into().unwrap());
+			if !filter.contains_timestamp(timestamp) {
+				break;
+			}
+			let id = u32::from_be_bytes(key[8..12].try_into().unwrap());
+			let schema_id = value.as_ref().try_into().map_err(|_| IndexerError::ParseError)?;
+			if id >= offset {
+				stats.entry(u32::from_be_bytes(schema_id)).or_default().add(timestamp);
+			}
+		}
+		Ok(stats)
+	}
+
+	fn next_id(&self) -> Result<u32, IndexerError> {
+		let events_cf = self.db.cf_handle(EVENTS_CF).ok_or(IndexerError::NotFoundError)?;
+		match self.db.iterator_cf(&events_cf, IteratorMode::End).next() {
+			Some(item) => {
+				let (key, _) = item.map_err(IndexerError::DbError)?;
+				let id = key.as_ref().try_into().map_err(|_| IndexerError::ParseError)?;
+				Ok(u32::from_be_bytes(id) + 1)
+			},
+			None => Ok(0),
+		}
+	}
+
+	/// Adds `event` to the schema and time indexes.
+	fn index(
+		batch: &mut WriteBatch, schema_cf: &impl AsColumnFamilyRef,
+		time_cf: &impl AsColumnFamilyRef, event: &IndexerEvent,
+	) {
+		let id_bytes = event.id.to_be_bytes();
+		batch.put_cf(
+			schema_cf,
+			[event.schema_id.to_be_bytes(), id_bytes].concat(),
+			event.timestamp.to_be_bytes(),
+		);
+		batch.put_cf(
+			time_cf,
+			[event.timestamp.to_be_bytes().as_slice(), &id_bytes].concat(),
+			event.schema_id.to_be_bytes(),
+		);
+	}
+
+	/// Appends `events` in one batch, along with `checkpoints`.
+	fn write(
+		&self, events: Vec<SourceEvent>, checkpoints: &[Checkpoint],
+	) -> Result<u32, IndexerError> {
+		let events_cf = self.db.cf_handle(EVENTS_CF).ok_or(IndexerError::NotFoundError)?;
+		let schema_cf = self.db.cf_handle(SCHEMA_INDEX_CF).ok_or(IndexerError::NotFoundError)?;
+		let time_cf = self.db.cf_handle(TIME_INDEX_CF).ok_or(IndexerError::NotFoundError)?;
+
+		let _guard = self.append_lock.lock().unwrap_or_else(PoisonError::into_inner);
+		let mut id = self.next_id()?;
+		let mut batch = WriteBatch::default();
+		for event in events {
+			let event = IndexerEvent {
+				id,
+				schema_id: event.schema_id,
+				schema_value: event.schema_value,
+				timestamp: event.timestamp,
+				source: event.source,
+				received_at: event.received_at,
+				domain: event.domain,
+			};
+			batch.put_cf(&events_cf, id.to_be_bytes(), event.encode_to_vec());
+			Self::index(&mut batch, &schema_cf, &time_cf, &event);
+			id += 1;
+		}
+		if !checkpoints.is_empty() {
+			let checkpoints_cf =
+				self.db.cf_handle(CHECKPOINTS_CF).ok_or(IndexerError::NotFoundError)?;
+			for checkpoint in checkpoints {
+				batch.put_cf(&checkpoints_cf, &checkpoint.source, &checkpoint.position);
+			}
+		}
+		self.db.write(batch).map_err(IndexerError::DbError)?;
+		Ok(id)
+	}
+
+	fn read(
+		&self, offset: u32, count: u32, filter: &EventFilter,
+	) -> Result<Vec<IndexerEvent>, IndexerError> {
+		if filter.schema_ids.is_empty() {
+			return self.scan_events(offset, count, filter);
+		}
+		let _guard = self.index_lock.read().unwrap_or_else(PoisonError::into_inner);
+
+		let events_cf = self.db.cf_handle(EVENTS_CF).ok_or(IndexerError::NotFoundError)?;
+		let ids = self.read_schema_index(offset, count, filter)?;
+		let keys = ids.into_iter().map(|id| (&events_cf, id.to_be_bytes()));
+		let mut events = Vec::new();
+		for value in self.db.multi_get_cf(keys) {
+			let value = value.map_err(IndexerError::DbError)?.ok_or(IndexerError::NotFoundError)?;
+			events.push(Self::decode(&value)?);
+		}
+		Ok(events)
+	}
+
+	/// Scans the schema index, which holds the timestamps, rather than the events, unless
+	/// domains are asked for. Time ranges of every schema are scanned off the time index.
+	fn stats(
+		&self, offset: u32, filter: &EventFilter,
+	) -> Result<BTreeMap<u32, EventStats>, IndexerError> {
+		if !filter.domains.is_empty() {
+			return self.scan_stats(offset, filter);
+		}
+		let _guard = self.index_lock.read().unwrap_or_else(PoisonError::into_inner);
+		if filter.schema_ids.is_empty() && filter.has_time_range() {
+			return self.time_index_stats(offset, filter);
+		}
+		let schema_cf = self.db.cf_handle(SCHEMA_INDEX_CF).ok_or(IndexerError::NotFoundError)?;
+		let prefixes: Vec<Option<[u8; 4]>> = if filter.schema_ids.is_empty() {
+			vec![None]
+		} else {
+			filter.schema_ids.iter().map(|schema_id| Some(schema_id.to_be_bytes())).collect()
+		};
+		let mut stats = BTreeMap::<_, EventStats>::new();
+		for prefix in prefixes {
+			let mode = match &prefix {
+				Some(prefix) => IteratorMode::From(prefix, Direction::Forward),
+				None => IteratorMode::Start,
+			};
+			for item in self.db.iterator_cf(&schema_cf, mode) {
+				let (key, value) = item.map_err(IndexerError::DbError)?;
+				if prefix.map_or(false, |prefix| !key.starts_with(&prefix)) {
+					break;
+				}
+				let schema_id = u32::from_be_bytes(key[..4].try_into().unwrap());
+				let id = u32::from_be_bytes(key[4..8].try_into().unwrap());
+				let timestamp = value.as_ref().try_into().map_err(|_| IndexerError::ParseError)?;
+				let timestamp = u64::from_be_bytes(timestamp);
+				if id >= offset && filter.contains_timestamp(timestamp) {
+					stats.entry(schema_id).or_default().add(timestamp);
+				}
+			}
+		}
+		Ok(stats)
+	}
+
+	fn read_checkpoint(&self, source: &str) -> Result<Option<String>, IndexerError> {
+		let checkpoints_cf =
+			self.db.cf_handle(CHECKPOINTS_CF).ok_or(IndexerError::NotFoundError)?;
+		let position = self.db.get_cf(&checkpoints_cf, source).map_err(IndexerError::DbError)?;
+		position
+			.map(|position| String::from_utf8(position).map_err(|_| IndexerError::ParseError))
+			.transpose()
+	}
+
+	fn reindex(&self) -> Result<u32, IndexerError> {
+		let events_cf = self.db.cf_handle(EVENTS_CF).ok_or(IndexerError::NotFoundError)?;
+		let schema_cf = self.db.cf_handle(SCHEMA_INDEX_CF).ok_or(IndexerError::NotFoundError)?;
+		let time_cf = self.db.cf_handle(TIME_INDEX_CF).ok_or(IndexerError::NotFoundError)?;
+
+		let _append_guard = self.append_lock.lock().unwrap_or_else(PoisonError::into_inner);
+		let _index_guard = self.index_lock.write().unwrap_or_else(PoisonError::into_inner);
+		// Index keys are at most 12 bytes long, so they all precede 13 0xff bytes.
+		for cf in [&schema_cf, &time_cf] {
+			let (first, last): (&[u8], &[u8]) = (&[], &[0xff; 13]);
+			self.db.delete_range_cf(cf, first, last).map_err(IndexerError::DbError)?;
+		}
+		let mut batch = WriteBatch::default();
+		let mut count = 0;
+		for item in self.db.iterator_cf(&events_cf, IteratorMode::Start) {
+			let (_, value) = item.map_err(IndexerError::DbError)?;
+			Self::index(&mut batch, &schema_cf, &time_cf, &Self::decode(&value)?);
+			count += 1;
+			if batch.len() >= REINDEX_BATCH_SIZE {
+				self.db.write(std::mem::take(&mut batch)).map_err(IndexerError::DbError)?;
+			}
+		}
+		self.db.write(batch).map_err(IndexerError::DbError)?;
+		Ok(count)
+	}
+}
+
+#[tonic::async_trait]
+impl EventStore for RocksDbStore {
+	async fn append(&self, events: Vec<SourceEvent>) -> Result<u32, IndexerError> {
+		self.with_log(move |log| log.write(events, &[])).await
+	}
+
+	async fn read(
+		&self, offset: u32, count: u32, filter: &EventFilter,
+	) -> Result<Vec<IndexerEvent>, IndexerError> {
+		let filter = filter.clone();
+		self.with_log(move |log| log.read(offset, count, &filter)).await
+	}
+
+	async fn count(&self) -> Result<u32, IndexerError> {
+		self.with_log(Log::next_id).await
+	}
+
+	async fn stats(
+		&self, offset: u32, filter: &EventFilter,
+	) -> Result<BTreeMap<u32, EventStats>, IndexerError> {
+		let filter = filter.clone();
+		self.with_log(move |log| log.stats(offset, &filter)).await
+	}
+
+	async fn append_checkpointed(
+		&self, events: Vec<SourceEvent>, checkpoints: &[Checkpoint],
+	) -> Result<u32, IndexerError> {
+		let checkpoints = checkpoints.to_vec();
+		self.with_log(move |log| log.write(events, &checkpoints)).await
+	}
+
+	async fn read_checkpoint(&self, source: &str) -> Result<Option<String>, IndexerError> {
+		let source = source.to_string();
+		self.with_log(move |log| log.read_checkpoint(&source)).await
+	}
+
+	async fn reindex(&self) -> Result<u32, IndexerError> {
+		self.with_log(Log::reindex).await
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{RocksDbStore, SCHEMA_INDEX_CF};
+	use crate::{
+		source::SourceEvent,
+		store::{Checkpoint, EventFilter, EventStats, EventStore},
+	};
+	use rocksdb::{Env, Options};
+
+	#[tokio::test]
+	async fn should_append_and_read_window() {
+		let mut opts = Options::default();
+		opts.set_env(&Env::mem_env().unwrap());
+		let store = RocksDbStore::open_with(&opts, "indexer-rocks-test-storage").unwrap();
+		let events = |range: std::ops::Range<u64>| {
+			range
+				.map(|timestamp| SourceEvent {
+					schema_id: 1,
+					schema_value: "{}".to_string(),
+					timestamp,
+					..SourceEvent::default()
+				})
+				.collect()
+		};
+		assert_eq!(store.append(events(0..6)).await.unwrap(), 6);
+		assert_eq!(store.append(events(6..10)).await.unwrap(), 10);
+		assert_eq!(store.count().await.unwrap(), 10);
+
+		let events = store.read(8, 5, &EventFilter::default()).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.timestamp)).collect();
+		assert_eq!(ids, vec![(8, 8), (9, 9)], "should stop at the last event");
+		assert!(store.read(10, 1, &EventFilter::default()).await.unwrap().is_empty());
+		assert!(store.read(3, 0, &EventFilter::default()).await.unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn should_filter_through_schema_index() {
+		let mut opts = Options::default();
+		opts.set_env(&Env::mem_env().unwrap());
+		let store = RocksDbStore::open_with(&opts, "indexer-rocks-filter-storage").unwrap();
+		let events = (0..9)
+			.map(|i| SourceEvent {
+				schema_id: i % 3 + 1,
+				schema_value: "{}".to_string(),
+				timestamp: 0,
+				..SourceEvent::default()
+			})
+			.collect();
+		store.append(events).await.unwrap();
+
+		let filter = EventFilter { schema_ids: [1, 3].into(), ..EventFilter::default() };
+		let events = store.read(1, 3, &filter).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.schema_id)).collect();
+		assert_eq!(ids, vec![(2, 3), (3, 1), (5, 3)]);
+
+		let filter = EventFilter { schema_ids: [4].into(), ..EventFilter::default() };
+		assert!(store.read(0, 10, &filter).await.unwrap().is_empty());
+	}
+
+	#[tokio::test]
+	async fn should_filter_by_time_range() {
+		let mut opts = Options::default();
+		opts.set_env(&Env::mem_env().unwrap());
+		let store = RocksDbStore::open_with(&opts, "indexer-rocks-time-storage").unwrap();
+		// Timestamps go backwards, so the time order differs from the ID order.
+		let events = (0..9)
+			.map(|i| SourceEvent {
+				schema_id: i % 3 + 1,
+				schema_value: "{}".to_string(),
+				timestamp: 100 - u64::from(i) * 10,
+				..SourceEvent::default()
+			})
+			.collect();
+		store.append(events).await.unwrap();
+
+		let window =
+			EventFilter { from_timestamp: 30, to_timestamp: Some(80), ..EventFilter::default() };
+		let events = store.read(3, 10, &window).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.timestamp)).collect();
+		assert_eq!(ids, vec![(3, 70), (4, 60), (5, 50), (6, 40), (7, 30)]);
+		let events = store.read(5, 2, &window).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| event.id).collect();
+		assert_eq!(ids, vec![5, 6], "should page from the offset");
+
+		let filter = EventFilter { schema_ids: [2].into(), ..window };
+		let events = store.read(0, 1, &filter).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.timestamp)).collect();
+		assert_eq!(
+			ids,
+			vec![(4, 60)],
+			"should apply both the schema and the time range"
+		);
+	}
+
+	#[tokio::test]
+	async fn should_keep_checkpoints_across_restarts() {
+		let mut opts = Options::default();
+		opts.set_env(&Env::mem_env().unwrap());
+		let path = "indexer-rocks-checkpoint-storage";
+		let store = RocksDbStore::open_with(&opts, path).unwrap();
+		assert_eq!(store.read_checkpoint("eas").await.unwrap(), None);
+		let checkpoint = |position: &str| Checkpoint {
+			source: "eas".to_string(),
+			position: position.to_string(),
+		};
+		let event =
+			SourceEvent { schema_id: 1, source: "eas".to_string(), ..SourceEvent::default() };
+		store.append_checkpointed(vec![event], &[checkpoint("120")]).await.unwrap();
+		store.append_checkpointed(Vec::new(), &[checkpoint("180")]).await.unwrap();
+		drop(store);
+
+		let store = RocksDbStore::open_with(&opts, path).unwrap();
+		assert_eq!(
+			store.read_checkpoint("eas").await.unwrap().as_deref(),
+			Some("180")
+		);
+		let events = store.read(0, 1, &EventFilter::default()).await.unwrap();
+		assert_eq!(
+			events[0].source, "eas",
+			"should record where events came from"
+		);
+	}
+
+	#[tokio::test]
+	async fn should_rebuild_indexes() {
+		let mut opts = Options::default();
+		opts.set_env(&Env::mem_env().unwrap());
+		let store = RocksDbStore::open_with(&opts, "indexer-rocks-reindex-storage").unwrap();
+		let events = (0..5)
+			.map(|i| SourceEvent {
+				schema_id: i % 2 + 1,
+				timestamp: i.into(),
+				..SourceEvent::default()
+			})
+			.collect();
+		store.append(events).await.unwrap();
+		let schema_cf = store.log.db.cf_handle(SCHEMA_INDEX_CF).unwrap();
+		store
+			.log
+			.db
+			.delete_cf(
+				&schema_cf,
+				[1u32.to_be_bytes(), 2u32.to_be_bytes()].concat(),
+			)
+			.unwrap();
+
+		let filter = EventFilter { schema_ids: [1].into(), ..EventFilter::default() };
+		assert_eq!(store.read(0, 5, &filter).await.unwrap().len(), 2);
+		assert_eq!(store.reindex().await.unwrap(), 5);
+		let events = store.read(0, 5, &filter).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| event.id).collect();
+		assert_eq!(ids, vec![0, 2, 4], "should restore lost entries");
+		let filter = EventFilter { from_timestamp: 3, ..EventFilter::default() };
+		assert_eq!(store.read(0, 5, &filter).await.unwrap().len(), 2);
+	}
+
+	#[tokio::test]
+	async fn should_compute_stats_by_schema() {
+		let mut opts = Options::default();
+		opts.set_env(&Env::mem_env().unwrap());
+		let store = RocksDbStore::open_with(&opts, "indexer-rocks-stats-storage").unwrap();
+		let events = (0..9)
+			.map(|i| SourceEvent {
+				schema_id: i % 3 + 1,
+				schema_value: "{}".to_string(),
+				timestamp: 100 - u64::from(i) * 10,
+				domain: i % 2,
+				..SourceEvent::default()
+			})
+			.collect();
+		store.append(events).await.unwrap();
+
+		let stats = store.stats(0, &EventFilter::default()).await.unwrap();
+		assert_eq!(stats.len(), 3);
+		assert_eq!(
+			stats[&1],
+			EventStats { count: 3, min_timestamp: 40, max_timestamp: 100 }
+		);
+
+		let filter = EventFilter {
+			schema_ids: [2, 4].into(),
+			to_timestamp: Some(90),
+			..EventFilter::default()
+		};
+		let stats = store.stats(2, &filter).await.unwrap();
+		assert_eq!(stats.keys().collect::<Vec<_>>(), vec![&2]);
+		assert_eq!(
+			stats[&2],
+			EventStats { count: 2, min_timestamp: 30, max_timestamp: 60 },
+			"should only count matching events from the offset"
+		);
+
+		let window =
+			EventFilter { from_timestamp: 30, to_timestamp: Some(80), ..EventFilter::default() };
+		let stats = store.stats(4, &window).await.unwrap();
+		assert_eq!(stats.keys().collect::<Vec<_>>(), vec![&1, &2, &3]);
+		assert_eq!(
+			stats[&2],
+			EventStats { count: 2, min_timestamp: 30, max_timestamp: 60 },
+			"should count the events of the period from the offset"
+		);
+
+		let filter = EventFilter { domains: [1].into(), ..EventFilter::default() };
+		let stats = store.stats(0, &filter).await.unwrap();
+		assert_eq!(stats.keys().collect::<Vec<_>>(), vec![&1, &2, &3]);
+		assert_eq!(
+			stats[&2],
+			EventStats { count: 2, min_timestamp: 30, max_timestamp: 90 },
+			"should only count events of the domains asked for"
+		);
+	}
+}
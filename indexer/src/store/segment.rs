@@ -0,0 +1,456 @@
+use super::{Checkpoint, EventFilter, EventStats, EventStore};
+use crate::{
+	error::IndexerError,
+	source::{now_secs, SourceEvent},
+};
+use proto_buf::indexer::IndexerEvent;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+	collections::{BTreeMap, HashMap},
+	fs::{self, File, OpenOptions},
+	io::{BufRead, BufReader, Write},
+	path::{Path, PathBuf},
+	process::Command,
+	sync::{Arc, Mutex, PoisonError},
+	thread,
+};
+
+/// When the active segment is sealed for a new one to be started.
+#[derive(Debug, Clone, Default)]
+pub struct Rotation {
+	/// Size in bytes the active segment is sealed at.
+	pub max_bytes: u64,
+	/// Seconds the active segment is sealed after, never if zero.
+	pub max_age_secs: u64,
+	/// Shell command run on every sealed segment, given its path as `$1`.
+	pub archive_command: Option<String>,
+}
+
+/// Events appended at once, along with the checkpoints saved with them. Every batch is a
+/// line of JSON, so one torn by a crash is dropped whole.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Batch {
+	events: Vec<Event>,
+	checkpoints: Vec<Checkpoint>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Event {
+	id: u32,
+	schema_id: u32,
+	schema_value: String,
+	timestamp: u64,
+	source: String,
+	received_at: u64,
+	domain: u32,
+}
+
+impl From<Event> for IndexerEvent {
+	fn from(event: Event) -> Self {
+		IndexerEvent {
+			id: event.id,
+			schema_id: event.schema_id,
+			schema_value: event.schema_value,
+			timestamp: event.timestamp,
+			source: event.source,
+			received_at: event.received_at,
+			domain: event.domain,
+		}
+	}
+}
+
+/// File of the events from `first_id` on, up to the first ID of the next segment.
+#[derive(Debug, Clone)]
+struct Segment {
+	first_id: u32,
+	/// Unix time in seconds the segment was started at.
+	created_at: u64,
+	path: PathBuf,
+}
+
+impl Segment {
+	fn new(dir: &Path, first_id: u32, created_at: u64) -> Self {
+		let path = dir.join(format!("{:010}-{}.jsonl", first_id, created_at));
+		Self { first_id, created_at, path }
+	}
+
+	/// Recognizes segments by their `<first ID>-<creation time>.jsonl` name.
+	fn parse(path: PathBuf) -> Option<Self> {
+		let name = path.file_name()?.to_str()?.strip_suffix(".jsonl")?;
+		let (first_id, created_at) = name.split_once('-')?;
+		Some(Self { first_id: first_id.parse().ok()?, created_at: created_at.parse().ok()?, path })
+	}
+
+	/// Reads the batches written in full, leaving out one still being written or torn.
+	fn read_batches(&self, mut f: impl FnMut(Batch, usize) -> bool) -> Result<(), IndexerError> {
+		let file = File::open(&self.path).map_err(IndexerError::IoError)?;
+		let mut reader = BufReader::new(file);
+		let mut line = Vec::new();
+		loop {
+			line.clear();
+			reader.read_until(b'\n', &mut line).map_err(IndexerError::IoError)?;
+			if line.last() != Some(&b'\n') {
+				return Ok(());
+			}
+			let batch = serde_json::from_slice(&line).map_err(|_| IndexerError::ParseError)?;
+			if !f(batch, line.len()) {
+				return Ok(());
+			}
+		}
+	}
+}
+
+struct State {
+	dir: PathBuf,
+	/// Sealed segments followed by the active one, in order of ID.
+	segments: Vec<Segment>,
+	active: File,
+	/// Bytes of the active segment holding whole batches.
+	active_len: u64,
+	next_id: u32,
+	/// Position of every source, by name.
+	checkpoints: HashMap<String, String>,
+}
+
+impl State {
+	fn open(dir: PathBuf) -> Result<Self, IndexerError> {
+		fs::create_dir_all(&dir).map_err(IndexerError::IoError)?;
+		let mut segments = Vec::new();
+		for entry in fs::read_dir(&dir).map_err(IndexerError::IoError)? {
+			let path = entry.map_err(IndexerError::IoError)?.path();
+			segments.extend(Segment::parse(path));
+		}
+		segments.sort_by_key(|segment| segment.first_id);
+		if segments.is_empty() {
+			segments.push(Segment::new(&dir, 0, now_secs()));
+		}
+
+		let last = segments.last().unwrap();
+		let active = Self::create(&last.path)?;
+		let mut next_id = last.first_id;
+		let mut checkpoints = HashMap::new();
+		let mut active_len = 0;
+		last.read_batches(|batch, len| {
+			next_id = batch.events.last().map_or(next_id, |event| event.id + 1);
+			for checkpoint in batch.checkpoints {
+				checkpoints.insert(checkpoint.source, checkpoint.position);
+			}
+			active_len += len as u64;
+			true
+		})?;
+		// A crash right after rotating leaves the active segment without the batch carrying
+		// the checkpoints, which the sealed one before it still holds.
+		if active_len == 0 && segments.len() > 1 {
+			segments[segments.len() - 2].read_batches(|batch, _| {
+				for checkpoint in batch.checkpoints {
+					checkpoints.insert(checkpoint.source, checkpoint.position);
+				}
+				true
+			})?;
+		}
+		// Drops a batch torn by a crash, for the next one to start on a line of its own.
+		active.set_len(active_len).map_err(IndexerError::IoError)?;
+		Ok(Self { dir, segments, active, active_len, next_id, checkpoints })
+	}
+
+	fn create(path: &Path) -> Result<File, IndexerError> {
+		OpenOptions::new().create(true).append(true).open(path).map_err(IndexerError::IoError)
+	}
+
+	fn write(
+		&mut self, events: Vec<SourceEvent>, mut checkpoints: Vec<Checkpoint>, rotation: &Rotation,
+	) -> Result<u32, IndexerError> {
+		if self.should_rotate(rotation) {
+			self.rotate(rotation)?;
+		}
+		// The first batch of a segment carries every checkpoint, so that opening the store
+		// only reads the active segment.
+		if self.active_len == 0 {
+			for (source, position) in &self.checkpoints {
+				if !checkpoints.iter().any(|checkpoint| &checkpoint.source == source) {
+					let checkpoint =
+						Checkpoint { source: source.clone(), position: position.clone() };
+					checkpoints.push(checkpoint);
+				}
+			}
+		}
+		let first_id = self.next_id;
+		let events: Vec<_> = (first_id..)
+			.zip(events)
+			.map(|(id, event)| Event {
+				id,
+				schema_id: event.schema_id,
+				schema_value: event.schema_value,
+				timestamp: event.timestamp,
+				source: event.source,
+				received_at: event.received_at,
+				domain: event.domain,
+			})
+			.collect();
+		let count = events.len() as u32;
+		self.write_batch(&Batch { events, checkpoints })?;
+		self.next_id = first_id + count;
+		Ok(self.next_id)
+	}
+
+	fn write_batch(&mut self, batch: &Batch) -> Result<(), IndexerError> {
+		let mut line = serde_json::to_vec(batch).map_err(|_| IndexerError::ParseError)?;
+		line.push(b'\n');
+		let written = self.active.write_all(&line).and_then(|()| self.active.sync_data());
+		if let Err(e) = written {
+			// Part of the batch may have made it to the file, which the next one would follow.
+			let _ = self.active.set_len(self.active_len);
+			return Err(IndexerError::IoError(e));
+		}
+		self.active_len += line.len() as u64;
+		for checkpoint in &batch.checkpoints {
+			self.checkpoints.insert(checkpoint.source.clone(), checkpoint.position.clone());
+		}
+		Ok(())
+	}
+
+	fn should_rotate(&self, rotation: &Rotation) -> bool {
+		let active = self.segments.last().unwrap();
+		let age = now_secs().saturating_sub(active.created_at);
+		self.next_id > active.first_id
+			&& (self.active_len >= rotation.max_bytes
+				|| (rotation.max_age_secs > 0 && age >= rotation.max_age_secs))
+	}
+
+	/// Seals the active segment and starts the next one.
+	fn rotate(&mut self, rotation: &Rotation) -> Result<(), IndexerError> {
+		let segment = Segment::new(&self.dir, self.next_id, now_secs());
+		self.active = Self::create(&segment.path)?;
+		self.active_len = 0;
+		self.segments.push(segment);
+		if let Some(command) = &rotation.archive_command {
+			let sealed = &self.segments[self.segments.len() - 2];
+			archive(command, sealed.path.clone());
+		}
+		Ok(())
+	}
+}
+
+/// Runs `command` on a sealed segment in the background, logging failures.
+fn archive(command: &str, path: PathBuf) {
+	let child = Command::new("sh").arg("-c").arg(command).arg("sh").arg(&path).spawn();
+	thread::spawn(move || match child.and_then(|mut child| child.wait()) {
+		Ok(status) if status.success() => {},
+		Ok(status) => println!("Archiving {} failed: {}", path.display(), status),
+		Err(e) => println!("Archiving {} failed: {}", path.display(), e),
+	});
+}
+
+/// Keeps events in a directory of JSON lines files, started anew once the active one grows
+/// too large or old. Sealed segments can be archived, e.g. uploaded to S3, and are read
+/// along with the active one.
+pub struct SegmentStore {
+	state: Arc<Mutex<State>>,
+	rotation: Arc<Rotation>,
+}
+
+impl SegmentStore {
+	pub fn open(dir: impl AsRef<Path>, rotation: Rotation) -> Result<Self, IndexerError> {
+		let state = State::open(dir.as_ref().to_path_buf())?;
+		Ok(Self { state: Arc::new(Mutex::new(state)), rotation: Arc::new(rotation) })
+	}
+
+	/// Runs `f` on the state off the async runtime, as file operations block.
+	async fn with_state<T, F>(&self, f: F) -> Result<T, IndexerError>
+	where
+		T: Send + 'static,
+		F: FnOnce(&mut State, &Rotation) -> Result<T, IndexerError> + Send + 'static,
+	{
+		let (state, rotation) = (self.state.clone(), self.rotation.clone());
+		tokio::task::spawn_blocking(move || {
+			let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+			f(&mut state, &rotation)
+		})
+		.await
+		.map_err(|_| IndexerError::TaskError)?
+	}
+
+	/// Folds the events from ID `offset` on into `acc` in order, until `f` returns false.
+	/// Only the segments holding them are read, without holding up appends.
+	async fn scan<T, F>(&self, offset: u32, mut acc: T, mut f: F) -> Result<T, IndexerError>
+	where
+		T: Send + 'static,
+		F: FnMut(&mut T, IndexerEvent) -> bool + Send + 'static,
+	{
+		let (segments, end) = {
+			let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+			(state.segments.clone(), state.next_id)
+		};
+		tokio::task::spawn_blocking(move || {
+			let start = segments.partition_point(|segment| segment.first_id <= offset);
+			let mut more = true;
+			for segment in &segments[start.saturating_sub(1)..] {
+				segment.read_batches(|batch, _| {
+					for event in batch.events {
+						// Batches appended since the scan started are left out.
+						if event.id >= end {
+							more = false;
+						} else if event.id >= offset {
+							more = f(&mut acc, event.into());
+						}
+						if !more {
+							break;
+						}
+					}
+					more
+				})?;
+				if !more {
+					break;
+				}
+			}
+			Ok(acc)
+		})
+		.await
+		.map_err(|_| IndexerError::TaskError)?
+	}
+}
+
+#[tonic::async_trait]
+impl EventStore for SegmentStore {
+	async fn append(&self, events: Vec<SourceEvent>) -> Result<u32, IndexerError> {
+		self.with_state(move |state, rotation| state.write(events, Vec::new(), rotation)).await
+	}
+
+	async fn read(
+		&self, offset: u32, count: u32, filter: &EventFilter,
+	) -> Result<Vec<IndexerEvent>, IndexerError> {
+		if count == 0 {
+			return Ok(Vec::new());
+		}
+		let filter = filter.clone();
+		self.scan(offset, Vec::new(), move |events, event| {
+			if filter.matches(&event) {
+				events.push(event);
+			}
+			events.len() < count as usize
+		})
+		.await
+	}
+
+	async fn count(&self) -> Result<u32, IndexerError> {
+		Ok(self.state.lock().unwrap_or_else(PoisonError::into_inner).next_id)
+	}
+
+	async fn stats(
+		&self, offset: u32, filter: &EventFilter,
+	) -> Result<BTreeMap<u32, EventStats>, IndexerError> {
+		let filter = filter.clone();
+		self.scan(offset, BTreeMap::new(), move |stats, event| {
+			if filter.matches(&event) && filter.matches_domain(&event) {
+				let schema_stats: &mut EventStats = stats.entry(event.schema_id).or_default();
+				schema_stats.add(event.timestamp);
+			}
+			true
+		})
+		.await
+	}
+
+	async fn append_checkpointed(
+		&self, events: Vec<SourceEvent>, checkpoints: &[Checkpoint],
+	) -> Result<u32, IndexerError> {
+		let checkpoints = checkpoints.to_vec();
+		self.with_state(move |state, rotation| state.write(events, checkpoints, rotation)).await
+	}
+
+	async fn read_checkpoint(&self, source: &str) -> Result<Option<String>, IndexerError> {
+		let state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+		Ok(state.checkpoints.get(source).cloned())
+	}
+
+	/// Segments are only ever scanned, leaving nothing to rebuild.
+	async fn reindex(&self) -> Result<u32, IndexerError> {
+		self.count().await
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Rotation, Segment, SegmentStore};
+	use crate::{
+		source::SourceEvent,
+		store::{Checkpoint, EventFilter, EventStore},
+	};
+	use std::{
+		env,
+		fs::{self, File},
+		io::Write,
+	};
+
+	#[tokio::test]
+	async fn should_read_across_rotated_segments() {
+		let dir = env::temp_dir().join(format!("indexer-segments-{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		let rotation = Rotation { max_bytes: 1, ..Rotation::default() };
+		let store = SegmentStore::open(&dir, rotation.clone()).unwrap();
+		let event = |schema_id, timestamp| SourceEvent {
+			schema_id,
+			schema_value: "{}".to_string(),
+			timestamp,
+			..SourceEvent::default()
+		};
+		let checkpoint = Checkpoint { source: "eas".to_string(), position: "7".to_string() };
+		store.append_checkpointed(vec![event(1, 10), event(2, 11)], &[checkpoint]).await.unwrap();
+		store.append(vec![event(1, 12)]).await.unwrap();
+		store.append(vec![event(2, 13), event(1, 14)]).await.unwrap();
+		assert_eq!(fs::read_dir(&dir).unwrap().count(), 3);
+
+		let filter = EventFilter { schema_ids: [1].into(), ..EventFilter::default() };
+		let events = store.read(1, 10, &filter).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.timestamp)).collect();
+		assert_eq!(ids, vec![(2, 12), (4, 14)], "should read every segment");
+		drop(store);
+
+		// A batch torn by a crash is dropped on reopening.
+		let active = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().path()).max().unwrap();
+		let mut file = fs::OpenOptions::new().append(true).open(active).unwrap();
+		file.write_all(b"{\"events\":[").unwrap();
+		let store = SegmentStore::open(&dir, rotation).unwrap();
+		assert_eq!(store.count().await.unwrap(), 5);
+		assert_eq!(
+			store.read_checkpoint("eas").await.unwrap().as_deref(),
+			Some("7"),
+			"should carry checkpoints over to new segments"
+		);
+		store.append(vec![event(1, 15)]).await.unwrap();
+		let events = store.read(5, 1, &EventFilter::default()).await.unwrap();
+		assert_eq!(events[0].timestamp, 15);
+		fs::remove_dir_all(dir).unwrap();
+	}
+
+	#[tokio::test]
+	async fn should_keep_checkpoints_across_interrupted_rotation() {
+		let dir = env::temp_dir().join(format!("indexer-rotation-{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		let rotation = Rotation { max_bytes: 1, ..Rotation::default() };
+		let store = SegmentStore::open(&dir, rotation.clone()).unwrap();
+		let event = SourceEvent { schema_value: "{}".to_string(), ..SourceEvent::default() };
+		let checkpoint = Checkpoint { source: "eas".to_string(), position: "7".to_string() };
+		store.append_checkpointed(vec![event.clone()], &[checkpoint]).await.unwrap();
+		store.append(vec![event.clone()]).await.unwrap();
+		drop(store);
+
+		// The next segment was created, but the batch carrying the checkpoints never landed.
+		File::create(Segment::new(&dir, 2, 0).path).unwrap();
+		let store = SegmentStore::open(&dir, rotation.clone()).unwrap();
+		assert_eq!(
+			store.read_checkpoint("eas").await.unwrap().as_deref(),
+			Some("7")
+		);
+		store.append(vec![event]).await.unwrap();
+		drop(store);
+
+		let store = SegmentStore::open(&dir, rotation).unwrap();
+		assert_eq!(
+			store.read_checkpoint("eas").await.unwrap().as_deref(),
+			Some("7"),
+			"should carry them over to the new segment"
+		);
+		assert_eq!(store.count().await.unwrap(), 3);
+		fs::remove_dir_all(dir).unwrap();
+	}
+}
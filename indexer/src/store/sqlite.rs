@@ -0,0 +1,248 @@
+use super::{Checkpoint, EventFilter, EventStats, EventStore};
+use crate::{error::IndexerError, source::SourceEvent};
+use proto_buf::indexer::IndexerEvent;
+use rusqlite::{
+	params, params_from_iter, Connection, Error as SqliteError, OptionalExtension, Row,
+};
+use std::{
+	collections::BTreeMap,
+	path::Path,
+	sync::{Arc, Mutex, PoisonError},
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS events (
+    id INTEGER PRIMARY KEY,
+    schema_id INTEGER NOT NULL,
+    schema_value TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    received_at INTEGER NOT NULL,
+    domain INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS events_schema_id_id ON events (schema_id, id);
+CREATE INDEX IF NOT EXISTS events_timestamp ON events (timestamp);
+CREATE TABLE IF NOT EXISTS checkpoints (
+    source TEXT PRIMARY KEY,
+    position TEXT NOT NULL
+);
+";
+
+/// Keeps events in an embedded SQLite database, for single node deployments.
+pub struct SqliteStore {
+	connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+	pub fn open(path: impl AsRef<Path>) -> Result<Self, IndexerError> {
+		Self::new(Connection::open(path).map_err(IndexerError::SqliteError)?)
+	}
+
+	fn new(connection: Connection) -> Result<Self, IndexerError> {
+		connection.execute_batch(SCHEMA).map_err(IndexerError::SqliteError)?;
+		Ok(Self { connection: Arc::new(Mutex::new(connection)) })
+	}
+
+	/// Runs `f` on the connection off the async runtime, as SQLite calls block.
+	async fn with_connection<T, F>(&self, f: F) -> Result<T, IndexerError>
+	where
+		T: Send + 'static,
+		F: FnOnce(&mut Connection) -> Result<T, SqliteError> + Send + 'static,
+	{
+		let connection = self.connection.clone();
+		tokio::task::spawn_blocking(move || {
+			let mut connection = connection.lock().unwrap_or_else(PoisonError::into_inner);
+			f(&mut connection)
+		})
+		.await
+		.map_err(|_| IndexerError::TaskError)?
+		.map_err(IndexerError::SqliteError)
+	}
+
+	fn next_id(connection: &Connection) -> Result<u32, SqliteError> {
+		connection.query_row("SELECT COALESCE(MAX(id) + 1, 0) FROM events", [], |row| {
+			row.get(0)
+		})
+	}
+
+	/// Appends `events` in one transaction, along with `checkpoints`.
+	async fn write(
+		&self, events: Vec<SourceEvent>, checkpoints: Vec<Checkpoint>,
+	) -> Result<u32, IndexerError> {
+		self.with_connection(move |connection| {
+			let transaction = connection.transaction()?;
+			let mut id = Self::next_id(&transaction)?;
+			{
+				let mut insert = transaction.prepare_cached(
+					"INSERT INTO events
+					(id, schema_id, schema_value, timestamp, source, received_at, domain)
+					VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+				)?;
+				for event in events {
+					insert.execute(params![
+						id, event.schema_id, event.schema_value, event.timestamp as i64,
+						event.source, event.received_at as i64, event.domain
+					])?;
+					id += 1;
+				}
+			}
+			for checkpoint in checkpoints {
+				transaction.execute(
+					"INSERT INTO checkpoints (source, position) VALUES (?1, ?2)
+					ON CONFLICT (source) DO UPDATE SET position = excluded.position",
+					params![checkpoint.source, checkpoint.position],
+				)?;
+			}
+			transaction.commit()?;
+			Ok(id)
+		})
+		.await
+	}
+
+	/// `WHERE` conditions selecting the events matching `filter` from ID `offset` on, along with
+	/// their parameters.
+	fn conditions(offset: u32, filter: &EventFilter) -> (String, Vec<i64>) {
+		let mut conditions = "id >= ? AND timestamp >= ?".to_string();
+		let mut params = vec![i64::from(offset), filter.from_timestamp as i64];
+		if let Some(to_timestamp) = filter.to_timestamp {
+			conditions.push_str(" AND timestamp < ?");
+			params.push(to_timestamp as i64);
+		}
+		if !filter.schema_ids.is_empty() {
+			let placeholders = vec!["?"; filter.schema_ids.len()].join(", ");
+			conditions.push_str(&format!(" AND schema_id IN ({})", placeholders));
+			params.extend(filter.schema_ids.iter().copied().map(i64::from));
+		}
+		(conditions, params)
+	}
+
+	fn event_from_row(row: &Row) -> Result<IndexerEvent, SqliteError> {
+		Ok(IndexerEvent {
+			id: row.get(0)?,
+			schema_id: row.get(1)?,
+			schema_value: row.get(2)?,
+			timestamp: row.get::<_, i64>(3)? as u64,
+			source: row.get(4)?,
+			received_at: row.get::<_, i64>(5)? as u64,
+			domain: row.get(6)?,
+		})
+	}
+}
+
+#[tonic::async_trait]
+impl EventStore for SqliteStore {
+	async fn append(&self, events: Vec<SourceEvent>) -> Result<u32, IndexerError> {
+		self.write(events, Vec::new()).await
+	}
+
+	async fn read(
+		&self, offset: u32, count: u32, filter: &EventFilter,
+	) -> Result<Vec<IndexerEvent>, IndexerError> {
+		let (conditions, mut params) = Self::conditions(offset, filter);
+		let sql = format!(
+			"SELECT id, schema_id, schema_value, timestamp, source, received_at, domain
+			FROM events WHERE {} ORDER BY id LIMIT ?",
+			conditions
+		);
+		params.push(i64::from(count));
+
+		self.with_connection(move |connection| {
+			let mut select = connection.prepare_cached(&sql)?;
+			let rows = select.query_map(params_from_iter(params), Self::event_from_row)?;
+			rows.collect()
+		})
+		.await
+	}
+
+	async fn count(&self) -> Result<u32, IndexerError> {
+		self.with_connection(|connection| Self::next_id(connection)).await
+	}
+
+	async fn stats(
+		&self, offset: u32, filter: &EventFilter,
+	) -> Result<BTreeMap<u32, EventStats>, IndexerError> {
+		let (mut conditions, mut params) = Self::conditions(offset, filter);
+		if !filter.domains.is_empty() {
+			let placeholders = vec!["?"; filter.domains.len()].join(", ");
+			conditions.push_str(&format!(" AND domain IN ({})", placeholders));
+			params.extend(filter.domains.iter().copied().map(i64::from));
+		}
+		let sql = format!(
+			"SELECT schema_id, COUNT(*), MIN(timestamp), MAX(timestamp) FROM events WHERE {}
+			GROUP BY schema_id",
+			conditions
+		);
+		self.with_connection(move |connection| {
+			let mut select = connection.prepare_cached(&sql)?;
+			let rows = select.query_map(params_from_iter(params), |row| {
+				let stats = EventStats {
+					count: row.get::<_, i64>(1)? as u64,
+					min_timestamp: row.get::<_, i64>(2)? as u64,
+					max_timestamp: row.get::<_, i64>(3)? as u64,
+				};
+				Ok((row.get(0)?, stats))
+			})?;
+			rows.collect()
+		})
+		.await
+	}
+
+	async fn append_checkpointed(
+		&self, events: Vec<SourceEvent>, checkpoints: &[Checkpoint],
+	) -> Result<u32, IndexerError> {
+		self.write(events, checkpoints.to_vec()).await
+	}
+
+	async fn read_checkpoint(&self, source: &str) -> Result<Option<String>, IndexerError> {
+		let source = source.to_string();
+		self.with_connection(move |connection| {
+			connection
+				.query_row(
+					"SELECT position FROM checkpoints WHERE source = ?1",
+					[source],
+					|row| row.get(0),
+				)
+				.optional()
+		})
+		.await
+	}
+
+	async fn reindex(&self) -> Result<u32, IndexerError> {
+		self.with_connection(|connection| {
+			connection.execute_batch("REINDEX events")?;
+			Self::next_id(connection)
+		})
+		.await
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::SqliteStore;
+	use crate::{
+		source::SourceEvent,
+		store::{EventFilter, EventStore},
+	};
+	use rusqlite::Connection;
+
+	#[tokio::test]
+	async fn should_append_and_read_window() {
+		let store = SqliteStore::new(Connection::open_in_memory().unwrap()).unwrap();
+		let events = (0..10)
+			.map(|timestamp| SourceEvent {
+				schema_id: 1,
+				schema_value: "{}".to_string(),
+				timestamp,
+				..SourceEvent::default()
+			})
+			.collect();
+		assert_eq!(store.append(events).await.unwrap(), 10);
+		assert_eq!(store.count().await.unwrap(), 10);
+
+		let events = store.read(8, 5, &EventFilter::default()).await.unwrap();
+		let ids: Vec<_> = events.iter().map(|event| (event.id, event.timestamp)).collect();
+		assert_eq!(ids, vec![(8, 8), (9, 9)], "should stop at the last event");
+		assert!(store.read(10, 1, &EventFilter::default()).await.unwrap().is_empty());
+		assert!(store.read(3, 0, &EventFilter::default()).await.unwrap().is_empty());
+	}
+}
@@ -0,0 +1,109 @@
+use super::{Checkpoint, EventFilter, EventStats, EventStore};
+use crate::{
+	error::IndexerError,
+	source::{now_secs, SourceEvent},
+};
+use proto_buf::indexer::IndexerEvent;
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::Mutex;
+
+/// Wraps a store to stamp appended events with the time they are received. Stamps never
+/// decrease in order of ID, even should the clock go back.
+pub struct StampingStore {
+	inner: Arc<dyn EventStore>,
+	/// Stamp of the events appended last. Held over appends, for them to be numbered in the
+	/// order they are stamped.
+	last: Mutex<u64>,
+}
+
+impl StampingStore {
+	pub async fn new(inner: Arc<dyn EventStore>) -> Result<Self, IndexerError> {
+		let count = inner.count().await?;
+		let last = match count.checked_sub(1) {
+			Some(id) => inner.read(id, 1, &EventFilter::default()).await?,
+			None => Vec::new(),
+		};
+		let last = last.first().map_or(0, |event| event.received_at);
+		Ok(Self { inner, last: Mutex::new(last) })
+	}
+
+	async fn stamped<F, Fut>(&self, mut events: Vec<SourceEvent>, append: F) -> Fut::Output
+	where
+		F: FnOnce(Vec<SourceEvent>) -> Fut,
+		Fut: std::future::Future<Output = Result<u32, IndexerError>>,
+	{
+		let mut last = self.last.lock().await;
+		let received_at = now_secs().max(*last);
+		for event in &mut events {
+			event.received_at = received_at;
+		}
+		let count = append(events).await?;
+		*last = received_at;
+		Ok(count)
+	}
+}
+
+#[tonic::async_trait]
+impl EventStore for StampingStore {
+	async fn append(&self, events: Vec<SourceEvent>) -> Result<u32, IndexerError> {
+		self.stamped(events, |events| self.inner.append(events)).await
+	}
+
+	async fn read(
+		&self, offset: u32, count: u32, filter: &EventFilter,
+	) -> Result<Vec<IndexerEvent>, IndexerError> {
+		self.inner.read(offset, count, filter).await
+	}
+
+	async fn count(&self) -> Result<u32, IndexerError> {
+		self.inner.count().await
+	}
+
+	async fn stats(
+		&self, offset: u32, filter: &EventFilter,
+	) -> Result<BTreeMap<u32, EventStats>, IndexerError> {
+		self.inner.stats(offset, filter).await
+	}
+
+	async fn append_checkpointed(
+		&self, events: Vec<SourceEvent>, checkpoints: &[Checkpoint],
+	) -> Result<u32, IndexerError> {
+		let append = |events| self.inner.append_checkpointed(events, checkpoints);
+		self.stamped(events, append).await
+	}
+
+	async fn read_checkpoint(&self, source: &str) -> Result<Option<String>, IndexerError> {
+		self.inner.read_checkpoint(source).await
+	}
+
+	async fn reindex(&self) -> Result<u32, IndexerError> {
+		self.inner.reindex().await
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::StampingStore;
+	use crate::{
+		source::SourceEvent,
+		store::{memory::MemoryStore, EventFilter, EventStore},
+	};
+	use std::sync::Arc;
+
+	#[tokio::test]
+	async fn should_never_stamp_back_in_time() {
+		let inner = Arc::new(MemoryStore::new());
+		let future = u64::MAX / 2;
+		let event = SourceEvent { received_at: future, ..SourceEvent::default() };
+		inner.append(vec![event]).await.unwrap();
+
+		let store = StampingStore::new(inner).await.unwrap();
+		store.append(vec![SourceEvent::default(); 2]).await.unwrap();
+		let events = store.read(0, 3, &EventFilter::default()).await.unwrap();
+		let stamps: Vec<_> = events.iter().map(|event| event.received_at).collect();
+		assert_eq!(
+			stamps, [future; 3],
+			"should hold stamps past a clock set back"
+		);
+	}
+}
@@ -0,0 +1,71 @@
+use crate::metrics::Metrics;
+use std::sync::Arc;
+use tokio::sync::mpsc::{channel, Sender};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+
+/// Sending half of a response stream, recording its flow control under the RPC's name.
+pub struct StreamSender<T> {
+	tx: Sender<Result<T, Status>>,
+	method: &'static str,
+	metrics: Arc<Metrics>,
+}
+
+impl<T> StreamSender<T> {
+	/// Opens a stream buffering up to `buffer_size` messages the client has yet to receive.
+	pub fn open(
+		method: &'static str, buffer_size: usize, metrics: Arc<Metrics>,
+	) -> (Self, ReceiverStream<Result<T, Status>>) {
+		let (tx, rx) = channel(buffer_size);
+		metrics.streams_active.with_label_values(&[method]).inc();
+		(Self { tx, method, metrics }, ReceiverStream::new(rx))
+	}
+
+	/// Sends `message`, waiting while the buffer is full. Returns whether the client is still
+	/// there to receive it.
+	pub async fn send(&self, message: Result<T, Status>) -> bool {
+		let timer = self.metrics.stream_send_wait.with_label_values(&[self.method]).start_timer();
+		let sent = self.tx.send(message).await.is_ok();
+		timer.observe_duration();
+		if sent {
+			self.metrics.stream_events_sent.with_label_values(&[self.method]).inc();
+		} else {
+			self.metrics.stream_disconnects.with_label_values(&[self.method]).inc();
+		}
+		sent
+	}
+}
+
+impl<T> Drop for StreamSender<T> {
+	fn drop(&mut self) {
+		self.metrics.streams_active.with_label_values(&[self.method]).dec();
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::StreamSender;
+	use crate::metrics::Metrics;
+	use std::sync::Arc;
+
+	#[tokio::test]
+	async fn should_count_sends_and_disconnects() {
+		let metrics = Arc::new(Metrics::new());
+		let (tx, rx) = StreamSender::open("subscribe", 1, metrics.clone());
+		assert!(tx.send(Ok(1)).await);
+		drop(rx);
+		assert!(!tx.send(Ok(2)).await, "should notice the client went away");
+		drop(tx);
+
+		let method = ["subscribe"];
+		assert_eq!(
+			metrics.stream_events_sent.with_label_values(&method).get(),
+			1
+		);
+		assert_eq!(
+			metrics.stream_disconnects.with_label_values(&method).get(),
+			1
+		);
+		assert_eq!(metrics.streams_active.with_label_values(&method).get(), 0);
+	}
+}
@@ -0,0 +1,571 @@
+use crate::{
+	error::IndexerError,
+	metrics::Metrics,
+	schedule::Schedule,
+	schemas::SchemaRegistry,
+	source::{now_secs, Source, SourceEvent},
+	store::{Checkpoint, EventFilter, EventStore},
+};
+use futures::future::join_all;
+use std::{
+	collections::{hash_map::RandomState, HashMap},
+	hash::{BuildHasher, Hasher},
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::watch, time::interval};
+
+/// How far the store has caught up with the sources.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HighWater {
+	/// Latest timestamp of the stored events.
+	pub timestamp: u64,
+	/// Whether every source was polled successfully in the last round and had nothing left
+	/// to fetch.
+	pub caught_up: bool,
+}
+
+/// A source along with its progress as known to the store.
+struct Task {
+	source: Box<dyn Source>,
+	/// Whether the source was restored to the checkpoint saved by an earlier run.
+	restored: bool,
+	/// Checkpoint last saved.
+	saved: Option<String>,
+	/// Polled every round if `None`, else whenever its schedule comes due.
+	schedule: Option<Schedule>,
+	/// Trust domain the events of the source are assigned to, overriding the one they came
+	/// with.
+	domain: Option<u32>,
+	/// Unix time in milliseconds the source is due to be polled at.
+	due: u64,
+	/// Whether the last poll failed.
+	failed: bool,
+}
+
+impl Task {
+	async fn restore(&mut self, store: &dyn EventStore) -> Result<(), IndexerError> {
+		let checkpoint = store.read_checkpoint(self.source.name()).await?;
+		if let Some(checkpoint) = &checkpoint {
+			self.source.restore(checkpoint)?;
+		}
+		self.saved = checkpoint;
+		self.restored = true;
+		Ok(())
+	}
+
+	/// Polls the source, returning the events that conform to their schema along with the
+	/// checkpoint following them if it moved. Returns `None` if the source failed.
+	async fn poll(
+		&mut self, store: &dyn EventStore, registry: &SchemaRegistry, metrics: &Metrics,
+	) -> Option<(Vec<SourceEvent>, Option<Checkpoint>)> {
+		let name = self.source.name().to_string();
+		// Polling from scratch could store events again, so sources wait to be restored.
+		if !self.restored {
+			if let Err(e) = self.restore(store).await {
+				println!("Failed to restore source {}: {}", name, e);
+				return None;
+			}
+		}
+
+		let result = self.source.poll().await;
+		if let Some(lag) = self.source.lag() {
+			metrics.source_lag.with_label_values(&[&name]).set(lag as i64);
+		}
+		let events = match result {
+			Ok(events) => events,
+			// A failing source is retried on the next tick, without holding up the others.
+			Err(e) => {
+				println!("Source {} failed: {}", name, e);
+				return None;
+			},
+		};
+		let polled = events.len();
+		let mut events = TaskService::validate(registry, &name, events);
+		if let Some(domain) = self.domain {
+			events.iter_mut().for_each(|event| event.domain = domain);
+		}
+		let rejected = (polled - events.len()) as u64;
+		metrics.events_rejected.with_label_values(&[&name]).inc_by(rejected);
+
+		let position =
+			self.source.checkpoint().filter(|position| Some(position) != self.saved.as_ref());
+		Some((
+			events,
+			position.map(|position| Checkpoint { source: name, position }),
+		))
+	}
+}
+
+/// Events of a round of polls, stored in a single write.
+struct Batch {
+	/// Events of every source, in timestamp order.
+	events: Vec<SourceEvent>,
+	checkpoints: Vec<Checkpoint>,
+	/// Tasks polled successfully, by index.
+	polled: Vec<usize>,
+}
+
+/// Keeps the store current by polling every source concurrently, appending the events of
+/// each round in timestamp order across sources. Sources tracking their own progress resume
+/// from the checkpoint saved along with the last events they produced.
+pub struct TaskService {
+	store: Arc<dyn EventStore>,
+	registry: Arc<SchemaRegistry>,
+	metrics: Arc<Metrics>,
+	tasks: Vec<Task>,
+	poll_interval: Duration,
+	/// Schedules of the sources not polled every round, by name.
+	schedules: HashMap<String, Schedule>,
+	/// Most scheduled polls are delayed by.
+	jitter: Duration,
+	/// Trust domains of the sources whose events don't belong to the one they came with, by
+	/// name.
+	domains: HashMap<String, u32>,
+	/// Round that failed to be stored.
+	unstored: Option<Batch>,
+	high_water: watch::Sender<HighWater>,
+}
+
+impl TaskService {
+	pub fn new(
+		store: Arc<dyn EventStore>, registry: Arc<SchemaRegistry>, metrics: Arc<Metrics>,
+		poll_interval: Duration,
+	) -> Self {
+		let (high_water, _) = watch::channel(HighWater::default());
+		Self {
+			store,
+			registry,
+			metrics,
+			tasks: Vec::new(),
+			poll_interval,
+			schedules: HashMap::new(),
+			jitter: Duration::ZERO,
+			domains: HashMap::new(),
+			unstored: None,
+			high_water,
+		}
+	}
+
+	/// High water mark of the store, updated once the events of every round are stored.
+	pub fn watch_high_water(&self) -> watch::Receiver<HighWater> {
+		self.high_water.subscribe()
+	}
+
+	/// Polls the sources named in `schedules` on their schedule rather than every round, each
+	/// poll delayed by up to `jitter` so sources on the same schedule don't all hit at once.
+	/// Schedules are checked every round, so they are no finer than the poll interval.
+	pub fn with_schedules(
+		mut self, schedules: HashMap<String, Schedule>, jitter: Duration,
+	) -> Self {
+		self.schedules = schedules;
+		self.jitter = jitter;
+		self
+	}
+
+	/// Assigns the events of the sources named in `domains` to their trust domain.
+	pub fn with_domains(mut self, domains: HashMap<String, u32>) -> Self {
+		self.domains = domains;
+		self
+	}
+
+	pub fn add_source(&mut self, source: Box<dyn Source>) {
+		let schedule = self.schedules.get(source.name()).cloned();
+		let due = schedule.as_ref().map_or(0, |schedule| schedule.first(now_millis()));
+		let domain = self.domains.get(source.name()).copied();
+		self.tasks.push(Task {
+			source,
+			restored: false,
+			saved: None,
+			schedule,
+			domain,
+			due,
+			failed: false,
+		});
+	}
+
+	/// Polls the sources until the process exits, appending what they return to the store.
+	pub async fn run(mut self) {
+		match self.last_timestamp().await {
+			Ok(timestamp) => self.high_water.send_modify(|high_water| {
+				high_water.timestamp = timestamp;
+			}),
+			Err(e) => println!("Failed to read the latest stored event: {}", e),
+		}
+		let mut ticker = interval(self.poll_interval);
+		loop {
+			ticker.tick().await;
+			self.poll_sources().await;
+		}
+	}
+
+	async fn poll_sources(&mut self) {
+		// A round that failed to be stored is retried before polling for more, which would
+		// move the sources past it.
+		let batch = match self.unstored.take() {
+			Some(batch) => batch,
+			None => self.poll_round().await,
+		};
+
+		let result = if !batch.checkpoints.is_empty() {
+			self.store.append_checkpointed(batch.events.clone(), &batch.checkpoints).await
+		} else if !batch.events.is_empty() {
+			self.store.append(batch.events.clone()).await
+		} else {
+			Ok(0)
+		};
+		if let Err(e) = result {
+			println!(
+				"Failed to store {} polled events: {}",
+				batch.events.len(),
+				e
+			);
+			self.unstored = Some(batch);
+			self.high_water
+				.send_if_modified(|high_water| std::mem::replace(&mut high_water.caught_up, false));
+			return;
+		}
+
+		for event in &batch.events {
+			self.metrics.events_ingested.with_label_values(&[&event.source]).inc();
+		}
+		for checkpoint in batch.checkpoints {
+			let task = self.tasks.iter_mut().find(|task| task.source.name() == checkpoint.source);
+			if let Some(task) = task {
+				task.saved = Some(checkpoint.position);
+			}
+		}
+		// Published after the events are stored, for readers seeing it to find them.
+		let timestamp = batch.events.iter().map(|event| event.timestamp).max().unwrap_or(0);
+		let caught_up =
+			self.tasks.iter().all(|task| !task.failed && task.source.lag().unwrap_or(0) == 0);
+		self.high_water.send_if_modified(|high_water| {
+			let next = HighWater { timestamp: high_water.timestamp.max(timestamp), caught_up };
+			std::mem::replace(high_water, next) != next
+		});
+
+		let now = now_secs() as i64;
+		for i in batch.polled {
+			let source = &mut self.tasks[i].source;
+			self.metrics.source_last_success.with_label_values(&[source.name()]).set(now);
+			if let Err(e) = source.commit().await {
+				println!("Source {} failed to commit: {}", source.name(), e);
+			}
+		}
+	}
+
+	/// Timestamp of the event appended last, which ended the latest round stored.
+	async fn last_timestamp(&self) -> Result<u64, IndexerError> {
+		let count = self.store.count().await?;
+		let Some(last) = count.checked_sub(1) else {
+			return Ok(0);
+		};
+		let events = self.store.read(last, 1, &EventFilter::default()).await?;
+		Ok(events.first().map_or(0, |event| event.timestamp))
+	}
+
+	/// Polls every source due at once, merging their events by timestamp. Events of the same
+	/// time keep the order of their sources, then the order they were polled in. A source is
+	/// not due again until its poll ended, so polls of a source never overlap.
+	async fn poll_round(&mut self) -> Batch {
+		let (store, registry, metrics) = (&*self.store, &*self.registry, &*self.metrics);
+		let now = now_millis();
+		let polls = self
+			.tasks
+			.iter_mut()
+			.enumerate()
+			.filter(|(_, task)| task.due <= now)
+			.map(|(i, task)| async move { (i, task.poll(store, registry, metrics).await) });
+		let mut batch = Batch { events: Vec::new(), checkpoints: Vec::new(), polled: Vec::new() };
+		for (i, result) in join_all(polls).await {
+			let task = &mut self.tasks[i];
+			task.failed = result.is_none();
+			if let Some(schedule) = &task.schedule {
+				task.due = schedule.after(now_millis()).saturating_add(jitter(self.jitter));
+			}
+			if let Some((events, checkpoint)) = result {
+				batch.events.extend(events);
+				batch.checkpoints.extend(checkpoint);
+				batch.polled.push(i);
+			}
+		}
+		batch.events.sort_by_key(|event| event.timestamp);
+		batch
+	}
+
+	/// Drops the events whose payload does not conform to their schema, logging why. They are
+	/// still committed, so a malformed credential is not delivered again.
+	fn validate(
+		registry: &SchemaRegistry, source: &str, events: Vec<SourceEvent>,
+	) -> Vec<SourceEvent> {
+		events
+			.into_iter()
+			.filter(
+				|event| match registry.validate_str(event.schema_id, &event.schema_value) {
+					Ok(()) => true,
+					Err(reason) => {
+						println!(
+							"Rejected event of {} under schema {}: {}",
+							source, event.schema_id, reason
+						);
+						false
+					},
+				},
+			)
+			.collect()
+	}
+}
+
+/// Unix time in milliseconds, the time schedules are kept in.
+fn now_millis() -> u64 {
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards");
+	now.as_millis() as u64
+}
+
+/// Random delay of milliseconds up to `max`.
+fn jitter(max: Duration) -> u64 {
+	match max.as_millis() as u64 {
+		0 => 0,
+		max => RandomState::new().build_hasher().finish() % max,
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{HighWater, TaskService};
+	use crate::{
+		error::IndexerError,
+		metrics::Metrics,
+		schedule::Schedule,
+		schemas::{SchemaRegistry, EAS_SCHEMA_ID},
+		source::{mock::MockSource, Source, SourceEvent},
+		store::{memory::MemoryStore, EventFilter, EventStore},
+	};
+	use serde_json::json;
+	use std::{sync::Arc, time::Duration};
+
+	/// Produces one event per page, stamped with its page number.
+	struct PagedSource {
+		name: &'static str,
+		mock: MockSource,
+		next_page: u64,
+	}
+
+	impl PagedSource {
+		fn new(name: &'static str, first_page: u64) -> Box<Self> {
+			Box::new(Self { name, mock: MockSource::new(1), next_page: first_page })
+		}
+	}
+
+	#[tonic::async_trait]
+	impl Source for PagedSource {
+		fn name(&self) -> &str {
+			self.name
+		}
+
+		async fn poll(&mut self) -> Result<Vec<SourceEvent>, IndexerError> {
+			let mut events = self.mock.poll().await?;
+			events[0].timestamp = self.next_page;
+			events[0].source = self.name.to_string();
+			self.next_page += 1;
+			Ok(events)
+		}
+
+		fn checkpoint(&self) -> Option<String> {
+			Some(self.next_page.to_string())
+		}
+
+		fn restore(&mut self, checkpoint: &str) -> Result<(), IndexerError> {
+			self.next_page = checkpoint.parse().map_err(|_| IndexerError::ParseError)?;
+			Ok(())
+		}
+	}
+
+	/// Returns its events on the first poll only.
+	struct OnceSource {
+		events: Vec<SourceEvent>,
+	}
+
+	#[tonic::async_trait]
+	impl Source for OnceSource {
+		fn name(&self) -> &str {
+			"eas"
+		}
+
+		async fn poll(&mut self) -> Result<Vec<SourceEvent>, IndexerError> {
+			Ok(std::mem::take(&mut self.events))
+		}
+	}
+
+	fn task_service(store: Arc<MemoryStore>) -> TaskService {
+		let registry = Arc::new(SchemaRegistry::default());
+		let metrics = Arc::new(Metrics::new());
+		TaskService::new(store, registry, metrics, Duration::from_secs(1))
+	}
+
+	#[tokio::test]
+	async fn should_append_polled_events() {
+		let store = Arc::new(MemoryStore::new());
+		let registry = Arc::new(SchemaRegistry::default());
+		let metrics = Arc::new(Metrics::new());
+		let mut tasks = TaskService::new(
+			store.clone(),
+			registry,
+			metrics.clone(),
+			Duration::from_secs(1),
+		);
+		tasks.add_source(Box::new(MockSource::new(2)));
+		tasks.poll_sources().await;
+		tasks.poll_sources().await;
+		assert_eq!(store.count().await.unwrap(), 4);
+		assert_eq!(
+			store.read(3, 1, &EventFilter::default()).await.unwrap()[0].id,
+			3
+		);
+		assert_eq!(
+			metrics.events_ingested.with_label_values(&["mock"]).get(),
+			4
+		);
+	}
+
+	#[tokio::test]
+	async fn should_resume_from_checkpoint() {
+		let store = Arc::new(MemoryStore::new());
+		let mut tasks = task_service(store.clone());
+		tasks.add_source(PagedSource::new("paged", 0));
+		tasks.poll_sources().await;
+		tasks.poll_sources().await;
+		assert_eq!(
+			store.read_checkpoint("paged").await.unwrap().as_deref(),
+			Some("2")
+		);
+
+		let mut tasks = task_service(store.clone());
+		tasks.add_source(PagedSource::new("paged", 0));
+		tasks.poll_sources().await;
+		let events = store.read(0, 10, &EventFilter::default()).await.unwrap();
+		let pages: Vec<_> = events.iter().map(|event| event.timestamp).collect();
+		assert_eq!(
+			pages,
+			vec![0, 1, 2],
+			"should neither refetch nor skip pages"
+		);
+	}
+
+	#[tokio::test]
+	async fn should_merge_sources_by_timestamp() {
+		let store = Arc::new(MemoryStore::new());
+		let mut tasks = task_service(store.clone());
+		tasks.add_source(PagedSource::new("mainnet", 10));
+		tasks.add_source(PagedSource::new("base", 5));
+		tasks.poll_sources().await;
+		tasks.poll_sources().await;
+
+		let events = store.read(0, 10, &EventFilter::default()).await.unwrap();
+		let events: Vec<_> =
+			events.iter().map(|event| (event.timestamp, event.source.as_str())).collect();
+		assert_eq!(
+			events,
+			vec![(5, "base"), (10, "mainnet"), (6, "base"), (11, "mainnet")],
+			"should order every round by timestamp"
+		);
+		assert_eq!(
+			store.read_checkpoint("base").await.unwrap().as_deref(),
+			Some("7")
+		);
+	}
+
+	#[tokio::test]
+	async fn should_raise_high_water_once_stored() {
+		let store = Arc::new(MemoryStore::new());
+		let mut tasks = task_service(store.clone());
+		tasks.add_source(PagedSource::new("mainnet", 10));
+		tasks.add_source(PagedSource::new("base", 5));
+		let high_water = tasks.watch_high_water();
+		tasks.poll_sources().await;
+		assert_eq!(
+			*high_water.borrow(),
+			HighWater { timestamp: 10, caught_up: true }
+		);
+		tasks.poll_sources().await;
+		assert_eq!(high_water.borrow().timestamp, 11);
+	}
+
+	#[tokio::test]
+	async fn should_poll_scheduled_sources_when_due() {
+		let store = Arc::new(MemoryStore::new());
+		let schedules = [(
+			"hourly".to_string(),
+			Schedule::Every(Duration::from_secs(3600)),
+		)];
+		let mut tasks =
+			task_service(store.clone()).with_schedules(schedules.into(), Duration::from_secs(1));
+		tasks.add_source(PagedSource::new("hourly", 0));
+		tasks.add_source(PagedSource::new("always", 0));
+		tasks.poll_sources().await;
+		tasks.poll_sources().await;
+
+		let events = store.read(0, 10, &EventFilter::default()).await.unwrap();
+		let sources: Vec<_> = events.iter().map(|event| event.source.as_str()).collect();
+		assert_eq!(
+			sources,
+			["hourly", "always", "always"],
+			"should poll scheduled sources once due only"
+		);
+	}
+
+	#[tokio::test]
+	async fn should_assign_sources_their_domain() {
+		let store = Arc::new(MemoryStore::new());
+		let domains = [("base".to_string(), 2)];
+		let mut tasks = task_service(store.clone()).with_domains(domains.into());
+		tasks.add_source(PagedSource::new("mainnet", 10));
+		tasks.add_source(PagedSource::new("base", 5));
+		tasks.poll_sources().await;
+
+		let events = store.read(0, 10, &EventFilter::default()).await.unwrap();
+		let domains: Vec<_> =
+			events.iter().map(|event| (event.source.as_str(), event.domain)).collect();
+		assert_eq!(domains, [("base", 2), ("mainnet", 0)]);
+	}
+
+	#[tokio::test]
+	async fn should_store_eas_attestations() {
+		let event = |revoked: bool, orphaned: bool| {
+			let value = json!({
+				"uid": format!("0x{}", "01".repeat(32)),
+				"schema": format!("0x{}", "02".repeat(32)),
+				"time": 1700000000,
+				"expirationTime": 0,
+				"revocationTime": if revoked { 1700000100 } else { 0 },
+				"refUID": format!("0x{}", "00".repeat(32)),
+				"recipient": format!("0x{}", "aa".repeat(20)),
+				"attester": format!("0x{}", "bb".repeat(20)),
+				"revocable": true,
+				"data": "0x666f6c6c6f77",
+				"revoked": revoked,
+				"orphaned": orphaned,
+			});
+			SourceEvent {
+				schema_id: EAS_SCHEMA_ID,
+				schema_value: value.to_string(),
+				timestamp: 1700000000,
+				source: "eas".to_string(),
+				..SourceEvent::default()
+			}
+		};
+		let mut malformed = event(false, false);
+		malformed.schema_value = json!({ "uid": "0x01" }).to_string();
+		let events = vec![event(false, false), event(true, false), event(false, true), malformed];
+
+		let store = Arc::new(MemoryStore::new());
+		let mut tasks = task_service(store.clone());
+		tasks.add_source(Box::new(OnceSource { events }));
+		tasks.poll_sources().await;
+		assert_eq!(
+			store.count().await.unwrap(),
+			3,
+			"should keep attestations, revocations and retractions"
+		);
+	}
+}
@@ -0,0 +1,148 @@
+use crate::{
+	source::now_secs,
+	store::{notify::NotifyingStore, EventFilter, EventStore},
+	stream::StreamSender,
+	tasks::HighWater,
+};
+use proto_buf::{
+	common::{Heartbeat, Watermark},
+	indexer::{watch_event::Kind, WatchEvent},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::{select, sync::watch, time::timeout};
+
+/// Streams the events matching `filter` from ID `offset` into `tx`, `page_size` at a time,
+/// then waits for new ones, sending a heartbeat whenever none came for `heartbeat_interval`.
+/// Once caught up, every new high water mark is sent as a watermark. Returns once the
+/// receiver is dropped or reading fails.
+pub async fn follow(
+	store: Arc<NotifyingStore>, mut high_water: watch::Receiver<HighWater>, filter: EventFilter,
+	offset: u32, page_size: u32, heartbeat_interval: Duration, tx: StreamSender<WatchEvent>,
+) {
+	let mut counts = store.watch_count();
+	let mut next_id = offset;
+	let mut sent = None;
+	loop {
+		let stored = *counts.borrow_and_update();
+		// Taken before reading, so that the events it accounts for are read.
+		let mark = *high_water.borrow_and_update();
+		let events = match store.read(next_id, page_size, &filter).await {
+			Ok(events) => events,
+			Err(e) => {
+				tx.send(Err(e.into_status())).await;
+				return;
+			},
+		};
+		let caught_up = events.len() < page_size as usize;
+		for event in events {
+			next_id = event.id + 1;
+			if !filter.matches_domain(&event) {
+				continue;
+			}
+			if !tx.send(Ok(WatchEvent { kind: Some(Kind::Event(event)) })).await {
+				return;
+			}
+		}
+		if !caught_up {
+			continue;
+		}
+
+		// The short page covered everything stored before it was read.
+		next_id = next_id.max(stored);
+		if sent != Some(mark) {
+			let watermark = Watermark {
+				timestamp: mark.timestamp,
+				caught_up: mark.caught_up,
+				next_position: next_id.into(),
+			};
+			if !tx.send(Ok(WatchEvent { kind: Some(Kind::Watermark(watermark)) })).await {
+				return;
+			}
+			sent = Some(mark);
+		}
+		let changed = async {
+			select! {
+				result = counts.changed() => result,
+				result = high_water.changed() => result,
+			}
+		};
+		match timeout(heartbeat_interval, changed).await {
+			Ok(Ok(())) => {},
+			Ok(Err(_)) => return,
+			Err(_) => {
+				let heartbeat = Heartbeat { next_position: next_id.into(), timestamp: now_secs() };
+				let event = WatchEvent { kind: Some(Kind::Heartbeat(heartbeat)) };
+				if !tx.send(Ok(event)).await {
+					return;
+				}
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::follow;
+	use crate::{
+		metrics::Metrics,
+		source::SourceEvent,
+		store::{memory::MemoryStore, notify::NotifyingStore, EventFilter, EventStore},
+		stream::StreamSender,
+		tasks::HighWater,
+	};
+	use proto_buf::indexer::{watch_event::Kind, WatchEvent};
+	use std::{sync::Arc, time::Duration};
+	use tokio::sync::watch;
+	use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+	use tonic::Status;
+
+	async fn next(rx: &mut ReceiverStream<Result<WatchEvent, Status>>) -> Kind {
+		rx.next().await.unwrap().unwrap().kind.unwrap()
+	}
+
+	#[tokio::test]
+	async fn should_push_new_events_and_heartbeats() {
+		let store = Arc::new(NotifyingStore::new(Arc::new(MemoryStore::new())).await.unwrap());
+		let event = |schema_id| SourceEvent {
+			schema_id,
+			schema_value: "{}".to_string(),
+			timestamp: 0,
+			..SourceEvent::default()
+		};
+		store.append(vec![event(1), event(2), event(1)]).await.unwrap();
+
+		let (tx, mut rx) = StreamSender::open("watch", 4, Arc::new(Metrics::new()));
+		let filter = EventFilter { schema_ids: [1].into(), ..EventFilter::default() };
+		let interval = Duration::from_millis(50);
+		let (high_water, marks) = watch::channel(HighWater::default());
+		tokio::spawn(follow(store.clone(), marks, filter, 0, 1, interval, tx));
+
+		assert!(matches!(next(&mut rx).await, Kind::Event(event) if event.id == 0));
+		assert!(matches!(next(&mut rx).await, Kind::Event(event) if event.id == 2));
+		assert!(matches!(next(&mut rx).await, Kind::Watermark(mark) if !mark.caught_up));
+		assert!(
+			matches!(next(&mut rx).await, Kind::Heartbeat(heartbeat) if heartbeat.next_position == 3),
+			"should send heartbeats once caught up"
+		);
+
+		store.append(vec![event(2), event(1)]).await.unwrap();
+		let event = loop {
+			if let Kind::Event(event) = next(&mut rx).await {
+				break event;
+			}
+		};
+		assert_eq!(event.id, 4, "should push events ingested later");
+
+		high_water.send_replace(HighWater { timestamp: 1_000, caught_up: true });
+		let mark = loop {
+			if let Kind::Watermark(mark) = next(&mut rx).await {
+				break mark;
+			}
+		};
+		assert_eq!(
+			(mark.timestamp, mark.caught_up, mark.next_position),
+			(1_000, true, 5),
+			"should send new high water marks"
+		);
+	}
+}
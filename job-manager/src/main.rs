@@ -1,4 +1,3 @@
-use proto_buf;
 use proto_buf::common::Void;
 use proto_buf::transformer::transformer_client::TransformerClient;
 use proto_buf::transformer::TermBatch;
@@ -6,18 +5,31 @@ use std::error::Error;
 use tonic::transport::Channel;
 use tonic::Request;
 
+const TERM_BATCH_SIZE: u32 = 1000;
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
 	let tr_channel = Channel::from_static("http://[::1]:50051").connect().await?;
 	let mut tr_client = TransformerClient::new(tr_channel);
 
+	// Fails fast against a transformer speaking another protocol, rather than misbehaving.
+	let info = tr_client.get_service_info(Request::new(Void {})).await?.into_inner();
+	println!(
+		"Connected to {} {}, features: {:?}, limits: {:?}",
+		info.service, info.version, info.features, info.limits
+	);
+	info.check(&[])?;
+	let size = info.limit("max_term_batch_size").map_or(TERM_BATCH_SIZE, |max| {
+		max.min(u64::from(TERM_BATCH_SIZE)) as u32
+	});
+
 	// BasicRequest
 	let void_request = Request::new(Void {});
 	let response = tr_client.sync_indexer(void_request).await?.into_inner();
 	println!("basic response {:?}", response);
 
 	// BasicRequest
-	let void_request = Request::new(TermBatch { start: 0, size: 1000 });
+	let void_request = Request::new(TermBatch { start: 0, size });
 	let response = tr_client.term_stream(void_request).await?.into_inner();
 	println!("basic response {:?}", response);
 
@@ -0,0 +1,159 @@
+use crate::config::AuthConfig;
+use std::{collections::HashMap, fs, io, path::Path, sync::Arc};
+use tonic::{service::Interceptor, Request, Status};
+
+/// Privileges a caller can hold. `Admin` implies every other role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+	/// Writes terms through `sync_transformer` or `sync_term_batches`.
+	Transformer,
+	/// Reads cells and mappings.
+	Reader,
+	/// Backs up, restores and resets the database.
+	Admin,
+}
+
+/// Identity the interceptor resolved for a request, `None` for anonymous callers.
+#[derive(Debug, Clone, Copy)]
+struct Caller(Option<Role>);
+
+/// Resolves callers from a bearer token or their TLS client certificate.
+///
+/// Roles without any credentials configured stay open to anonymous callers, except `Admin`
+/// which is disabled instead.
+#[derive(Debug, Clone, Default)]
+pub struct Authenticator {
+	tokens: Arc<HashMap<String, Role>>,
+	certs: Arc<HashMap<Vec<u8>, Role>>,
+}
+
+impl Authenticator {
+	pub fn new(config: &AuthConfig) -> io::Result<Self> {
+		let mut tokens = HashMap::new();
+		let mut certs = HashMap::new();
+		let roles = [
+			(
+				Role::Transformer,
+				&config.transformer_token,
+				&config.transformer_cert,
+			),
+			(Role::Reader, &config.reader_token, &config.reader_cert),
+			(Role::Admin, &config.admin_token, &config.admin_cert),
+		];
+		for (role, token, cert) in roles {
+			if let Some(token) = token {
+				tokens.insert(token.clone(), role);
+			}
+			if let Some(cert) = cert {
+				certs.insert(read_leaf_cert(cert)?, role);
+			}
+		}
+		Ok(Self { tokens: Arc::new(tokens), certs: Arc::new(certs) })
+	}
+
+	fn has_credentials(&self, role: Role) -> bool {
+		self.tokens.values().chain(self.certs.values()).any(|&r| r == role)
+	}
+
+	fn identify<T>(&self, request: &Request<T>) -> Result<Option<Role>, Status> {
+		let header = request.metadata().get("authorization").and_then(|v| v.to_str().ok());
+		if let Some(header) = header {
+			let token = header.strip_prefix("Bearer ").unwrap_or(header);
+			return match self.tokens.get(token) {
+				Some(&role) => Ok(Some(role)),
+				None => Err(Status::unauthenticated("Invalid token!")),
+			};
+		}
+		let leaf = request.peer_certs().and_then(|certs| certs.first().cloned());
+		Ok(leaf.and_then(|cert| self.certs.get(cert.get_ref()).copied()))
+	}
+
+	/// Checks that the caller of `request` holds `role`.
+	pub fn authorize<T>(&self, request: &Request<T>, role: Role) -> Result<(), Status> {
+		if !self.has_credentials(role) {
+			return match role {
+				Role::Admin => Err(Status::permission_denied("Admin RPCs are disabled!")),
+				_ => Ok(()),
+			};
+		}
+		let caller = match request.extensions().get::<Caller>() {
+			Some(Caller(caller)) => *caller,
+			None => self.identify(request)?,
+		};
+		match caller {
+			Some(r) if r == role || r == Role::Admin => Ok(()),
+			Some(_) => Err(Status::permission_denied("Caller may not use this RPC!")),
+			None => Err(Status::unauthenticated("Missing credentials!")),
+		}
+	}
+}
+
+impl Interceptor for Authenticator {
+	fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+		let caller = self.identify(&request)?;
+		request.extensions_mut().insert(Caller(caller));
+		Ok(request)
+	}
+}
+
+/// Reads the DER encoding of the first certificate in a PEM file.
+fn read_leaf_cert(path: &Path) -> io::Result<Vec<u8>> {
+	let pem = fs::read(path)?;
+	let certs = rustls_pemfile::certs(&mut pem.as_slice())?;
+	certs.into_iter().next().ok_or_else(|| {
+		io::Error::new(
+			io::ErrorKind::InvalidData,
+			format!("No certificate in {}", path.display()),
+		)
+	})
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Authenticator, Role};
+	use crate::config::AuthConfig;
+	use tonic::{service::Interceptor, Code, Request};
+
+	fn bearer(token: &str) -> Request<()> {
+		let mut request = Request::new(());
+		let value = format!("Bearer {}", token).parse().unwrap();
+		request.metadata_mut().insert("authorization", value);
+		request
+	}
+
+	#[test]
+	fn should_authorize_per_role() {
+		let config = AuthConfig {
+			transformer_token: Some("tr".to_string()),
+			admin_token: Some("admin".to_string()),
+			..AuthConfig::default()
+		};
+		let mut auth = Authenticator::new(&config).unwrap();
+
+		let transformer = auth.call(bearer("tr")).unwrap();
+		auth.authorize(&transformer, Role::Transformer).unwrap();
+		let status = auth.authorize(&transformer, Role::Admin).unwrap_err();
+		assert_eq!(status.code(), Code::PermissionDenied);
+
+		let admin = auth.call(bearer("admin")).unwrap();
+		auth.authorize(&admin, Role::Transformer).unwrap();
+
+		let anonymous = auth.call(Request::new(())).unwrap();
+		auth.authorize(&anonymous, Role::Reader).unwrap();
+		let status = auth.authorize(&anonymous, Role::Transformer).unwrap_err();
+		assert_eq!(status.code(), Code::Unauthenticated);
+
+		let status = auth.call(bearer("forged")).unwrap_err();
+		assert_eq!(status.code(), Code::Unauthenticated);
+	}
+
+	#[test]
+	fn should_disable_admin_without_credentials() {
+		let config =
+			AuthConfig { reader_token: Some("reader".to_string()), ..AuthConfig::default() };
+		let auth = Authenticator::new(&config).unwrap();
+		let status = auth.authorize(&Request::new(()), Role::Admin).unwrap_err();
+		assert_eq!(status.code(), Code::PermissionDenied);
+		auth.authorize(&Request::new(()), Role::Transformer).unwrap();
+	}
+}
@@ -0,0 +1,180 @@
+//! Offline inspection of the linear combiner's storage.
+//!
+//! Usage: `lc-tool <db-path> <mapping|lt|updates|stats> [csv|jsonl]`
+
+use linear_combiner::{
+	error::LcError,
+	item::{decode_timestamp, decode_value},
+	COLUMN_FAMILIES, LT_CF, MAPPING_CF, UPDATE_CF,
+};
+use rocksdb::{IteratorMode, Options, DB};
+use serde_derive::Serialize;
+use std::{
+	env,
+	error::Error,
+	io::{self, BufWriter, Write},
+	process,
+};
+
+const USAGE: &str = "Usage: lc-tool <db-path> <mapping|lt|updates|stats> [csv|jsonl]";
+
+#[derive(Debug, PartialEq, Serialize)]
+struct MappingRow {
+	domain: u32,
+	index: u32,
+	key: String,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct CellRow {
+	domain: u32,
+	form: i32,
+	x: u32,
+	y: u32,
+	value: f64,
+	timestamp: u64,
+}
+
+trait Row: serde::Serialize {
+	const HEADER: &'static str;
+
+	fn parse(key: &[u8], value: &[u8]) -> Result<Self, LcError>
+	where
+		Self: Sized;
+
+	fn to_csv(&self) -> String;
+}
+
+impl Row for MappingRow {
+	const HEADER: &'static str = "domain,index,key";
+
+	fn parse(key: &[u8], value: &[u8]) -> Result<Self, LcError> {
+		let key_bytes: [u8; 8] = key.try_into().map_err(|_| LcError::ParseError)?;
+		let domain = u32::from_be_bytes(key_bytes[..4].try_into().unwrap());
+		let index = u32::from_be_bytes(key_bytes[4..].try_into().unwrap());
+		Ok(Self { domain, index, key: hex::encode(value) })
+	}
+
+	fn to_csv(&self) -> String {
+		format!("{},{},{}", self.domain, self.index, self.key)
+	}
+}
+
+impl Row for CellRow {
+	const HEADER: &'static str = "domain,form,x,y,value,timestamp";
+
+	fn parse(key: &[u8], value: &[u8]) -> Result<Self, LcError> {
+		let key_bytes: [u8; 16] = key.try_into().map_err(|_| LcError::ParseError)?;
+		let part = |i: usize| -> [u8; 4] { key_bytes[i * 4..(i + 1) * 4].try_into().unwrap() };
+		Ok(Self {
+			domain: u32::from_be_bytes(part(0)),
+			form: i32::from_be_bytes(part(1)),
+			x: u32::from_be_bytes(part(2)),
+			y: u32::from_be_bytes(part(3)),
+			value: decode_value(value)?,
+			timestamp: decode_timestamp(value),
+		})
+	}
+
+	fn to_csv(&self) -> String {
+		let CellRow { domain, form, x, y, value, timestamp } = self;
+		format!("{},{},{},{},{},{}", domain, form, x, y, value, timestamp)
+	}
+}
+
+enum Format {
+	Csv,
+	Jsonl,
+}
+
+fn dump<R: Row>(db: &DB, cf_name: &str, format: &Format) -> Result<(), Box<dyn Error>> {
+	let cf = db.cf_handle(cf_name).ok_or(LcError::NotFoundError)?;
+	let mut out = BufWriter::new(io::stdout().lock());
+	if let Format::Csv = format {
+		writeln!(out, "{}", R::HEADER)?;
+	}
+	for item in db.iterator_cf(&cf, IteratorMode::Start) {
+		let (key, value) = item?;
+		let row = R::parse(&key, &value)?;
+		match format {
+			Format::Csv => writeln!(out, "{}", row.to_csv())?,
+			Format::Jsonl => writeln!(out, "{}", serde_json::to_string(&row)?)?,
+		}
+	}
+	out.flush()?;
+	Ok(())
+}
+
+fn print_stats(db: &DB) -> Result<(), Box<dyn Error>> {
+	const PROPERTIES: [&str; 3] = [
+		"rocksdb.estimate-num-keys", "rocksdb.total-sst-files-size", "rocksdb.size-all-mem-tables",
+	];
+
+	println!("column_family,{}", PROPERTIES.join(","));
+	for name in COLUMN_FAMILIES {
+		let cf = db.cf_handle(name).ok_or(LcError::NotFoundError)?;
+		let mut values = Vec::new();
+		for property in PROPERTIES {
+			let value = db.property_int_value_cf(&cf, property)?;
+			values.push(value.map_or(String::new(), |v| v.to_string()));
+		}
+		println!("{},{}", name, values.join(","));
+	}
+	Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let (db_path, command) = match args.as_slice() {
+		[db_path, command] | [db_path, command, _] => (db_path, command),
+		_ => {
+			eprintln!("{}", USAGE);
+			process::exit(2);
+		},
+	};
+	let format = match args.get(2).map(String::as_str) {
+		None | Some("csv") => Format::Csv,
+		Some("jsonl") => Format::Jsonl,
+		Some(_) => {
+			eprintln!("{}", USAGE);
+			process::exit(2);
+		},
+	};
+
+	// Read-only, so it can run next to a live combiner.
+	let db = DB::open_cf_for_read_only(&Options::default(), db_path, COLUMN_FAMILIES, false)?;
+	match command.as_str() {
+		"mapping" => dump::<MappingRow>(&db, MAPPING_CF, &format),
+		"lt" => dump::<CellRow>(&db, LT_CF, &format),
+		"updates" => dump::<CellRow>(&db, UPDATE_CF, &format),
+		"stats" => print_stats(&db),
+		_ => {
+			eprintln!("{}", USAGE);
+			process::exit(2);
+		},
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::{CellRow, MappingRow, Row};
+
+	#[test]
+	fn should_parse_rows() {
+		let key = [1u32.to_be_bytes(), 0i32.to_be_bytes(), 2u32.to_be_bytes(), 3u32.to_be_bytes()];
+		let value = [1.5f64.to_be_bytes(), 100u64.to_be_bytes()].concat();
+		let row = CellRow::parse(&key.concat(), &value).unwrap();
+		assert_eq!(
+			row,
+			CellRow { domain: 1, form: 0, x: 2, y: 3, value: 1.5, timestamp: 100 }
+		);
+		assert_eq!(row.to_csv(), "1,0,2,3,1.5,100");
+
+		let key = [1u32.to_be_bytes(), 4u32.to_be_bytes()].concat();
+		let row = MappingRow::parse(&key, &[0xab, 0xcd]).unwrap();
+		assert_eq!(
+			row,
+			MappingRow { domain: 1, index: 4, key: "abcd".to_string() }
+		);
+	}
+}
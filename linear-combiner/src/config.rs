@@ -0,0 +1,271 @@
+use clap::{Args, Parser, ValueEnum};
+use rocksdb::{BlockBasedOptions, Cache, DBCompactionStyle, DBCompressionType, Options};
+use std::{fs, io, net::SocketAddr, path::PathBuf, time::Duration};
+use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+
+const DEFAULT_UPDATE_TTL_DAYS: u64 = 30;
+const DEFAULT_MAX_OPEN_FILES: i32 = -1;
+const DEFAULT_WRITE_BUFFER_SIZE_MB: usize = 64;
+const DEFAULT_MAX_BACKGROUND_JOBS: i32 = 2;
+const DEFAULT_BLOCK_CACHE_SIZE_MB: usize = 256;
+const DEFAULT_BLOOM_FILTER_BITS: f64 = 10.;
+const MIB: usize = 1024 * 1024;
+
+/// Linear combiner service. Every option can also be set through its environment variable.
+#[derive(Debug, Clone, Parser)]
+#[command(version, about)]
+pub struct Config {
+	/// Address the gRPC server listens on.
+	#[arg(long, env = "LC_LISTEN_ADDR", default_value = "[::1]:50052")]
+	pub listen_addr: SocketAddr,
+
+	/// Address of the HTTP `/metrics` endpoint and status page, which are disabled when unset.
+	#[arg(long, env = "LC_METRICS_ADDR")]
+	pub metrics_addr: Option<SocketAddr>,
+
+	/// Directory of the RocksDB database.
+	#[arg(long, env = "LC_DB_PATH", default_value = "lc-storage")]
+	pub db_path: String,
+
+	/// Directory scheduled and on-demand backups are written to.
+	#[arg(long, env = "LC_BACKUP_DIR", default_value = "lc-backups")]
+	pub backup_dir: String,
+
+	#[command(flatten)]
+	pub auth: AuthConfig,
+
+	#[command(flatten)]
+	pub storage: StorageConfig,
+
+	#[command(flatten)]
+	pub tls: TlsConfig,
+
+	#[command(flatten)]
+	pub ingest: IngestConfig,
+}
+
+/// Limits protecting the database from transformers sending more than it can absorb.
+#[derive(Debug, Clone, Default, Args)]
+pub struct IngestConfig {
+	/// Maximum number of terms a single `sync_transformer` or `sync_term_batches` stream may
+	/// send, 0 for no limit.
+	#[arg(long, env = "LC_MAX_STREAM_TERMS", default_value_t = 0)]
+	pub max_stream_terms: u64,
+
+	/// Terms per second accepted across all streams, 0 for no limit.
+	#[arg(long, env = "LC_MAX_INGEST_RATE", default_value_t = 0)]
+	pub max_ingest_rate: u32,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct StorageConfig {
+	/// Days an update not consumed by `get_new_data` is kept for.
+	#[arg(long, env = "LC_UPDATE_TTL_DAYS", default_value_t = DEFAULT_UPDATE_TTL_DAYS)]
+	pub update_ttl_days: u64,
+
+	/// Maximum number of files RocksDB keeps open, -1 for no limit.
+	#[arg(long, env = "LC_MAX_OPEN_FILES", default_value_t = DEFAULT_MAX_OPEN_FILES)]
+	pub max_open_files: i32,
+
+	/// Size of each memtable in MiB.
+	#[arg(long, env = "LC_WRITE_BUFFER_SIZE_MB", default_value_t = DEFAULT_WRITE_BUFFER_SIZE_MB)]
+	pub write_buffer_size_mb: usize,
+
+	/// Maximum number of concurrent RocksDB flushes and compactions.
+	#[arg(long, env = "LC_MAX_BACKGROUND_JOBS", default_value_t = DEFAULT_MAX_BACKGROUND_JOBS)]
+	pub max_background_jobs: i32,
+
+	/// Size of the block cache shared by all column families in MiB.
+	#[arg(long, env = "LC_BLOCK_CACHE_SIZE_MB", default_value_t = DEFAULT_BLOCK_CACHE_SIZE_MB)]
+	pub block_cache_size_mb: usize,
+
+	/// Bits per key of the bloom filters on SST blocks, 0 to disable them.
+	#[arg(long, env = "LC_BLOOM_FILTER_BITS", default_value_t = DEFAULT_BLOOM_FILTER_BITS)]
+	pub bloom_filter_bits: f64,
+
+	#[arg(long, env = "LC_COMPRESSION", value_enum, default_value_t = Compression::Lz4)]
+	pub compression: Compression,
+
+	#[arg(long, env = "LC_COMPACTION_STYLE", value_enum, default_value_t = CompactionStyle::Level)]
+	pub compaction_style: CompactionStyle,
+
+	/// Where the database and its backups are kept.
+	#[arg(long, env = "LC_STORAGE_BACKEND", value_enum, default_value_t = StorageBackend::Disk)]
+	pub backend: StorageBackend,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum StorageBackend {
+	/// Directories on local disk.
+	#[default]
+	Disk,
+	/// Keeps the database and its backups in memory, losing them on exit. Meant for tests and
+	/// simulations.
+	Memory,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+	None,
+	Snappy,
+	Lz4,
+	Zstd,
+}
+
+impl From<Compression> for DBCompressionType {
+	fn from(compression: Compression) -> Self {
+		match compression {
+			Compression::None => Self::None,
+			Compression::Snappy => Self::Snappy,
+			Compression::Lz4 => Self::Lz4,
+			Compression::Zstd => Self::Zstd,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CompactionStyle {
+	Level,
+	Universal,
+}
+
+impl From<CompactionStyle> for DBCompactionStyle {
+	fn from(style: CompactionStyle) -> Self {
+		match style {
+			CompactionStyle::Level => Self::Level,
+			CompactionStyle::Universal => Self::Universal,
+		}
+	}
+}
+
+impl StorageConfig {
+	pub fn update_ttl(&self) -> Duration {
+		Duration::from_secs(self.update_ttl_days.saturating_mul(24 * 60 * 60))
+	}
+
+	/// Block cache to share between the column families of one database.
+	pub fn block_cache(&self) -> Cache {
+		Cache::new_lru_cache(self.block_cache_size_mb.saturating_mul(MIB))
+	}
+
+	/// Tuned options for a column family, reading through `cache`.
+	pub fn cf_options(&self, cache: &Cache) -> Options {
+		let mut table_opts = BlockBasedOptions::default();
+		table_opts.set_block_cache(cache);
+		if self.bloom_filter_bits > 0. {
+			table_opts.set_bloom_filter(self.bloom_filter_bits, false);
+		}
+
+		let mut opts = Options::default();
+		opts.set_block_based_table_factory(&table_opts);
+		opts.set_write_buffer_size(self.write_buffer_size_mb.saturating_mul(MIB));
+		opts.set_compression_type(self.compression.into());
+		opts.set_compaction_style(self.compaction_style.into());
+		opts
+	}
+}
+
+/// Credentials per role. A role without any is open to everyone, except admin which is disabled.
+#[derive(Debug, Clone, Default, Args)]
+pub struct AuthConfig {
+	/// Bearer token of the transformer, the only caller allowed to write terms.
+	#[arg(long, env = "LC_TRANSFORMER_TOKEN")]
+	pub transformer_token: Option<String>,
+
+	/// PEM client certificate identifying the transformer.
+	#[arg(long, env = "LC_TRANSFORMER_CERT")]
+	pub transformer_cert: Option<PathBuf>,
+
+	/// Bearer token of callers reading cells and mappings.
+	#[arg(long, env = "LC_READER_TOKEN")]
+	pub reader_token: Option<String>,
+
+	/// PEM client certificate identifying readers.
+	#[arg(long, env = "LC_READER_CERT")]
+	pub reader_cert: Option<PathBuf>,
+
+	/// Bearer token admin RPCs must present.
+	#[arg(long, env = "LC_ADMIN_TOKEN")]
+	pub admin_token: Option<String>,
+
+	/// PEM client certificate identifying admins.
+	#[arg(long, env = "LC_ADMIN_CERT")]
+	pub admin_cert: Option<PathBuf>,
+}
+
+/// Serves plaintext unless a certificate and key are given.
+#[derive(Debug, Clone, Default, Args)]
+pub struct TlsConfig {
+	/// PEM certificate chain presented by the server.
+	#[arg(long, env = "LC_TLS_CERT", requires = "tls_key")]
+	pub tls_cert: Option<PathBuf>,
+
+	/// PEM private key of the server certificate.
+	#[arg(long, env = "LC_TLS_KEY", requires = "tls_cert")]
+	pub tls_key: Option<PathBuf>,
+
+	/// PEM CA bundle client certificates must chain to. Enables mutual TLS.
+	#[arg(long, env = "LC_TLS_CLIENT_CA", requires = "tls_cert")]
+	pub tls_client_ca: Option<PathBuf>,
+}
+
+impl TlsConfig {
+	/// Loads the configured certificates, `None` when TLS is disabled.
+	pub fn load(&self) -> io::Result<Option<ServerTlsConfig>> {
+		let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) else {
+			return Ok(None);
+		};
+		let identity = Identity::from_pem(fs::read(cert)?, fs::read(key)?);
+		let mut tls = ServerTlsConfig::new().identity(identity);
+		if let Some(client_ca) = &self.tls_client_ca {
+			tls = tls.client_ca_root(Certificate::from_pem(fs::read(client_ca)?));
+		}
+		Ok(Some(tls))
+	}
+}
+
+impl Default for StorageConfig {
+	fn default() -> Self {
+		Self {
+			update_ttl_days: DEFAULT_UPDATE_TTL_DAYS,
+			max_open_files: DEFAULT_MAX_OPEN_FILES,
+			write_buffer_size_mb: DEFAULT_WRITE_BUFFER_SIZE_MB,
+			max_background_jobs: DEFAULT_MAX_BACKGROUND_JOBS,
+			block_cache_size_mb: DEFAULT_BLOCK_CACHE_SIZE_MB,
+			bloom_filter_bits: DEFAULT_BLOOM_FILTER_BITS,
+			compression: Compression::Lz4,
+			compaction_style: CompactionStyle::Level,
+			backend: StorageBackend::Disk,
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{CompactionStyle, Compression, Config, StorageBackend};
+	use clap::Parser;
+
+	#[test]
+	fn should_parse_flags_over_defaults() {
+		let config = Config::try_parse_from([
+			"linear-combiner", "--listen-addr", "0.0.0.0:6000", "--db-path", "/var/lib/lc",
+			"--update-ttl-days", "7", "--compression", "zstd", "--backend", "memory",
+		])
+		.unwrap();
+		assert_eq!(config.listen_addr.port(), 6000);
+		assert_eq!(config.db_path, "/var/lib/lc");
+		assert_eq!(config.backup_dir, "lc-backups");
+		assert_eq!(config.storage.update_ttl().as_secs(), 7 * 24 * 60 * 60);
+		assert_eq!(config.storage.max_open_files, -1);
+		assert_eq!(config.storage.compression, Compression::Zstd);
+		assert_eq!(config.storage.compaction_style, CompactionStyle::Level);
+		assert_eq!(config.storage.backend, StorageBackend::Memory);
+		assert!(config.tls.load().unwrap().is_none());
+	}
+
+	#[test]
+	fn should_require_tls_key_with_cert() {
+		let result = Config::try_parse_from(["linear-combiner", "--tls-cert", "server.pem"]);
+		assert!(result.is_err());
+	}
+}
@@ -1,12 +1,11 @@
+use proto_buf::common::ErrorDetail;
 use rocksdb::Error as RocksDbError;
 use thiserror::Error;
-use tonic::Status;
+use tonic::{Code, Status};
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Error)]
 pub enum LcError {
-	#[error("SerialisationError")]
-	SerialisationError,
-
 	#[error("DbError: {0}")]
 	DbError(RocksDbError),
 
@@ -15,10 +14,27 @@ pub enum LcError {
 
 	#[error("ParseError")]
 	ParseError,
+
+	#[error("OverflowError")]
+	OverflowError,
+
+	#[error("UnsupportedSchemaError: {0}")]
+	UnsupportedSchemaError(usize),
 }
 
 impl LcError {
 	pub fn into_status(self) -> Status {
-		Status::internal(format!("Internal error: {}", self))
+		match self {
+			LcError::OverflowError => ErrorDetail::permanent()
+				.with_field("weight")
+				.into_status(Code::OutOfRange, "Cell value out of range!"),
+			// The database may well come back, unlike data it can't make sense of.
+			LcError::DbError(e) => ErrorDetail::retryable(0)
+				.with_cause(&e)
+				.into_status(Code::Internal, format!("Internal error: DbError: {}", e)),
+			e => ErrorDetail::permanent()
+				.with_cause(&e)
+				.into_status(Code::Internal, format!("Internal error: {}", e)),
+		}
 	}
 }
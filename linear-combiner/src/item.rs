@@ -1,15 +1,35 @@
-use proto_buf::combiner::LtObject;
+use proto_buf::combiner::{DidMapping, LtObject, MappingChange};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+use crate::error::LcError;
+
+/// Decodes a stored cell value, a big-endian `f64` optionally followed by the write time.
+pub fn decode_value(bytes: &[u8]) -> Result<f64, LcError> {
+	let value_bytes: [u8; 8] =
+		bytes.get(..8).and_then(|b| b.try_into().ok()).ok_or(LcError::ParseError)?;
+	Ok(f64::from_be_bytes(value_bytes))
+}
+
+/// Decodes the write time following a stored cell value, zero for values written without.
+pub fn decode_timestamp(bytes: &[u8]) -> u64 {
+	bytes.get(8..16).map_or(0, |b| u64::from_be_bytes(b.try_into().unwrap()))
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct LtItem {
 	x: u32,
 	y: u32,
-	value: u32,
+	value: f64,
+	/// Unix time in milliseconds the cell was last written at, zero if unknown.
+	timestamp: u64,
 }
 
 impl LtItem {
-	pub fn new(x: u32, y: u32, value: u32) -> Self {
-		LtItem { x, y, value }
+	pub fn new(x: u32, y: u32, value: f64) -> Self {
+		LtItem { x, y, value, timestamp: 0 }
+	}
+
+	pub fn with_timestamp(self, timestamp: u64) -> Self {
+		Self { timestamp, ..self }
 	}
 
 	pub fn key_bytes(&self) -> Vec<u8> {
@@ -27,8 +47,10 @@ impl LtItem {
 		let mut key_bytes = [0; 16];
 		key_bytes.copy_from_slice(key.as_ref());
 
-		let mut value_bytes = [0; 4];
-		value_bytes.copy_from_slice(value.as_ref());
+		// Values may be followed by the time they were written at.
+		let mut value_bytes = [0; 8];
+		value_bytes.copy_from_slice(&value.as_ref()[..8]);
+		let timestamp = decode_timestamp(value.as_ref());
 
 		let mut x_bytes = [0; 4];
 		let mut y_bytes = [0; 4];
@@ -37,14 +59,51 @@ impl LtItem {
 
 		let x = u32::from_be_bytes(x_bytes);
 		let y = u32::from_be_bytes(y_bytes);
-		let value = u32::from_be_bytes(value_bytes);
+		let value = f64::from_be_bytes(value_bytes);
+
+		Self { x, y, value, timestamp }
+	}
+}
+
+impl From<LtItem> for LtObject {
+	fn from(item: LtItem) -> Self {
+		let cursor = item.key_bytes();
+		LtObject { x: item.x, y: item.y, value: item.value, cursor, timestamp: item.timestamp }
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappingItem {
+	key: Vec<u8>,
+	index: u32,
+}
+
+impl MappingItem {
+	pub fn from_raw<I: AsRef<[u8]>>(key: I, index: I) -> Self {
+		let mut index_bytes = [0; 4];
+		index_bytes.copy_from_slice(index.as_ref());
+
+		Self { key: key.as_ref().to_vec(), index: u32::from_be_bytes(index_bytes) }
+	}
+
+	pub fn key(&self) -> &[u8] {
+		&self.key
+	}
+
+	pub fn index(&self) -> u32 {
+		self.index
+	}
+}
 
-		Self { x, y, value }
+impl From<MappingItem> for DidMapping {
+	fn from(item: MappingItem) -> Self {
+		DidMapping { key: hex::encode(&item.key), index: item.index, cursor: item.key }
 	}
 }
 
-impl Into<LtObject> for LtItem {
-	fn into(self) -> LtObject {
-		LtObject { x: self.x, y: self.y, value: self.value }
+impl From<MappingItem> for MappingChange {
+	fn from(item: MappingItem) -> Self {
+		// Indices are handed out one after another, so they double as the sequence.
+		MappingChange { sequence: u64::from(item.index), mapping: Some(item.into()) }
 	}
 }
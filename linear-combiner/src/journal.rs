@@ -0,0 +1,60 @@
+use prost::Message;
+use proto_buf::transformer::TermObject;
+
+use crate::error::LcError;
+
+/// A term as received by `sync_transformer`, before it was aggregated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+	/// Unix time in milliseconds the term was received at.
+	pub timestamp: u64,
+	/// Peer that streamed the term.
+	pub source: String,
+	pub term: TermObject,
+}
+
+impl JournalEntry {
+	/// Encodes the entry as timestamp, source length, source and the protobuf encoded term.
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let source = self.source.as_bytes();
+		let mut bytes = Vec::with_capacity(12 + source.len() + self.term.encoded_len());
+		bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+		bytes.extend_from_slice(&(source.len() as u32).to_be_bytes());
+		bytes.extend_from_slice(source);
+		self.term.encode(&mut bytes).expect("Vec grows as needed");
+		bytes
+	}
+
+	pub fn from_raw(value: &[u8]) -> Result<Self, LcError> {
+		let header = value.get(..12).ok_or(LcError::ParseError)?;
+		let timestamp = u64::from_be_bytes(header[..8].try_into().unwrap());
+		let source_len = u32::from_be_bytes(header[8..].try_into().unwrap()) as usize;
+		let source = value.get(12..12 + source_len).ok_or(LcError::ParseError)?;
+		let source = String::from_utf8(source.to_vec()).map_err(|_| LcError::ParseError)?;
+		let term =
+			TermObject::decode(&value[12 + source_len..]).map_err(|_| LcError::ParseError)?;
+		Ok(Self { timestamp, source, term })
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::journal::JournalEntry;
+	use proto_buf::transformer::TermObject;
+
+	#[test]
+	fn should_round_trip_entry() {
+		let term = TermObject {
+			from: "90f8bf6a479f320ead074411a4b0e7944ea8c9c2".to_string(),
+			to: "90f8bf6a479f320ead074411a4b0e7944ea8c9c3".to_string(),
+			weight: 2.5,
+			domain: 3,
+			form: 1,
+			sequence: 7,
+			source: "pipeline".to_string(),
+		};
+		let entry = JournalEntry { timestamp: 42, source: "[::1]:1234".to_string(), term };
+		assert_eq!(JournalEntry::from_raw(&entry.to_bytes()).unwrap(), entry);
+		assert!(JournalEntry::from_raw(&[0; 4]).is_err());
+	}
+}
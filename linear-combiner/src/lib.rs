@@ -0,0 +1,29 @@
+//! Storage layout of the linear combiner, shared by the server and `lc-tool`.
+
+pub mod error;
+pub mod item;
+pub mod journal;
+pub mod mapping;
+pub mod window;
+
+/// Domain and DID key -> peer index.
+pub const INDEX_CF: &str = "index";
+/// Aggregated local trust cells.
+pub const LT_CF: &str = "lt";
+/// Cells changed since they were last served by `get_new_data`.
+pub const UPDATE_CF: &str = "update";
+/// Domain and peer index -> DID key.
+pub const MAPPING_CF: &str = "mapping";
+/// Cell values keyed by write time: domain, form, timestamp, x, y.
+pub const LT_TIME_CF: &str = "lt_time";
+/// Replay window of applied term sequences, keyed by source.
+pub const SEQUENCE_CF: &str = "sequence";
+
+/// Domain and index of a removed peer -> removal time.
+pub const TOMBSTONE_CF: &str = "tombstone";
+
+/// Every term received, keyed by a big-endian `u64` journal sequence.
+pub const JOURNAL_CF: &str = "journal";
+
+pub const COLUMN_FAMILIES: [&str; 8] =
+	[INDEX_CF, LT_CF, UPDATE_CF, MAPPING_CF, LT_TIME_CF, SEQUENCE_CF, TOMBSTONE_CF, JOURNAL_CF];
@@ -0,0 +1,61 @@
+use std::{
+	sync::{Mutex, PoisonError},
+	time::Instant,
+};
+
+/// Token bucket admitting up to `rate` terms per second on average, in bursts of at most a
+/// second's worth.
+#[derive(Debug)]
+pub struct RateLimiter {
+	rate: f64,
+	bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+	tokens: f64,
+	refilled_at: Instant,
+}
+
+impl RateLimiter {
+	/// A `rate` of zero admits everything.
+	pub fn new(rate: u32) -> Self {
+		let rate = f64::from(rate);
+		Self { rate, bucket: Mutex::new(Bucket { tokens: rate, refilled_at: Instant::now() }) }
+	}
+
+	/// Takes `n` tokens, returning `false` without taking any if there aren't enough.
+	pub fn try_acquire(&self, n: u32) -> bool {
+		if self.rate == 0. {
+			return true;
+		}
+		let mut bucket = self.bucket.lock().unwrap_or_else(PoisonError::into_inner);
+		let now = Instant::now();
+		let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+		bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.rate);
+		bucket.refilled_at = now;
+
+		let n = f64::from(n);
+		if bucket.tokens < n {
+			return false;
+		}
+		bucket.tokens -= n;
+		true
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::RateLimiter;
+
+	#[test]
+	fn should_limit_bursts() {
+		let limiter = RateLimiter::new(10);
+		assert!(limiter.try_acquire(8));
+		assert!(!limiter.try_acquire(5));
+		assert!(limiter.try_acquire(2));
+
+		let unlimited = RateLimiter::new(0);
+		assert!(unlimited.try_acquire(u32::MAX));
+	}
+}
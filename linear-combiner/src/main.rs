@@ -1,43 +1,655 @@
-use error::LcError;
-use item::LtItem;
+use auth::{Authenticator, Role};
+use clap::Parser;
+use config::{Config, StorageConfig};
+use limit::RateLimiter;
+use linear_combiner::{
+	error::LcError,
+	item::{decode_timestamp, decode_value, LtItem, MappingItem},
+	journal::JournalEntry,
+	mapping::KeyPattern,
+	window::ReplayWindow,
+	INDEX_CF, JOURNAL_CF, LT_CF, LT_TIME_CF, MAPPING_CF, SEQUENCE_CF, TOMBSTONE_CF, UPDATE_CF,
+};
+use metrics::{Metrics, RpcMetricsLayer};
 use proto_buf::{
 	combiner::{
 		linear_combiner_server::{LinearCombiner, LinearCombinerServer},
-		LtBatch, LtHistoryBatch, LtObject,
+		lt_history_event, lt_watch_event, mapping_watch_event, BackupInfo, ConsistencyCheck,
+		ConsistencyReport, DidMapping, DivergentCell, DomainReset, JournalReplay, LtBatch, LtDelta,
+		LtHistoryBatch, LtHistoryEvent, LtObject, LtSnapshotCell, LtSnapshotRequest, LtStats,
+		LtStatsRequest, LtTombstone, LtWatch, LtWatchEvent, MappingQuery, MappingWatch,
+		MappingWatchEvent, MatchMode, PeerRemoval, PeerRemovalInfo, RestoreRequest, RollbackInfo,
+		RollbackRequest, SourceCheckpoint, SourceQuery, TermBatchAck,
+	},
+	common::{ErrorDetail, Heartbeat, PageRequest, PageResponse, ServiceInfo, Void, Watermark},
+	transformer::{TermObject, TermObjectBatch},
+	PROTOCOL_VERSION,
+};
+use rocksdb::{
+	backup::{BackupEngineInfo, RestoreOptions},
+	compaction_filter::Decision,
+	BoundColumnFamily, ColumnFamilyDescriptor, Direction, IteratorMode, WriteBatch, DB,
+};
+use status::Activity;
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	error::Error,
+	fs::canonicalize,
+	net::SocketAddr,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex, PoisonError,
 	},
-	common::Void,
-	transformer::TermObject,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use storage::Storage;
+use tokio::{
+	select,
+	signal::{
+		ctrl_c,
+		unix::{signal, SignalKind},
+	},
+	sync::{
+		broadcast::{self, error::RecvError},
+		mpsc::{channel, Sender},
+		watch,
+	},
+	time::interval,
 };
-use rocksdb::DB;
-use rocksdb::{IteratorMode, WriteBatch};
-use std::error::Error;
-use tokio::sync::mpsc::channel;
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::{transport::Server, Request, Response, Status, Streaming};
+use tonic::{
+	codegen::InterceptedService,
+	metadata::{BinaryMetadataValue, MetadataValue},
+	transport::{NamedService, Server},
+	Code, Request, Response, Status, Streaming,
+};
+use tonic_health::{
+	server::{health_reporter, HealthReporter},
+	ServingStatus,
+};
+
+mod auth;
+mod config;
+mod limit;
+mod metrics;
+mod status;
+mod storage;
+
+/// Version of the on-disk layout, counting the `MIGRATIONS` applied to the database.
+const SCHEMA_VERSION_KEY: &[u8] = b"layout_version";
+/// Upgrades of the on-disk layout, applied in order, each exactly once per database.
+const MIGRATIONS: [Migration; 3] = [
+	(
+		"column families",
+		LinearCombinerService::migrate_column_families,
+	),
+	("f64 cell values", LinearCombinerService::migrate_values),
+	(
+		"per domain keyspace",
+		LinearCombinerService::migrate_keyspace,
+	),
+];
+
+/// Terms written per atomic batch while ingesting a transformer stream, and most terms of a
+/// batch sent through `sync_term_batches`.
+const INGEST_BATCH_SIZE: usize = 1000;
+const MAX_HISTORY_BATCH_SIZE: u32 = 1000;
+const UPDATE_COMPACTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const BACKUP_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+const BACKUP_RETENTION: usize = 8;
+const MAX_MAPPING_BATCH_SIZE: u32 = 1000;
+/// Most matches the first page of a paged read counts, the total being left out beyond.
+const MAX_COUNTED_MATCHES: usize = 10_000;
+const SNAPSHOT_BUFFER_SIZE: usize = 1024;
+/// Divergent cells listed per kind in a consistency report.
+const MAX_REPORTED_CELLS: usize = 1000;
+/// Cell updates buffered for slow `watch_lt` subscribers before they are dropped.
+const WATCH_BUFFER_SIZE: usize = 1024;
+const WATERMARK_INTERVAL: Duration = Duration::from_secs(5);
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+/// Wait suggested to transformers going over the ingest rate, for the bucket to refill.
+const INGEST_RETRY_AFTER: Duration = Duration::from_secs(1);
+/// Optional RPCs and behaviors reported to clients by `get_service_info`.
+const FEATURES: [&str; 8] = [
+	"source_checkpoints", "sequence_dedup", "watch_lt", "watch_did_mapping", "snapshots",
+	"backups", "rollback", "term_batches",
+];
+/// Response headers describing a page of `GetHistoricData` or `GetDidMapping`.
+const TOTAL_HEADER: &str = "lc-total";
+const RETURNED_HEADER: &str = "lc-returned";
+const NEXT_CURSOR_HEADER: &str = "lc-next-cursor-bin";
+const WATERMARK_HEADER: &str = "lc-watermark";
+
+/// A cell write, as published to `watch_lt` subscribers.
+#[derive(Debug, Clone)]
+struct CellUpdate {
+	domain: u32,
+	form: i32,
+	item: LtItem,
+}
+
+/// An index assignment, as published to `watch_did_mapping` subscribers.
+#[derive(Debug, Clone)]
+struct MappingUpdate {
+	domain: u32,
+	item: MappingItem,
+}
 
-mod error;
-mod item;
+/// Writes of one chunk of ingested terms, not yet visible in the database.
+struct PendingWrites {
+	batch: WriteBatch,
+	indices: HashMap<Vec<u8>, [u8; 4]>,
+	/// Indices newly assigned by this chunk, in the order they were handed out.
+	assigned: Vec<MappingUpdate>,
+	values: HashMap<Vec<u8>, f64>,
+	windows: HashMap<Vec<u8>, ReplayWindow>,
+	/// Checkpoint of every domain touched by this chunk, the next index to hand out.
+	offsets: HashMap<u32, u32>,
+	/// Highest sequence applied from every source touched by this chunk.
+	source_checkpoints: HashMap<String, u64>,
+}
+
+impl PendingWrites {
+	fn new() -> Self {
+		Self {
+			batch: WriteBatch::default(),
+			indices: HashMap::new(),
+			assigned: Vec::new(),
+			values: HashMap::new(),
+			windows: HashMap::new(),
+			offsets: HashMap::new(),
+			source_checkpoints: HashMap::new(),
+		}
+	}
+}
+
+/// Describes a migration and fills a batch with the writes upgrading the database.
+type Migration = (
+	&'static str,
+	fn(&DB, &mut WriteBatch) -> Result<(), LcError>,
+);
+
+/// Where a page of a paged read sits in the whole result.
+#[derive(Debug, PartialEq)]
+struct PageInfo {
+	/// Number of all matches, only counted for the first page.
+	total: Option<usize>,
+	returned: usize,
+	/// Cursor to request the next page with, unless this page was the last one.
+	next_cursor: Option<Vec<u8>>,
+}
+
+impl From<&PageInfo> for PageResponse {
+	fn from(page: &PageInfo) -> Self {
+		Self {
+			next_cursor: page.next_cursor.clone().unwrap_or_default(),
+			total: page.total.unwrap_or(0) as u64,
+			has_more: page.next_cursor.is_some(),
+		}
+	}
+}
 
 #[derive(Clone)]
 struct LinearCombinerService {
-	main_db: String,
-	updates_db: String,
+	db: Arc<DB>,
+	db_url: String,
+	backup_dir: String,
+	/// Where the database and its backups are kept.
+	storage: Arc<dyn Storage>,
+	auth: Authenticator,
+	metrics: Arc<Metrics>,
+	activity: Arc<Activity>,
+	updates: broadcast::Sender<CellUpdate>,
+	assignments: broadcast::Sender<MappingUpdate>,
+	last_write: Arc<AtomicU64>,
+	write_lock: Arc<Mutex<()>>,
+	ingest_limiter: Arc<RateLimiter>,
+	max_stream_terms: u64,
+	max_ingest_rate: u32,
+	/// Time between the watermarks of watches, or their heartbeats while nothing changed.
+	watermark_interval: Duration,
+	/// Flips to `true` once the server starts shutting down.
+	shutdown: Arc<watch::Sender<bool>>,
 }
 
 impl LinearCombinerService {
-	pub fn new(main_db_url: &str, updates_db_url: &str) -> Result<Self, LcError> {
-		let main_db = DB::open_default(main_db_url).map_err(|x| LcError::DbError(x))?;
-		let checkpoint = main_db.get(b"checkpoint").map_err(|x| LcError::DbError(x))?;
-		if let None = checkpoint {
-			let count = 0u32.to_be_bytes();
-			main_db.put(b"checkpoint", count).map_err(|x| LcError::DbError(x))?;
+	pub fn new(config: &Config, auth: Authenticator) -> Result<Self, LcError> {
+		let storage = storage::open(&config.storage)?;
+		let db = Self::open_db(&config.db_path, &config.storage, storage.as_ref())?;
+		Self::migrate(&db, &config.backup_dir, storage.as_ref())?;
+
+		let (updates, _) = broadcast::channel(WATCH_BUFFER_SIZE);
+		let (assignments, _) = broadcast::channel(WATCH_BUFFER_SIZE);
+		Ok(Self {
+			db: Arc::new(db),
+			db_url: config.db_path.clone(),
+			backup_dir: config.backup_dir.clone(),
+			storage,
+			auth,
+			metrics: Arc::new(Metrics::new()),
+			activity: Arc::new(Activity::new()),
+			updates,
+			assignments,
+			last_write: Arc::new(AtomicU64::new(0)),
+			write_lock: Arc::new(Mutex::new(())),
+			ingest_limiter: Arc::new(RateLimiter::new(config.ingest.max_ingest_rate)),
+			max_stream_terms: config.ingest.max_stream_terms,
+			max_ingest_rate: config.ingest.max_ingest_rate,
+			watermark_interval: WATERMARK_INTERVAL,
+			shutdown: Arc::new(watch::channel(false).0),
+		})
+	}
+
+	/// Makes open streams end with a retryable status, so the server can drain.
+	fn begin_shutdown(&self) {
+		self.shutdown.send_replace(true);
+	}
+
+	/// Persists the WAL and memtables before the database is dropped.
+	fn close(&self) -> Result<(), LcError> {
+		self.db.flush_wal(true).map_err(LcError::DbError)?;
+		self.db.flush().map_err(LcError::DbError)
+	}
+
+	fn open_db(db_url: &str, config: &StorageConfig, storage: &dyn Storage) -> Result<DB, LcError> {
+		let cache = config.block_cache();
+		let mut opts = config.cf_options(&cache);
+		opts.create_if_missing(true);
+		opts.create_missing_column_families(true);
+		opts.set_max_open_files(config.max_open_files);
+		opts.set_max_background_jobs(config.max_background_jobs);
+
+		// Updates nobody consumed within the TTL are dropped whenever they get compacted.
+		let mut update_opts = config.cf_options(&cache);
+		let ttl = u64::try_from(config.update_ttl().as_millis()).unwrap_or(u64::MAX);
+		update_opts.set_compaction_filter("update_ttl", move |_, _, value: &[u8]| {
+			match value.get(8..16) {
+				Some(timestamp) => {
+					let timestamp = u64::from_be_bytes(timestamp.try_into().unwrap());
+					if timestamp.saturating_add(ttl) < now_millis() {
+						Decision::Remove
+					} else {
+						Decision::Keep
+					}
+				},
+				None => Decision::Keep,
+			}
+		});
+
+		let cfs = [INDEX_CF, LT_CF, MAPPING_CF, LT_TIME_CF, SEQUENCE_CF, TOMBSTONE_CF, JOURNAL_CF]
+			.into_iter()
+			.map(|name| ColumnFamilyDescriptor::new(name, config.cf_options(&cache)))
+			.chain([ColumnFamilyDescriptor::new(UPDATE_CF, update_opts)]);
+		storage.open_db(db_url, opts, cfs.collect())
+	}
+
+	/// Reads the schema version, inferring it for databases written before it was stored.
+	fn schema_version(db: &DB) -> Result<usize, LcError> {
+		if let Some(version) = db.get(SCHEMA_VERSION_KEY).map_err(LcError::DbError)? {
+			let bytes = version.as_slice().try_into().map_err(|_| LcError::ParseError)?;
+			return Ok(u32::from_be_bytes(bytes) as usize);
+		}
+		// A database without any cells or indices has nothing to migrate.
+		for name in [LT_CF, INDEX_CF] {
+			let cf = Self::cf(db, name)?;
+			if db.iterator_cf(&cf, IteratorMode::Start).next().is_some() {
+				return Ok(0);
+			}
+		}
+		for item in db.iterator(IteratorMode::Start) {
+			let (key, value) = item.map_err(LcError::DbError)?;
+			if Self::is_default_cf_entry(&key, &value) {
+				return Ok(0);
+			}
+		}
+		Ok(MIGRATIONS.len())
+	}
+
+	/// Upgrades the database to the latest schema version, backing it up into `backup_dir`
+	/// before migrating anything.
+	fn migrate(db: &DB, backup_dir: &str, storage: &dyn Storage) -> Result<(), LcError> {
+		let version = Self::schema_version(db)?;
+		let pending = MIGRATIONS.get(version..).ok_or(LcError::UnsupportedSchemaError(version))?;
+		let is_stored = db.get(SCHEMA_VERSION_KEY).map_err(LcError::DbError)?.is_some();
+		if pending.is_empty() {
+			if !is_stored {
+				let mut batch = WriteBatch::default();
+				Self::put_schema_version(&mut batch, version);
+				db.write(batch).map_err(LcError::DbError)?;
+			}
+			return Ok(());
+		}
+
+		let info = Self::create_backup(db, backup_dir, storage)?;
+		println!(
+			"Backed up the database before migrating: {}",
+			info.backup_id
+		);
+		// Every migration commits along with its version, so an interrupted run resumes.
+		for (i, (name, migration)) in pending.iter().enumerate() {
+			println!("Migrating the database to {}", name);
+			let mut batch = WriteBatch::default();
+			migration(db, &mut batch)?;
+			Self::put_schema_version(&mut batch, version + i + 1);
+			db.write(batch).map_err(LcError::DbError)?;
+		}
+		Ok(())
+	}
+
+	fn put_schema_version(batch: &mut WriteBatch, version: usize) {
+		let version = u32::try_from(version).expect("schema version should fit a u32");
+		batch.put(SCHEMA_VERSION_KEY, version.to_be_bytes());
+	}
+
+	/// Whether an entry of the default column family is a cell or an index entry, as laid
+	/// out before they had column families of their own. Cells are keyed by domain, form and
+	/// both peers, index entries by the 20 byte address of the peer, and both held a `u32`.
+	fn is_default_cf_entry(key: &[u8], value: &[u8]) -> bool {
+		value.len() == 4 && (key.len() == 16 || key.len() == 20)
+	}
+
+	/// Moves the cells and index out of the default column family, mapping every index back
+	/// to its key. Cells are marked updated too, since those not yet served were kept in a
+	/// separate database which is left behind.
+	fn migrate_column_families(db: &DB, batch: &mut WriteBatch) -> Result<(), LcError> {
+		let lt_cf = Self::cf(db, LT_CF)?;
+		let update_cf = Self::cf(db, UPDATE_CF)?;
+		let index_cf = Self::cf(db, INDEX_CF)?;
+		let mapping_cf = Self::cf(db, MAPPING_CF)?;
+		for item in db.iterator(IteratorMode::Start) {
+			let (key, value) = item.map_err(LcError::DbError)?;
+			if !Self::is_default_cf_entry(&key, &value) {
+				continue;
+			}
+			if key.len() == 16 {
+				batch.put_cf(&lt_cf, &key, &value);
+				batch.put_cf(&update_cf, &key, &value);
+			} else {
+				batch.put_cf(&index_cf, &key, &value);
+				batch.put_cf(&mapping_cf, &value, &key);
+			}
+			batch.delete(key);
+		}
+		Ok(())
+	}
+
+	/// Rewrites `u32` cell values written before they became `f64`.
+	fn migrate_values(db: &DB, batch: &mut WriteBatch) -> Result<(), LcError> {
+		for name in [LT_CF, UPDATE_CF, LT_TIME_CF] {
+			let cf = Self::cf(db, name)?;
+			for item in db.iterator_cf(&cf, IteratorMode::Start) {
+				let (key, value) = item.map_err(LcError::DbError)?;
+				if let Ok(value_bytes) = <[u8; 4]>::try_from(value.as_ref()) {
+					let value = f64::from(u32::from_be_bytes(value_bytes));
+					batch.put_cf(&cf, key, value.to_be_bytes());
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Copies the index, mapping and checkpoint shared by all domains before they were
+	/// scoped into every domain that has cells.
+	fn migrate_keyspace(db: &DB, batch: &mut WriteBatch) -> Result<(), LcError> {
+		let lt_cf = Self::cf(db, LT_CF)?;
+		let index_cf = Self::cf(db, INDEX_CF)?;
+		let mapping_cf = Self::cf(db, MAPPING_CF)?;
+
+		let mut domains = Vec::new();
+		let mut iter = db.raw_iterator_cf(&lt_cf);
+		iter.seek_to_first();
+		while let Some(key) = iter.key() {
+			let domain = u32::from_be_bytes(key[..4].try_into().unwrap());
+			domains.push(domain);
+			match domain.checked_add(1) {
+				Some(next) => iter.seek(next.to_be_bytes()),
+				None => break,
+			}
+		}
+		iter.status().map_err(LcError::DbError)?;
+
+		let checkpoint = db.get(b"checkpoint").map_err(LcError::DbError)?;
+		for cf in [&index_cf, &mapping_cf] {
+			for item in db.iterator_cf(cf, IteratorMode::Start) {
+				let (key, value) = item.map_err(LcError::DbError)?;
+				for domain in &domains {
+					batch.put_cf(cf, [&domain.to_be_bytes(), key.as_ref()].concat(), &value);
+				}
+				batch.delete_cf(cf, key);
+			}
+		}
+		if let Some(checkpoint) = checkpoint {
+			for domain in &domains {
+				batch.put(Self::checkpoint_key(*domain), &checkpoint);
+			}
+			batch.delete(b"checkpoint");
+		}
+		Ok(())
+	}
+
+	/// Compacts the updates so expired ones are purged even without new writes.
+	fn compact_updates(db: &DB) -> Result<(), LcError> {
+		let update_cf = Self::cf(db, UPDATE_CF)?;
+		db.compact_range_cf(&update_cf, None::<&[u8]>, None::<&[u8]>);
+		Ok(())
+	}
+
+	fn spawn_update_compaction(&self) {
+		let db = self.db.clone();
+		tokio::spawn(async move {
+			let mut ticker = interval(UPDATE_COMPACTION_INTERVAL);
+			loop {
+				ticker.tick().await;
+				let db = db.clone();
+				let res = tokio::task::spawn_blocking(move || Self::compact_updates(&db)).await;
+				if let Ok(Err(e)) = res {
+					println!("Failed to compact updates: {}", e);
+				}
+			}
+		});
+	}
+
+	/// Deletes every cell of `domain` and the journal entries they came from, together with
+	/// its index and tombstones unless `preserve_mapping`. Replaying the journal leaves the
+	/// domain empty, and the terms dropped may be sent again.
+	fn reset_domain(db: &DB, domain: u32, preserve_mapping: bool) -> Result<(), LcError> {
+		let prefix = domain.to_be_bytes();
+		// Sorts after every key of the domain, since keys are shorter than this.
+		let end = [prefix.as_slice(), &[0xff; 32]].concat();
+
+		let mut cfs = vec![LT_CF, UPDATE_CF, LT_TIME_CF];
+		if !preserve_mapping {
+			cfs.extend([INDEX_CF, MAPPING_CF, TOMBSTONE_CF]);
+		}
+
+		let mut batch = WriteBatch::default();
+		for name in cfs {
+			let cf = Self::cf(db, name)?;
+			batch.delete_range_cf(&cf, prefix.as_slice(), &end);
+		}
+		if !preserve_mapping {
+			batch.delete(Self::checkpoint_key(domain));
+		}
+		Self::drop_journal_entries(db, &mut batch, |entry| entry.term.domain == domain)?;
+		db.write(batch).map_err(LcError::DbError)
+	}
+
+	/// Counts the keys of `cf` starting with `prefix` whose value satisfies `filter`.
+	fn count_keys(
+		db: &DB, cf: &str, prefix: &[u8], filter: impl Fn(&[u8]) -> bool,
+	) -> Result<u64, LcError> {
+		let cf = Self::cf(db, cf)?;
+		let mut count = 0;
+		let iter = db.iterator_cf(&cf, IteratorMode::From(prefix, Direction::Forward));
+		for item in iter {
+			let (key, value) = item.map_err(LcError::DbError)?;
+			if !key.starts_with(prefix) {
+				break;
+			}
+			if filter(&value) {
+				count += 1;
+			}
+		}
+		Ok(count)
+	}
+
+	/// Finds the latest write to a cell of `domain`, seeking to the last key of every form.
+	fn read_last_write(db: &DB, domain: u32) -> Result<u64, LcError> {
+		let lt_time_cf = Self::cf(db, LT_TIME_CF)?;
+		let domain_bytes = domain.to_be_bytes();
+
+		let mut last_write = 0;
+		let mut iter = db.raw_iterator_cf(&lt_time_cf);
+		iter.seek(domain_bytes);
+		while let Some(key) = iter.key() {
+			if !key.starts_with(&domain_bytes) {
+				break;
+			}
+			let form = u32::from_be_bytes(key[4..8].try_into().unwrap());
+			let form_prefix = key[..8].to_vec();
+			iter.seek_for_prev([form_prefix.as_slice(), &[0xff; 16]].concat());
+			if let Some(key) = iter.key() {
+				let timestamp = u64::from_be_bytes(key[8..16].try_into().unwrap());
+				last_write = last_write.max(timestamp);
+			}
+			match form.checked_add(1) {
+				Some(next) => iter.seek([domain_bytes, next.to_be_bytes()].concat()),
+				None => break,
+			}
+		}
+		iter.status().map_err(LcError::DbError)?;
+		Ok(last_write)
+	}
+
+	fn read_stats(db: &DB, domain: u32) -> Result<LtStats, LcError> {
+		let prefix = domain.to_be_bytes();
+		let is_non_zero = |value: &[u8]| decode_value(value).map_or(false, |v| v != 0.);
+		Ok(LtStats {
+			dimension: Self::read_checkpoint(db, domain)?,
+			non_zero_cells: Self::count_keys(db, LT_CF, &prefix, is_non_zero)?,
+			// Removed peers keep their index, without a key.
+			did_count: Self::count_keys(db, MAPPING_CF, &prefix, |key| !key.is_empty())?,
+			pending_updates: Self::count_keys(db, UPDATE_CF, &prefix, |_| true)?,
+			last_write: Self::read_last_write(db, domain)?,
+		})
+	}
+
+	/// Reads the statistics of every domain an index was assigned in.
+	fn read_domain_stats(db: &DB) -> Result<Vec<(u32, LtStats)>, LcError> {
+		let mut domains = Vec::new();
+		let prefix = b"checkpoint";
+		for item in db.iterator(IteratorMode::From(prefix, Direction::Forward)) {
+			let (key, _) = item.map_err(LcError::DbError)?;
+			if !key.starts_with(prefix) {
+				break;
+			}
+			if let Ok(domain) = key[prefix.len()..].try_into() {
+				domains.push(u32::from_be_bytes(domain));
+			}
+		}
+		domains.into_iter().map(|domain| Ok((domain, Self::read_stats(db, domain)?))).collect()
+	}
+
+	/// Backs up the database, keeping only the latest `BACKUP_RETENTION` backups.
+	fn create_backup(
+		db: &DB, backup_dir: &str, storage: &dyn Storage,
+	) -> Result<BackupEngineInfo, LcError> {
+		let mut engine = storage.open_backup_engine(backup_dir)?;
+		engine.create_new_backup_flush(db, true).map_err(LcError::DbError)?;
+		engine.purge_old_backups(BACKUP_RETENTION).map_err(LcError::DbError)?;
+		let infos = engine.get_backup_info();
+		infos.into_iter().max_by_key(|info| info.backup_id).ok_or(LcError::NotFoundError)
+	}
+
+	/// Restores a backup into `target_dir`, the latest one if `backup_id` is `None`.
+	fn restore_backup(
+		backup_dir: &str, backup_id: Option<u32>, target_dir: &str, storage: &dyn Storage,
+	) -> Result<(), LcError> {
+		let mut engine = storage.open_backup_engine(backup_dir)?;
+		let opts = RestoreOptions::default();
+		match backup_id {
+			Some(id) => engine.restore_from_backup(target_dir, target_dir, &opts, id),
+			None => engine.restore_from_latest_backup(target_dir, target_dir, &opts),
 		}
+		.map_err(LcError::DbError)
+	}
+
+	fn spawn_backups(&self) {
+		let db = self.db.clone();
+		let backup_dir = self.backup_dir.clone();
+		let storage = self.storage.clone();
+		tokio::spawn(async move {
+			let mut ticker = interval(BACKUP_INTERVAL);
+			loop {
+				ticker.tick().await;
+				let db = db.clone();
+				let backup_dir = backup_dir.clone();
+				let storage = storage.clone();
+				let res = tokio::task::spawn_blocking(move || {
+					Self::create_backup(&db, &backup_dir, storage.as_ref())
+				})
+				.await;
+				if let Ok(Err(e)) = res {
+					println!("Failed to back up the database: {}", e);
+				}
+			}
+		});
+	}
+
+	/// Healthy as long as RocksDB has not hit a background error, which stops all writes.
+	fn is_healthy(db: &DB) -> bool {
+		matches!(
+			db.property_int_value("rocksdb.background-errors"),
+			Ok(Some(0))
+		)
+	}
+
+	fn spawn_health_checks(&self, mut reporter: HealthReporter) {
+		let db = self.db.clone();
+		let mut shutdown = self.shutdown.subscribe();
+		tokio::spawn(async move {
+			let mut ticker = interval(HEALTH_CHECK_INTERVAL);
+			loop {
+				let is_closing = select! {
+					_ = ticker.tick() => false,
+					_ = shutdown.wait_for(|&closing| closing) => true,
+				};
+				let status = match !is_closing && Self::is_healthy(&db) {
+					true => ServingStatus::Serving,
+					false => ServingStatus::NotServing,
+				};
+				reporter.set_service_status("", status).await;
+				reporter
+					.set_service_status(<LinearCombinerServer<Self> as NamedService>::NAME, status)
+					.await;
+				if is_closing {
+					break;
+				}
+			}
+		});
+	}
+
+	fn spawn_metrics(&self, addr: SocketAddr) {
+		let metrics = self.metrics.clone();
+		let activity = self.activity.clone();
+		let db = self.db.clone();
+		tokio::spawn(async move {
+			if let Err(e) = metrics::serve(addr, metrics, activity, db).await {
+				println!("Metrics endpoint failed: {}", e);
+			}
+		});
+	}
+
+	fn cf<'a>(db: &'a DB, name: &str) -> Result<Arc<BoundColumnFamily<'a>>, LcError> {
+		db.cf_handle(name).ok_or(LcError::NotFoundError)
+	}
 
-		Ok(Self { main_db: main_db_url.to_string(), updates_db: updates_db_url.to_string() })
+	fn checkpoint_key(domain: u32) -> Vec<u8> {
+		[b"checkpoint".as_slice(), &domain.to_be_bytes()].concat()
 	}
 
-	fn read_checkpoint(db: &DB) -> Result<u32, LcError> {
-		let offset_bytes_opt = db.get(b"checkpoint").map_err(|x| LcError::DbError(x))?;
+	fn read_checkpoint(db: &DB, domain: u32) -> Result<u32, LcError> {
+		let offset_bytes_opt = db.get(Self::checkpoint_key(domain)).map_err(LcError::DbError)?;
 		let offset_bytes = offset_bytes_opt.map_or([0; 4], |x| {
 			let mut bytes: [u8; 4] = [0; 4];
 			bytes.copy_from_slice(&x);
@@ -47,120 +659,389 @@ impl LinearCombinerService {
 		Ok(offset)
 	}
 
-	fn write_checkpoint(db: &DB, count: u32) -> Result<(), LcError> {
-		db.put(b"checkpoint", count.to_be_bytes()).map_err(|x| LcError::DbError(x))?;
-		Ok(())
+	/// Commits the pending writes together with the checkpoints they advance.
+	fn commit_writes(db: &DB, pending: PendingWrites) -> Result<(), LcError> {
+		let mut batch = pending.batch;
+		for (domain, offset) in pending.offsets {
+			batch.put(Self::checkpoint_key(domain), offset.to_be_bytes());
+		}
+		for (source, sequence) in pending.source_checkpoints {
+			batch.put(Self::source_checkpoint_key(&source), sequence.to_be_bytes());
+		}
+		db.write(batch).map_err(LcError::DbError)
 	}
 
-	fn get_index(db: &DB, source: String, offset: &mut u32) -> Result<[u8; 4], LcError> {
-		let key = hex::decode(source).map_err(|_| LcError::ParseError)?;
-		let source_index = db.get(&key).map_err(|e| LcError::DbError(e))?;
+	/// Looks up the index of `source` within `domain`, assigning the next free one if new.
+	fn get_index(
+		db: &DB, pending: &mut PendingWrites, domain: u32, source: String,
+	) -> Result<[u8; 4], LcError> {
+		let index_cf = Self::cf(db, INDEX_CF)?;
+		let mapping_cf = Self::cf(db, MAPPING_CF)?;
+
+		let domain_bytes = domain.to_be_bytes();
+		let source_bytes = hex::decode(source).map_err(|_| LcError::ParseError)?;
+		let key = [domain_bytes.as_slice(), &source_bytes].concat();
+		if let Some(index) = pending.indices.get(&key) {
+			return Ok(*index);
+		}
+		let source_index = db.get_cf(&index_cf, &key).map_err(LcError::DbError)?;
 
 		let x = if let Some(from_i) = source_index {
 			let from_bytes: [u8; 4] = from_i.try_into().map_err(|_| LcError::ParseError)?;
 			from_bytes
 		} else {
+			let offset = match pending.offsets.get(&domain) {
+				Some(offset) => *offset,
+				None => Self::read_checkpoint(db, domain)?,
+			};
 			let curr_offset = offset.to_be_bytes();
-			db.put(&key, curr_offset).map_err(|e| LcError::DbError(e))?;
-			*offset += 1;
+			let mapping_key = [domain_bytes, curr_offset].concat();
+			pending.batch.put_cf(&index_cf, &key, curr_offset);
+			pending.batch.put_cf(&mapping_cf, mapping_key, &source_bytes);
+			let item = MappingItem::from_raw(source_bytes.as_slice(), &curr_offset);
+			pending.assigned.push(MappingUpdate { domain, item });
+			pending.indices.insert(key, curr_offset);
+			pending.offsets.insert(domain, offset + 1);
 			curr_offset
 		};
 
 		Ok(x)
 	}
 
-	fn get_value(main_db: &DB, key: &Vec<u8>) -> Result<u32, LcError> {
-		let value_opt = main_db.get(&key).map_err(|e| LcError::DbError(e))?;
-		let value_bytes = value_opt.map_or([0; 4], |x| {
-			let mut bytes: [u8; 4] = [0; 4];
-			bytes.copy_from_slice(&x);
-			bytes
-		});
-		Ok(u32::from_be_bytes(value_bytes))
+	fn source_checkpoint_key(source: &str) -> Vec<u8> {
+		[b"source_checkpoint".as_slice(), source.as_bytes()].concat()
 	}
 
-	fn update_value(
-		main_db: &DB, updates_db: &DB, key: Vec<u8>, weight: u32,
+	fn read_source_checkpoint(db: &DB, source: &str) -> Result<u64, LcError> {
+		match db.get(Self::source_checkpoint_key(source)).map_err(LcError::DbError)? {
+			Some(bytes) => {
+				let bytes = bytes.as_slice().try_into().map_err(|_| LcError::ParseError)?;
+				Ok(u64::from_be_bytes(bytes))
+			},
+			None => Ok(0),
+		}
+	}
+
+	/// Key of the replay window of `from` within `source`, length prefixed so sources with
+	/// a common prefix stay apart.
+	fn window_key(source: &str, from: &str) -> Result<Vec<u8>, LcError> {
+		let source_len = u8::try_from(source.len()).map_err(|_| LcError::ParseError)?;
+		let from = hex::decode(from).map_err(|_| LcError::ParseError)?;
+		Ok([[source_len].as_slice(), source.as_bytes(), &from].concat())
+	}
+
+	/// Records `sequence` as applied for `from` within `source`, returning `false` if it
+	/// already was.
+	fn mark_applied(
+		db: &DB, pending: &mut PendingWrites, source: &str, from: &str, sequence: u64,
+	) -> Result<bool, LcError> {
+		let sequence_cf = Self::cf(db, SEQUENCE_CF)?;
+
+		let key = Self::window_key(source, from)?;
+		let mut window = match pending.windows.get(&key) {
+			Some(window) => *window,
+			None => match db.get_cf(&sequence_cf, &key).map_err(LcError::DbError)? {
+				Some(bytes) => ReplayWindow::from_bytes(&bytes)?,
+				None => ReplayWindow::default(),
+			},
+		};
+		if !window.insert(sequence) {
+			return Ok(false);
+		}
+
+		pending.batch.put_cf(&sequence_cf, &key, window.to_bytes());
+		pending.windows.insert(key, window);
+
+		let checkpoint = match pending.source_checkpoints.get(source) {
+			Some(checkpoint) => *checkpoint,
+			None => Self::read_source_checkpoint(db, source)?,
+		};
+		pending.source_checkpoints.insert(source.to_string(), checkpoint.max(sequence));
+		Ok(true)
+	}
+
+	fn get_value(db: &DB, key: &Vec<u8>) -> Result<f64, LcError> {
+		let lt_cf = Self::cf(db, LT_CF)?;
+		let value_opt = db.get_cf(&lt_cf, key).map_err(LcError::DbError)?;
+		value_opt.map_or(Ok(0.), |x| decode_value(&x))
+	}
+
+	/// Loads the values of `keys` not yet in `pending` with a single batched read, so
+	/// aggregating a chunk doesn't cost a read round-trip per term.
+	fn prefetch_values<'a>(
+		db: &DB, pending: &mut PendingWrites, keys: impl Iterator<Item = &'a Vec<u8>>,
 	) -> Result<(), LcError> {
-		let value = Self::get_value(main_db, &key)?;
-		let new_value = (value + weight).to_be_bytes();
-		main_db.put(key.clone(), new_value).map_err(|e| LcError::DbError(e))?;
-		updates_db.put(key.clone(), new_value).map_err(|e| LcError::DbError(e))?;
+		let lt_cf = Self::cf(db, LT_CF)?;
+		let mut missing: Vec<&Vec<u8>> =
+			keys.filter(|k| !pending.values.contains_key(*k)).collect();
+		missing.sort_unstable();
+		missing.dedup();
+
+		let values = db.multi_get_cf(missing.iter().map(|key| (&lt_cf, key)));
+		for (key, value) in missing.into_iter().zip(values) {
+			let value = value.map_err(LcError::DbError)?;
+			let value = value.map_or(Ok(0.), |x| decode_value(&x))?;
+			pending.values.insert(key.clone(), value);
+		}
 		Ok(())
 	}
 
-	fn read_batch(updates_db: &DB, prefix: Vec<u8>, n: u32) -> Result<Vec<LtItem>, LcError> {
-		let mut iter = updates_db.prefix_iterator(prefix);
+	fn update_value(
+		db: &DB, pending: &mut PendingWrites, key: Vec<u8>, weight: f64, timestamp: u64,
+	) -> Result<f64, LcError> {
+		let lt_cf = Self::cf(db, LT_CF)?;
+		let update_cf = Self::cf(db, UPDATE_CF)?;
+		let lt_time_cf = Self::cf(db, LT_TIME_CF)?;
+
+		let value = match pending.values.get(&key) {
+			Some(value) => *value,
+			None => Self::get_value(db, &key)?,
+		} + weight;
+		if !value.is_finite() {
+			return Err(LcError::OverflowError);
+		}
+		let new_value = value.to_be_bytes();
+
+		let mut time_key = Vec::new();
+		time_key.extend_from_slice(&key[..8]);
+		time_key.extend_from_slice(&timestamp.to_be_bytes());
+		time_key.extend_from_slice(&key[8..]);
+
+		let stamped_value = [new_value, timestamp.to_be_bytes()].concat();
+		pending.batch.put_cf(&lt_cf, &key, &stamped_value);
+		pending.batch.put_cf(&update_cf, &key, stamped_value);
+		pending.batch.put_cf(&lt_time_cf, time_key, new_value);
+		pending.values.insert(key, value);
+		Ok(value)
+	}
+
+	fn read_batch(db: &DB, prefix: Vec<u8>, n: u32) -> Result<Vec<LtItem>, LcError> {
+		let update_cf = Self::cf(db, UPDATE_CF)?;
+		let mut iter = db.prefix_iterator_cf(&update_cf, &prefix);
 		iter.set_mode(IteratorMode::Start);
 
 		let size = usize::try_from(n).map_err(|_| LcError::ParseError)?;
-		let items = iter.take(size).try_fold(Vec::new(), |mut acc, item| {
-			item.map(|(key, value)| {
-				let lt_item = LtItem::from_raw(key, value);
-				acc.push(lt_item);
-				acc
-			})
-			.map_err(|e| LcError::DbError(e))
-		});
+		let mut items = Vec::new();
+		for item in iter {
+			let (key, value) = item.map_err(LcError::DbError)?;
+			// Without a prefix extractor the iterator runs past the prefix.
+			if !key.starts_with(&prefix) || items.len() == size {
+				break;
+			}
+			items.push(LtItem::from_raw(key.as_ref(), value.as_ref()));
+		}
 
-		items
+		Ok(items)
 	}
 
-	fn delete_batch(updates_db: &DB, prefix: Vec<u8>, items: Vec<LtItem>) -> Result<(), LcError> {
+	fn delete_batch(db: &DB, prefix: Vec<u8>, items: Vec<LtItem>) -> Result<(), LcError> {
+		let update_cf = Self::cf(db, UPDATE_CF)?;
 		let mut batch = WriteBatch::default();
 		items.iter().for_each(|x| {
 			let mut key = Vec::new();
 			key.extend_from_slice(&prefix);
 			key.extend_from_slice(&x.key_bytes());
-			batch.delete(key);
+			batch.delete_cf(&update_cf, key);
 		});
-		updates_db.write(batch).map_err(|e| LcError::DbError(e))?;
+		db.write(batch).map_err(LcError::DbError)?;
 		Ok(())
 	}
 
-	fn read_window(main_db: &DB, prefix: Vec<u8>, p0: (u32, u32), p1: (u32, u32)) -> Vec<LtItem> {
+	/// Decodes a cursor handed out with an `LtObject` back into the cell it points at.
+	fn parse_cursor(cursor: &[u8]) -> Result<Option<(u32, u32)>, LcError> {
+		if cursor.is_empty() {
+			return Ok(None);
+		}
+		let bytes: [u8; 8] = cursor.try_into().map_err(|_| LcError::ParseError)?;
+		let x = u32::from_be_bytes(bytes[..4].try_into().unwrap());
+		let y = u32::from_be_bytes(bytes[4..].try_into().unwrap());
+		Ok(Some((x, y)))
+	}
+
+	fn read_window(
+		db: &DB, prefix: Vec<u8>, p0: (u32, u32), p1: (u32, u32), after: Option<(u32, u32)>,
+		limit: usize,
+	) -> Result<Vec<LtItem>, LcError> {
+		let lt_cf = Self::cf(db, LT_CF)?;
 		let mut items = Vec::new();
-		(p0.0..=p1.0).zip(p0.1..=p1.1).into_iter().for_each(|(x, y)| {
-			let mut key = Vec::new();
-			key.extend_from_slice(&prefix);
-			key.extend_from_slice(&x.to_be_bytes());
-			key.extend_from_slice(&y.to_be_bytes());
+		for x in p0.0..=p1.0 {
+			let y_start = match after {
+				Some((ax, _)) if x < ax => continue,
+				Some((ax, ay)) if x == ax => match ay.checked_add(1) {
+					Some(y) => y.max(p0.1),
+					None => continue,
+				},
+				_ => p0.1,
+			};
+
+			let mut start = Vec::new();
+			start.extend_from_slice(&prefix);
+			start.extend_from_slice(&x.to_be_bytes());
+			start.extend_from_slice(&y_start.to_be_bytes());
 
-			let item_res = main_db.get(key.clone());
-			if let Ok(Some(value)) = item_res {
-				let let_item = LtItem::from_raw(key, value);
-				items.push(let_item);
+			let mut end = Vec::new();
+			end.extend_from_slice(&prefix);
+			end.extend_from_slice(&x.to_be_bytes());
+			end.extend_from_slice(&p1.1.to_be_bytes());
+
+			let iter = db.iterator_cf(&lt_cf, IteratorMode::From(&start, Direction::Forward));
+			for item in iter {
+				let (key, value) = item.map_err(LcError::DbError)?;
+				if key.as_ref() > end.as_slice() {
+					break;
+				}
+				if items.len() == limit {
+					return Ok(items);
+				}
+				items.push(LtItem::from_raw(key.as_ref(), value.as_ref()));
 			}
-		});
-		items
+		}
+		Ok(items)
 	}
-}
 
-#[tonic::async_trait]
-impl LinearCombiner for LinearCombinerService {
-	type GetNewDataStream = ReceiverStream<Result<LtObject, Status>>;
-	type GetHistoricDataStream = ReceiverStream<Result<LtObject, Status>>;
+	/// Reads the latest value of every cell in the window written between `since` and
+	/// `until` (inclusive), seeking on the time-ordered keys instead of the whole window.
+	fn read_window_between(
+		db: &DB, prefix: Vec<u8>, p0: (u32, u32), p1: (u32, u32), (since, until): (u64, u64),
+		after: Option<(u32, u32)>, limit: usize,
+	) -> Result<Vec<LtItem>, LcError> {
+		let lt_time_cf = Self::cf(db, LT_TIME_CF)?;
 
-	async fn sync_transformer(
-		&self, request: Request<Streaming<TermObject>>,
-	) -> Result<Response<Void>, Status> {
-		let main_db = DB::open_default(&self.main_db)
-			.map_err(|e| Status::internal(format!("Internal error: {}", e)))?;
-		let updates_db = DB::open_default(&self.updates_db)
-			.map_err(|e| Status::internal(format!("Internal error: {}", e)))?;
+		let mut start = Vec::new();
+		start.extend_from_slice(&prefix);
+		start.extend_from_slice(&since.to_be_bytes());
 
-		let mut offset = Self::read_checkpoint(&main_db).map_err(|e| e.into_status())?;
+		let mut cells = BTreeMap::new();
+		let iter = db.iterator_cf(&lt_time_cf, IteratorMode::From(&start, Direction::Forward));
+		for item in iter {
+			let (key, value) = item.map_err(LcError::DbError)?;
+			if !key.starts_with(&prefix) {
+				break;
+			}
+			let timestamp = u64::from_be_bytes(key[8..16].try_into().unwrap());
+			if timestamp > until {
+				break;
+			}
+			let x = u32::from_be_bytes(key[16..20].try_into().unwrap());
+			let y = u32::from_be_bytes(key[20..24].try_into().unwrap());
+			if x < p0.0 || x > p1.0 || y < p0.1 || y > p1.1 {
+				continue;
+			}
+			if after.map_or(false, |cell| (x, y) <= cell) {
+				continue;
+			}
+			let item = LtItem::new(x, y, decode_value(&value)?).with_timestamp(timestamp);
+			cells.insert((x, y), item);
+		}
 
-		let mut terms = Vec::new();
-		let mut stream = request.into_inner();
-		while let Some(term) = stream.message().await? {
-			terms.push(term);
+		Ok(cells.into_values().take(limit).collect())
+	}
+
+	/// Reads a page of at most `limit` items, asking `read` for one more to tell whether any
+	/// are left. The first page reads up to `MAX_COUNTED_MATCHES` matches to count them, and
+	/// leaves the total out when there are more.
+	fn read_page<I>(
+		read: impl FnOnce(usize) -> Result<Vec<I>, LcError>, is_first: bool, limit: usize,
+		cursor: impl Fn(&I) -> Vec<u8>,
+	) -> Result<(Vec<I>, PageInfo), LcError> {
+		let counted = if is_first { limit.max(MAX_COUNTED_MATCHES) } else { limit };
+		let mut items = read(counted + 1)?;
+		let total = (is_first && items.len() <= counted).then_some(items.len());
+		let next_cursor = if items.len() > limit {
+			items.truncate(limit);
+			items.last().map(cursor)
+		} else {
+			None
+		};
+		let page = PageInfo { total, returned: items.len(), next_cursor };
+		Ok((items, page))
+	}
+
+	/// Wraps a page in a response, describing it in the headers along with the `watermark`
+	/// the page was read at, so clients know how fresh it is.
+	fn page_response<T>(body: T, page: PageInfo, watermark: u64) -> Response<T> {
+		let mut response = Response::new(body);
+		let metadata = response.metadata_mut();
+		if let Some(total) = page.total {
+			metadata.insert(TOTAL_HEADER, MetadataValue::from(total));
+		}
+		metadata.insert(RETURNED_HEADER, MetadataValue::from(page.returned));
+		if let Some(cursor) = page.next_cursor {
+			metadata.insert_bin(NEXT_CURSOR_HEADER, BinaryMetadataValue::from_bytes(&cursor));
 		}
+		metadata.insert(WATERMARK_HEADER, MetadataValue::from(watermark));
+		response
+	}
+
+	/// Journals and applies a chunk of ingested terms atomically, returning how many were not
+	/// skipped as already applied.
+	///
+	/// Chunks are applied one at a time, so concurrent streams never allocate the same index
+	/// or overwrite each other's cell values.
+	fn apply_chunk(
+		&self, terms: &[TermObject], timestamp: u64, source: &str,
+	) -> Result<usize, Status> {
+		// The lock guards no data, so a panicked holder leaves nothing inconsistent behind.
+		let _guard = self.write_lock.lock().unwrap_or_else(PoisonError::into_inner);
+		let mut pending = PendingWrites::new();
+		Self::journal(&self.db, &mut pending, terms, timestamp, source)
+			.map_err(|e| e.into_status())?;
+		self.aggregate(pending, terms, timestamp)
+	}
+
+	/// Applies a chunk on a blocking task, as journaling it writes to the database while
+	/// holding the write lock.
+	async fn spawn_apply_chunk(
+		&self, terms: Vec<TermObject>, timestamp: u64, source: &str,
+	) -> Result<usize, Status> {
+		let service = self.clone();
+		let source = source.to_string();
+		tokio::task::spawn_blocking(move || service.apply_chunk(&terms, timestamp, &source))
+			.await
+			.map_err(|_| Status::internal("Ingest task failed!"))?
+	}
+
+	/// Appends `terms` to the journal, continuing after the last journaled sequence.
+	fn journal(
+		db: &DB, pending: &mut PendingWrites, terms: &[TermObject], timestamp: u64, source: &str,
+	) -> Result<(), LcError> {
+		let journal_cf = Self::cf(db, JOURNAL_CF)?;
+		let next = db.get(b"journal_sequence").map_err(LcError::DbError)?;
+		let mut sequence = match next {
+			Some(bytes) => {
+				u64::from_be_bytes(bytes[..].try_into().map_err(|_| LcError::ParseError)?)
+			},
+			None => 0,
+		};
+		for term in terms {
+			let entry = JournalEntry { timestamp, source: source.to_string(), term: term.clone() };
+			pending.batch.put_cf(&journal_cf, sequence.to_be_bytes(), entry.to_bytes());
+			sequence += 1;
+		}
+		pending.batch.put(b"journal_sequence", sequence.to_be_bytes());
+		Ok(())
+	}
 
+	/// Adds `terms` to their cells and only announces the updates once they are committed,
+	/// returning how many were not skipped as already applied.
+	fn aggregate(
+		&self, mut pending: PendingWrites, terms: &[TermObject], timestamp: u64,
+	) -> Result<usize, Status> {
+		let mut cells = Vec::with_capacity(terms.len());
 		for term in terms {
-			let x = Self::get_index(&main_db, term.from.clone(), &mut offset)
+			if term.sequence != 0 {
+				let is_new = Self::mark_applied(
+					&self.db, &mut pending, &term.source, &term.from, term.sequence,
+				)
+				.map_err(|e| e.into_status())?;
+				if !is_new {
+					continue;
+				}
+			}
+
+			let x = Self::get_index(&self.db, &mut pending, term.domain, term.from.clone())
 				.map_err(|e| e.into_status())?;
-			let y = Self::get_index(&main_db, term.to.clone(), &mut offset)
+			let y = Self::get_index(&self.db, &mut pending, term.domain, term.to.clone())
 				.map_err(|e| e.into_status())?;
 			let domain = term.domain.to_be_bytes();
 			let form = term.form.to_be_bytes();
@@ -170,53 +1051,796 @@ impl LinearCombiner for LinearCombinerService {
 			key.extend_from_slice(&form);
 			key.extend_from_slice(&x);
 			key.extend_from_slice(&y);
+			cells.push((term, x, y, key));
+		}
 
-			Self::update_value(&main_db, &updates_db, key.clone(), term.weight)
+		Self::prefetch_values(&self.db, &mut pending, cells.iter().map(|(.., key)| key))
+			.map_err(|e| e.into_status())?;
+		let mut updates = Vec::with_capacity(cells.len());
+		let mut ingested = HashMap::<&str, u64>::new();
+		let mut applied = Vec::with_capacity(cells.len());
+		for (term, x, y, key) in cells {
+			applied.push(term);
+			*ingested.entry(&term.source).or_default() += 1;
+			let value = Self::update_value(&self.db, &mut pending, key, term.weight, timestamp)
 				.map_err(|e| e.into_status())?;
+
+			let item = LtItem::new(u32::from_be_bytes(x), u32::from_be_bytes(y), value)
+				.with_timestamp(timestamp);
+			updates.push(CellUpdate { domain: term.domain, form: term.form, item });
 		}
 
-		Self::write_checkpoint(&main_db, offset).map_err(|e| e.into_status())?;
+		let cells = pending.values.len() as u64;
+		let assigned = std::mem::take(&mut pending.assigned);
+		let timer = self.metrics.db_write_latency.start_timer();
+		Self::commit_writes(&self.db, pending).map_err(|e| e.into_status())?;
+		timer.observe_duration();
+		for (source, terms) in ingested {
+			self.metrics.terms_ingested.with_label_values(&[source]).inc_by(terms);
+		}
+		self.metrics.cells_written.inc_by(cells);
+		let count = applied.len();
+		self.activity.record(applied, timestamp);
+		updates.into_iter().for_each(|update| self.publish(update));
+		for assignment in assigned {
+			// Sending only fails when nobody is watching.
+			let _ = self.assignments.send(assignment);
+		}
+		// Advanced last, so watchers have every update covered by a watermark queued.
+		self.last_write.fetch_max(timestamp, Ordering::AcqRel);
+		Ok(count)
+	}
 
-		Ok(Response::new(Void {}))
+	/// Most terms a batch may hold, also bounded by the ingest rate for every batch to be
+	/// admitted eventually.
+	fn max_term_batch_size(&self) -> usize {
+		match self.max_ingest_rate {
+			0 => INGEST_BATCH_SIZE,
+			rate => INGEST_BATCH_SIZE.min(rate as usize),
+		}
 	}
 
-	async fn get_new_data(
-		&self, request: Request<LtBatch>,
-	) -> Result<Response<Self::GetNewDataStream>, Status> {
-		let batch = request.into_inner();
-		let updates_db = DB::open_default(&self.updates_db)
-			.map_err(|e| Status::internal(format!("Internal error: {}", e)))?;
+	/// Validates and applies a batch of `sync_term_batches` as a whole, or not at all.
+	fn apply_batch(
+		&self, batch: &TermObjectBatch, timestamp: u64, source: &str,
+	) -> Result<TermBatchAck, Status> {
+		let max_size = self.max_term_batch_size();
+		if batch.terms.len() > max_size {
+			let msg = format!("Batches are limited to {} terms!", max_size);
+			return Err(invalid_argument("terms", &msg));
+		}
+		batch.terms.iter().try_for_each(check_term)?;
+		if !self.ingest_limiter.try_acquire(batch.terms.len() as u32) {
+			let detail = ErrorDetail::retryable(INGEST_RETRY_AFTER.as_millis() as u64);
+			return Err(detail.into_status(
+				Code::ResourceExhausted,
+				"Ingest rate exceeded, retry later!",
+			));
+		}
+		let applied = self.apply_chunk(&batch.terms, timestamp, source)?;
+		Ok(TermBatchAck { sequence: batch.sequence, applied: applied as u32 })
+	}
 
-		let mut prefix = Vec::new();
-		prefix.extend_from_slice(&batch.domain.to_be_bytes());
-		prefix.extend_from_slice(&batch.form.to_be_bytes());
-		let items = Self::read_batch(&updates_db, prefix.clone(), batch.size)
-			.map_err(|e| e.into_status())?;
+	/// Rebuilds every cell from the journal, returning the number of terms replayed.
+	///
+	/// Cells are derived from the journal alone, so anything ingested before it existed is
+	/// dropped. Indices are kept, so peers keep their positions in the matrix.
+	fn replay_journal(&self) -> Result<u64, Status> {
+		let _guard = self.write_lock.lock().unwrap_or_else(PoisonError::into_inner);
+		Self::clear_cells(&self.db).map_err(|e| e.into_status())?;
 
-		let (tx, rx) = channel(1);
-		for x in items.clone() {
-			let x_obj: LtObject = x.into();
-			if let Err(e) = tx.send(Ok(x_obj)).await {
-				e.0?;
+		let journal_cf = Self::cf(&self.db, JOURNAL_CF).map_err(|e| e.into_status())?;
+		let mut terms = Vec::with_capacity(INGEST_BATCH_SIZE);
+		let mut timestamp = 0;
+		let mut entries = 0;
+		for item in self.db.iterator_cf(&journal_cf, IteratorMode::Start) {
+			let (_, value) = item.map_err(|e| LcError::DbError(e).into_status())?;
+			let entry = JournalEntry::from_raw(&value).map_err(|e| e.into_status())?;
+			// Terms of one chunk share its receive time, which becomes the cells' write time.
+			if entry.timestamp != timestamp || terms.len() == INGEST_BATCH_SIZE {
+				self.aggregate(PendingWrites::new(), &terms, timestamp)?;
+				terms.clear();
+				timestamp = entry.timestamp;
 			}
+			terms.push(entry.term);
+			entries += 1;
 		}
+		self.aggregate(PendingWrites::new(), &terms, timestamp)?;
+		Ok(entries)
+	}
 
-		Self::delete_batch(&updates_db, prefix, items).map_err(|e| e.into_status())?;
-
-		Ok(Response::new(ReceiverStream::new(rx)))
+	/// Checks the cells against the journal and their pending updates, rebuilding them from the
+	/// journal if `repair` is set and any diverged.
+	fn check_consistency(&self, repair: bool) -> Result<ConsistencyReport, Status> {
+		let mut report = {
+			// Keeps terms from landing between reading the journal and the cells.
+			let _guard = self.write_lock.lock().unwrap_or_else(PoisonError::into_inner);
+			Self::find_divergent_cells(&self.db).map_err(|e| e.into_status())?
+		};
+		if repair && report.mismatches > 0 {
+			self.replay_journal()?;
+			report.repaired = true;
+		}
+		Ok(report)
 	}
 
-	async fn get_historic_data(
-		&self, request: Request<LtHistoryBatch>,
-	) -> Result<Response<Self::GetHistoricDataStream>, Status> {
-		let batch = request.into_inner();
-		let main_db = DB::open_default(&self.main_db)
-			.map_err(|e| Status::internal(format!("Internal error: {}", e)))?;
+	fn find_divergent_cells(db: &DB) -> Result<ConsistencyReport, LcError> {
+		let lt_cf = Self::cf(db, LT_CF)?;
+		let update_cf = Self::cf(db, UPDATE_CF)?;
+		let mut report = ConsistencyReport::default();
 
-		let is_x_bigger = batch.x0 <= batch.x1;
-		let is_y_bigger = batch.y0 <= batch.y1;
-		if !is_x_bigger && !is_y_bigger {
-			return Err(Status::invalid_argument("Invalid points!"));
+		let mut journal_values = Self::sum_journal(db)?;
+		let mut journal_mismatches = Vec::new();
+		for item in db.iterator_cf(&lt_cf, IteratorMode::Start) {
+			let (key, value) = item.map_err(LcError::DbError)?;
+			let value = decode_value(&value)?;
+			let expected = journal_values.remove(key.as_ref()).unwrap_or(0.);
+			if value != expected {
+				report_mismatch(&mut report, &mut journal_mismatches, &key, value, expected);
+			}
+			report.cells += 1;
+		}
+		for (key, expected) in journal_values {
+			if expected != 0. {
+				report_mismatch(&mut report, &mut journal_mismatches, &key, 0., expected);
+			}
+		}
+
+		let mut update_mismatches = Vec::new();
+		for item in db.iterator_cf(&update_cf, IteratorMode::Start) {
+			let (key, update) = item.map_err(LcError::DbError)?;
+			let expected = decode_value(&update)?;
+			let value = Self::get_value(db, &key.to_vec())?;
+			if value != expected {
+				report_mismatch(&mut report, &mut update_mismatches, &key, value, expected);
+			}
+		}
+
+		report.journal_mismatches = journal_mismatches;
+		report.update_mismatches = update_mismatches;
+		Ok(report)
+	}
+
+	/// Sums the journaled terms of every cell, skipping replayed sequences like `aggregate`
+	/// does. Terms of peers without an index have no cell and are left out.
+	fn sum_journal(db: &DB) -> Result<BTreeMap<Vec<u8>, f64>, LcError> {
+		let journal_cf = Self::cf(db, JOURNAL_CF)?;
+		let index_cf = Self::cf(db, INDEX_CF)?;
+
+		let mut windows = HashMap::<Vec<u8>, ReplayWindow>::new();
+		let mut sums = BTreeMap::new();
+		for item in db.iterator_cf(&journal_cf, IteratorMode::Start) {
+			let (_, value) = item.map_err(LcError::DbError)?;
+			let term = JournalEntry::from_raw(&value)?.term;
+			if term.sequence != 0 {
+				let window = windows.entry(Self::window_key(&term.source, &term.from)?);
+				if !window.or_default().insert(term.sequence) {
+					continue;
+				}
+			}
+
+			let domain = term.domain.to_be_bytes();
+			let mut indices = Vec::with_capacity(2);
+			for peer in [&term.from, &term.to] {
+				let peer = hex::decode(peer).map_err(|_| LcError::ParseError)?;
+				let key = [domain.as_slice(), &peer].concat();
+				indices.push(db.get_cf(&index_cf, key).map_err(LcError::DbError)?);
+			}
+			let [Some(x), Some(y)] = indices.as_slice() else {
+				continue;
+			};
+			let key = [domain.as_slice(), &term.form.to_be_bytes(), x, y].concat();
+			*sums.entry(key).or_insert(0.) += term.weight;
+		}
+		Ok(sums)
+	}
+
+	/// Reverts every cell written after `timestamp` to its value at that time and drops the
+	/// journal entries after it, returning the number of cells and entries rolled back. The
+	/// terms dropped may be sent again.
+	fn rollback(&self, timestamp: u64) -> Result<(u64, u64), LcError> {
+		let _guard = self.write_lock.lock().unwrap_or_else(PoisonError::into_inner);
+		let mut batch = WriteBatch::default();
+		let updates = Self::rollback_cells(&self.db, &mut batch, timestamp)?;
+		let entries =
+			Self::drop_journal_entries(&self.db, &mut batch, |entry| entry.timestamp > timestamp)?;
+		self.db.write(batch).map_err(LcError::DbError)?;
+
+		let cells = updates.len() as u64;
+		updates.into_iter().for_each(|update| self.publish(update));
+		Ok((cells, entries))
+	}
+
+	/// Reverts the cells of every domain and form written after `timestamp`, finding the
+	/// values they had then on the time-ordered keys.
+	fn rollback_cells(
+		db: &DB, batch: &mut WriteBatch, timestamp: u64,
+	) -> Result<Vec<CellUpdate>, LcError> {
+		let lt_cf = Self::cf(db, LT_CF)?;
+		let update_cf = Self::cf(db, UPDATE_CF)?;
+		let lt_time_cf = Self::cf(db, LT_TIME_CF)?;
+		let Some(after) = timestamp.checked_add(1) else {
+			return Ok(Vec::new());
+		};
+
+		let mut updates = Vec::new();
+		let mut iter = db.raw_iterator_cf(&lt_time_cf);
+		iter.seek_to_first();
+		while let Some(key) = iter.key() {
+			let prefix: [u8; 8] = key[..8].try_into().unwrap();
+
+			let mut cells = BTreeSet::new();
+			iter.seek([prefix, after.to_be_bytes()].concat());
+			while let Some(key) = iter.key().filter(|key| key.starts_with(&prefix)) {
+				cells.insert(key[16..24].to_vec());
+				batch.delete_cf(&lt_time_cf, key);
+				iter.next();
+			}
+
+			// Searched backwards, so the first key of a cell holds its value as of `timestamp`.
+			let mut values = HashMap::new();
+			if !cells.is_empty() {
+				iter.seek_for_prev(
+					[prefix.as_slice(), &timestamp.to_be_bytes(), &[0xff; 8]].concat(),
+				);
+			}
+			while values.len() < cells.len() {
+				let (Some(key), Some(value)) = (iter.key(), iter.value()) else {
+					break;
+				};
+				if !key.starts_with(&prefix) {
+					break;
+				}
+				let cell = &key[16..24];
+				if cells.contains(cell) && !values.contains_key(cell) {
+					let written_at = u64::from_be_bytes(key[8..16].try_into().unwrap());
+					values.insert(cell.to_vec(), (decode_value(value)?, written_at));
+				}
+				iter.prev();
+			}
+			iter.status().map_err(LcError::DbError)?;
+
+			let now = now_millis();
+			for cell in cells {
+				let key = [prefix.as_slice(), &cell].concat();
+				let (value, written_at) = match values.get(&cell) {
+					Some(&(value, written_at)) => {
+						let stamped_value =
+							[value.to_be_bytes(), written_at.to_be_bytes()].concat();
+						batch.put_cf(&lt_cf, &key, stamped_value);
+						(value, written_at)
+					},
+					None => {
+						batch.delete_cf(&lt_cf, &key);
+						(0., 0)
+					},
+				};
+				// Stamped now, so the correction outlives the update TTL.
+				batch.put_cf(
+					&update_cf,
+					&key,
+					[value.to_be_bytes(), now.to_be_bytes()].concat(),
+				);
+
+				let x = u32::from_be_bytes(cell[..4].try_into().unwrap());
+				let y = u32::from_be_bytes(cell[4..].try_into().unwrap());
+				let domain = u32::from_be_bytes(prefix[..4].try_into().unwrap());
+				let form = i32::from_be_bytes(prefix[4..].try_into().unwrap());
+				let item = LtItem::new(x, y, value).with_timestamp(written_at);
+				updates.push(CellUpdate { domain, form, item });
+			}
+
+			match u64::from_be_bytes(prefix).checked_add(1) {
+				Some(next) => iter.seek(next.to_be_bytes()),
+				None => break,
+			}
+		}
+		iter.status().map_err(LcError::DbError)?;
+		Ok(updates)
+	}
+
+	/// Deletes the journal entries `dropped` selects, returning how many there were, and
+	/// rebuilds the replay windows and source checkpoints from the entries kept, so the terms
+	/// dropped are no longer skipped as applied.
+	///
+	/// Chunks are stamped before they are journaled, so the journal, keyed by sequence, is not
+	/// ordered by timestamp and is scanned whole.
+	fn drop_journal_entries(
+		db: &DB, batch: &mut WriteBatch, dropped: impl Fn(&JournalEntry) -> bool,
+	) -> Result<u64, LcError> {
+		let journal_cf = Self::cf(db, JOURNAL_CF)?;
+		let sequence_cf = Self::cf(db, SEQUENCE_CF)?;
+		let mut windows = BTreeMap::<Vec<u8>, ReplayWindow>::new();
+		let mut checkpoints = HashMap::<String, u64>::new();
+		let mut entries = 0;
+		for item in db.iterator_cf(&journal_cf, IteratorMode::Start) {
+			let (key, value) = item.map_err(LcError::DbError)?;
+			let entry = JournalEntry::from_raw(&value)?;
+			if dropped(&entry) {
+				batch.delete_cf(&journal_cf, key);
+				entries += 1;
+				continue;
+			}
+			let term = entry.term;
+			if term.sequence != 0 {
+				let window = windows.entry(Self::window_key(&term.source, &term.from)?);
+				window.or_default().insert(term.sequence);
+				let checkpoint = checkpoints.entry(term.source).or_default();
+				*checkpoint = (*checkpoint).max(term.sequence);
+			}
+		}
+
+		// Cleared over the same ranges as `clear_cells`, before the rebuilt state is put back.
+		batch.delete_range_cf(&sequence_cf, [].as_slice(), [0xff; 64].as_slice());
+		let checkpoints_start = Self::source_checkpoint_key("");
+		let checkpoints_end = [checkpoints_start.as_slice(), &[0xff]].concat();
+		batch.delete_range(checkpoints_start, checkpoints_end);
+		for (key, window) in windows {
+			batch.put_cf(&sequence_cf, key, window.to_bytes());
+		}
+		for (source, sequence) in checkpoints {
+			batch.put(Self::source_checkpoint_key(&source), sequence.to_be_bytes());
+		}
+		Ok(entries)
+	}
+
+	/// Removes the peer keyed `key` from `domain`: zeroes its row and column, drops the journal
+	/// entries naming it, erases its key and tombstones its index. Returns `None` if the peer
+	/// has no index in the domain.
+	fn remove_peer(&self, domain: u32, key: &[u8]) -> Result<Option<PeerRemovalInfo>, LcError> {
+		let _guard = self.write_lock.lock().unwrap_or_else(PoisonError::into_inner);
+		let index_cf = Self::cf(&self.db, INDEX_CF)?;
+		let mapping_cf = Self::cf(&self.db, MAPPING_CF)?;
+		let tombstone_cf = Self::cf(&self.db, TOMBSTONE_CF)?;
+
+		let domain_bytes = domain.to_be_bytes();
+		let index_key = [domain_bytes.as_slice(), key].concat();
+		let Some(index) = self.db.get_cf(&index_cf, &index_key).map_err(LcError::DbError)? else {
+			return Ok(None);
+		};
+		let index: [u8; 4] = index.try_into().map_err(|_| LcError::ParseError)?;
+
+		let timestamp = now_millis();
+		let mut pending = PendingWrites::new();
+		let updates = Self::zero_peer_cells(&self.db, &mut pending, domain, index, timestamp)?;
+		let entries = Self::purge_journal(&self.db, &mut pending.batch, domain, key)?;
+		// The index stays taken, so cells of the removed peer are never reused by another.
+		let mapping_key = [domain_bytes, index].concat();
+		pending.batch.delete_cf(&index_cf, index_key);
+		pending.batch.put_cf(&mapping_cf, &mapping_key, b"");
+		pending.batch.put_cf(&tombstone_cf, mapping_key, timestamp.to_be_bytes());
+		Self::commit_writes(&self.db, pending)?;
+
+		let cells = updates.len() as u64;
+		updates.into_iter().for_each(|update| self.publish(update));
+		self.last_write.fetch_max(timestamp, Ordering::AcqRel);
+		Ok(Some(PeerRemovalInfo {
+			index: u32::from_be_bytes(index),
+			cells,
+			entries,
+		}))
+	}
+
+	/// Zeroes every non-zero cell of `domain` in the row or column of `index`.
+	fn zero_peer_cells(
+		db: &DB, pending: &mut PendingWrites, domain: u32, index: [u8; 4], timestamp: u64,
+	) -> Result<Vec<CellUpdate>, LcError> {
+		let lt_cf = Self::cf(db, LT_CF)?;
+		let prefix = domain.to_be_bytes();
+
+		let mut cells = Vec::new();
+		let iter = db.iterator_cf(&lt_cf, IteratorMode::From(&prefix, Direction::Forward));
+		for item in iter {
+			let (key, value) = item.map_err(LcError::DbError)?;
+			if !key.starts_with(&prefix) {
+				break;
+			}
+			let value = decode_value(&value)?;
+			if value != 0. && (key[8..12] == index || key[12..16] == index) {
+				cells.push((key.to_vec(), value));
+			}
+		}
+
+		let mut updates = Vec::with_capacity(cells.len());
+		for (key, value) in cells {
+			let form = i32::from_be_bytes(key[4..8].try_into().unwrap());
+			let x = u32::from_be_bytes(key[8..12].try_into().unwrap());
+			let y = u32::from_be_bytes(key[12..16].try_into().unwrap());
+			pending.values.insert(key.clone(), value);
+			let value = Self::update_value(db, pending, key, -value, timestamp)?;
+			let item = LtItem::new(x, y, value).with_timestamp(timestamp);
+			updates.push(CellUpdate { domain, form, item });
+		}
+		Ok(updates)
+	}
+
+	/// Deletes the journal entries of `domain` naming `key`, returning how many there were.
+	fn purge_journal(
+		db: &DB, batch: &mut WriteBatch, domain: u32, key: &[u8],
+	) -> Result<u64, LcError> {
+		let journal_cf = Self::cf(db, JOURNAL_CF)?;
+		let names = |peer: &str| hex::decode(peer).map_or(false, |peer| peer == key);
+		let mut entries = 0;
+		for item in db.iterator_cf(&journal_cf, IteratorMode::Start) {
+			let (sequence, value) = item.map_err(LcError::DbError)?;
+			let term = JournalEntry::from_raw(&value)?.term;
+			if term.domain == domain && (names(&term.from) || names(&term.to)) {
+				batch.delete_cf(&journal_cf, sequence);
+				entries += 1;
+			}
+		}
+		Ok(entries)
+	}
+
+	/// Reads the tombstones of `domain` in the rows or columns of the window, of peers removed
+	/// between `since` and `until` (inclusive).
+	fn read_tombstones(
+		db: &DB, domain: u32, p0: (u32, u32), p1: (u32, u32), (since, until): (u64, u64),
+	) -> Result<Vec<LtTombstone>, LcError> {
+		let tombstone_cf = Self::cf(db, TOMBSTONE_CF)?;
+		let prefix = domain.to_be_bytes();
+
+		let mut tombstones = Vec::new();
+		let iter = db.iterator_cf(
+			&tombstone_cf,
+			IteratorMode::From(&prefix, Direction::Forward),
+		);
+		for item in iter {
+			let (key, value) = item.map_err(LcError::DbError)?;
+			if !key.starts_with(&prefix) {
+				break;
+			}
+			let index = u32::from_be_bytes(key[4..8].try_into().unwrap());
+			let bytes = value.as_ref().try_into().map_err(|_| LcError::ParseError)?;
+			let timestamp = u64::from_be_bytes(bytes);
+			let is_in_window = (p0.0..=p1.0).contains(&index) || (p0.1..=p1.1).contains(&index);
+			if is_in_window && (since..=until).contains(&timestamp) {
+				tombstones.push(LtTombstone { index, timestamp });
+			}
+		}
+		Ok(tombstones)
+	}
+
+	/// Deletes every cell, pending update and applied sequence, keeping indices and mappings.
+	fn clear_cells(db: &DB) -> Result<(), LcError> {
+		// Sorts after every stored key, since keys are shorter than this or, for replay
+		// windows, continue with a UTF-8 source ID.
+		let end = [0xff; 64];
+		let mut batch = WriteBatch::default();
+		for name in [LT_CF, UPDATE_CF, LT_TIME_CF, SEQUENCE_CF] {
+			let cf = Self::cf(db, name)?;
+			batch.delete_range_cf(&cf, [].as_slice(), end.as_slice());
+		}
+		// Source IDs are UTF-8, so never contain 0xff.
+		let checkpoints = Self::source_checkpoint_key("");
+		let checkpoints_end = [checkpoints.as_slice(), &[0xff]].concat();
+		batch.delete_range(checkpoints, checkpoints_end);
+		db.write(batch).map_err(LcError::DbError)
+	}
+
+	fn publish(&self, update: CellUpdate) {
+		// Sending only fails when nobody is watching.
+		let _ = self.updates.send(update);
+	}
+
+	/// Scans the index of `domain` for keys matching `pattern`, resuming strictly after
+	/// `after`.
+	fn read_mappings(
+		db: &DB, domain: u32, pattern: &KeyPattern, after: Option<Vec<u8>>, limit: usize,
+	) -> Result<Vec<MappingItem>, LcError> {
+		let index_cf = Self::cf(db, INDEX_CF)?;
+		let key_prefix = pattern.key_prefix();
+		let start = match &after {
+			Some(after) if *after > key_prefix => after.clone(),
+			_ => key_prefix.clone(),
+		};
+		let domain_bytes = domain.to_be_bytes();
+		let prefix = [domain_bytes.as_slice(), &key_prefix].concat();
+		let start = [domain_bytes.as_slice(), &start].concat();
+
+		let mut items = Vec::new();
+		let iter = db.iterator_cf(&index_cf, IteratorMode::From(&start, Direction::Forward));
+		for res in iter {
+			let (key, value) = res.map_err(LcError::DbError)?;
+			if !key.starts_with(&prefix) || items.len() >= limit {
+				break;
+			}
+			let key = &key[domain_bytes.len()..];
+			if Some(key) == after.as_deref() || !pattern.matches(key) {
+				continue;
+			}
+			items.push(MappingItem::from_raw(key, &value));
+		}
+
+		Ok(items)
+	}
+
+	/// Reads up to `limit` index assignments of `domain`, starting at index `from`.
+	fn read_assigned(
+		db: &DB, domain: u32, from: u64, limit: usize,
+	) -> Result<Vec<MappingItem>, LcError> {
+		let Ok(from) = u32::try_from(from) else {
+			return Ok(Vec::new());
+		};
+		let mapping_cf = Self::cf(db, MAPPING_CF)?;
+		let domain_bytes = domain.to_be_bytes();
+		let start = [domain_bytes, from.to_be_bytes()].concat();
+
+		let mut items = Vec::new();
+		let iter = db.iterator_cf(&mapping_cf, IteratorMode::From(&start, Direction::Forward));
+		for res in iter {
+			let (key, value) = res.map_err(LcError::DbError)?;
+			if !key.starts_with(&domain_bytes) || items.len() >= limit {
+				break;
+			}
+			items.push(MappingItem::from_raw(
+				&value[..],
+				&key[domain_bytes.len()..],
+			));
+		}
+
+		Ok(items)
+	}
+
+	/// Sends the assignments of `domain` stored from `next` on, advancing `next` past them.
+	/// Returns `false` once the subscriber is gone.
+	async fn send_assigned(
+		db: &DB, domain: u32, next: &mut u64, tx: &Sender<Result<MappingWatchEvent, Status>>,
+	) -> bool {
+		loop {
+			let items = match Self::read_assigned(db, domain, *next, SNAPSHOT_BUFFER_SIZE) {
+				Ok(items) => items,
+				Err(e) => {
+					let _ = tx.send(Err(e.into_status())).await;
+					return false;
+				},
+			};
+			let is_last = items.len() < SNAPSHOT_BUFFER_SIZE;
+			for item in items {
+				*next = u64::from(item.index()) + 1;
+				let event = mapping_watch_event::Event::Change(item.into());
+				if tx.send(Ok(MappingWatchEvent { event: Some(event) })).await.is_err() {
+					return false;
+				}
+			}
+			if is_last {
+				return true;
+			}
+		}
+	}
+
+	/// Reads every cell of `domain` updated at or after `since` from one consistent snapshot,
+	/// handing them to `send` until it returns `false`.
+	fn read_snapshot(
+		db: &DB, domain: u32, since: u64, mut send: impl FnMut(LtSnapshotCell) -> bool,
+	) -> Result<(), LcError> {
+		let lt_cf = Self::cf(db, LT_CF)?;
+		let snapshot = db.snapshot();
+		let prefix = domain.to_be_bytes();
+
+		let iter = snapshot.iterator_cf(&lt_cf, IteratorMode::From(&prefix, Direction::Forward));
+		for item in iter {
+			let (key, value) = item.map_err(LcError::DbError)?;
+			if !key.starts_with(&prefix) {
+				break;
+			}
+			let timestamp = decode_timestamp(&value);
+			if timestamp < since {
+				continue;
+			}
+			let form = i32::from_be_bytes(key[4..8].try_into().unwrap());
+			let cell = LtSnapshotCell {
+				form,
+				object: Some(LtItem::from_raw(key.as_ref(), value.as_ref()).into()),
+				timestamp,
+			};
+			if !send(cell) {
+				break;
+			}
+		}
+		Ok(())
+	}
+
+	fn stream_items<I, O>(items: Vec<I>) -> ReceiverStream<Result<O, Status>>
+	where
+		I: Into<O> + Send + 'static,
+		O: Send + 'static,
+	{
+		let (tx, rx) = channel(1);
+		tokio::spawn(async move {
+			for x in items {
+				if tx.send(Ok(x.into())).await.is_err() {
+					break;
+				}
+			}
+		});
+		ReceiverStream::new(rx)
+	}
+}
+
+/// Counts a mismatch of the cell at `key`, listing it in `cells` unless the list is full.
+fn report_mismatch(
+	report: &mut ConsistencyReport, cells: &mut Vec<DivergentCell>, key: &[u8], value: f64,
+	expected: f64,
+) {
+	report.mismatches += 1;
+	if cells.len() == MAX_REPORTED_CELLS {
+		return;
+	}
+	let part = |i: usize| -> [u8; 4] { key[i * 4..(i + 1) * 4].try_into().unwrap() };
+	cells.push(DivergentCell {
+		domain: u32::from_be_bytes(part(0)),
+		form: i32::from_be_bytes(part(1)),
+		x: u32::from_be_bytes(part(2)),
+		y: u32::from_be_bytes(part(3)),
+		value,
+		expected,
+	});
+}
+
+fn now_millis() -> u64 {
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards");
+	now.as_millis() as u64
+}
+
+#[tonic::async_trait]
+impl LinearCombiner for LinearCombinerService {
+	type GetNewDataStream = ReceiverStream<Result<LtObject, Status>>;
+	type GetHistoricDataStream = ReceiverStream<Result<LtHistoryEvent, Status>>;
+	type WatchLtStream = ReceiverStream<Result<LtWatchEvent, Status>>;
+	type GetDidMappingStream = ReceiverStream<Result<DidMapping, Status>>;
+	type WatchDidMappingStream = ReceiverStream<Result<MappingWatchEvent, Status>>;
+	type SnapshotLtStream = ReceiverStream<Result<LtSnapshotCell, Status>>;
+	type SyncTermBatchesStream = ReceiverStream<Result<TermBatchAck, Status>>;
+
+	async fn sync_transformer(
+		&self, request: Request<Streaming<TermObject>>,
+	) -> Result<Response<Void>, Status> {
+		self.auth.authorize(&request, Role::Transformer)?;
+		let _timer =
+			self.metrics.stream_duration.with_label_values(&["sync_transformer"]).start_timer();
+		let timestamp = now_millis();
+
+		let source = request.remote_addr().map_or_else(String::new, |addr| addr.to_string());
+		let mut terms = Vec::with_capacity(INGEST_BATCH_SIZE);
+		let mut received = 0;
+		let mut stream = request.into_inner();
+		let mut shutdown = self.shutdown.subscribe();
+		// Chunks already applied stay applied if a later term is rejected.
+		loop {
+			let term = select! {
+				term = stream.message() => term?,
+				_ = shutdown.wait_for(|&closing| closing) => {
+					self.spawn_apply_chunk(terms, timestamp, &source).await?;
+					return Err(unavailable("Shutting down, retry the stream!"));
+				},
+			};
+			let Some(term) = term else {
+				break;
+			};
+			check_term(&term)?;
+			received += 1;
+			if self.max_stream_terms != 0 && received > self.max_stream_terms {
+				self.spawn_apply_chunk(terms, timestamp, &source).await?;
+				// Splitting the stream is up to the transformer, retrying it would fail again.
+				let msg = format!("Streams are limited to {} terms!", self.max_stream_terms);
+				return Err(ErrorDetail::permanent().into_status(Code::ResourceExhausted, msg));
+			}
+			if !self.ingest_limiter.try_acquire(1) {
+				self.spawn_apply_chunk(terms, timestamp, &source).await?;
+				let detail = ErrorDetail::retryable(INGEST_RETRY_AFTER.as_millis() as u64);
+				return Err(detail.into_status(
+					Code::ResourceExhausted,
+					"Ingest rate exceeded, retry later!",
+				));
+			}
+			terms.push(term);
+
+			if terms.len() == INGEST_BATCH_SIZE {
+				let chunk = std::mem::replace(&mut terms, Vec::with_capacity(INGEST_BATCH_SIZE));
+				self.spawn_apply_chunk(chunk, timestamp, &source).await?;
+			}
+		}
+		self.spawn_apply_chunk(terms, timestamp, &source).await?;
+
+		Ok(Response::new(Void {}))
+	}
+
+	async fn sync_term_batches(
+		&self, request: Request<Streaming<TermObjectBatch>>,
+	) -> Result<Response<Self::SyncTermBatchesStream>, Status> {
+		self.auth.authorize(&request, Role::Transformer)?;
+		let source = request.remote_addr().map_or_else(String::new, |addr| addr.to_string());
+		let mut stream = request.into_inner();
+		let mut shutdown = self.shutdown.subscribe();
+		let service = self.clone();
+
+		let (tx, rx) = channel(1);
+		tokio::spawn(async move {
+			let _timer = service
+				.metrics
+				.stream_duration
+				.with_label_values(&["sync_term_batches"])
+				.start_timer();
+			let mut received = 0;
+			loop {
+				// Batches are applied whole, so none is left half applied by shutting down.
+				let batch = select! {
+					batch = stream.message() => batch,
+					_ = shutdown.wait_for(|&closing| closing) => {
+						Err(unavailable("Shutting down, retry the stream!"))
+					},
+				};
+				let ack = match batch {
+					Ok(Some(batch)) => {
+						received += batch.terms.len() as u64;
+						if service.max_stream_terms != 0 && received > service.max_stream_terms {
+							let msg = format!(
+								"Streams are limited to {} terms!",
+								service.max_stream_terms
+							);
+							Err(ErrorDetail::permanent().into_status(Code::ResourceExhausted, msg))
+						} else {
+							// Streams are long lived, so each batch is stamped as it arrives.
+							let (batch_service, source) = (service.clone(), source.clone());
+							tokio::task::spawn_blocking(move || {
+								batch_service.apply_batch(&batch, now_millis(), &source)
+							})
+							.await
+							.unwrap_or_else(|_| Err(Status::internal("Ingest task failed!")))
+						}
+					},
+					Ok(None) => break,
+					Err(status) => Err(status),
+				};
+				let is_err = ack.is_err();
+				if tx.send(ack).await.is_err() || is_err {
+					break;
+				}
+			}
+		});
+
+		Ok(Response::new(ReceiverStream::new(rx)))
+	}
+
+	async fn get_source_checkpoint(
+		&self, request: Request<SourceQuery>,
+	) -> Result<Response<SourceCheckpoint>, Status> {
+		self.auth.authorize(&request, Role::Transformer)?;
+		let source = request.into_inner().source;
+		let sequence =
+			Self::read_source_checkpoint(&self.db, &source).map_err(|e| e.into_status())?;
+		Ok(Response::new(SourceCheckpoint { sequence }))
+	}
+
+	async fn get_new_data(
+		&self, request: Request<LtBatch>,
+	) -> Result<Response<Self::GetNewDataStream>, Status> {
+		self.auth.authorize(&request, Role::Reader)?;
+		let batch = request.into_inner();
+
+		let mut prefix = Vec::new();
+		prefix.extend_from_slice(&batch.domain.to_be_bytes());
+		prefix.extend_from_slice(&batch.form.to_be_bytes());
+		let items =
+			Self::read_batch(&self.db, prefix.clone(), batch.size).map_err(|e| e.into_status())?;
+
+		Self::delete_batch(&self.db, prefix, items.clone()).map_err(|e| e.into_status())?;
+
+		Ok(Response::new(Self::stream_items(items)))
+	}
+
+	async fn get_historic_data(
+		&self, request: Request<LtHistoryBatch>,
+	) -> Result<Response<Self::GetHistoricDataStream>, Status> {
+		self.auth.authorize(&request, Role::Reader)?;
+		let batch = request.into_inner();
+
+		if batch.x0 > batch.x1 {
+			return Err(invalid_argument("x0", "Invalid points, x0 is past x1!"));
+		}
+		if batch.y0 > batch.y1 {
+			return Err(invalid_argument("y0", "Invalid points, y0 is past y1!"));
 		}
 
 		let domain_bytes = batch.domain.to_be_bytes();
@@ -232,48 +1856,581 @@ impl LinearCombiner for LinearCombinerService {
 		prefix.extend_from_slice(&domain_bytes);
 		prefix.extend_from_slice(&form_bytes);
 
-		let items = Self::read_window(&main_db, prefix, (x_start, y_start), (x_end, y_end));
+		let since = batch.since_timestamp;
+		let until = if batch.until_timestamp == 0 { u64::MAX } else { batch.until_timestamp };
+		if since > until {
+			return Err(invalid_argument(
+				"since_timestamp", "Invalid timestamp range!",
+			));
+		}
 
-		let (tx, rx) = channel(1);
-		for x in items.clone() {
-			let x_obj: LtObject = x.into();
-			if let Err(e) = tx.send(Ok(x_obj)).await {
-				e.0?;
+		// Clients of protocol version 1 page by the cursor and limit `page` supersedes.
+		let page =
+			batch.page.unwrap_or(PageRequest { cursor: batch.cursor, page_size: batch.limit });
+		let after = Self::parse_cursor(&page.cursor)
+			.map_err(|_| invalid_argument("page.cursor", "Invalid cursor!"))?;
+		let limit = usize::try_from(page.size_within(MAX_HISTORY_BATCH_SIZE))
+			.map_err(|_| invalid_argument("page.page_size", "Invalid page size!"))?;
+
+		let p0 = (x_start, y_start);
+		let p1 = (x_end, y_end);
+		// Loaded before reading, so every write it covers is reflected in the page.
+		let watermark = self.last_write.load(Ordering::Acquire);
+		let tombstones = match after {
+			None => Self::read_tombstones(&self.db, batch.domain, p0, p1, (since, until))
+				.map_err(|e| e.into_status())?,
+			Some(_) => Vec::new(),
+		};
+		let read = |limit| {
+			if since == 0 && until == u64::MAX {
+				Self::read_window(&self.db, prefix, p0, p1, after, limit)
+			} else {
+				Self::read_window_between(&self.db, prefix, p0, p1, (since, until), after, limit)
+			}
+		};
+		let (items, page) = Self::read_page(read, after.is_none(), limit, LtItem::key_bytes)
+			.map_err(|e| e.into_status())?;
+
+		let events: Vec<_> = tombstones
+			.into_iter()
+			.map(lt_history_event::Event::Tombstone)
+			.chain(items.into_iter().map(|item| lt_history_event::Event::Object(item.into())))
+			.chain([
+				lt_history_event::Event::Watermark(Watermark {
+					timestamp: watermark,
+					// Windows are only complete once their last page was read.
+					caught_up: page.next_cursor.is_none(),
+					..Watermark::default()
+				}),
+				lt_history_event::Event::Page(PageResponse::from(&page)),
+			])
+			.map(|event| LtHistoryEvent { event: Some(event) })
+			.collect();
+		Ok(Self::page_response(
+			Self::stream_items(events),
+			page,
+			watermark,
+		))
+	}
+
+	async fn watch_lt(
+		&self, request: Request<LtWatch>,
+	) -> Result<Response<Self::WatchLtStream>, Status> {
+		self.auth.authorize(&request, Role::Reader)?;
+		let watch = request.into_inner();
+		let mut updates = self.updates.subscribe();
+		let mut shutdown = self.shutdown.subscribe();
+		let last_write = self.last_write.clone();
+		let watermark_interval = self.watermark_interval;
+
+		let (tx, rx) = channel(WATCH_BUFFER_SIZE);
+		tokio::spawn(async move {
+			let mut ticker = interval(watermark_interval);
+			let mut last_watermark = None;
+			loop {
+				// Updates come first, so none covered by a watermark is sent after it.
+				let event = select! {
+					biased;
+					_ = shutdown.wait_for(|&closing| closing) => {
+						Err(unavailable("Shutting down, resubscribe later!"))
+					},
+					update = updates.recv() => match update {
+						Ok(update) => {
+							let is_form_watched =
+								watch.forms.is_empty() || watch.forms.contains(&update.form);
+							if update.domain != watch.domain || !is_form_watched {
+								continue;
+							}
+							let object = Some(update.item.into());
+							Ok(lt_watch_event::Event::Delta(LtDelta { form: update.form, object }))
+						},
+						// The subscriber has missed updates and has to resync from history.
+						Err(RecvError::Lagged(n)) => {
+							Err(Status::data_loss(format!("Missed {} updates", n)))
+						},
+						Err(RecvError::Closed) => break,
+					},
+					_ = ticker.tick() => {
+						let timestamp = last_write.load(Ordering::Acquire);
+						// Updates are queued before the write they belong to is marked, so the
+						// watch has sent every update up to `timestamp` once none are queued.
+						let caught_up = updates.is_empty();
+						let watermark = Watermark { timestamp, caught_up, ..Watermark::default() };
+						if last_watermark.as_ref() == Some(&watermark) {
+							let heartbeat = Heartbeat { next_position: 0, timestamp: now_millis() };
+							Ok(lt_watch_event::Event::Heartbeat(heartbeat))
+						} else {
+							last_watermark = Some(watermark.clone());
+							Ok(lt_watch_event::Event::Watermark(watermark))
+						}
+					},
+				};
+
+				let is_err = event.is_err();
+				let event = event.map(|e| LtWatchEvent { event: Some(e) });
+				if tx.send(event).await.is_err() || is_err {
+					break;
+				}
+			}
+		});
+
+		Ok(Response::new(ReceiverStream::new(rx)))
+	}
+
+	async fn get_did_mapping(
+		&self, request: Request<MappingQuery>,
+	) -> Result<Response<Self::GetDidMappingStream>, Status> {
+		self.auth.authorize(&request, Role::Reader)?;
+		let query = request.into_inner();
+		let mode = MatchMode::from_i32(query.mode)
+			.ok_or_else(|| invalid_argument("mode", "Invalid match mode!"))?;
+		let pattern = KeyPattern::parse(&query.pattern, mode)
+			.map_err(|_| invalid_argument("pattern", "Invalid pattern!"))?;
+
+		// Clients of protocol version 1 page by the cursor and limit `page` supersedes.
+		let page =
+			query.page.unwrap_or(PageRequest { cursor: query.cursor, page_size: query.limit });
+		let limit = usize::try_from(page.size_within(MAX_MAPPING_BATCH_SIZE))
+			.map_err(|_| invalid_argument("page.page_size", "Invalid page size!"))?;
+		let after = if page.cursor.is_empty() { None } else { Some(page.cursor) };
+
+		let is_first = after.is_none();
+		let watermark = self.last_write.load(Ordering::Acquire);
+		let read = |limit| Self::read_mappings(&self.db, query.domain, &pattern, after, limit);
+		let (items, page) = Self::read_page(read, is_first, limit, |item| item.key().to_vec())
+			.map_err(|e| e.into_status())?;
+
+		Ok(Self::page_response(
+			Self::stream_items(items),
+			page,
+			watermark,
+		))
+	}
+
+	async fn watch_did_mapping(
+		&self, request: Request<MappingWatch>,
+	) -> Result<Response<Self::WatchDidMappingStream>, Status> {
+		self.auth.authorize(&request, Role::Reader)?;
+		let watch = request.into_inner();
+		// Subscribed before catching up, so nothing assigned in between is missed.
+		let mut assignments = self.assignments.subscribe();
+		let mut shutdown = self.shutdown.subscribe();
+		let db = self.db.clone();
+		let last_write = self.last_write.clone();
+		let watermark_interval = self.watermark_interval;
+		// Assignments are stored before the write they belong to is marked, so the watch has
+		// sent every one up to `timestamp` once `next` reaches the checkpoint read after it.
+		let watermark = move |db: &DB, timestamp: u64, next: u64| -> Result<Watermark, Status> {
+			let assigned = Self::read_checkpoint(db, watch.domain).map_err(|e| e.into_status())?;
+			let caught_up = next >= u64::from(assigned);
+			Ok(Watermark { timestamp, caught_up, next_position: next })
+		};
+
+		let (tx, rx) = channel(WATCH_BUFFER_SIZE);
+		tokio::spawn(async move {
+			let mut ticker = interval(watermark_interval);
+			let mut next = watch.from_sequence;
+			let mut is_behind = true;
+			let mut last_watermark = None;
+			loop {
+				if is_behind {
+					let timestamp = last_write.load(Ordering::Acquire);
+					if !Self::send_assigned(&db, watch.domain, &mut next, &tx).await {
+						break;
+					}
+					let watermark = match watermark(&db, timestamp, next) {
+						Ok(watermark) => watermark,
+						Err(status) => {
+							let _ = tx.send(Err(status)).await;
+							break;
+						},
+					};
+					last_watermark = Some(watermark.clone());
+					let event = mapping_watch_event::Event::Watermark(watermark);
+					if tx.send(Ok(MappingWatchEvent { event: Some(event) })).await.is_err() {
+						break;
+					}
+					is_behind = false;
+					ticker.reset();
+				}
+				// Assignments come first, so none covered by a watermark is sent after it.
+				let event = select! {
+					biased;
+					_ = shutdown.wait_for(|&closing| closing) => {
+						Err(unavailable("Shutting down, resubscribe later!"))
+					},
+					update = assignments.recv() => match update {
+						Ok(update) if update.domain != watch.domain => continue,
+						Ok(update) => {
+							let sequence = u64::from(update.item.index());
+							if sequence < next {
+								continue;
+							}
+							// Anything skipped or missed is in the database.
+							if sequence > next {
+								is_behind = true;
+								continue;
+							}
+							next += 1;
+							Ok(mapping_watch_event::Event::Change(update.item.into()))
+						},
+						Err(RecvError::Lagged(_)) => {
+							is_behind = true;
+							continue;
+						},
+						Err(RecvError::Closed) => break,
+					},
+					_ = ticker.tick() => {
+						let timestamp = last_write.load(Ordering::Acquire);
+						match watermark(&db, timestamp, next) {
+							Ok(watermark) if last_watermark.as_ref() == Some(&watermark) => {
+								let timestamp = now_millis();
+								let heartbeat = Heartbeat { next_position: next, timestamp };
+								Ok(mapping_watch_event::Event::Heartbeat(heartbeat))
+							},
+							Ok(watermark) => {
+								last_watermark = Some(watermark.clone());
+								Ok(mapping_watch_event::Event::Watermark(watermark))
+							},
+							Err(status) => Err(status),
+						}
+					},
+				};
+
+				let is_err = event.is_err();
+				let event = event.map(|e| MappingWatchEvent { event: Some(e) });
+				if tx.send(event).await.is_err() || is_err {
+					break;
+				}
 			}
+		});
+
+		Ok(Response::new(ReceiverStream::new(rx)))
+	}
+
+	async fn backup(&self, request: Request<Void>) -> Result<Response<BackupInfo>, Status> {
+		self.auth.authorize(&request, Role::Admin)?;
+		let db = self.db.clone();
+		let backup_dir = self.backup_dir.clone();
+		let storage = self.storage.clone();
+		let info = tokio::task::spawn_blocking(move || {
+			Self::create_backup(&db, &backup_dir, storage.as_ref())
+		})
+		.await
+		.map_err(|_| Status::internal("Backup task failed!"))?
+		.map_err(|e| e.into_status())?;
+
+		let BackupEngineInfo { backup_id, timestamp, size, .. } = info;
+		Ok(Response::new(BackupInfo { backup_id, timestamp, size }))
+	}
+
+	async fn restore(&self, request: Request<RestoreRequest>) -> Result<Response<Void>, Status> {
+		self.auth.authorize(&request, Role::Admin)?;
+		let restore = request.into_inner();
+		if restore.target_dir.is_empty() {
+			return Err(invalid_argument("target_dir", "Missing target directory!"));
+		}
+		// The live database can only be replaced while the combiner is stopped.
+		let is_live_db = match (
+			canonicalize(&restore.target_dir),
+			canonicalize(&self.db_url),
+		) {
+			(Ok(target), Ok(live)) => target == live,
+			_ => false,
+		};
+		if is_live_db {
+			return Err(Status::failed_precondition(
+				"Cannot restore over the live database!",
+			));
 		}
 
+		let backup_dir = self.backup_dir.clone();
+		let storage = self.storage.clone();
+		let backup_id = if restore.backup_id == 0 { None } else { Some(restore.backup_id) };
+		tokio::task::spawn_blocking(move || {
+			Self::restore_backup(
+				&backup_dir,
+				backup_id,
+				&restore.target_dir,
+				storage.as_ref(),
+			)
+		})
+		.await
+		.map_err(|_| Status::internal("Restore task failed!"))?
+		.map_err(|e| e.into_status())?;
+
+		Ok(Response::new(Void {}))
+	}
+
+	async fn snapshot_lt(
+		&self, request: Request<LtSnapshotRequest>,
+	) -> Result<Response<Self::SnapshotLtStream>, Status> {
+		self.auth.authorize(&request, Role::Reader)?;
+		let LtSnapshotRequest { domain, since_timestamp } = request.into_inner();
+		let db = self.db.clone();
+
+		let (tx, rx) = channel(SNAPSHOT_BUFFER_SIZE);
+		tokio::task::spawn_blocking(move || {
+			let res = Self::read_snapshot(&db, domain, since_timestamp, |cell| {
+				tx.blocking_send(Ok(cell)).is_ok()
+			});
+			if let Err(e) = res {
+				let _ = tx.blocking_send(Err(e.into_status()));
+			}
+		});
+
 		Ok(Response::new(ReceiverStream::new(rx)))
 	}
+
+	async fn replay_journal(
+		&self, request: Request<Void>,
+	) -> Result<Response<JournalReplay>, Status> {
+		self.auth.authorize(&request, Role::Admin)?;
+		let service = self.clone();
+		let entries = tokio::task::spawn_blocking(move || service.replay_journal())
+			.await
+			.map_err(|_| Status::internal("Replay task failed!"))??;
+		Ok(Response::new(JournalReplay { entries }))
+	}
+
+	async fn check_consistency(
+		&self, request: Request<ConsistencyCheck>,
+	) -> Result<Response<ConsistencyReport>, Status> {
+		self.auth.authorize(&request, Role::Admin)?;
+		let repair = request.into_inner().repair;
+		let service = self.clone();
+		let report = tokio::task::spawn_blocking(move || service.check_consistency(repair))
+			.await
+			.map_err(|_| Status::internal("Consistency check task failed!"))??;
+		Ok(Response::new(report))
+	}
+
+	async fn rollback(
+		&self, request: Request<RollbackRequest>,
+	) -> Result<Response<RollbackInfo>, Status> {
+		self.auth.authorize(&request, Role::Admin)?;
+		let timestamp = request.into_inner().timestamp;
+		let service = self.clone();
+		let (cells, entries) = tokio::task::spawn_blocking(move || service.rollback(timestamp))
+			.await
+			.map_err(|_| Status::internal("Rollback task failed!"))?
+			.map_err(|e| e.into_status())?;
+		Ok(Response::new(RollbackInfo { cells, entries }))
+	}
+
+	async fn reset_domain(&self, request: Request<DomainReset>) -> Result<Response<Void>, Status> {
+		self.auth.authorize(&request, Role::Admin)?;
+		let reset = request.into_inner();
+		// Taken like ingestion does, so no chunk lands in the domain halfway through the reset.
+		let _guard = self.write_lock.lock().unwrap_or_else(PoisonError::into_inner);
+		Self::reset_domain(&self.db, reset.domain, reset.preserve_mapping)
+			.map_err(|e| e.into_status())?;
+		Ok(Response::new(Void {}))
+	}
+
+	async fn get_stats(
+		&self, request: Request<LtStatsRequest>,
+	) -> Result<Response<LtStats>, Status> {
+		self.auth.authorize(&request, Role::Reader)?;
+		let domain = request.into_inner().domain;
+		let db = self.db.clone();
+		let stats = tokio::task::spawn_blocking(move || Self::read_stats(&db, domain))
+			.await
+			.map_err(|_| Status::internal("Stats task failed!"))?
+			.map_err(|e| e.into_status())?;
+		Ok(Response::new(stats))
+	}
+
+	async fn remove_peer(
+		&self, request: Request<PeerRemoval>,
+	) -> Result<Response<PeerRemovalInfo>, Status> {
+		self.auth.authorize(&request, Role::Admin)?;
+		let removal = request.into_inner();
+		let key = hex::decode(&removal.key).map_err(|_| invalid_argument("key", "Invalid key!"))?;
+		let service = self.clone();
+		let info = tokio::task::spawn_blocking(move || service.remove_peer(removal.domain, &key))
+			.await
+			.map_err(|_| Status::internal("Removal task failed!"))?
+			.map_err(|e| e.into_status())?;
+		info.map(Response::new).ok_or_else(|| Status::not_found("Unknown peer!"))
+	}
+
+	async fn get_service_info(&self, _: Request<Void>) -> Result<Response<ServiceInfo>, Status> {
+		let mut limits = HashMap::from([
+			(
+				"max_term_batch_size".to_string(),
+				self.max_term_batch_size() as u64,
+			),
+			(
+				"max_history_batch_size".to_string(),
+				u64::from(MAX_HISTORY_BATCH_SIZE),
+			),
+			(
+				"max_mapping_batch_size".to_string(),
+				u64::from(MAX_MAPPING_BATCH_SIZE),
+			),
+		]);
+		if self.max_stream_terms > 0 {
+			limits.insert("max_stream_terms".to_string(), self.max_stream_terms);
+		}
+		if self.max_ingest_rate > 0 {
+			limits.insert(
+				"max_ingest_rate".to_string(),
+				u64::from(self.max_ingest_rate),
+			);
+		}
+		Ok(Response::new(ServiceInfo {
+			service: "linear-combiner".to_string(),
+			version: env!("CARGO_PKG_VERSION").to_string(),
+			protocol_version: PROTOCOL_VERSION,
+			features: FEATURES.iter().map(|f| f.to_string()).collect(),
+			limits,
+		}))
+	}
+}
+
+/// Rejects terms that can't be applied, whichever way they are streamed.
+fn check_term(term: &TermObject) -> Result<(), Status> {
+	if !term.weight.is_finite() {
+		return Err(invalid_argument("weight", "Invalid weight!"));
+	}
+	if term.source.len() > usize::from(u8::MAX) {
+		return Err(invalid_argument("source", "Invalid source!"));
+	}
+	Ok(())
+}
+
+/// Status rejecting a request for its `field`, not worth retrying as is.
+fn invalid_argument(field: &str, message: &str) -> Status {
+	ErrorDetail::permanent().with_field(field).into_status(Code::InvalidArgument, message)
+}
+
+/// Status of a request the server can't serve for now, worth retrying elsewhere or later.
+fn unavailable(message: &str) -> Status {
+	ErrorDetail::retryable(0).into_status(Code::Unavailable, message)
+}
+
+/// Resolves on SIGTERM or Ctrl-C, after telling open streams to wind down.
+async fn shutdown_signal(service: &LinearCombinerService) {
+	let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+	select! {
+		_ = terminate.recv() => {},
+		_ = ctrl_c() => {},
+	}
+	service.begin_shutdown();
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-	let addr = "[::1]:50052".parse()?;
-	let service = LinearCombinerService::new("lc-storage", "lc-updates-storage")?;
-	Server::builder().add_service(LinearCombinerServer::new(service)).serve(addr).await?;
+	let config = Config::parse();
+	let auth = Authenticator::new(&config.auth)?;
+	let service = LinearCombinerService::new(&config, auth.clone())?;
+	service.spawn_update_compaction();
+	service.spawn_backups();
+	let (reporter, health_service) = health_reporter();
+	service.spawn_health_checks(reporter);
+	if let Some(addr) = config.metrics_addr {
+		service.spawn_metrics(addr);
+	}
+	let rpc_metrics = RpcMetricsLayer::new(service.metrics.clone());
+	let handle = service.clone();
+	// Cell streams are highly repetitive. Responses are only compressed for clients that
+	// accept gzip.
+	let combiner = LinearCombinerServer::new(service).send_gzip().accept_gzip();
+	let mut server = Server::builder().layer(rpc_metrics);
+	if let Some(tls) = config.tls.load()? {
+		server = server.tls_config(tls)?;
+	}
+	server
+		.add_service(health_service)
+		.add_service(InterceptedService::new(combiner, auth))
+		.serve_with_shutdown(config.listen_addr, shutdown_signal(&handle))
+		.await?;
+	handle.close()?;
 	Ok(())
 }
 
 #[cfg(test)]
 mod test {
-	use rocksdb::DB;
+	use crate::{
+		auth::Authenticator,
+		config::{AuthConfig, Config, IngestConfig, StorageBackend, StorageConfig, TlsConfig},
+		now_millis,
+		storage::{MemoryStorage, Storage},
+		CellUpdate, LinearCombinerService, PendingWrites, MAX_COUNTED_MATCHES, MIGRATIONS,
+		NEXT_CURSOR_HEADER, RETURNED_HEADER, SCHEMA_VERSION_KEY, TOTAL_HEADER, WATERMARK_HEADER,
+	};
+	use linear_combiner::{
+		error::LcError,
+		item::{LtItem, MappingItem},
+		mapping::KeyPattern,
+		INDEX_CF, LT_CF, MAPPING_CF,
+	};
+	use proto_buf::combiner::{
+		linear_combiner_server::LinearCombiner, lt_history_event, lt_watch_event::Event,
+		mapping_watch_event, DomainReset, LtHistoryBatch, LtObject, LtWatch, MappingChange,
+		MappingQuery, MappingWatch, MappingWatchEvent, MatchMode,
+	};
+	use proto_buf::{
+		common::{ErrorDetail, PageRequest, Void},
+		is_retryable,
+		transformer::{TermObject, TermObjectBatch},
+	};
+	use rocksdb::{Options, DB};
+	use std::{collections::HashSet, time::Duration};
+	use tokio_stream::{Stream, StreamExt};
+	use tonic::{Code, Request, Status};
+
+	fn test_service(
+		db_path: &str, backup_dir: &str, admin_token: Option<String>,
+	) -> LinearCombinerService {
+		let config = Config {
+			listen_addr: "[::1]:0".parse().unwrap(),
+			metrics_addr: None,
+			db_path: db_path.to_string(),
+			backup_dir: backup_dir.to_string(),
+			auth: AuthConfig { admin_token, ..AuthConfig::default() },
+			storage: StorageConfig { backend: StorageBackend::Memory, ..StorageConfig::default() },
+			tls: TlsConfig::default(),
+			ingest: IngestConfig::default(),
+		};
+		let auth = Authenticator::new(&config.auth).unwrap();
+		LinearCombinerService::new(&config, auth).unwrap()
+	}
+
+	/// Opens an empty database living in memory, so tests neither see each other's writes nor
+	/// leave directories behind.
+	fn test_db() -> DB {
+		let storage = MemoryStorage::new().unwrap();
+		LinearCombinerService::open_db("lc-test-storage", &StorageConfig::default(), &storage)
+			.unwrap()
+	}
+
+	fn update(db: &DB, key: Vec<u8>, weight: f64, timestamp: u64) -> Result<f64, LcError> {
+		let mut pending = PendingWrites::new();
+		let value = LinearCombinerService::update_value(db, &mut pending, key, weight, timestamp)?;
+		LinearCombinerService::commit_writes(db, pending)?;
+		Ok(value)
+	}
 
-	use crate::{item::LtItem, LinearCombinerService};
 	#[test]
 	fn should_write_read_checkpoint() {
-		let db = DB::open_default("lc-checkpoint-test-storage").unwrap();
-		LinearCombinerService::write_checkpoint(&db, 15).unwrap();
-		let checkpoint = LinearCombinerService::read_checkpoint(&db).unwrap();
+		let db = test_db();
+		let mut pending = PendingWrites::new();
+		pending.offsets.insert(0, 15);
+		LinearCombinerService::commit_writes(&db, pending).unwrap();
+		let checkpoint = LinearCombinerService::read_checkpoint(&db, 0).unwrap();
 		assert_eq!(checkpoint, 15);
 	}
 
 	#[test]
 	fn should_update_and_get_index() {
-		let main_db = DB::open_default("lc-index-test-storage").unwrap();
+		let db = test_db();
 		let source = "90f8bf6a479f320ead074411a4b0e7944ea8c9c2".to_string();
-		let mut offset = 0;
+		let mut pending = PendingWrites::new();
 
-		let index = LinearCombinerService::get_index(&main_db, source, &mut offset).unwrap();
+		let index = LinearCombinerService::get_index(&db, &mut pending, 0, source).unwrap();
+		LinearCombinerService::commit_writes(&db, pending).unwrap();
 
 		let mut bytes = [0; 4];
 		bytes.copy_from_slice(&index);
@@ -282,45 +2439,558 @@ mod test {
 		assert_eq!(i, 0);
 	}
 
+	#[test]
+	fn should_read_mappings_by_pattern() {
+		let db = test_db();
+		let sources = [
+			"90f8bf6a479f320ead074411a4b0e7944ea8c9c1",
+			"90f8bf6a479f320ead074411a4b0e7944ea8c9c2",
+			"a0f8bf6a479f320ead074411a4b0e7944ea8c9c3",
+		];
+		let mut pending = PendingWrites::new();
+		let mut index = |s: &str| {
+			LinearCombinerService::get_index(&db, &mut pending, 0, s.to_string()).unwrap()
+		};
+		let indices: Vec<[u8; 4]> = sources.iter().map(|s| index(s)).collect();
+		LinearCombinerService::commit_writes(&db, pending).unwrap();
+		let expected: Vec<MappingItem> = sources
+			.iter()
+			.zip(indices)
+			.map(|(s, i)| MappingItem::from_raw(hex::decode(s).unwrap(), i.to_vec()))
+			.collect();
+
+		let pattern = KeyPattern::parse("did:pkh:eip155:1:0x90f8b", MatchMode::Prefix).unwrap();
+		let items = LinearCombinerService::read_mappings(&db, 0, &pattern, None, 10).unwrap();
+		assert_eq!(items, expected[..2]);
+
+		let after = Some(hex::decode(sources[0]).unwrap());
+		let items = LinearCombinerService::read_mappings(&db, 0, &pattern, after, 10).unwrap();
+		assert_eq!(items, expected[1..2]);
+
+		let pattern = KeyPattern::parse("8c9c", MatchMode::Substring).unwrap();
+		let items = LinearCombinerService::read_mappings(&db, 0, &pattern, None, 2).unwrap();
+		assert_eq!(items, expected[..2]);
+
+		let items = LinearCombinerService::read_mappings(&db, 1, &pattern, None, 2).unwrap();
+		assert_eq!(
+			items,
+			Vec::new(),
+			"should not return mappings of other domains"
+		);
+	}
+
+	#[test]
+	fn should_index_sources_per_domain() {
+		let db = test_db();
+		let sources = [
+			"90f8bf6a479f320ead074411a4b0e7944ea8c9c6",
+			"90f8bf6a479f320ead074411a4b0e7944ea8c9c7",
+		];
+
+		let mut pending = PendingWrites::new();
+		let mut index = |domain, source: &str| {
+			LinearCombinerService::get_index(&db, &mut pending, domain, source.to_string()).unwrap()
+		};
+		let first = index(1, sources[0]);
+		let second = index(1, sources[1]);
+		let other_domain = index(2, sources[1]);
+		LinearCombinerService::commit_writes(&db, pending).unwrap();
+
+		assert_ne!(first, second);
+		assert_eq!(
+			LinearCombinerService::read_checkpoint(&db, 1).unwrap(),
+			u32::from_be_bytes(first).max(u32::from_be_bytes(second)) + 1
+		);
+		assert!(
+			u32::from_be_bytes(other_domain)
+				< LinearCombinerService::read_checkpoint(&db, 2).unwrap()
+		);
+	}
+
+	#[test]
+	fn should_migrate_shared_keyspace() {
+		let storage = MemoryStorage::new().unwrap();
+		let db = LinearCombinerService::open_db(
+			"lc-keyspace-test-storage",
+			&StorageConfig::default(),
+			&storage,
+		)
+		.unwrap();
+		let key = hex::decode("90f8bf6a479f320ead074411a4b0e7944ea8c9c8").unwrap();
+		let index = 7u32.to_be_bytes();
+		let cell = [5u32.to_be_bytes(), [0; 4], [0; 4], [0; 4]].concat();
+
+		let lt_cf = LinearCombinerService::cf(&db, LT_CF).unwrap();
+		let index_cf = LinearCombinerService::cf(&db, INDEX_CF).unwrap();
+		let mapping_cf = LinearCombinerService::cf(&db, MAPPING_CF).unwrap();
+		db.put_cf(&lt_cf, cell, 1f64.to_be_bytes()).unwrap();
+		db.put_cf(&index_cf, &key, index).unwrap();
+		db.put_cf(&mapping_cf, index, &key).unwrap();
+		db.put(b"checkpoint", 8u32.to_be_bytes()).unwrap();
+		db.put(SCHEMA_VERSION_KEY, 2u32.to_be_bytes()).unwrap();
+		drop((lt_cf, index_cf, mapping_cf));
+		drop(db);
+
+		let db = LinearCombinerService::open_db(
+			"lc-keyspace-test-storage",
+			&StorageConfig::default(),
+			&storage,
+		)
+		.unwrap();
+		LinearCombinerService::migrate(&db, "lc-keyspace-backup-storage", &storage).unwrap();
+		let mut pending = PendingWrites::new();
+		let migrated =
+			LinearCombinerService::get_index(&db, &mut pending, 5, hex::encode(&key)).unwrap();
+		assert_eq!(migrated, index);
+		assert_eq!(LinearCombinerService::read_checkpoint(&db, 5).unwrap(), 8);
+	}
+
+	#[test]
+	fn should_migrate_default_column_family() {
+		let storage = MemoryStorage::new().unwrap();
+		let mut opts = Options::default();
+		opts.create_if_missing(true);
+		let db = storage.open_db("lc-baseline-test-storage", opts, Vec::new()).unwrap();
+		let keys = [
+			hex::decode("90f8bf6a479f320ead074411a4b0e7944ea8c9c1").unwrap(),
+			hex::decode("90f8bf6a479f320ead074411a4b0e7944ea8c9c2").unwrap(),
+		];
+		let cell = [3u32.to_be_bytes(), [0; 4], [0; 4], 1u32.to_be_bytes()].concat();
+		db.put(&keys[0], 0u32.to_be_bytes()).unwrap();
+		db.put(&keys[1], 1u32.to_be_bytes()).unwrap();
+		db.put(&cell, 5u32.to_be_bytes()).unwrap();
+		db.put(b"checkpoint", 2u32.to_be_bytes()).unwrap();
+		drop(db);
+
+		let db = LinearCombinerService::open_db(
+			"lc-baseline-test-storage",
+			&StorageConfig::default(),
+			&storage,
+		)
+		.unwrap();
+		assert_eq!(LinearCombinerService::schema_version(&db).unwrap(), 0);
+		LinearCombinerService::migrate(&db, "lc-baseline-backup-storage", &storage).unwrap();
+		assert_eq!(LinearCombinerService::get_value(&db, &cell).unwrap(), 5.);
+		assert_eq!(LinearCombinerService::read_checkpoint(&db, 3).unwrap(), 2);
+		let mapped: Vec<_> = LinearCombinerService::read_assigned(&db, 3, 0, usize::MAX)
+			.unwrap()
+			.iter()
+			.map(|item| item.key().to_vec())
+			.collect();
+		assert_eq!(mapped, keys);
+		let updates = LinearCombinerService::read_batch(&db, cell[..8].to_vec(), 10).unwrap();
+		assert_eq!(updates.len(), 1, "should serve the moved cells as updates");
+		assert!(
+			db.get(&cell).unwrap().is_none() && db.get(&keys[0]).unwrap().is_none(),
+			"should leave nothing behind in the default column family"
+		);
+	}
+
 	#[test]
 	fn should_update_item() {
-		let main_db = DB::open_default("lc-items-test-storage").unwrap();
-		let updates_db = DB::open_default("lc-updates-test-storage").unwrap();
+		let db = test_db();
 		let key = vec![0; 8];
-		let weight = 50;
+		let weight = 50.;
 
-		let prev_value = LinearCombinerService::get_value(&main_db, &key).unwrap();
-		LinearCombinerService::update_value(&main_db, &updates_db, key.clone(), weight).unwrap();
-		let value = LinearCombinerService::get_value(&main_db, &key).unwrap();
+		let prev_value = LinearCombinerService::get_value(&db, &key).unwrap();
+		update(&db, key.clone(), weight, 0).unwrap();
+		let value = LinearCombinerService::get_value(&db, &key).unwrap();
 
 		assert_eq!(value, prev_value + weight);
 	}
 
+	#[test]
+	fn should_apply_pending_writes_on_commit() {
+		let db = test_db();
+		let source = "90f8bf6a479f320ead074411a4b0e7944ea8c9c4".to_string();
+		let key = vec![3; 16];
+
+		let prev_value = LinearCombinerService::get_value(&db, &key).unwrap();
+		let mut pending = PendingWrites::new();
+		let index = LinearCombinerService::get_index(&db, &mut pending, 0, source.clone()).unwrap();
+		let same_index = LinearCombinerService::get_index(&db, &mut pending, 0, source).unwrap();
+		assert_eq!(index, same_index);
+		let new_offset = pending.offsets.get(&0).copied();
+		assert_eq!(
+			new_offset,
+			Some(u32::from_be_bytes(index) + 1),
+			"should hand out the next index"
+		);
+
+		LinearCombinerService::update_value(&db, &mut pending, key.clone(), 1., 0).unwrap();
+		let value = LinearCombinerService::update_value(&db, &mut pending, key.clone(), 1., 0);
+		assert_eq!(value.unwrap(), prev_value + 2.);
+		assert_eq!(
+			LinearCombinerService::get_value(&db, &key).unwrap(),
+			prev_value
+		);
+
+		LinearCombinerService::commit_writes(&db, pending).unwrap();
+		assert_eq!(
+			LinearCombinerService::get_value(&db, &key).unwrap(),
+			prev_value + 2.
+		);
+		assert_eq!(
+			LinearCombinerService::read_checkpoint(&db, 0).unwrap(),
+			new_offset.unwrap()
+		);
+	}
+
+	#[test]
+	fn should_skip_applied_sequences() {
+		let db = test_db();
+		let from = "90f8bf6a479f320ead074411a4b0e7944ea8c9c5";
+		let sequence = now_millis();
+
+		let mut pending = PendingWrites::new();
+		let mark = |pending: &mut PendingWrites, source, sequence| {
+			LinearCombinerService::mark_applied(&db, pending, source, from, sequence).unwrap()
+		};
+		assert!(mark(&mut pending, "", sequence));
+		assert!(!mark(&mut pending, "", sequence));
+		assert!(
+			mark(&mut pending, "other", sequence),
+			"should keep the sequences of sources apart"
+		);
+		LinearCombinerService::commit_writes(&db, pending).unwrap();
+
+		let mut pending = PendingWrites::new();
+		assert!(
+			!mark(&mut pending, "", sequence),
+			"should remember committed sequences"
+		);
+		assert!(mark(&mut pending, "", sequence + 1));
+		LinearCombinerService::commit_writes(&db, pending).unwrap();
+		assert_eq!(
+			LinearCombinerService::read_source_checkpoint(&db, "").unwrap(),
+			sequence + 1
+		);
+		assert_eq!(
+			LinearCombinerService::read_source_checkpoint(&db, "other").unwrap(),
+			sequence
+		);
+	}
+
+	#[test]
+	fn should_decrement_item() {
+		let db = test_db();
+		let key = vec![0; 16];
+
+		let prev_value = LinearCombinerService::get_value(&db, &key).unwrap();
+		update(&db, key.clone(), 2.5, 0).unwrap();
+		update(&db, key.clone(), -1.25, 0).unwrap();
+		let value = LinearCombinerService::get_value(&db, &key).unwrap();
+
+		assert_eq!(value, prev_value + 1.25);
+	}
+
+	#[test]
+	fn should_prefetch_chunk_values() {
+		let db = test_db();
+		let key = vec![2; 16];
+		let prev_value = LinearCombinerService::get_value(&db, &key).unwrap();
+
+		let mut pending = PendingWrites::new();
+		let keys = [key.clone(), vec![3; 16], key.clone()];
+		LinearCombinerService::prefetch_values(&db, &mut pending, keys.iter()).unwrap();
+		assert_eq!(pending.values.len(), 2);
+		assert_eq!(pending.values[&key], prev_value);
+
+		let value = LinearCombinerService::update_value(&db, &mut pending, key, 1., 0).unwrap();
+		assert_eq!(value, prev_value + 1.);
+	}
+
+	#[test]
+	fn should_reject_overflowing_item() {
+		let db = test_db();
+		let key = vec![1; 16];
+
+		update(&db, key.clone(), f64::MAX, 0).unwrap();
+		let res = update(&db, key.clone(), f64::MAX, 0);
+		assert!(matches!(res, Err(LcError::OverflowError)));
+
+		let value = LinearCombinerService::get_value(&db, &key).unwrap();
+		assert!(value.is_finite());
+		update(&db, key, -value, 0).unwrap();
+	}
+
+	#[test]
+	fn should_migrate_u32_values() {
+		let storage = MemoryStorage::new().unwrap();
+		let db = LinearCombinerService::open_db(
+			"lc-migrate-test-storage",
+			&StorageConfig::default(),
+			&storage,
+		)
+		.unwrap();
+		let lt_cf = LinearCombinerService::cf(&db, LT_CF).unwrap();
+		let key = vec![2; 16];
+		db.put_cf(&lt_cf, &key, 7u32.to_be_bytes()).unwrap();
+		db.put(SCHEMA_VERSION_KEY, 1u32.to_be_bytes()).unwrap();
+		drop(lt_cf);
+		drop(db);
+
+		let db = LinearCombinerService::open_db(
+			"lc-migrate-test-storage",
+			&StorageConfig::default(),
+			&storage,
+		)
+		.unwrap();
+		let backup_dir = "lc-migrate-backup-storage";
+		let latest_backup = || {
+			let engine = storage.open_backup_engine(backup_dir).unwrap();
+			engine.get_backup_info().into_iter().map(|info| info.backup_id).max()
+		};
+		let backup_before = latest_backup();
+		LinearCombinerService::migrate(&db, backup_dir, &storage).unwrap();
+		let value = LinearCombinerService::get_value(&db, &key).unwrap();
+		assert_eq!(value, 7.);
+		assert_eq!(
+			LinearCombinerService::schema_version(&db).unwrap(),
+			MIGRATIONS.len()
+		);
+		assert!(
+			latest_backup() > backup_before,
+			"should back up before migrating"
+		);
+	}
+
+	#[test]
+	fn should_stamp_schema_version() {
+		let storage = MemoryStorage::new().unwrap();
+		let db = LinearCombinerService::open_db(
+			"lc-schema-test-storage",
+			&StorageConfig::default(),
+			&storage,
+		)
+		.unwrap();
+		let backup_dir = "lc-schema-backup-storage";
+		db.delete(SCHEMA_VERSION_KEY).unwrap();
+		LinearCombinerService::migrate(&db, backup_dir, &storage).unwrap();
+		assert_eq!(
+			LinearCombinerService::schema_version(&db).unwrap(),
+			MIGRATIONS.len(),
+			"should not migrate an empty database"
+		);
+
+		db.put(SCHEMA_VERSION_KEY, 99u32.to_be_bytes()).unwrap();
+		let res = LinearCombinerService::migrate(&db, backup_dir, &storage);
+		assert!(matches!(res, Err(LcError::UnsupportedSchemaError(99))));
+	}
+
 	#[test]
 	fn should_read_delete_batch() {
-		let main_db = DB::open_default("lc-rd-items-test-storage").unwrap();
-		let updates_db = DB::open_default("lc-rd-updates-test-storage").unwrap();
+		let db = test_db();
 		let prefix = vec![0; 8];
 		let key = vec![0; 16];
-		let weight = 50u32;
+		let weight = 50.;
 
-		let prev_value = LinearCombinerService::get_value(&main_db, &key).unwrap();
-		LinearCombinerService::update_value(&main_db, &updates_db, key.clone(), weight).unwrap();
+		let prev_value = LinearCombinerService::get_value(&db, &key).unwrap();
+		update(&db, key.clone(), weight, 0).unwrap();
 
 		let org_items =
-			vec![LtItem::from_raw(key.clone(), (weight + prev_value).to_be_bytes().to_vec())];
-		let items = LinearCombinerService::read_batch(&updates_db, prefix.clone(), 1).unwrap();
+			vec![LtItem::from_raw(key.clone(), (prev_value + weight).to_be_bytes().to_vec())];
+		let items = LinearCombinerService::read_batch(&db, prefix.clone(), 1).unwrap();
 		assert_eq!(items, org_items);
 
-		LinearCombinerService::delete_batch(&updates_db, prefix.clone(), items).unwrap();
-		let items = LinearCombinerService::read_batch(&updates_db, prefix, 1).unwrap();
+		LinearCombinerService::delete_batch(&db, prefix.clone(), items).unwrap();
+		let items = LinearCombinerService::read_batch(&db, prefix, 1).unwrap();
 		assert_eq!(items, Vec::new());
 	}
 
+	#[test]
+	fn should_purge_expired_updates() {
+		let expiring = StorageConfig { update_ttl_days: 0, ..StorageConfig::default() };
+		let db = LinearCombinerService::open_db(
+			"lc-ttl-test-storage",
+			&expiring,
+			&MemoryStorage::new().unwrap(),
+		)
+		.unwrap();
+		let prefix = vec![0; 8];
+		let key = vec![0; 16];
+		let timestamp = now_millis() - 1;
+
+		let prev_value = LinearCombinerService::get_value(&db, &key).unwrap();
+		update(&db, key.clone(), 1., timestamp).unwrap();
+		assert_eq!(
+			LinearCombinerService::read_batch(&db, prefix.clone(), 1).unwrap().len(),
+			1
+		);
+
+		LinearCombinerService::compact_updates(&db).unwrap();
+		assert_eq!(
+			LinearCombinerService::read_batch(&db, prefix, 1).unwrap(),
+			Vec::new()
+		);
+		assert_eq!(
+			LinearCombinerService::get_value(&db, &key).unwrap(),
+			prev_value + 1.,
+			"should keep the aggregate value"
+		);
+	}
+
+	#[test]
+	fn should_back_up_and_restore() {
+		let storage = MemoryStorage::new().unwrap();
+		let db = LinearCombinerService::open_db(
+			"lc-backup-test-storage",
+			&StorageConfig::default(),
+			&storage,
+		)
+		.unwrap();
+		let key = vec![4; 16];
+		update(&db, key.clone(), 1., 0).unwrap();
+		let value = LinearCombinerService::get_value(&db, &key).unwrap();
+
+		let backup_dir = "lc-backup-test-backup-storage";
+		let info = LinearCombinerService::create_backup(&db, backup_dir, &storage).unwrap();
+		update(&db, key.clone(), 1., 0).unwrap();
+
+		let target_dir = "lc-backup-test-restore-storage";
+		LinearCombinerService::restore_backup(
+			backup_dir,
+			Some(info.backup_id),
+			target_dir,
+			&storage,
+		)
+		.unwrap();
+		let restored =
+			LinearCombinerService::open_db(target_dir, &StorageConfig::default(), &storage)
+				.unwrap();
+		assert_eq!(
+			LinearCombinerService::get_value(&restored, &key).unwrap(),
+			value
+		);
+	}
+
+	#[test]
+	fn should_snapshot_domain() {
+		let db = test_db();
+		let mut key1 = 7u32.to_be_bytes().to_vec();
+		key1.extend_from_slice(&[0; 12]);
+		let mut key2 = 7u32.to_be_bytes().to_vec();
+		key2.extend_from_slice(&1i32.to_be_bytes());
+		key2.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 2]);
+		let mut other_domain = 8u32.to_be_bytes().to_vec();
+		other_domain.extend_from_slice(&[0; 12]);
+
+		let value1 = update(&db, key1, 1., 100).unwrap();
+		let value2 = update(&db, key2, 1., 200).unwrap();
+		update(&db, other_domain, 1., 300).unwrap();
+
+		let read = |since| {
+			let mut cells = Vec::new();
+			LinearCombinerService::read_snapshot(&db, 7, since, |cell| {
+				cells.push(cell);
+				true
+			})
+			.unwrap();
+			cells.into_iter().map(|c| (c.form, c.object.unwrap(), c.timestamp)).collect::<Vec<_>>()
+		};
+
+		let cell1 = (
+			0,
+			LtObject::from(LtItem::new(0, 0, value1).with_timestamp(100)),
+			100,
+		);
+		let cell2 = (
+			1,
+			LtObject::from(LtItem::new(1, 2, value2).with_timestamp(200)),
+			200,
+		);
+		assert_eq!(read(0), vec![cell1, cell2.clone()]);
+		assert_eq!(
+			read(150),
+			vec![cell2],
+			"should only return cells updated since"
+		);
+	}
+
+	#[test]
+	fn should_reset_domain() {
+		let db = test_db();
+		let source = "90f8bf6a479f320ead074411a4b0e7944ea8c9c9".to_string();
+		let cell = |domain: u32| [domain.to_be_bytes(), [0; 4], [0; 4], [0; 4]].concat();
+
+		for domain in [9, 10] {
+			let mut pending = PendingWrites::new();
+			LinearCombinerService::get_index(&db, &mut pending, domain, source.clone()).unwrap();
+			LinearCombinerService::commit_writes(&db, pending).unwrap();
+			update(&db, cell(domain), 1., 0).unwrap();
+		}
+		let kept_value = LinearCombinerService::get_value(&db, &cell(10)).unwrap();
+
+		LinearCombinerService::reset_domain(&db, 9, true).unwrap();
+		assert_eq!(LinearCombinerService::get_value(&db, &cell(9)).unwrap(), 0.);
+		assert_eq!(
+			LinearCombinerService::read_batch(&db, cell(9)[..8].to_vec(), 1).unwrap(),
+			[]
+		);
+		assert!(LinearCombinerService::read_checkpoint(&db, 9).unwrap() > 0);
+
+		LinearCombinerService::reset_domain(&db, 9, false).unwrap();
+		assert_eq!(LinearCombinerService::read_checkpoint(&db, 9).unwrap(), 0);
+		assert_eq!(
+			LinearCombinerService::get_value(&db, &cell(10)).unwrap(),
+			kept_value
+		);
+	}
+
+	#[test]
+	fn should_keep_reset_domains_empty_on_replay() {
+		let service = test_service(
+			"lc-reset-replay-test-storage", "lc-reset-replay-backup-storage", None,
+		);
+		let term = |domain, sequence| TermObject {
+			from: "90f8bf6a479f320ead074411a4b0e7944ea8c9c1".to_string(),
+			to: "90f8bf6a479f320ead074411a4b0e7944ea8c9c2".to_string(),
+			weight: 1.,
+			domain,
+			form: 0,
+			sequence,
+			source: "reset".to_string(),
+		};
+		let cell =
+			|domain: u32| [domain.to_be_bytes(), [0; 4], [0; 4], 1u32.to_be_bytes()].concat();
+		service.apply_chunk(&[term(12, 1), term(13, 2)], now_millis(), "test").unwrap();
+
+		LinearCombinerService::reset_domain(&service.db, 12, true).unwrap();
+		assert_eq!(service.replay_journal().unwrap(), 1);
+		assert_eq!(
+			LinearCombinerService::get_value(&service.db, &cell(12)).unwrap(),
+			0.,
+			"should not bring back the cells reset"
+		);
+		assert_eq!(
+			LinearCombinerService::get_value(&service.db, &cell(13)).unwrap(),
+			1.
+		);
+
+		let applied = service.apply_chunk(&[term(12, 1)], now_millis(), "test").unwrap();
+		assert_eq!(applied, 1, "should take the terms of the domain again");
+	}
+
+	#[tokio::test]
+	async fn should_require_admin_token() {
+		let service = test_service(
+			"lc-admin-test-storage",
+			"lc-admin-backup-storage",
+			Some("secret".to_string()),
+		);
+		let reset = || Request::new(DomainReset { domain: 11, preserve_mapping: true });
+
+		let status = service.reset_domain(reset()).await.unwrap_err();
+		assert_eq!(status.code(), Code::Unauthenticated);
+
+		let mut request = reset();
+		request.metadata_mut().insert("authorization", "Bearer secret".parse().unwrap());
+		service.reset_domain(request).await.unwrap();
+	}
+
 	#[test]
 	fn should_read_window() {
-		let main_db = DB::open_default("lc-rdw-items-test-storage").unwrap();
-		let updates_db = DB::open_default("lc-rdw-updates-test-storage").unwrap();
+		let db = test_db();
 		let prefix = vec![0; 8];
 
 		let x1: u32 = 0;
@@ -329,7 +2999,7 @@ mod test {
 		let x2: u32 = 1;
 		let y2: u32 = 1;
 
-		let weight = 50u32;
+		let weight = 50.;
 
 		let mut key1 = Vec::new();
 		key1.extend_from_slice(&prefix);
@@ -341,16 +3011,710 @@ mod test {
 		key2.extend_from_slice(&x2.to_be_bytes());
 		key2.extend_from_slice(&y2.to_be_bytes());
 
-		let prev_value1 = LinearCombinerService::get_value(&main_db, &key1).unwrap();
-		let prev_value2 = LinearCombinerService::get_value(&main_db, &key2).unwrap();
-		LinearCombinerService::update_value(&main_db, &updates_db, key1.clone(), weight).unwrap();
-		LinearCombinerService::update_value(&main_db, &updates_db, key2.clone(), weight).unwrap();
+		let prev_value1 = LinearCombinerService::get_value(&db, &key1).unwrap();
+		let prev_value2 = LinearCombinerService::get_value(&db, &key2).unwrap();
+		update(&db, key1.clone(), weight, 0).unwrap();
+		update(&db, key2.clone(), weight, 0).unwrap();
 		let new_item1 = LtItem::new(x1, y1, prev_value1 + weight);
 		let new_item2 = LtItem::new(x2, y2, prev_value2 + weight);
 		let new_items = vec![new_item1, new_item2];
 
-		let items = LinearCombinerService::read_window(&main_db, prefix, (x1, y1), (x2, y2));
+		let items =
+			LinearCombinerService::read_window(&db, prefix, (x1, y1), (x2, y2), None, usize::MAX)
+				.unwrap();
 
 		assert_eq!(new_items, items);
 	}
+
+	#[test]
+	fn should_report_healthy_db() {
+		let db = test_db();
+		assert!(LinearCombinerService::is_healthy(&db));
+	}
+
+	#[test]
+	fn should_allocate_distinct_indices_concurrently() {
+		let service = test_service(
+			"lc-concurrent-test-storage", "lc-concurrent-backup-storage", None,
+		);
+		let domain = 21;
+		std::thread::scope(|scope| {
+			for t in 0..4u8 {
+				let service = &service;
+				scope.spawn(move || {
+					let terms: Vec<TermObject> = (0..50u8)
+						.map(|i| TermObject {
+							from: hex::encode([t, i]),
+							to: hex::encode([0xff, t, i]),
+							weight: 1.,
+							domain,
+							form: 0,
+							sequence: 0,
+							source: String::new(),
+						})
+						.collect();
+					for chunk in terms.chunks(10) {
+						service.apply_chunk(chunk, now_millis(), "test").unwrap();
+					}
+				});
+			}
+		});
+
+		let index_cf = service.db.cf_handle(INDEX_CF).unwrap();
+		let prefix = domain.to_be_bytes();
+		let mut indices = HashSet::new();
+		for item in service.db.prefix_iterator_cf(&index_cf, prefix) {
+			let (key, index) = item.unwrap();
+			if !key.starts_with(&prefix) {
+				break;
+			}
+			assert!(indices.insert(index.to_vec()), "index assigned twice");
+		}
+		assert_eq!(indices.len(), 4 * 50 * 2);
+		let checkpoint = LinearCombinerService::read_checkpoint(&service.db, domain).unwrap();
+		assert_eq!(checkpoint as usize, indices.len());
+	}
+
+	#[test]
+	fn should_rebuild_cells_from_journal() {
+		let service = test_service("lc-journal-test-storage", "lc-journal-backup-storage", None);
+		let term = TermObject {
+			from: "90f8bf6a479f320ead074411a4b0e7944ea8c9c2".to_string(),
+			to: "90f8bf6a479f320ead074411a4b0e7944ea8c9c3".to_string(),
+			weight: 2.,
+			domain: 31,
+			form: 0,
+			sequence: 0,
+			source: String::new(),
+		};
+		service.apply_chunk(&[term.clone(), term.clone()], now_millis(), "test").unwrap();
+		service.apply_chunk(&[term], now_millis(), "test").unwrap();
+
+		let mut pending = PendingWrites::new();
+		let x = LinearCombinerService::get_index(
+			&service.db,
+			&mut pending,
+			31,
+			"90f8bf6a479f320ead074411a4b0e7944ea8c9c2".to_string(),
+		)
+		.unwrap();
+		let y = LinearCombinerService::get_index(
+			&service.db,
+			&mut pending,
+			31,
+			"90f8bf6a479f320ead074411a4b0e7944ea8c9c3".to_string(),
+		)
+		.unwrap();
+		let key = [31u32.to_be_bytes(), 0i32.to_be_bytes(), x, y].concat();
+		let value = LinearCombinerService::get_value(&service.db, &key).unwrap();
+
+		// Simulate an aggregation bug that corrupted the cell.
+		let lt_cf = service.db.cf_handle(LT_CF).unwrap();
+		service.db.put_cf(&lt_cf, &key, 0f64.to_be_bytes()).unwrap();
+
+		let entries = service.replay_journal().unwrap();
+		assert!(entries >= 3);
+		assert_eq!(
+			LinearCombinerService::get_value(&service.db, &key).unwrap(),
+			value
+		);
+	}
+
+	#[test]
+	fn should_repair_divergent_cells() {
+		let service = test_service(
+			"lc-consistency-test-storage", "lc-consistency-backup-storage", None,
+		);
+		let from = "90f8bf6a479f320ead074411a4b0e7944ea8c9c2";
+		let to = "90f8bf6a479f320ead074411a4b0e7944ea8c9c3";
+		let term = TermObject {
+			from: from.to_string(),
+			to: to.to_string(),
+			weight: 2.,
+			domain: 101,
+			form: 0,
+			sequence: 0,
+			source: String::new(),
+		};
+		service.apply_chunk(&[term], now_millis(), "test").unwrap();
+		let report = service.check_consistency(false).unwrap();
+		assert_eq!(report.mismatches, 0);
+
+		let mut pending = PendingWrites::new();
+		let x = LinearCombinerService::get_index(&service.db, &mut pending, 101, from.to_string())
+			.unwrap();
+		let y = LinearCombinerService::get_index(&service.db, &mut pending, 101, to.to_string())
+			.unwrap();
+		let key = [101u32.to_be_bytes(), 0i32.to_be_bytes(), x, y].concat();
+		let value = LinearCombinerService::get_value(&service.db, &key).unwrap();
+		// Simulate an aggregation bug that corrupted the cell.
+		let lt_cf = service.db.cf_handle(LT_CF).unwrap();
+		service.db.put_cf(&lt_cf, &key, 0f64.to_be_bytes()).unwrap();
+
+		let report = service.check_consistency(true).unwrap();
+		assert_eq!(report.journal_mismatches.len(), 1);
+		assert_eq!(report.journal_mismatches[0].expected, value);
+		assert_eq!(
+			report.update_mismatches.len(),
+			1,
+			"should compare the pending update too"
+		);
+		assert!(report.repaired);
+		assert_eq!(
+			LinearCombinerService::get_value(&service.db, &key).unwrap(),
+			value
+		);
+		assert_eq!(service.check_consistency(false).unwrap().mismatches, 0);
+	}
+
+	#[test]
+	fn should_share_db_handle_across_calls() {
+		let service = test_service("lc-shared-test-storage", "lc-shared-backup-storage", None);
+		let clone = service.clone();
+		let source = "90f8bf6a479f320ead074411a4b0e7944ea8c9c3".to_string();
+
+		let mut pending = PendingWrites::new();
+		let index = LinearCombinerService::get_index(&service.db, &mut pending, 0, source.clone());
+		LinearCombinerService::commit_writes(&service.db, pending).unwrap();
+
+		let mut pending = PendingWrites::new();
+		let same_index = LinearCombinerService::get_index(&clone.db, &mut pending, 0, source);
+
+		assert_eq!(index.unwrap(), same_index.unwrap());
+	}
+
+	#[test]
+	fn should_read_window_between_timestamps() {
+		let db = test_db();
+		let prefix = vec![0; 8];
+
+		let mut key1 = prefix.clone();
+		key1.extend_from_slice(&0u32.to_be_bytes());
+		key1.extend_from_slice(&1u32.to_be_bytes());
+
+		let mut key2 = prefix.clone();
+		key2.extend_from_slice(&1u32.to_be_bytes());
+		key2.extend_from_slice(&0u32.to_be_bytes());
+
+		let prev_value1 = LinearCombinerService::get_value(&db, &key1).unwrap();
+		let prev_value2 = LinearCombinerService::get_value(&db, &key2).unwrap();
+		update(&db, key1.clone(), 10., 100).unwrap();
+		update(&db, key2.clone(), 10., 200).unwrap();
+		update(&db, key1.clone(), 10., 300).unwrap();
+
+		let read = |since, until| {
+			LinearCombinerService::read_window_between(
+				&db,
+				prefix.clone(),
+				(0, 0),
+				(1, 1),
+				(since, until),
+				None,
+				usize::MAX,
+			)
+			.unwrap()
+		};
+
+		let item1 = |value, timestamp| LtItem::new(0, 1, value).with_timestamp(timestamp);
+		let item2 = LtItem::new(1, 0, prev_value2 + 10.).with_timestamp(200);
+		assert_eq!(read(150, 250), vec![item2.clone()]);
+		assert_eq!(
+			read(50, 150),
+			vec![item1(prev_value1 + 10., 100)],
+			"should return the value as of the range"
+		);
+		assert_eq!(
+			read(0, u64::MAX),
+			vec![item1(prev_value1 + 20., 300), item2]
+		);
+	}
+
+	#[test]
+	fn should_resume_window_from_cursor() {
+		let db = test_db();
+		let prefix = vec![0; 8];
+		for (x, y) in [(0u32, 0u32), (0, 2), (1, 1), (2, 0)] {
+			let mut key = prefix.clone();
+			key.extend_from_slice(&x.to_be_bytes());
+			key.extend_from_slice(&y.to_be_bytes());
+			update(&db, key, 1., 0).unwrap();
+		}
+
+		let read = |after, limit| {
+			let items = LinearCombinerService::read_window(
+				&db,
+				prefix.clone(),
+				(0, 0),
+				(2, 2),
+				after,
+				limit,
+			)
+			.unwrap();
+			items.into_iter().map(|x| x.key_bytes()).collect::<Vec<_>>()
+		};
+
+		let first = read(None, 2);
+		assert_eq!(first.len(), 2);
+
+		let cursor = LinearCombinerService::parse_cursor(first.last().unwrap()).unwrap();
+		let rest = read(cursor, 10);
+		assert_eq!(rest.len(), 2);
+		assert_eq!([first, rest].concat(), read(None, 10));
+	}
+
+	#[test]
+	fn should_bound_counted_matches() {
+		let read = |limit: usize| Ok((0..limit.min(MAX_COUNTED_MATCHES * 2)).collect::<Vec<_>>());
+		let cursor = |item: &usize| item.to_be_bytes().to_vec();
+		let (items, page) = LinearCombinerService::read_page(read, true, 10, cursor).unwrap();
+		assert_eq!(items.len(), 10);
+		assert_eq!(page.total, None, "should not count past the bound");
+		assert!(page.next_cursor.is_some());
+
+		let read = |limit: usize| Ok((0..limit.min(25)).collect::<Vec<_>>());
+		let (_, page) = LinearCombinerService::read_page(read, true, 10, cursor).unwrap();
+		assert_eq!(page.total, Some(25));
+		let (_, page) = LinearCombinerService::read_page(read, false, 10, cursor).unwrap();
+		assert_eq!(page.total, None);
+	}
+
+	#[tokio::test]
+	async fn should_describe_pages_in_headers() {
+		let service = test_service("lc-page-test-storage", "lc-page-backup-storage", None);
+		let mut pending = PendingWrites::new();
+		for source in ["0a", "0b", "0c"] {
+			LinearCombinerService::get_index(&service.db, &mut pending, 41, source.to_string())
+				.unwrap();
+		}
+		LinearCombinerService::commit_writes(&service.db, pending).unwrap();
+
+		let query = |cursor| MappingQuery {
+			pattern: String::new(),
+			mode: MatchMode::Prefix.into(),
+			domain: 41,
+			page: Some(PageRequest { cursor, page_size: 2 }),
+			..MappingQuery::default()
+		};
+		let first = service.get_did_mapping(Request::new(query(Vec::new()))).await.unwrap();
+		let metadata = first.metadata();
+		assert_eq!(metadata.get(TOTAL_HEADER).unwrap(), "3");
+		assert_eq!(metadata.get(RETURNED_HEADER).unwrap(), "2");
+		assert!(metadata.get(WATERMARK_HEADER).is_some());
+		let cursor = metadata.get_bin(NEXT_CURSOR_HEADER).unwrap().to_bytes().unwrap();
+		assert_eq!(cursor.as_ref(), hex::decode("0b").unwrap());
+
+		let last = service.get_did_mapping(Request::new(query(cursor.to_vec()))).await.unwrap();
+		let metadata = last.metadata();
+		assert!(metadata.get(TOTAL_HEADER).is_none());
+		assert_eq!(metadata.get(RETURNED_HEADER).unwrap(), "1");
+		assert!(
+			metadata.get_bin(NEXT_CURSOR_HEADER).is_none(),
+			"should mark the last page"
+		);
+		let mappings: Vec<_> = last.into_inner().collect().await;
+		assert_eq!(mappings.len(), 1);
+
+		let legacy =
+			MappingQuery { cursor: cursor.to_vec(), limit: 2, page: None, ..query(Vec::new()) };
+		let last = service.get_did_mapping(Request::new(legacy)).await.unwrap();
+		assert_eq!(
+			last.metadata().get(RETURNED_HEADER).unwrap(),
+			"1",
+			"should page older clients by their cursor"
+		);
+	}
+
+	#[tokio::test]
+	async fn should_watch_domain_updates() {
+		let mut service = test_service("lc-watch-test-storage", "lc-watch-backup-storage", None);
+		service.watermark_interval = Duration::from_millis(50);
+		let watch = LtWatch { domain: 1, forms: vec![0] };
+		let mut stream = service.watch_lt(Request::new(watch)).await.unwrap().into_inner();
+
+		let first = stream.next().await.unwrap().unwrap();
+		assert!(matches!(first.event, Some(Event::Watermark(watermark)) if watermark.caught_up));
+
+		let item = LtItem::new(0, 1, 50.);
+		service.publish(CellUpdate { domain: 2, form: 0, item: item.clone() });
+		service.publish(CellUpdate { domain: 1, form: 1, item: item.clone() });
+		service.publish(CellUpdate { domain: 1, form: 0, item: item.clone() });
+
+		let event = stream.next().await.unwrap().unwrap();
+		match event.event {
+			Some(Event::Delta(delta)) => {
+				assert_eq!(delta.form, 0);
+				assert_eq!(delta.object, Some(item.into()));
+			},
+			other => panic!("Unexpected event: {:?}", other),
+		}
+		let event = stream.next().await.unwrap().unwrap();
+		assert!(
+			matches!(event.event, Some(Event::Heartbeat(_))),
+			"should send heartbeats while nothing was written"
+		);
+	}
+
+	#[tokio::test]
+	async fn should_stream_mapping_changes() {
+		let mut service = test_service(
+			"lc-mapping-watch-test-storage", "lc-mapping-watch-backup-storage", None,
+		);
+		service.watermark_interval = Duration::from_millis(50);
+		let watch = || Request::new(MappingWatch { domain: 51, from_sequence: 0 });
+		let mut live = service.watch_did_mapping(watch()).await.unwrap().into_inner();
+
+		let now = now_millis();
+		let keys = [
+			"90f8bf6a479f320ead074411a4b0e7944ea8c9c1".to_string(),
+			"90f8bf6a479f320ead074411a4b0e7944ea8c9c2".to_string(),
+		];
+		let term = TermObject {
+			from: keys[0].clone(),
+			to: keys[1].clone(),
+			weight: 1.,
+			domain: 51,
+			form: 0,
+			sequence: 0,
+			source: String::new(),
+		};
+		service.apply_chunk(&[term], now, "test").unwrap();
+
+		let mut caught_up = service.watch_did_mapping(watch()).await.unwrap().into_inner();
+		for stream in [&mut live, &mut caught_up] {
+			for (sequence, key) in (0..).zip(&keys) {
+				let change = next_change(stream).await;
+				assert_eq!(change.sequence, sequence);
+				assert_eq!(&change.mapping.unwrap().key, key);
+			}
+		}
+		let event = caught_up.next().await.unwrap().unwrap().event.unwrap();
+		match event {
+			mapping_watch_event::Event::Watermark(watermark) => {
+				assert!(watermark.timestamp >= now);
+				assert!(watermark.caught_up);
+				assert_eq!(watermark.next_position, 2);
+			},
+			event => panic!("should mark the end of the catch up, got {:?}", event),
+		}
+		match caught_up.next().await.unwrap().unwrap().event.unwrap() {
+			mapping_watch_event::Event::Heartbeat(heartbeat) => {
+				assert_eq!(heartbeat.next_position, 2)
+			},
+			event => panic!(
+				"should send heartbeats while nothing was assigned, got {:?}",
+				event
+			),
+		}
+	}
+
+	/// Skips the watermarks of a mapping watch up to its next change.
+	async fn next_change(
+		stream: &mut (impl Stream<Item = Result<MappingWatchEvent, Status>> + Unpin),
+	) -> MappingChange {
+		loop {
+			let event = stream.next().await.unwrap().unwrap().event.unwrap();
+			if let mapping_watch_event::Event::Change(change) = event {
+				return change;
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn should_end_history_pages_with_watermark() {
+		let service = test_service(
+			"lc-history-watermark-test-storage", "lc-history-watermark-backup-storage", None,
+		);
+		let term = TermObject {
+			from: "90f8bf6a479f320ead074411a4b0e7944ea8c9c1".to_string(),
+			to: "90f8bf6a479f320ead074411a4b0e7944ea8c9c2".to_string(),
+			weight: 1.,
+			domain: 71,
+			form: 0,
+			sequence: 0,
+			source: String::new(),
+		};
+		let now = now_millis();
+		service.apply_chunk(&[term], now, "test").unwrap();
+
+		let batch = LtHistoryBatch { domain: 71, x1: 1, y1: 1, ..LtHistoryBatch::default() };
+		let events: Vec<_> = service
+			.get_historic_data(Request::new(batch))
+			.await
+			.unwrap()
+			.into_inner()
+			.map(|event| event.unwrap().event.unwrap())
+			.collect()
+			.await;
+		let [object, watermark, page] = &events[..] else {
+			panic!("should send the object, a watermark and the page, got {:?}", events);
+		};
+		assert!(matches!(object, lt_history_event::Event::Object(_)));
+		match watermark {
+			lt_history_event::Event::Watermark(watermark) => {
+				assert_eq!(watermark.timestamp, now);
+				assert!(watermark.caught_up, "should be caught up on the last page");
+			},
+			event => panic!("should follow with a watermark, got {:?}", event),
+		}
+		match page {
+			lt_history_event::Event::Page(page) => {
+				assert_eq!((page.total, page.has_more), (1, false))
+			},
+			event => panic!("should end with the page, got {:?}", event),
+		}
+
+		let batch = LtHistoryBatch { domain: 71, x1: 1, y0: 2, y1: 1, ..LtHistoryBatch::default() };
+		let status = service.get_historic_data(Request::new(batch)).await.unwrap_err();
+		assert_eq!(status.code(), Code::InvalidArgument);
+		assert_eq!(
+			ErrorDetail::of(&status).unwrap().field,
+			"y0",
+			"should name the field out of order"
+		);
+	}
+
+	#[test]
+	fn should_count_domain_stats() {
+		let service = test_service("lc-stats-test-storage", "lc-stats-backup-storage", None);
+		let term = |to: &str, weight, form| TermObject {
+			from: "90f8bf6a479f320ead074411a4b0e7944ea8c9c1".to_string(),
+			to: to.to_string(),
+			weight,
+			domain: 61,
+			form,
+			sequence: 0,
+			source: String::new(),
+		};
+		let terms = [
+			term("90f8bf6a479f320ead074411a4b0e7944ea8c9c2", 1., 0),
+			term("90f8bf6a479f320ead074411a4b0e7944ea8c9c3", 0., 0),
+			term("90f8bf6a479f320ead074411a4b0e7944ea8c9c3", 2., 1),
+		];
+		let now = now_millis();
+		service.apply_chunk(&terms[..2], now, "test").unwrap();
+		service.apply_chunk(&terms[2..], now + 1, "test").unwrap();
+
+		let stats = LinearCombinerService::read_stats(&service.db, 61).unwrap();
+		assert_eq!(stats.dimension, 3);
+		assert_eq!(stats.did_count, 3);
+		assert_eq!(stats.non_zero_cells, 2);
+		assert_eq!(stats.pending_updates, 3);
+		assert_eq!(stats.last_write, now + 1);
+	}
+
+	#[test]
+	fn should_roll_back_cells_after_timestamp() {
+		let service = test_service(
+			"lc-rollback-test-storage", "lc-rollback-backup-storage", None,
+		);
+		let term = |to: &str, weight| TermObject {
+			from: "90f8bf6a479f320ead074411a4b0e7944ea8c9c1".to_string(),
+			to: to.to_string(),
+			weight,
+			domain: 81,
+			form: 0,
+			sequence: 0,
+			source: String::new(),
+		};
+		let kept = term("90f8bf6a479f320ead074411a4b0e7944ea8c9c2", 1.);
+		let added = TermObject {
+			sequence: 1,
+			source: "rollback".to_string(),
+			..term("90f8bf6a479f320ead074411a4b0e7944ea8c9c3", 3.)
+		};
+		let late = term("90f8bf6a479f320ead074411a4b0e7944ea8c9c4", 2.);
+		let now = now_millis();
+		service.apply_chunk(&[kept.clone()], now, "test").unwrap();
+		service.apply_chunk(&[kept, added.clone()], now + 10, "test").unwrap();
+		// Journaled after the chunk above, though stamped before it.
+		service.apply_chunk(&[late], now + 2, "test").unwrap();
+
+		let (cells, entries) = service.rollback(now + 5).unwrap();
+		assert_eq!(
+			(cells, entries),
+			(2, 2),
+			"should drop the entries after the timestamp wherever they are journaled"
+		);
+
+		let cell = |y: u32| {
+			[81u32.to_be_bytes(), 0i32.to_be_bytes(), 0u32.to_be_bytes(), y.to_be_bytes()].concat()
+		};
+		assert_eq!(
+			LinearCombinerService::get_value(&service.db, &cell(1)).unwrap(),
+			1.
+		);
+		assert_eq!(
+			LinearCombinerService::get_value(&service.db, &cell(2)).unwrap(),
+			0.
+		);
+		let history = LinearCombinerService::read_window_between(
+			&service.db,
+			[81u32.to_be_bytes(), 0i32.to_be_bytes()].concat(),
+			(0, 0),
+			(2, 2),
+			(now + 1, u64::MAX),
+			None,
+			10,
+		)
+		.unwrap();
+		assert!(
+			history.is_empty(),
+			"should drop the history after the timestamp"
+		);
+		assert_eq!(
+			LinearCombinerService::get_value(&service.db, &cell(3)).unwrap(),
+			2.
+		);
+
+		let applied = service.apply_chunk(&[added], now + 20, "test").unwrap();
+		assert_eq!(applied, 1, "should take the terms rolled back again");
+	}
+
+	#[tokio::test]
+	async fn should_remove_peer() {
+		let service = test_service("lc-remove-test-storage", "lc-remove-backup-storage", None);
+		let peers = [
+			"90f8bf6a479f320ead074411a4b0e7944ea8c9c1",
+			"90f8bf6a479f320ead074411a4b0e7944ea8c9c2",
+			"90f8bf6a479f320ead074411a4b0e7944ea8c9c3",
+		];
+		let term = |from: usize, to: usize| TermObject {
+			from: peers[from].to_string(),
+			to: peers[to].to_string(),
+			weight: 1.,
+			domain: 91,
+			form: 0,
+			sequence: 0,
+			source: String::new(),
+		};
+		service.apply_chunk(&[term(0, 1), term(1, 2), term(0, 2)], now_millis(), "test").unwrap();
+
+		let removed = hex::decode(peers[1]).unwrap();
+		let info = service.remove_peer(91, &removed).unwrap().unwrap();
+		assert_eq!((info.index, info.cells, info.entries), (1, 2, 2));
+		assert!(
+			service.remove_peer(91, &removed).unwrap().is_none(),
+			"should forget the key"
+		);
+
+		service.replay_journal().unwrap();
+		let cell = |x: u32, y: u32| {
+			[91u32.to_be_bytes(), 0i32.to_be_bytes(), x.to_be_bytes(), y.to_be_bytes()].concat()
+		};
+		for (x, y) in [(0, 1), (1, 2)] {
+			assert_eq!(
+				LinearCombinerService::get_value(&service.db, &cell(x, y)).unwrap(),
+				0.,
+				"should keep the removal across replays"
+			);
+		}
+		assert!(LinearCombinerService::get_value(&service.db, &cell(0, 2)).unwrap() > 0.);
+		let stats = LinearCombinerService::read_stats(&service.db, 91).unwrap();
+		assert_eq!(stats.did_count, 2);
+
+		let batch = LtHistoryBatch { domain: 91, x1: 2, y1: 2, ..LtHistoryBatch::default() };
+		let first = service
+			.get_historic_data(Request::new(batch))
+			.await
+			.unwrap()
+			.into_inner()
+			.next()
+			.await
+			.unwrap()
+			.unwrap();
+		match first.event.unwrap() {
+			lt_history_event::Event::Tombstone(tombstone) => assert_eq!(tombstone.index, 1),
+			event => panic!("should start with the tombstone, got {:?}", event),
+		}
+	}
+
+	#[tokio::test]
+	async fn should_end_watches_on_shutdown() {
+		let service = test_service(
+			"lc-shutdown-test-storage", "lc-shutdown-backup-storage", None,
+		);
+		let watch = LtWatch { domain: 1, forms: vec![] };
+		let mut stream = service.watch_lt(Request::new(watch)).await.unwrap().into_inner();
+
+		service.begin_shutdown();
+		loop {
+			match stream.next().await.unwrap() {
+				Ok(_) => continue,
+				Err(status) => {
+					assert_eq!(status.code(), Code::Unavailable);
+					assert!(is_retryable(&status), "should tell clients to come back");
+					break;
+				},
+			}
+		}
+		assert!(stream.next().await.is_none());
+		service.close().unwrap();
+	}
+
+	#[tokio::test]
+	async fn should_report_service_info() {
+		let service = test_service("lc-info-test-storage", "lc-info-backup-storage", None);
+		let info = service.get_service_info(Request::new(Void {})).await.unwrap().into_inner();
+		info.check(&["source_checkpoints", "sequence_dedup"]).unwrap();
+		assert!(info.check(&["teleportation"]).is_err());
+		assert_eq!(info.limit("max_history_batch_size"), Some(1000));
+		assert_eq!(info.limit("max_term_batch_size"), Some(1000));
+		assert_eq!(
+			info.limit("max_stream_terms"),
+			None,
+			"should leave out limits not enforced"
+		);
+		service.close().unwrap();
+	}
+
+	#[test]
+	fn should_apply_term_batches_whole() {
+		let service = test_service("lc-batch-test-storage", "lc-batch-backup-storage", None);
+		let term = |to: &str, weight: f64, sequence| TermObject {
+			from: "90f8bf6a479f320ead074411a4b0e7944ea8c9c2".to_string(),
+			to: to.to_string(),
+			weight,
+			domain: 7,
+			form: 0,
+			sequence,
+			source: "batches".to_string(),
+		};
+		let batch = TermObjectBatch {
+			terms: vec![
+				term("90f8bf6a479f320ead074411a4b0e7944ea8c9c3", 1., 1),
+				term("90f8bf6a479f320ead074411a4b0e7944ea8c9c4", 1., 2),
+			],
+			sequence: 5,
+		};
+		let ack = service.apply_batch(&batch, now_millis(), "test").unwrap();
+		assert_eq!((ack.sequence, ack.applied), (5, 2));
+		let ack = service.apply_batch(&batch, now_millis(), "test").unwrap();
+		assert_eq!(ack.applied, 0, "should skip applied sequences");
+
+		let batch = TermObjectBatch {
+			terms: vec![
+				term("90f8bf6a479f320ead074411a4b0e7944ea8c9c5", 1., 3),
+				term("90f8bf6a479f320ead074411a4b0e7944ea8c9c6", f64::NAN, 4),
+			],
+			sequence: 6,
+		};
+		let status = service.apply_batch(&batch, now_millis(), "test").unwrap_err();
+		assert_eq!(status.code(), Code::InvalidArgument);
+		assert_eq!(
+			LinearCombinerService::read_source_checkpoint(&service.db, "batches").unwrap(),
+			2,
+			"should apply none of a rejected batch"
+		);
+
+		let terms = vec![term("90f8bf6a479f320ead074411a4b0e7944ea8c9c5", 1., 0); 1001];
+		let status = service
+			.apply_batch(
+				&TermObjectBatch { terms, sequence: 7 },
+				now_millis(),
+				"test",
+			)
+			.unwrap_err();
+		assert!(!is_retryable(&status));
+		service.close().unwrap();
+	}
 }
@@ -0,0 +1,143 @@
+use proto_buf::combiner::MatchMode;
+
+use crate::error::LcError;
+
+/// Key pattern of a mapping query, matched against the hex encoding of the stored keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPattern {
+	hex: String,
+	mode: MatchMode,
+	/// Whether the pattern was derived from whole bytes, so matches must start on a byte.
+	byte_aligned: bool,
+}
+
+impl KeyPattern {
+	pub fn parse(query: &str, mode: MatchMode) -> Result<Self, LcError> {
+		let web_id: String;
+		let address = match query.strip_prefix("did:pkh:") {
+			Some(rest) => {
+				let parts: Vec<&str> = rest.split(':').collect();
+				match parts.as_slice() {
+					[hash] => Address::Hex(hash),
+					["eip155", _, address] => {
+						Address::Hex(address.strip_prefix("0x").ok_or(LcError::ParseError)?)
+					},
+					[_, _, address] => Address::Text(address),
+					_ => return Err(LcError::ParseError),
+				}
+			},
+			// Other methods are keyed by their method followed by their method-specific id.
+			None => match query.strip_prefix("did:") {
+				Some(rest) => match rest.split_once(':').ok_or(LcError::ParseError)? {
+					("web", id) => {
+						// Only the domain of a did:web is case-insensitive.
+						web_id = match id.split_once(':') {
+							Some((domain, path)) => {
+								format!("web:{}:{}", domain.to_lowercase(), path)
+							},
+							None => format!("web:{}", id.to_lowercase()),
+						};
+						Address::Text(&web_id)
+					},
+					_ => Address::Text(rest),
+				},
+				None => match query.strip_prefix("0x") {
+					Some(hex) => Address::Hex(hex),
+					None if is_hex(query) => Address::Hex(query),
+					None => Address::Text(query),
+				},
+			},
+		};
+
+		let (hex, byte_aligned) = match address {
+			Address::Hex(hex) if is_hex(hex) => (hex.to_lowercase(), false),
+			Address::Hex(_) => return Err(LcError::ParseError),
+			Address::Text(text) => (hex::encode(text), true),
+		};
+
+		Ok(Self { hex, mode, byte_aligned })
+	}
+
+	/// Bytes every key matching this pattern starts with, empty when there is no such prefix.
+	pub fn key_prefix(&self) -> Vec<u8> {
+		match self.mode {
+			MatchMode::Prefix => {
+				let whole_bytes = self.hex.len() - self.hex.len() % 2;
+				hex::decode(&self.hex[..whole_bytes]).unwrap_or_default()
+			},
+			MatchMode::Substring => Vec::new(),
+		}
+	}
+
+	pub fn matches(&self, key: &[u8]) -> bool {
+		let key_hex = hex::encode(key);
+		match self.mode {
+			MatchMode::Prefix => key_hex.starts_with(&self.hex),
+			MatchMode::Substring if self.byte_aligned => {
+				key_hex.match_indices(&self.hex).any(|(i, _)| i % 2 == 0)
+			},
+			MatchMode::Substring => key_hex.contains(&self.hex),
+		}
+	}
+}
+
+enum Address<'a> {
+	Hex(&'a str),
+	Text(&'a str),
+}
+
+fn is_hex(value: &str) -> bool {
+	value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod test {
+	use crate::mapping::KeyPattern;
+	use proto_buf::combiner::MatchMode;
+
+	#[test]
+	fn should_match_did_prefix() {
+		let key = hex::decode("90f8bf6a479f320ead074411a4b0e7944ea8c9c2").unwrap();
+		let pattern = KeyPattern::parse("did:pkh:eip155:1:0x90F8b", MatchMode::Prefix).unwrap();
+		assert_eq!(pattern.key_prefix(), vec![0x90, 0xf8]);
+		assert!(pattern.matches(&key));
+
+		let pattern = KeyPattern::parse("0xf8bf", MatchMode::Prefix).unwrap();
+		assert!(!pattern.matches(&key));
+	}
+
+	#[test]
+	fn should_match_address_substring() {
+		let key = b"cosmos1t2uflqwqe0fsj0shcfkrvpukewcw40yjj6hdc0".to_vec();
+		let pattern = KeyPattern::parse("fsj0sh", MatchMode::Substring).unwrap();
+		assert!(pattern.matches(&key));
+
+		let did = "did:pkh:cosmos:cosmoshub-3:cosmos1t2uf";
+		let pattern = KeyPattern::parse(did, MatchMode::Prefix).unwrap();
+		assert!(pattern.matches(&key));
+
+		let key = hex::decode("90f8bf6a479f320ead074411a4b0e7944ea8c9c2").unwrap();
+		let pattern = KeyPattern::parse("0f8b", MatchMode::Substring).unwrap();
+		assert!(pattern.matches(&key));
+	}
+
+	#[test]
+	fn should_match_other_did_methods() {
+		let key = b"web:example.com:user:alice".to_vec();
+		let pattern = KeyPattern::parse("did:web:Example.com:user", MatchMode::Prefix).unwrap();
+		assert!(pattern.matches(&key));
+
+		let key = b"key:z6MkhaXgBZDvotDkL5257faiztiGiC2QtKLGpbnnEGta2doK".to_vec();
+		let pattern = KeyPattern::parse("did:key:z6Mkha", MatchMode::Prefix).unwrap();
+		assert!(pattern.matches(&key));
+		let pattern = KeyPattern::parse("did:web:z6Mkha", MatchMode::Prefix).unwrap();
+		assert!(!pattern.matches(&key), "should tell methods apart");
+	}
+
+	#[test]
+	fn should_reject_malformed_patterns() {
+		assert!(KeyPattern::parse("did:pkh:eip155:1:90f8", MatchMode::Prefix).is_err());
+		assert!(KeyPattern::parse("0xzz", MatchMode::Prefix).is_err());
+		assert!(KeyPattern::parse("did:web", MatchMode::Prefix).is_err());
+	}
+}
@@ -0,0 +1,211 @@
+use crate::status::{self, Activity};
+use hyper::{
+	header::CONTENT_TYPE,
+	service::{make_service_fn, service_fn},
+	Body, Response, StatusCode,
+};
+use linear_combiner::COLUMN_FAMILIES;
+use prometheus::{
+	Encoder, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts,
+	Registry, TextEncoder,
+};
+use rocksdb::DB;
+use std::{
+	convert::Infallible,
+	future::Future,
+	net::SocketAddr,
+	pin::Pin,
+	sync::Arc,
+	task::{Context, Poll},
+};
+use tower::{Layer, Service};
+
+/// Metrics of the combiner, exported in the Prometheus text format.
+pub struct Metrics {
+	registry: Registry,
+	pub terms_ingested: IntCounterVec,
+	pub cells_written: IntCounter,
+	pub stream_duration: HistogramVec,
+	pub db_write_latency: Histogram,
+	db_size: IntGaugeVec,
+	rpc_errors: IntCounterVec,
+}
+
+impl Metrics {
+	pub fn new() -> Self {
+		let terms_ingested = IntCounterVec::new(
+			Opts::new(
+				"lc_terms_ingested_total", "Terms applied from transformer streams",
+			),
+			&["source"],
+		)
+		.unwrap();
+		let cells_written = IntCounter::new(
+			"lc_cells_written_total",
+			"Distinct cells written per committed batch",
+		)
+		.unwrap();
+		let stream_duration = HistogramVec::new(
+			HistogramOpts::new("lc_stream_duration_seconds", "Duration of inbound streams")
+				.buckets(vec![0.01, 0.1, 1.0, 10.0, 60.0, 300.0, 1800.0]),
+			&["method"],
+		)
+		.unwrap();
+		let db_write_latency = Histogram::with_opts(HistogramOpts::new(
+			"lc_db_write_seconds", "Latency of atomic write batches",
+		))
+		.unwrap();
+		let db_size = IntGaugeVec::new(
+			Opts::new(
+				"lc_db_size_bytes",
+				"Size of the SST files of each column family",
+			),
+			&["cf"],
+		)
+		.unwrap();
+		let rpc_errors = IntCounterVec::new(
+			Opts::new(
+				"lc_rpc_errors_total",
+				"RPCs that completed with a non-OK status",
+			),
+			&["method", "code"],
+		)
+		.unwrap();
+
+		let registry = Registry::new();
+		registry.register(Box::new(terms_ingested.clone())).unwrap();
+		registry.register(Box::new(cells_written.clone())).unwrap();
+		registry.register(Box::new(stream_duration.clone())).unwrap();
+		registry.register(Box::new(db_write_latency.clone())).unwrap();
+		registry.register(Box::new(db_size.clone())).unwrap();
+		registry.register(Box::new(rpc_errors.clone())).unwrap();
+
+		Self {
+			registry,
+			terms_ingested,
+			cells_written,
+			stream_duration,
+			db_write_latency,
+			db_size,
+			rpc_errors,
+		}
+	}
+
+	/// Refreshes the column family sizes and encodes every metric.
+	fn render(&self, db: &DB) -> Vec<u8> {
+		for name in COLUMN_FAMILIES {
+			let size = db
+				.cf_handle(name)
+				.and_then(|cf| db.property_int_value_cf(&cf, "rocksdb.total-sst-files-size").ok())
+				.flatten();
+			if let Some(size) = size {
+				self.db_size.with_label_values(&[name]).set(size as i64);
+			}
+		}
+		let mut buffer = Vec::new();
+		// Encoding into a `Vec` cannot fail.
+		let _ = TextEncoder::new().encode(&self.registry.gather(), &mut buffer);
+		buffer
+	}
+}
+
+/// Serves `/metrics` and the status page at `/` on `addr` until the server fails.
+pub async fn serve(
+	addr: SocketAddr, metrics: Arc<Metrics>, activity: Arc<Activity>, db: Arc<DB>,
+) -> hyper::Result<()> {
+	let make_service = make_service_fn(move |_| {
+		let metrics = metrics.clone();
+		let activity = activity.clone();
+		let db = db.clone();
+		async move {
+			Ok::<_, Infallible>(service_fn(move |request: hyper::Request<Body>| {
+				let metrics = metrics.clone();
+				let activity = activity.clone();
+				let db = db.clone();
+				async move {
+					match request.uri().path() {
+						"/metrics" => Response::builder()
+							.header(CONTENT_TYPE, TextEncoder::new().format_type())
+							.body(Body::from(metrics.render(&db))),
+						"/" => status::page(db, activity).await,
+						_ => Response::builder().status(StatusCode::NOT_FOUND).body(Body::empty()),
+					}
+				}
+			}))
+		}
+	});
+	hyper::Server::bind(&addr).serve(make_service).await
+}
+
+/// Counts the gRPC status every call of the wrapped service completes with.
+#[derive(Clone)]
+pub struct RpcMetricsLayer {
+	metrics: Arc<Metrics>,
+}
+
+impl RpcMetricsLayer {
+	pub fn new(metrics: Arc<Metrics>) -> Self {
+		Self { metrics }
+	}
+}
+
+impl<S> Layer<S> for RpcMetricsLayer {
+	type Service = RpcMetrics<S>;
+
+	fn layer(&self, inner: S) -> Self::Service {
+		RpcMetrics { inner, metrics: self.metrics.clone() }
+	}
+}
+
+#[derive(Clone)]
+pub struct RpcMetrics<S> {
+	inner: S,
+	metrics: Arc<Metrics>,
+}
+
+impl<S, B, R> Service<hyper::Request<B>> for RpcMetrics<S>
+where
+	S: Service<hyper::Request<B>, Response = Response<R>>,
+	S::Future: Send + 'static,
+{
+	type Response = S::Response;
+	type Error = S::Error;
+	type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+	fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+		self.inner.poll_ready(cx)
+	}
+
+	fn call(&mut self, request: hyper::Request<B>) -> Self::Future {
+		let method = request.uri().path().rsplit('/').next().unwrap_or_default().to_string();
+		let metrics = self.metrics.clone();
+		let response = self.inner.call(request);
+		Box::pin(async move {
+			let response = response.await?;
+			// Failed calls answer with the status in the headers, without a body.
+			let code = response.headers().get("grpc-status").and_then(|v| v.to_str().ok());
+			if let Some(code) = code.filter(|code| *code != "0") {
+				metrics.rpc_errors.with_label_values(&[&method, code]).inc();
+			}
+			Ok(response)
+		})
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::Metrics;
+	use rocksdb::{Env, Options, DB};
+
+	#[test]
+	fn should_render_metrics() {
+		let mut opts = Options::default();
+		opts.create_if_missing(true);
+		opts.set_env(&Env::mem_env().unwrap());
+		let db = DB::open(&opts, "lc-metrics-test-storage").unwrap();
+		let metrics = Metrics::new();
+		metrics.terms_ingested.with_label_values(&["pipeline"]).inc_by(3);
+		let text = String::from_utf8(metrics.render(&db)).unwrap();
+		assert!(text.contains("lc_terms_ingested_total{source=\"pipeline\"} 3"));
+	}
+}
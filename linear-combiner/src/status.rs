@@ -0,0 +1,201 @@
+use crate::LinearCombinerService;
+use hyper::{header::CONTENT_TYPE, http, Body, Response, StatusCode};
+use proto_buf::{combiner::LtStats, transformer::TermObject};
+use rocksdb::DB;
+use std::{
+	collections::VecDeque,
+	fmt::Write,
+	sync::{Arc, Mutex, PoisonError},
+};
+
+/// Terms listed on the status page, the most recent first.
+const RECENT_TERMS: usize = 50;
+/// Milliseconds the ingestion rate is averaged over.
+const RATE_WINDOW: u64 = 60_000;
+/// Seconds between reloads of the status page.
+const REFRESH_INTERVAL: u32 = 10;
+
+/// A term applied to the matrix, as shown on the status page.
+#[derive(Debug, Clone)]
+struct TermEvent {
+	timestamp: u64,
+	term: TermObject,
+}
+
+#[derive(Default)]
+struct ActivityLog {
+	recent: VecDeque<TermEvent>,
+	/// Time and number of terms of every batch committed within the rate window.
+	batches: VecDeque<(u64, u64)>,
+}
+
+/// Recent ingestion, kept in memory for the status page.
+#[derive(Default)]
+pub struct Activity {
+	log: Mutex<ActivityLog>,
+}
+
+impl Activity {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Records the terms of a batch committed at `timestamp`.
+	pub fn record<'a>(&self, terms: impl IntoIterator<Item = &'a TermObject>, timestamp: u64) {
+		let mut log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+		let mut count = 0;
+		for term in terms {
+			count += 1;
+			log.recent.push_front(TermEvent { timestamp, term: term.clone() });
+		}
+		log.recent.truncate(RECENT_TERMS);
+		log.batches.push_back((timestamp, count));
+		let start = timestamp.saturating_sub(RATE_WINDOW);
+		while log.batches.front().map_or(false, |(time, _)| *time < start) {
+			log.batches.pop_front();
+		}
+	}
+
+	/// Terms per second applied over the rate window ending at `now`.
+	fn rate(&self, now: u64) -> f64 {
+		let log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+		let start = now.saturating_sub(RATE_WINDOW);
+		let terms: u64 =
+			log.batches.iter().filter(|(time, _)| *time >= start).map(|(_, count)| count).sum();
+		terms as f64 * 1000. / RATE_WINDOW as f64
+	}
+
+	fn recent(&self) -> Vec<TermEvent> {
+		let log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+		log.recent.iter().cloned().collect()
+	}
+}
+
+/// Answers with the status page, reading the domain statistics off the async runtime.
+pub async fn page(db: Arc<DB>, activity: Arc<Activity>) -> Result<Response<Body>, http::Error> {
+	let stats = tokio::task::spawn_blocking(move || LinearCombinerService::read_domain_stats(&db));
+	match stats.await {
+		Ok(Ok(stats)) => Response::builder()
+			.header(CONTENT_TYPE, "text/html; charset=utf-8")
+			.body(Body::from(render(&stats, &activity, crate::now_millis()))),
+		_ => Response::builder().status(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty()),
+	}
+}
+
+fn render(stats: &[(u32, LtStats)], activity: &Activity, now: u64) -> String {
+	let mut html = String::new();
+	// Writing into a `String` cannot fail.
+	let _ = write!(
+		html,
+		"<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+		<meta http-equiv=\"refresh\" content=\"{}\"><title>Linear combiner</title></head><body>\
+		<h1>Linear combiner</h1><p>Ingesting {:.2} terms/s over the last {} s.</p>",
+		REFRESH_INTERVAL,
+		activity.rate(now),
+		RATE_WINDOW / 1000,
+	);
+
+	html.push_str(
+		"<h2>Domains</h2><table><tr><th>Domain</th><th>DIDs</th><th>Non-zero cells</th>\
+		<th>Density</th><th>Pending updates</th><th>Last write</th></tr>",
+	);
+	for (domain, stats) in stats {
+		let cells = stats.dimension as f64 * stats.dimension as f64;
+		let density = if cells > 0. { stats.non_zero_cells as f64 / cells } else { 0. };
+		let _ = write!(
+			html,
+			"<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.4}%</td><td>{}</td><td>{}</td></tr>",
+			domain,
+			stats.did_count,
+			stats.non_zero_cells,
+			density * 100.,
+			stats.pending_updates,
+			age(now, stats.last_write),
+		);
+	}
+	html.push_str("</table>");
+
+	html.push_str(
+		"<h2>Recent terms</h2><table><tr><th>Received</th><th>Source</th><th>Domain</th>\
+		<th>Form</th><th>From</th><th>To</th><th>Weight</th></tr>",
+	);
+	for TermEvent { timestamp, term } in activity.recent() {
+		let _ = write!(
+			html,
+			"<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+			age(now, timestamp),
+			escape(&term.source),
+			term.domain,
+			term.form,
+			escape(&term.from),
+			escape(&term.to),
+			term.weight,
+		);
+	}
+	html.push_str("</table></body></html>");
+	html
+}
+
+/// Describes how long before `now` the millisecond `timestamp` was.
+fn age(now: u64, timestamp: u64) -> String {
+	if timestamp == 0 {
+		return "never".to_string();
+	}
+	format!("{} s ago", now.saturating_sub(timestamp) / 1000)
+}
+
+/// Escapes text received from transformers before it is embedded in the page.
+fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&#39;"),
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+#[cfg(test)]
+mod test {
+	use super::{render, Activity, RECENT_TERMS};
+	use proto_buf::{combiner::LtStats, transformer::TermObject};
+
+	#[test]
+	fn should_render_status_page() {
+		let activity = Activity::new();
+		let term = |to: &str| TermObject {
+			from: "<script>".to_string(),
+			to: to.to_string(),
+			weight: 1.,
+			source: "pipeline".to_string(),
+			..TermObject::default()
+		};
+		let terms: Vec<_> = (0..RECENT_TERMS + 10).map(|i| term(&format!("peer-{}", i))).collect();
+		activity.record(&terms, 1_000);
+		activity.record(&terms[..0], 100_000);
+
+		let stats =
+			LtStats { dimension: 10, non_zero_cells: 5, did_count: 10, ..LtStats::default() };
+		let html = render(&[(1, stats)], &activity, 100_000);
+		assert!(
+			html.contains("<td>5.0000%</td>"),
+			"should show the matrix density"
+		);
+		assert!(
+			html.contains("Ingesting 0.00 terms/s"),
+			"should only rate the last minute"
+		);
+		assert!(!html.contains("<script>"), "should escape term fields");
+		assert_eq!(
+			html.matches("&lt;script&gt;").count(),
+			RECENT_TERMS,
+			"should list the most recent terms"
+		);
+		assert!(html.contains("peer-59") && !html.contains("peer-9<"));
+	}
+}
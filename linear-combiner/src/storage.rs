@@ -0,0 +1,71 @@
+use crate::config::{StorageBackend, StorageConfig};
+use linear_combiner::error::LcError;
+use rocksdb::{
+	backup::{BackupEngine, BackupEngineOptions},
+	ColumnFamilyDescriptor, Env, Options, DB,
+};
+use std::sync::Arc;
+
+/// Where the database and its backups are kept.
+pub trait Storage: Send + Sync {
+	/// Opens the database at `path` with the column families `cfs`.
+	fn open_db(
+		&self, path: &str, opts: Options, cfs: Vec<ColumnFamilyDescriptor>,
+	) -> Result<DB, LcError>;
+
+	/// Opens the engine keeping backups in `backup_dir`.
+	fn open_backup_engine(&self, backup_dir: &str) -> Result<BackupEngine, LcError>;
+}
+
+/// Keeps the database and its backups in directories on local disk.
+#[derive(Debug, Default)]
+pub struct DiskStorage;
+
+impl Storage for DiskStorage {
+	fn open_db(
+		&self, path: &str, opts: Options, cfs: Vec<ColumnFamilyDescriptor>,
+	) -> Result<DB, LcError> {
+		DB::open_cf_descriptors(&opts, path, cfs).map_err(LcError::DbError)
+	}
+
+	fn open_backup_engine(&self, backup_dir: &str) -> Result<BackupEngine, LcError> {
+		let opts = BackupEngineOptions::new(backup_dir).map_err(LcError::DbError)?;
+		let env = Env::new().map_err(LcError::DbError)?;
+		BackupEngine::open(&opts, &env).map_err(LcError::DbError)
+	}
+}
+
+/// Keeps the database and its backups in memory, losing them once dropped. Paths only tell
+/// databases apart, so tests sharing none see none of each other's writes and leave nothing
+/// behind on disk.
+pub struct MemoryStorage {
+	env: Env,
+}
+
+impl MemoryStorage {
+	pub fn new() -> Result<Self, LcError> {
+		Ok(Self { env: Env::mem_env().map_err(LcError::DbError)? })
+	}
+}
+
+impl Storage for MemoryStorage {
+	fn open_db(
+		&self, path: &str, mut opts: Options, cfs: Vec<ColumnFamilyDescriptor>,
+	) -> Result<DB, LcError> {
+		opts.set_env(&self.env);
+		DB::open_cf_descriptors(&opts, path, cfs).map_err(LcError::DbError)
+	}
+
+	fn open_backup_engine(&self, backup_dir: &str) -> Result<BackupEngine, LcError> {
+		let opts = BackupEngineOptions::new(backup_dir).map_err(LcError::DbError)?;
+		BackupEngine::open(&opts, &self.env).map_err(LcError::DbError)
+	}
+}
+
+/// Opens the configured backend.
+pub fn open(config: &StorageConfig) -> Result<Arc<dyn Storage>, LcError> {
+	match config.backend {
+		StorageBackend::Disk => Ok(Arc::new(DiskStorage)),
+		StorageBackend::Memory => Ok(Arc::new(MemoryStorage::new()?)),
+	}
+}
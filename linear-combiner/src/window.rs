@@ -0,0 +1,80 @@
+use crate::error::LcError;
+
+/// Number of sequence numbers below the high-water mark that are tracked individually.
+const WINDOW_SIZE: u64 = 64;
+
+/// Sequence numbers already applied for one source: everything up to `high - WINDOW_SIZE`
+/// plus the ones marked in `seen`, where bit `n` stands for `high - n`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayWindow {
+	high: u64,
+	seen: u64,
+}
+
+impl ReplayWindow {
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, LcError> {
+		let bytes: [u8; 16] = bytes.try_into().map_err(|_| LcError::ParseError)?;
+		let high = u64::from_be_bytes(bytes[..8].try_into().unwrap());
+		let seen = u64::from_be_bytes(bytes[8..].try_into().unwrap());
+		Ok(Self { high, seen })
+	}
+
+	pub fn to_bytes(self) -> [u8; 16] {
+		let mut bytes = [0; 16];
+		bytes[..8].copy_from_slice(&self.high.to_be_bytes());
+		bytes[8..].copy_from_slice(&self.seen.to_be_bytes());
+		bytes
+	}
+
+	/// Marks `sequence` as applied, returning `false` if it already was.
+	pub fn insert(&mut self, sequence: u64) -> bool {
+		if sequence > self.high {
+			let shift = sequence - self.high;
+			self.seen = if shift >= WINDOW_SIZE { 0 } else { self.seen << shift };
+			self.seen |= 1;
+			self.high = sequence;
+			return true;
+		}
+
+		let age = self.high - sequence;
+		if age >= WINDOW_SIZE || self.seen & (1 << age) != 0 {
+			return false;
+		}
+		self.seen |= 1 << age;
+		true
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use crate::window::ReplayWindow;
+
+	#[test]
+	fn should_skip_replayed_sequences() {
+		let mut window = ReplayWindow::default();
+		assert!(window.insert(1));
+		assert!(window.insert(3));
+		assert!(!window.insert(3));
+		assert!(
+			window.insert(2),
+			"should accept late sequences inside the window"
+		);
+		assert!(!window.insert(1));
+
+		assert!(window.insert(100));
+		assert!(
+			!window.insert(4),
+			"should treat sequences behind the window as applied"
+		);
+		assert!(window.insert(99));
+	}
+
+	#[test]
+	fn should_convert_window_to_bytes_and_back() {
+		let mut window = ReplayWindow::default();
+		window.insert(5);
+		window.insert(7);
+		let bytes = window.to_bytes();
+		assert_eq!(ReplayWindow::from_bytes(&bytes).unwrap(), window);
+	}
+}
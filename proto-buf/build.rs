@@ -5,5 +5,6 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 	compile_protos("services/indexer.proto")?;
 	compile_protos("services/transformer.proto")?;
 	compile_protos("services/combiner.proto")?;
+	compile_protos("services/score.proto")?;
 	Ok(())
 }
@@ -1,3 +1,6 @@
+use prost::Message;
+use tonic::{codegen::Bytes, Code, Status};
+
 pub mod common {
 	tonic::include_proto!("common");
 }
@@ -13,3 +16,124 @@ pub mod transformer {
 pub mod combiner {
 	tonic::include_proto!("combiner");
 }
+
+pub mod score {
+	tonic::include_proto!("score");
+}
+
+/// Revision of the wire protocol the services speak, bumped on changes older peers can't cope
+/// with. Servers report theirs in `ServiceInfo`.
+///
+/// 2 pages queries with `common.PageRequest` and `common.PageResponse`. The paging fields of
+/// version 1 are still read when `page` is absent, and filled in indexer chunks.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+impl common::ServiceInfo {
+	/// Fails with what is amiss unless the server speaks this protocol and supports every one
+	/// of `features`.
+	pub fn check(&self, features: &[&str]) -> Result<(), String> {
+		if self.protocol_version != PROTOCOL_VERSION {
+			return Err(format!(
+				"{} {} speaks protocol version {}, expected {}",
+				self.service, self.version, self.protocol_version, PROTOCOL_VERSION
+			));
+		}
+		let missing: Vec<_> =
+			features.iter().filter(|f| !self.features.iter().any(|s| s == *f)).collect();
+		if !missing.is_empty() {
+			return Err(format!(
+				"{} {} lacks required features {:?}",
+				self.service, self.version, missing
+			));
+		}
+		Ok(())
+	}
+
+	/// Limit named `name`, if the server enforces it.
+	pub fn limit(&self, name: &str) -> Option<u64> {
+		self.limits.get(name).copied()
+	}
+}
+
+impl common::ErrorDetail {
+	/// Not worth retrying, as when the request was at fault.
+	pub fn permanent() -> Self {
+		Self::default()
+	}
+
+	/// Worth retrying, after `retry_after` milliseconds if non-zero.
+	pub fn retryable(retry_after: u64) -> Self {
+		Self { retryable: true, retry_after, ..Self::default() }
+	}
+
+	pub fn with_field(self, field: &str) -> Self {
+		Self { field: field.to_string(), ..self }
+	}
+
+	pub fn with_cause(self, cause: impl ToString) -> Self {
+		Self { cause: cause.to_string(), ..self }
+	}
+
+	/// Status of `code` with `message`, carrying this as its details.
+	pub fn into_status(self, code: Code, message: impl Into<String>) -> Status {
+		Status::with_details(code, message, Bytes::from(self.encode_to_vec()))
+	}
+
+	/// Detail `status` carries, if it carries one.
+	pub fn of(status: &Status) -> Option<Self> {
+		match status.details() {
+			[] => None,
+			details => Self::decode(details).ok(),
+		}
+	}
+}
+
+impl common::PageRequest {
+	/// Request for up to `page_size` items from position `offset`, for queries paging by
+	/// position such as those of the indexer.
+	pub fn from_offset(offset: u32, page_size: u32) -> Self {
+		Self { cursor: offset.to_be_bytes().to_vec(), page_size }
+	}
+
+	/// Position the page starts from, or `None` if the cursor is no position.
+	pub fn offset(&self) -> Option<u32> {
+		parse_offset(&self.cursor)
+	}
+
+	/// Page size the server returns given its `max`.
+	pub fn size_within(&self, max: u32) -> u32 {
+		match self.page_size {
+			0 => max,
+			size => size.min(max),
+		}
+	}
+}
+
+impl common::PageResponse {
+	/// Page of a query paging by position, continuing from `next_offset`.
+	pub fn at_offset(next_offset: u32, has_more: bool) -> Self {
+		Self { next_cursor: next_offset.to_be_bytes().to_vec(), total: 0, has_more }
+	}
+
+	/// Position the page after starts from, if the cursor is a position.
+	pub fn next_offset(&self) -> Option<u32> {
+		parse_offset(&self.next_cursor)
+	}
+}
+
+/// Position of a cursor of a query paging by position, zero if empty.
+fn parse_offset(cursor: &[u8]) -> Option<u32> {
+	match cursor {
+		[] => Some(0),
+		cursor => cursor.try_into().ok().map(u32::from_be_bytes),
+	}
+}
+
+/// Whether the request failing with `status` may succeed later. Statuses without details, e.g.
+/// from servers predating them, are told by their code.
+pub fn is_retryable(status: &Status) -> bool {
+	match common::ErrorDetail::of(status) {
+		Some(detail) => detail.retryable,
+		None => matches!(status.code(), Code::Unavailable | Code::ResourceExhausted),
+	}
+}
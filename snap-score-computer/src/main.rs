@@ -1,3 +1,298 @@
-fn main() {
-	println!("Hello, world!");
+use proto_buf::{
+	common::{ErrorDetail, Heartbeat, PageResponse, ServiceInfo, Void},
+	score::{
+		score_service_server::{ScoreService, ScoreServiceServer},
+		score_watch_event, PeerScore, PeerScoreQuery, ScoreListQuery, ScorePage, ScoreWatch,
+		ScoreWatchEvent, SnapScoreQuery,
+	},
+	PROTOCOL_VERSION,
+};
+use std::{
+	collections::HashMap,
+	error::Error,
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use store::{Snap, SnapStore};
+use tokio::{
+	select,
+	sync::{broadcast::error::RecvError, mpsc::channel},
+	time::interval,
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{transport::Server, Code, Request, Response, Status};
+
+mod store;
+
+/// Snaps kept per domain for `GetSnapScore` and `ListScores` to read from.
+const RETAINED_SNAPS: usize = 8;
+/// Most scores returned per page of `ListScores`.
+const MAX_PAGE_SIZE: u32 = 1000;
+/// Events buffered per `WatchScores` stream.
+const WATCH_BUFFER_SIZE: usize = 16;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Optional RPCs and behaviors reported to clients by `get_service_info`.
+const FEATURES: [&str; 1] = ["watch_scores"];
+
+/// Answers score queries from the snaps computed last.
+struct SnapScoreService {
+	store: Arc<SnapStore>,
+	heartbeat_interval: Duration,
+}
+
+impl SnapScoreService {
+	fn new(store: Arc<SnapStore>) -> Self {
+		Self { store, heartbeat_interval: HEARTBEAT_INTERVAL }
+	}
+
+	/// Snap of `domain` computed at `timestamp`, or its latest one if zero.
+	fn snap(&self, domain: u32, timestamp: u64) -> Result<Arc<Snap>, Status> {
+		self.store.get(domain, timestamp).ok_or_else(|| match timestamp {
+			0 => not_found("domain", "No scores computed for this domain yet!"),
+			_ => not_found("snap", "Snap not found, it may have been dropped!"),
+		})
+	}
+
+	fn peer_score(snap: &Snap, peer: &str) -> Result<PeerScore, Status> {
+		let (score, rank) =
+			snap.score(peer).ok_or_else(|| not_found("peer", "Peer not scored in this snap!"))?;
+		let rank = rank as u64 + 1;
+		Ok(PeerScore { peer: peer.to_string(), score, snap: snap.timestamp, rank })
+	}
+}
+
+#[tonic::async_trait]
+impl ScoreService for SnapScoreService {
+	type WatchScoresStream = ReceiverStream<Result<ScoreWatchEvent, Status>>;
+
+	async fn get_peer_score(
+		&self, request: Request<PeerScoreQuery>,
+	) -> Result<Response<PeerScore>, Status> {
+		let query = request.into_inner();
+		let snap = self.snap(query.domain, 0)?;
+		Ok(Response::new(Self::peer_score(&snap, &query.peer)?))
+	}
+
+	async fn get_snap_score(
+		&self, request: Request<SnapScoreQuery>,
+	) -> Result<Response<PeerScore>, Status> {
+		let query = request.into_inner();
+		let snap = self.snap(query.domain, query.snap)?;
+		Ok(Response::new(Self::peer_score(&snap, &query.peer)?))
+	}
+
+	async fn list_scores(
+		&self, request: Request<ScoreListQuery>,
+	) -> Result<Response<ScorePage>, Status> {
+		let query = request.into_inner();
+		let page = query.page.unwrap_or_default();
+		let offset = page.offset().ok_or_else(|| {
+			let detail = ErrorDetail::permanent().with_field("page.cursor");
+			detail.into_status(Code::InvalidArgument, "Invalid cursor!")
+		})?;
+		let snap = self.snap(query.domain, query.snap)?;
+
+		let size = page.size_within(MAX_PAGE_SIZE);
+		let scores: Vec<_> = snap
+			.page(offset as usize, size as usize)
+			.iter()
+			.enumerate()
+			.map(|(i, (peer, score))| PeerScore {
+				peer: peer.clone(),
+				score: *score,
+				snap: snap.timestamp,
+				rank: u64::from(offset) + i as u64 + 1,
+			})
+			.collect();
+		let next_offset = offset + scores.len() as u32;
+		let mut page = PageResponse::at_offset(next_offset, (next_offset as usize) < snap.len());
+		if offset == 0 {
+			page.total = snap.len() as u64;
+		}
+		Ok(Response::new(ScorePage {
+			scores,
+			page: Some(page),
+			snap: Some(snap_info(&snap)),
+		}))
+	}
+
+	async fn watch_scores(
+		&self, request: Request<ScoreWatch>,
+	) -> Result<Response<Self::WatchScoresStream>, Status> {
+		let domain = request.into_inner().domain;
+		let mut published = self.store.subscribe();
+		let store = self.store.clone();
+		let heartbeat_interval = self.heartbeat_interval;
+
+		let (tx, rx) = channel(WATCH_BUFFER_SIZE);
+		tokio::spawn(async move {
+			// Subscribed before, so no snap published meanwhile is missed.
+			let mut latest = store.get(domain, 0);
+			let mut sent = 0;
+			let mut ticker = interval(heartbeat_interval);
+			ticker.tick().await;
+			loop {
+				let event = match latest.take() {
+					Some(snap) if snap.timestamp > sent => {
+						sent = snap.timestamp;
+						score_watch_event::Event::Snap(snap_info(&snap))
+					},
+					Some(_) => continue,
+					None => select! {
+						snap = published.recv() => {
+							latest = match snap {
+								Ok(snap) if snap.domain == domain => Some(snap),
+								Ok(_) => None,
+								// Only the latest snap matters to watchers falling behind.
+								Err(RecvError::Lagged(_)) => store.get(domain, 0),
+								Err(RecvError::Closed) => break,
+							};
+							ticker.reset();
+							continue;
+						},
+						_ = ticker.tick() => {
+							let heartbeat = Heartbeat { next_position: sent, timestamp: now_secs() };
+							score_watch_event::Event::Heartbeat(heartbeat)
+						},
+					},
+				};
+				if tx.send(Ok(ScoreWatchEvent { event: Some(event) })).await.is_err() {
+					break;
+				}
+			}
+		});
+
+		Ok(Response::new(ReceiverStream::new(rx)))
+	}
+
+	async fn get_service_info(&self, _: Request<Void>) -> Result<Response<ServiceInfo>, Status> {
+		let limits = HashMap::from([("max_page_size".to_string(), u64::from(MAX_PAGE_SIZE))]);
+		Ok(Response::new(ServiceInfo {
+			service: "snap-score-computer".to_string(),
+			version: env!("CARGO_PKG_VERSION").to_string(),
+			protocol_version: PROTOCOL_VERSION,
+			features: FEATURES.iter().map(|f| f.to_string()).collect(),
+			limits,
+		}))
+	}
+}
+
+fn snap_info(snap: &Snap) -> proto_buf::score::Snap {
+	proto_buf::score::Snap {
+		domain: snap.domain,
+		timestamp: snap.timestamp,
+		peers: snap.len() as u64,
+	}
+}
+
+/// Status of a query for something not there, not worth retrying as is.
+fn not_found(field: &str, message: &str) -> Status {
+	ErrorDetail::permanent().with_field(field).into_status(Code::NotFound, message)
+}
+
+fn now_secs() -> u64 {
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards");
+	now.as_secs()
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+	// The compute loop publishes its snaps into the store once it lands, until then every
+	// domain answers NOT_FOUND.
+	let store = Arc::new(SnapStore::new(RETAINED_SNAPS));
+	let service = SnapScoreService::new(store);
+
+	let addr = "[::1]:50053".parse()?;
+	Server::builder().add_service(ScoreServiceServer::new(service)).serve(addr).await?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod test {
+	use super::SnapScoreService;
+	use crate::store::{Snap, SnapStore};
+	use proto_buf::{
+		common::PageRequest,
+		score::{
+			score_service_server::ScoreService, score_watch_event::Event, PeerScoreQuery,
+			ScoreListQuery, ScoreWatch, SnapScoreQuery,
+		},
+	};
+	use std::{sync::Arc, time::Duration};
+	use tokio_stream::StreamExt;
+	use tonic::{Code, Request};
+
+	fn scores(peers: &[(&str, f64)]) -> Vec<(String, f64)> {
+		peers.iter().map(|(peer, score)| (peer.to_string(), *score)).collect()
+	}
+
+	#[tokio::test]
+	async fn should_get_peer_scores() {
+		let store = Arc::new(SnapStore::new(2));
+		let service = SnapScoreService::new(store.clone());
+		let query = PeerScoreQuery { domain: 1, peer: "a".to_string() };
+		let status = service.get_peer_score(Request::new(query.clone())).await.unwrap_err();
+		assert_eq!(status.code(), Code::NotFound);
+
+		store.publish(Snap::new(1, 10, scores(&[("a", 0.4), ("b", 0.6)])));
+		store.publish(Snap::new(1, 20, scores(&[("a", 0.7), ("b", 0.3)])));
+		let score = service.get_peer_score(Request::new(query)).await.unwrap().into_inner();
+		assert_eq!((score.score, score.snap, score.rank), (0.7, 20, 1));
+
+		let query = SnapScoreQuery { domain: 1, peer: "a".to_string(), snap: 10 };
+		let score = service.get_snap_score(Request::new(query)).await.unwrap().into_inner();
+		assert_eq!(
+			(score.score, score.rank),
+			(0.4, 2),
+			"should read the snap asked for"
+		);
+	}
+
+	#[tokio::test]
+	async fn should_list_scores_in_pages() {
+		let store = Arc::new(SnapStore::new(2));
+		let service = SnapScoreService::new(store.clone());
+		store.publish(Snap::new(
+			1,
+			10,
+			scores(&[("a", 0.1), ("b", 0.6), ("c", 0.3)]),
+		));
+
+		let query =
+			ScoreListQuery { domain: 1, snap: 0, page: Some(PageRequest::from_offset(0, 2)) };
+		let page = service.list_scores(Request::new(query)).await.unwrap().into_inner();
+		let peers: Vec<_> = page.scores.iter().map(|score| score.peer.as_str()).collect();
+		assert_eq!(peers, ["b", "c"]);
+		let next = page.page.unwrap();
+		assert_eq!((next.total, next.has_more), (3, true));
+
+		let page = Some(PageRequest { cursor: next.next_cursor, page_size: 2 });
+		let query = ScoreListQuery { domain: 1, snap: 10, page };
+		let page = service.list_scores(Request::new(query)).await.unwrap().into_inner();
+		assert_eq!(page.scores[0].rank, 3);
+		assert!(!page.page.unwrap().has_more);
+	}
+
+	#[tokio::test]
+	async fn should_announce_new_snaps() {
+		let store = Arc::new(SnapStore::new(2));
+		let mut service = SnapScoreService::new(store.clone());
+		service.heartbeat_interval = Duration::from_millis(50);
+		store.publish(Snap::new(1, 10, scores(&[("a", 1.)])));
+
+		let request = Request::new(ScoreWatch { domain: 1 });
+		let mut stream = service.watch_scores(request).await.unwrap().into_inner();
+		let event = stream.next().await.unwrap().unwrap().event.unwrap();
+		assert!(matches!(event, Event::Snap(snap) if snap.timestamp == 10));
+
+		store.publish(Snap::new(2, 20, scores(&[("a", 1.)])));
+		store.publish(Snap::new(1, 30, scores(&[("a", 0.5), ("b", 0.5)])));
+		let event = stream.next().await.unwrap().unwrap().event.unwrap();
+		assert!(
+			matches!(event, Event::Snap(snap) if (snap.timestamp, snap.peers) == (30, 2)),
+			"should announce the snaps of the domain watched only"
+		);
+		let event = stream.next().await.unwrap().unwrap().event.unwrap();
+		assert!(matches!(event, Event::Heartbeat(heartbeat) if heartbeat.next_position == 30));
+	}
 }
@@ -0,0 +1,130 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	sync::{Arc, PoisonError, RwLock},
+};
+use tokio::sync::broadcast;
+
+/// Snaps announced to watchers not keeping up before they miss some.
+const PUBLISHED_BUFFER_SIZE: usize = 64;
+
+/// Scores of the peers of a domain as of one computation.
+#[derive(Debug)]
+pub struct Snap {
+	pub domain: u32,
+	/// Unix time in milliseconds the snap was computed at, identifying it within its domain.
+	pub timestamp: u64,
+	/// Scores of every peer, from the highest down.
+	scores: Vec<(String, f64)>,
+	/// Position of every peer in `scores`.
+	ranks: HashMap<String, usize>,
+}
+
+impl Snap {
+	/// Ranks `scores`, ties going to the peer sorting first.
+	pub fn new(domain: u32, timestamp: u64, mut scores: Vec<(String, f64)>) -> Self {
+		scores.sort_by(|(a, x), (b, y)| y.total_cmp(x).then_with(|| a.cmp(b)));
+		let ranks = scores.iter().enumerate().map(|(i, (peer, _))| (peer.clone(), i)).collect();
+		Self { domain, timestamp, scores, ranks }
+	}
+
+	/// Score of `peer` along with its position from the highest score down, from zero.
+	pub fn score(&self, peer: &str) -> Option<(f64, usize)> {
+		let rank = *self.ranks.get(peer)?;
+		Some((self.scores[rank].1, rank))
+	}
+
+	/// Up to `size` scores from position `offset` on.
+	pub fn page(&self, offset: usize, size: usize) -> &[(String, f64)] {
+		let start = offset.min(self.scores.len());
+		let end = start.saturating_add(size).min(self.scores.len());
+		&self.scores[start..end]
+	}
+
+	pub fn len(&self) -> usize {
+		self.scores.len()
+	}
+}
+
+/// Latest snaps of every domain, announcing new ones as they are published.
+pub struct SnapStore {
+	/// Snaps of every domain, by timestamp.
+	domains: RwLock<HashMap<u32, BTreeMap<u64, Arc<Snap>>>>,
+	/// Snaps kept per domain, the oldest going first.
+	retained: usize,
+	published: broadcast::Sender<Arc<Snap>>,
+}
+
+impl SnapStore {
+	pub fn new(retained: usize) -> Self {
+		let (published, _) = broadcast::channel(PUBLISHED_BUFFER_SIZE);
+		Self { domains: RwLock::new(HashMap::new()), retained: retained.max(1), published }
+	}
+
+	/// Keeps `snap` among those of its domain, dropping the oldest past the number retained,
+	/// and announces it.
+	pub fn publish(&self, snap: Snap) -> Arc<Snap> {
+		let snap = Arc::new(snap);
+		{
+			let mut domains = self.domains.write().unwrap_or_else(PoisonError::into_inner);
+			let snaps = domains.entry(snap.domain).or_default();
+			snaps.insert(snap.timestamp, snap.clone());
+			while snaps.len() > self.retained {
+				snaps.pop_first();
+			}
+		}
+		// Sending only fails when nobody is watching.
+		let _ = self.published.send(snap.clone());
+		snap
+	}
+
+	/// Snap of `domain` computed at `timestamp`, or its latest one if zero.
+	pub fn get(&self, domain: u32, timestamp: u64) -> Option<Arc<Snap>> {
+		let domains = self.domains.read().unwrap_or_else(PoisonError::into_inner);
+		let snaps = domains.get(&domain)?;
+		match timestamp {
+			0 => snaps.values().next_back().cloned(),
+			timestamp => snaps.get(&timestamp).cloned(),
+		}
+	}
+
+	/// Snaps published from now on, of every domain.
+	pub fn subscribe(&self) -> broadcast::Receiver<Arc<Snap>> {
+		self.published.subscribe()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Snap, SnapStore};
+
+	fn snap(domain: u32, timestamp: u64) -> Snap {
+		let scores = vec![("a".to_string(), 0.2), ("b".to_string(), 0.5), ("c".to_string(), 0.2)];
+		Snap::new(domain, timestamp, scores)
+	}
+
+	#[test]
+	fn should_rank_scores() {
+		let snap = snap(1, 10);
+		assert_eq!(snap.score("b"), Some((0.5, 0)));
+		assert_eq!(snap.score("c"), Some((0.2, 2)), "should break ties by peer");
+		assert_eq!(snap.score("d"), None);
+		let peers: Vec<_> = snap.page(1, 5).iter().map(|(peer, _)| peer.as_str()).collect();
+		assert_eq!(peers, ["a", "c"]);
+		assert!(snap.page(5, 5).is_empty());
+	}
+
+	#[test]
+	fn should_keep_latest_snaps() {
+		let store = SnapStore::new(2);
+		let mut published = store.subscribe();
+		store.publish(snap(1, 10));
+		store.publish(snap(1, 30));
+		store.publish(snap(1, 20));
+		store.publish(snap(2, 5));
+		assert_eq!(store.get(1, 0).unwrap().timestamp, 30);
+		assert_eq!(store.get(1, 20).unwrap().timestamp, 20);
+		assert!(store.get(1, 10).is_none(), "should drop the oldest snaps");
+		assert!(store.get(3, 0).is_none());
+		assert_eq!(published.try_recv().unwrap().timestamp, 10);
+	}
+}